@@ -0,0 +1,280 @@
+//! Re-verification of blocks which have already been imported.
+//!
+//! This is used to check the integrity of a database: for each block, the commitment state diff is rebuilt from the
+//! stored state diff and applied on top of the global tries as they were at the previous block, and the resulting
+//! global state root is compared against the one stored in the block header. A mismatch means that either the stored
+//! block, its state diff or the tries have been corrupted.
+//!
+//! The state diffs are applied on in-memory views of the tries, nothing is written to the database.
+
+use crate::verify_apply::classes::class_leaf_hash;
+use crate::verify_apply::contracts::{contract_leaves, contract_state_leaf_hash};
+use crate::verify_apply::{calculate_state_root, make_db_error};
+use crate::BlockImportError;
+use bitvec::{order::Msb0, vec::BitVec, view::AsBits};
+use bonsai_trie::databases::HashMapDb;
+use bonsai_trie::{BonsaiDatabase, BonsaiStorage, BonsaiStorageError};
+use mc_db::{bonsai_identifier, db_block_id::DbBlockId, BasicId, GlobalTrie, GlobalTrieView, MadaraBackend};
+use mp_block::BlockId;
+use mp_state_update::{ContractStorageDiffItem, DeclaredClassItem, StateDiff, StorageEntry};
+use starknet_types_core::felt::Felt;
+use starknet_types_core::hash::{Pedersen, Poseidon, StarkHash};
+use std::ops::RangeInclusive;
+
+/// First block of a checked range whose recomputed global state root does not match the one stored in its header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateRootMismatch {
+    pub block_n: u64,
+    pub stored: Felt,
+    pub computed: Felt,
+}
+
+/// Re-applies the stored state diff of every block in `range` and checks the resulting global state root, returning
+/// the first divergent block if any.
+///
+/// The state diff of a block is applied on the tries as they were at the previous block, so only the blocks whose
+/// parent is still covered by the trie logs can be checked, see
+/// [`TrieLogConfig::max_saved_trie_logs`](mc_db::TrieLogConfig). The genesis block is applied on empty tries.
+pub fn check_global_state_roots(
+    backend: &MadaraBackend,
+    range: RangeInclusive<u64>,
+) -> Result<Option<StateRootMismatch>, BlockImportError> {
+    for block_n in range {
+        let stored = backend
+            .get_block_info(&DbBlockId::Number(block_n))
+            .map_err(make_db_error(format!("getting block info for block #{block_n}")))?
+            .and_then(|block_info| block_info.as_nonpending_owned())
+            .ok_or_else(|| BlockImportError::Internal(format!("Block #{block_n} not found in database").into()))?
+            .header
+            .global_state_root;
+        let state_diff = backend
+            .get_block_state_diff(&DbBlockId::Number(block_n))
+            .map_err(make_db_error(format!("getting the state diff of block #{block_n}")))?
+            .ok_or_else(|| {
+                BlockImportError::Internal(format!("State diff of block #{block_n} not found in database").into())
+            })?;
+
+        let computed = match block_n.checked_sub(1) {
+            None => {
+                let tries = ScratchTries {
+                    contract_storage: empty_trie(backend.contract_storage_trie()),
+                    contract: empty_trie(backend.contract_trie()),
+                    class: empty_trie(backend.class_trie()),
+                };
+                tries.apply(backend, &state_diff, block_n)?
+            }
+            Some(parent_block_n) => {
+                let (contract_storage_trie, contract_trie, class_trie) =
+                    (backend.contract_storage_trie(), backend.contract_trie(), backend.class_trie());
+                let tries = ScratchTries {
+                    contract_storage: trie_view_at(&contract_storage_trie, parent_block_n)?,
+                    contract: trie_view_at(&contract_trie, parent_block_n)?,
+                    class: trie_view_at(&class_trie, parent_block_n)?,
+                };
+                tries.apply(backend, &state_diff, block_n)?
+            }
+        };
+
+        tracing::debug!("Checked block #{block_n}: stored state root {stored:#x}, computed {computed:#x}");
+
+        if stored != computed {
+            return Ok(Some(StateRootMismatch { block_n, stored, computed }));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The global tries a state diff is re-applied on.
+struct ScratchTries<DB: BonsaiDatabase> {
+    contract_storage: BonsaiStorage<BasicId, DB, Pedersen>,
+    contract: BonsaiStorage<BasicId, DB, Pedersen>,
+    class: BonsaiStorage<BasicId, DB, Poseidon>,
+}
+
+impl<DB: BonsaiDatabase> ScratchTries<DB> {
+    /// Applies `state_diff` the same way the block importer does, and returns the new global state root. The nonces
+    /// and class hashes of the contract leaves which are not in the state diff are read at `block_n`.
+    fn apply(self, backend: &MadaraBackend, state_diff: &StateDiff, block_n: u64) -> Result<Felt, BlockImportError> {
+        let Self { mut contract_storage, mut contract, mut class } = self;
+        let trie_error = |err: BonsaiStorageError<DB::DatabaseError>| {
+            BlockImportError::Internal(format!("Re-applying the state diff of block #{block_n}: {err}").into())
+        };
+
+        for ContractStorageDiffItem { address, storage_entries } in &state_diff.storage_diffs {
+            for StorageEntry { key, value } in storage_entries {
+                contract_storage.insert(&address.to_bytes_be(), &key_bits(key), value).map_err(trie_error)?;
+            }
+        }
+        contract_storage.transactional_commit().map_err(trie_error)?;
+
+        let contract_leafs = contract_leaves(
+            &state_diff.deployed_contracts,
+            &state_diff.replaced_classes,
+            &state_diff.nonces,
+            &state_diff.storage_diffs,
+        );
+        for (contract_address, mut leaf) in contract_leafs {
+            leaf.storage_root = Some(contract_storage.root_hash(&contract_address.to_bytes_be()).map_err(trie_error)?);
+            let leaf_hash = contract_state_leaf_hash(backend, &BlockId::Number(block_n), &contract_address, &leaf)
+                .map_err(make_db_error(format!("computing the leaf of contract {contract_address:#x}")))?;
+            contract
+                .insert(bonsai_identifier::CONTRACT, &key_bits(&contract_address), &leaf_hash)
+                .map_err(trie_error)?;
+        }
+        contract.transactional_commit().map_err(trie_error)?;
+
+        for DeclaredClassItem { class_hash, compiled_class_hash } in &state_diff.declared_classes {
+            class
+                .insert(bonsai_identifier::CLASS, &key_bits(class_hash), &class_leaf_hash(compiled_class_hash))
+                .map_err(trie_error)?;
+        }
+        class.transactional_commit().map_err(trie_error)?;
+
+        Ok(calculate_state_root(
+            contract.root_hash(bonsai_identifier::CONTRACT).map_err(trie_error)?,
+            class.root_hash(bonsai_identifier::CLASS).map_err(trie_error)?,
+        ))
+    }
+}
+
+fn key_bits(key: &Felt) -> BitVec<u8, Msb0> {
+    key.to_bytes_be().as_bits()[5..].to_owned()
+}
+
+/// In-memory trie with the configuration of `trie`, but none of its content.
+fn empty_trie<H: StarkHash + Send + Sync>(trie: GlobalTrie<H>) -> BonsaiStorage<BasicId, HashMapDb<BasicId>, H> {
+    // Every global tree has keys of 251 bits.
+    BonsaiStorage::new(HashMapDb::default(), trie.get_config(), 251)
+}
+
+fn trie_view_at<H: StarkHash + Send + Sync>(
+    trie: &GlobalTrie<H>,
+    block_n: u64,
+) -> Result<GlobalTrieView<H>, BlockImportError> {
+    trie.get_transactional_state(BasicId::new(block_n), trie.get_config())
+        .map_err(|err| BlockImportError::Internal(format!("Getting trie state at block #{block_n}: {err:#}").into()))?
+        .ok_or_else(|| {
+            BlockImportError::Internal(
+                format!("Block #{} is too old to be checked, its trie logs have been pruned", block_n + 1).into(),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::block_import_utils::create_dummy_block;
+    use crate::verify_apply_inner;
+    use crate::BlockValidationContext;
+    use mc_db::TrieLogConfig;
+    use mp_chain_config::ChainConfig;
+    use rstest::*;
+    use starknet_api::{core::ChainId, felt};
+    use std::sync::Arc;
+
+    #[fixture]
+    fn backend_with_blocks() -> Arc<MadaraBackend> {
+        let backend = MadaraBackend::open_for_testing_with_trie_logs(
+            Arc::new(ChainConfig::madara_test()),
+            TrieLogConfig { max_saved_trie_logs: 16, ..Default::default() },
+        );
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+
+        let mut parent_block_hash = Felt::ZERO;
+        for block_n in 0..3 {
+            let mut block = create_dummy_block();
+            block.unverified_block_number = Some(block_n);
+            block.header.parent_block_hash = Some(parent_block_hash);
+            block.unverified_global_state_root = None;
+            block.unverified_block_hash = None;
+            block.state_diff = StateDiff {
+                storage_diffs: vec![ContractStorageDiffItem {
+                    address: felt!("0x1"),
+                    storage_entries: vec![StorageEntry { key: Felt::from(block_n), value: felt!("0x1") }],
+                }],
+                ..Default::default()
+            };
+
+            let res = verify_apply_inner(&backend, block, validation.clone()).unwrap();
+            parent_block_hash = res.block_hash;
+        }
+
+        backend
+    }
+
+    /// Stores block #1 again with its header and state diff modified by `corrupt`, leaving the tries untouched.
+    fn corrupt_block_1(backend: &MadaraBackend, corrupt: impl FnOnce(&mut mp_block::Header, &mut StateDiff)) {
+        let mut block = backend.get_block(&DbBlockId::Number(1)).unwrap().unwrap();
+        let mp_block::MadaraMaybePendingBlockInfo::NotPending(info) = &mut block.info else { unreachable!() };
+        let mut state_diff = backend.get_block_state_diff(&DbBlockId::Number(1)).unwrap().unwrap();
+        corrupt(&mut info.header, &mut state_diff);
+        backend.store_block(block, state_diff, vec![]).unwrap();
+
+        // Store the latest block again, so that the tries are read back from it
+        let block = backend.get_block(&DbBlockId::Number(2)).unwrap().unwrap();
+        let state_diff = backend.get_block_state_diff(&DbBlockId::Number(2)).unwrap().unwrap();
+        backend.store_block(block, state_diff, vec![]).unwrap();
+    }
+
+    fn stored_state_root(backend: &MadaraBackend, block_n: u64) -> Felt {
+        let block_info = backend.get_block_info(&DbBlockId::Number(block_n)).unwrap().unwrap();
+        block_info.as_nonpending().unwrap().header.global_state_root
+    }
+
+    #[rstest]
+    fn test_check_global_state_roots_ok(backend_with_blocks: Arc<MadaraBackend>) {
+        assert_eq!(check_global_state_roots(&backend_with_blocks, 0..=2).unwrap(), None);
+    }
+
+    #[rstest]
+    fn test_check_global_state_roots_corrupted_header(backend_with_blocks: Arc<MadaraBackend>) {
+        let backend = backend_with_blocks;
+        let stored_root = stored_state_root(&backend, 1);
+
+        corrupt_block_1(&backend, |header, _| header.global_state_root = felt!("0xdead"));
+
+        assert_eq!(
+            check_global_state_roots(&backend, 0..=2).unwrap(),
+            Some(StateRootMismatch { block_n: 1, stored: felt!("0xdead"), computed: stored_root })
+        );
+    }
+
+    #[rstest]
+    fn test_check_global_state_roots_corrupted_state_diff(backend_with_blocks: Arc<MadaraBackend>) {
+        let backend = backend_with_blocks;
+        let stored_root = stored_state_root(&backend, 1);
+
+        // The header and the tries still agree, only the stored state diff of block #1 is corrupted
+        corrupt_block_1(&backend, |_, state_diff| state_diff.storage_diffs[0].storage_entries[0].value = felt!("0x2"));
+
+        let mismatch = check_global_state_roots(&backend, 0..=2).unwrap().expect("The corruption must be detected");
+        assert_eq!(mismatch.block_n, 1);
+        assert_eq!(mismatch.stored, stored_root);
+        assert_ne!(mismatch.computed, stored_root);
+    }
+
+    #[rstest]
+    fn test_check_global_state_roots_without_trie_logs() {
+        let backend = MadaraBackend::open_for_testing(Arc::new(ChainConfig::madara_test()));
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+        let mut parent_block_hash = Felt::ZERO;
+        for block_n in 0..2 {
+            let mut block = create_dummy_block();
+            block.unverified_block_number = Some(block_n);
+            block.header.parent_block_hash = Some(parent_block_hash);
+            block.unverified_global_state_root = None;
+            block.unverified_block_hash = None;
+            parent_block_hash = verify_apply_inner(&backend, block, validation.clone()).unwrap().block_hash;
+        }
+
+        // The genesis block is applied on empty tries, but the tries cannot be read back at block #0
+        assert_eq!(check_global_state_roots(&backend, 0..=0).unwrap(), None);
+        assert!(matches!(check_global_state_roots(&backend, 1..=1), Err(BlockImportError::Internal(_))));
+    }
+
+    #[rstest]
+    fn test_check_global_state_roots_missing_block(backend_with_blocks: Arc<MadaraBackend>) {
+        assert!(matches!(check_global_state_roots(&backend_with_blocks, 0..=5), Err(BlockImportError::Internal(_))));
+    }
+}
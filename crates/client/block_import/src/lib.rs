@@ -43,14 +43,16 @@ use mc_db::{MadaraBackend, MadaraStorageError};
 use metrics::BlockMetrics;
 use mp_class::{class_hash::ComputeClassHashError, compile::ClassCompilationError};
 use starknet_types_core::felt::Felt;
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, collections::BTreeSet, num::NonZeroU64, path::PathBuf, sync::Arc};
 
+mod check_state;
 mod metrics;
 mod pre_validate;
 mod rayon;
 pub mod tests;
 mod types;
 mod verify_apply;
+pub use check_state::*;
 pub use pre_validate::*;
 pub use rayon::*;
 pub use types::*;
@@ -99,6 +101,13 @@ pub enum BlockImportError {
     ParentHash { got: Felt, expected: Felt },
     #[error("Global state root mismatch: expected {expected:#x}, got {got:#x}")]
     GlobalStateRoot { got: Felt, expected: Felt },
+    /// The trie updates of several blocks were committed at once, see [`BlockImporter::with_trie_commit_interval`].
+    /// Only the state root of the last block could be checked, so any block of the range may be the faulty one.
+    #[error(
+        "Global state root mismatch at block #{last_block_n}: expected {expected:#x}, got {got:#x}. The trie updates \
+         of blocks #{first_block_n}..=#{last_block_n} were committed together and are all suspect"
+    )]
+    StagedGlobalStateRoot { got: Felt, expected: Felt, first_block_n: u64, last_block_n: u64 },
 
     /// Internal error, see [`BlockImportError::is_internal`].
     #[error("Internal database error while {context}: {error:#}")]
@@ -143,6 +152,46 @@ impl BlockImporter {
         })
     }
 
+    /// Only commit the global tries every `trie_commit_interval` blocks. The trie updates of the blocks in between
+    /// are staged in memory, and their global state root is checked once the tries are committed. This speeds up
+    /// historical sync, at the cost of:
+    /// - the blocks following a staged block cannot be checked with [`check_global_state_roots`], as the tries are
+    ///   never committed at the staged blocks;
+    /// - staged updates are lost if the node stops without [`BlockImporter::commit_staged_tries`] being called.
+    pub fn with_trie_commit_interval(mut self, trie_commit_interval: NonZeroU64) -> Self {
+        self.verify_apply.trie_commit_interval = trie_commit_interval.get();
+        self
+    }
+
+    /// Replaces the computation of the global state root, see [`StateRootVerifier`].
+    pub fn with_state_root_verifier(mut self, state_root_verifier: Arc<dyn StateRootVerifier>) -> Self {
+        self.verify_apply.state_root_verifier = state_root_verifier;
+        self
+    }
+
+    /// Writes the state diff of every block to `<dir>/<block_number>.json` before applying it on the global tries,
+    /// to debug state root mismatches. See [`state_diff_dump_path`].
+    pub fn with_state_diff_dump_dir(mut self, dir: PathBuf) -> Self {
+        self.verify_apply.state_diff_dump_dir = Some(dir);
+        self
+    }
+
+    /// Commits the trie updates staged by [`BlockImporter::with_trie_commit_interval`]. This must be called before
+    /// the node stops. Calling it again is a no-op, as nothing is staged anymore. A state root mismatch on one of
+    /// the `skip_blocks` is only logged, see [`BlockValidationContext::skip_blocks()`].
+    #[tracing::instrument(skip(self, skip_blocks), fields(module = "BlockImporter"))]
+    pub async fn commit_staged_tries(&self, skip_blocks: &BTreeSet<u64>) -> Result<(), BlockImportError> {
+        self.verify_apply.commit_staged_tries(skip_blocks).await
+    }
+
+    /// Re-applies the state diffs of the stored blocks which are missing from the global tries, e.g. when the node
+    /// was killed before [`BlockImporter::commit_staged_tries`] could run. This fails if the resulting global state
+    /// root does not match the one of the latest block, unless it is listed in `skip_blocks`.
+    #[tracing::instrument(skip(self, skip_blocks), fields(module = "BlockImporter"))]
+    pub async fn recover_tries(&self, skip_blocks: &BTreeSet<u64>) -> Result<(), BlockImportError> {
+        self.verify_apply.recover_tries(skip_blocks).await
+    }
+
     /// Perform [`BlockImporter::pre_validate`] followed by [`BlockImporter::verify_apply`] to import a block.
     #[tracing::instrument(skip(self, block, validation), fields(module = "BlockImporter"))]
     pub async fn add_block(
@@ -8,7 +8,7 @@ use opentelemetry::{
     KeyValue,
 };
 use std::{
-    sync::Mutex,
+    sync::{Mutex, PoisonError},
     time::{Duration, Instant},
 };
 
@@ -131,7 +131,7 @@ impl BlockMetrics {
 
         // Update Block sync time metrics
         let latest_sync_time = {
-            let mut last_update = self.last_update_instant.lock().expect("Poisoned lock");
+            let mut last_update = self.last_update_instant.lock().unwrap_or_else(PoisonError::into_inner);
             let latest_sync_time = last_update.map(|inst| now.duration_since(inst)).unwrap_or_default();
             *last_update = Some(now);
             latest_sync_time.as_secs_f64()
@@ -151,7 +151,8 @@ impl BlockMetrics {
         self.l1_gas_price_strk.record(f64::from_u128(block_header.l1_gas_price.strk_l1_gas_price).unwrap_or(0f64), &[]);
 
         {
-            let mut last_db_instant = self.last_db_metrics_update_instant.lock().expect("Poisoned lock");
+            let mut last_db_instant =
+                self.last_db_metrics_update_instant.lock().unwrap_or_else(PoisonError::into_inner);
             let last_update_duration = last_db_instant.map(|inst| now.duration_since(inst));
 
             if last_update_duration.is_none() || last_update_duration.is_some_and(|d| d >= Duration::from_secs(5)) {
@@ -161,11 +161,14 @@ fn class_conversion(
                     return Err(BlockImportError::ClassHash { got: sierra.class_hash, expected: class_hash });
                 }
             }
-            let (compiled_class_hash, compiled_class) = sierra
-                .contract_class
-                .compile_to_casm()
+            let compiled = match sierra.compiled_class {
+                // Compiled when the class was fetched, only the compiled class hash is left to compute
+                Some(compiled_class) => compiled_class.compiled_class_hash().map(|hash| (hash, compiled_class)),
+                None => sierra.contract_class.compile_to_casm(),
+            };
+            let (compiled_class_hash, compiled_class) = compiled
                 .map_err(|e| BlockImportError::CompilationClassError { class_hash: sierra.class_hash, error: e })?;
-            if compiled_class_hash != sierra.compiled_class_hash {
+            if !validation.trust_class_hashes && compiled_class_hash != sierra.compiled_class_hash {
                 return Err(BlockImportError::CompiledClassHash {
                     class_hash: sierra.class_hash,
                     got: sierra.compiled_class_hash,
@@ -281,7 +284,7 @@ fn event_commitment(
     let events_with_tx_hash: Vec<_> = block
         .receipts
         .iter()
-        .flat_map(|receipt| receipt.events().iter().map(move |event| (receipt.transaction_hash(), event.clone())))
+        .flat_map(|receipt| receipt.events().iter().map(move |event| (receipt.transaction_hash(), event)))
         .collect();
 
     if let Some(expected) = block.commitments.event_count {
@@ -385,6 +388,7 @@ fn compute_merkle_root<H: StarkHash + Send + Sync>(values: &[Felt]) -> Felt {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::SierraDeclaredClass;
 
     #[test]
     fn test_compute_root() {
@@ -393,4 +397,112 @@ mod tests {
 
         assert_eq!(root, Felt::from_hex_unchecked("0x3b5cc7f1292eb3847c3f902d048a7e5dc7702d1c191ccd17c2d33f797e6fc32"));
     }
+
+    fn test_sierra_class() -> mp_class::FlattenedSierraClass {
+        let sierra_class: starknet_core::types::contract::SierraClass =
+            serde_json::from_slice(m_cairo_test_contracts::TEST_CONTRACT_SIERRA).unwrap();
+        sierra_class.flatten().unwrap().into()
+    }
+
+    #[test]
+    fn test_class_conversion_sierra_class_hash() {
+        let contract_class = test_sierra_class();
+        let class_hash = contract_class.compute_class_hash().unwrap();
+        let (compiled_class_hash, _) = contract_class.compile_to_casm().unwrap();
+        let validation = BlockValidationContext::new(ChainId::Mainnet);
+
+        let class = DeclaredClass::Sierra(SierraDeclaredClass {
+            class_hash,
+            contract_class,
+            compiled_class_hash,
+            compiled_class: None,
+        });
+        let converted = class_conversion(class, &validation).unwrap();
+        assert_eq!(converted.class_hash(), class_hash);
+    }
+
+    #[test]
+    fn test_class_conversion_sierra_class_hash_mismatch() {
+        let contract_class = test_sierra_class();
+        let class_hash = contract_class.compute_class_hash().unwrap();
+        let (compiled_class_hash, _) = contract_class.compile_to_casm().unwrap();
+        let validation = BlockValidationContext::new(ChainId::Mainnet);
+
+        // The gateway served a class which does not match the requested class hash.
+        let requested = Felt::from_hex_unchecked("0x1234");
+        let class = DeclaredClass::Sierra(SierraDeclaredClass {
+            class_hash: requested,
+            contract_class,
+            compiled_class_hash,
+            compiled_class: None,
+        });
+        assert!(matches!(
+            class_conversion(class, &validation),
+            Err(BlockImportError::ClassHash { got, expected }) if got == requested && expected == class_hash
+        ));
+    }
+
+    /// A class compiled when it was fetched is not compiled again, but its compiled class hash is still verified.
+    #[test]
+    fn test_class_conversion_attached_compiled_class() {
+        let contract_class = test_sierra_class();
+        let class_hash = contract_class.compute_class_hash().unwrap();
+        let (compiled_class_hash, compiled_class) = contract_class.compile_to_casm().unwrap();
+        let validation = BlockValidationContext::new(ChainId::Mainnet);
+
+        let class = DeclaredClass::Sierra(SierraDeclaredClass {
+            class_hash,
+            contract_class: contract_class.clone(),
+            compiled_class_hash,
+            compiled_class: Some(compiled_class.clone()),
+        });
+        let ConvertedClass::Sierra(converted) = class_conversion(class, &validation).unwrap() else {
+            panic!("Expected a sierra class")
+        };
+        assert_eq!(converted.info.compiled_class_hash, compiled_class_hash);
+        assert_eq!(*converted.compiled, compiled_class);
+
+        let class = DeclaredClass::Sierra(SierraDeclaredClass {
+            class_hash,
+            contract_class,
+            compiled_class_hash: Felt::from_hex_unchecked("0x5678"),
+            compiled_class: Some(compiled_class),
+        });
+        assert!(matches!(
+            class_conversion(class, &validation),
+            Err(BlockImportError::CompiledClassHash { got, expected, .. })
+                if got == Felt::from_hex_unchecked("0x5678") && expected == compiled_class_hash
+        ));
+    }
+
+    #[rstest::rstest]
+    #[case::verified(false)]
+    #[case::trusted(true)]
+    fn test_class_conversion_trust_class_hashes(#[case] trust_class_hashes: bool) {
+        let contract_class = test_sierra_class();
+        let validation = BlockValidationContext::new(ChainId::Mainnet).trust_class_hashes(trust_class_hashes);
+
+        let class = DeclaredClass::Sierra(SierraDeclaredClass {
+            class_hash: Felt::from_hex_unchecked("0x1234"),
+            contract_class: contract_class.clone(),
+            compiled_class_hash: Felt::from_hex_unchecked("0x5678"),
+            compiled_class: None,
+        });
+        let res = class_conversion(class, &validation);
+        assert_eq!(res.is_ok(), trust_class_hashes, "{res:?}");
+
+        // Only the compiled class hash is wrong
+        let class = DeclaredClass::Sierra(SierraDeclaredClass {
+            class_hash: contract_class.compute_class_hash().unwrap(),
+            contract_class,
+            compiled_class_hash: Felt::from_hex_unchecked("0x5678"),
+            compiled_class: None,
+        });
+        let res = class_conversion(class, &validation);
+        if trust_class_hashes {
+            assert!(res.is_ok(), "{res:?}");
+        } else {
+            assert!(matches!(res, Err(BlockImportError::CompiledClassHash { .. })), "{res:?}");
+        }
+    }
 }
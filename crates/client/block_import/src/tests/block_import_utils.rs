@@ -57,6 +57,8 @@ pub fn create_validation_context(ignore_block_order: bool) -> BlockValidationCon
         trust_global_tries: false,
         trust_transaction_hashes: false,
         trust_class_hashes: false,
+        skip_blocks: Default::default(),
+        reapply_stored_blocks: false,
     }
 }
 
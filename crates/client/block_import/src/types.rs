@@ -8,7 +8,7 @@ use mp_block::{
 use mp_chain_config::StarknetVersion;
 use mp_class::{
     class_update::{ClassUpdate, LegacyClassUpdate, SierraClassUpdate},
-    CompressedLegacyContractClass, ConvertedClass, FlattenedSierraClass,
+    CompiledSierra, CompressedLegacyContractClass, ConvertedClass, FlattenedSierraClass,
 };
 use mp_receipt::TransactionReceipt;
 use mp_state_update::StateDiff;
@@ -16,6 +16,7 @@ use mp_transactions::Transaction;
 use serde::{Deserialize, Serialize};
 use starknet_api::core::ChainId;
 use starknet_types_core::felt::Felt;
+use std::collections::BTreeSet;
 
 #[derive(Clone, Debug, Eq, PartialEq, Default, Serialize, Deserialize)]
 pub struct UnverifiedHeader {
@@ -37,7 +38,7 @@ pub struct UnverifiedHeader {
 pub struct BlockValidationContext {
     /// Use the transaction hashes from the transaction receipts instead of computing them.
     pub trust_transaction_hashes: bool,
-    /// Trust class hashes.
+    /// Trust class hashes and compiled class hashes.
     pub trust_class_hashes: bool,
     /// Do not recomppute the trie commitments, trust them instead.
     /// If the global state root commitment is missing during import, this will error.
@@ -47,6 +48,12 @@ pub struct BlockValidationContext {
     pub ignore_block_order: bool,
     /// The chain id of the current block.
     pub chain_id: ChainId,
+    /// Blocks known to have a wrong global state root. A state root mismatch on one of these blocks is only logged,
+    /// and the block is imported with the expected state root. This is an escape hatch for syncs stuck on bad data.
+    pub skip_blocks: BTreeSet<u64>,
+    /// Re-check a block which is already stored against the stored one instead of rejecting it, leaving the database
+    /// untouched. This is used to re-fetch the last blocks of the database with `--resync-tail`.
+    pub reapply_stored_blocks: bool,
 }
 
 impl BlockValidationContext {
@@ -57,6 +64,8 @@ impl BlockValidationContext {
             trust_global_tries: false,
             chain_id,
             ignore_block_order: false,
+            skip_blocks: BTreeSet::new(),
+            reapply_stored_blocks: false,
         }
     }
     pub fn trust_transaction_hashes(mut self, v: bool) -> Self {
@@ -71,6 +80,14 @@ impl BlockValidationContext {
         self.trust_global_tries = v;
         self
     }
+    pub fn skip_blocks(mut self, v: BTreeSet<u64>) -> Self {
+        self.skip_blocks = v;
+        self
+    }
+    pub fn reapply_stored_blocks(mut self, v: bool) -> Self {
+        self.reapply_stored_blocks = v;
+        self
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -114,6 +131,9 @@ pub struct SierraDeclaredClass {
     pub class_hash: Felt,
     pub contract_class: FlattenedSierraClass,
     pub compiled_class_hash: Felt,
+    /// CASM compiled when the class was fetched. The class is only compiled on import when this is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compiled_class: Option<CompiledSierra>,
 }
 
 impl From<SierraClassUpdate> for SierraDeclaredClass {
@@ -122,6 +142,7 @@ impl From<SierraClassUpdate> for SierraDeclaredClass {
             class_hash: value.class_hash,
             contract_class: value.contract_class,
             compiled_class_hash: value.compiled_class_hash,
+            compiled_class: None,
         }
     }
 }
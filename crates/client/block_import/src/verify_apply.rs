@@ -3,31 +3,52 @@ use crate::{
     PreValidatedBlock, PreValidatedPendingBlock, UnverifiedHeader, ValidatedCommitments,
 };
 use itertools::Itertools;
-use mc_db::{MadaraBackend, MadaraStorageError};
+use mc_db::{db_block_id::DbBlockId, MadaraBackend, MadaraStorageError};
 use mp_block::BlockTag;
 use mp_block::{
     header::PendingHeader, BlockId, Header, MadaraBlockInfo, MadaraBlockInner, MadaraMaybePendingBlock,
     MadaraMaybePendingBlockInfo, MadaraPendingBlockInfo,
 };
 use mp_convert::{FeltHexDisplay, ToFelt};
+use mp_state_update::StateDiff;
+use staged::StagedTrieUpdates;
 use starknet_api::core::ChainId;
 use starknet_types_core::felt::Felt;
 use starknet_types_core::hash::{Poseidon, StarkHash};
-use std::{borrow::Cow, sync::Arc};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::{
+    borrow::Cow,
+    sync::{Arc, PoisonError},
+};
 
-mod classes;
-mod contracts;
+pub(crate) mod classes;
+pub(crate) mod contracts;
+mod staged;
 
 pub struct VerifyApply {
     pub(crate) backend: Arc<MadaraBackend>,
     // Only one thread at once can verify_apply. This is the update trie step cannot be parallelized over blocks, and in addition
     // our database does not support concurrent write access.
     mutex: tokio::sync::Mutex<()>,
+    /// Number of blocks between two trie commits.
+    pub(crate) trie_commit_interval: u64,
+    staged: Arc<std::sync::Mutex<StagedTrieUpdates>>,
+    pub(crate) state_root_verifier: Arc<dyn StateRootVerifier>,
+    /// Directory the state diff of every block is written to before it is applied on the tries.
+    pub(crate) state_diff_dump_dir: Option<PathBuf>,
 }
 
 impl VerifyApply {
     pub fn new(backend: Arc<MadaraBackend>) -> Self {
-        Self { backend, mutex: Default::default() }
+        Self {
+            backend,
+            mutex: Default::default(),
+            trie_commit_interval: 1,
+            staged: Default::default(),
+            state_root_verifier: Arc::new(BonsaiStateRootVerifier),
+            state_diff_dump_dir: None,
+        }
     }
 
     /// This function wraps the [`verify_apply_inner`] step, which runs on the rayon pool, in a tokio-friendly future.
@@ -45,11 +66,69 @@ impl VerifyApply {
         tracing::debug!("acquired verify_apply exclusive");
 
         let backend = Arc::clone(&self.backend);
-        let res = global_spawn_rayon_task(move || verify_apply_inner(&backend, block, validation)).await;
+        let staged = Arc::clone(&self.staged);
+        let trie_commit_interval = self.trie_commit_interval;
+        let state_root_verifier = Arc::clone(&self.state_root_verifier);
+        let state_diff_dump_dir = self.state_diff_dump_dir.clone();
+        let res = global_spawn_rayon_task(move || {
+            let mut staged = staged.lock().unwrap_or_else(PoisonError::into_inner);
+            verify_apply_staged(
+                &backend,
+                block,
+                validation,
+                &mut staged,
+                trie_commit_interval,
+                &*state_root_verifier,
+                state_diff_dump_dir.as_deref(),
+            )
+        })
+        .await;
         tracing::debug!("releasing verify_apply exclusive");
         res
     }
 
+    /// Commits the trie updates staged since the last trie commit, and checks the resulting global state root
+    /// against the one of the last imported block. This does nothing when every block is committed. A mismatch is
+    /// only logged when the last block is listed in `skip_blocks`, see [`BlockValidationContext::skip_blocks()`].
+    pub async fn commit_staged_tries(&self, skip_blocks: &BTreeSet<u64>) -> Result<(), BlockImportError> {
+        let _exclusive = self.mutex.lock().await;
+
+        let backend = Arc::clone(&self.backend);
+        let staged = Arc::clone(&self.staged);
+        let state_root_verifier = Arc::clone(&self.state_root_verifier);
+        let skip_blocks = skip_blocks.clone();
+        global_spawn_rayon_task(move || {
+            commit_staged_tries(
+                &backend,
+                &mut staged.lock().unwrap_or_else(PoisonError::into_inner),
+                &*state_root_verifier,
+                &skip_blocks,
+            )
+        })
+        .await
+    }
+
+    /// Re-applies the state diffs of the stored blocks which are missing from the global tries, and checks the
+    /// resulting global state root against the one of the latest block. This does nothing when the tries are up to
+    /// date. The blocks listed in `skip_blocks` are handled like in [`Self::commit_staged_tries`].
+    pub async fn recover_tries(&self, skip_blocks: &BTreeSet<u64>) -> Result<(), BlockImportError> {
+        let _exclusive = self.mutex.lock().await;
+
+        let backend = Arc::clone(&self.backend);
+        let staged = Arc::clone(&self.staged);
+        let state_root_verifier = Arc::clone(&self.state_root_verifier);
+        let skip_blocks = skip_blocks.clone();
+        global_spawn_rayon_task(move || {
+            recover_tries(
+                &backend,
+                &mut staged.lock().unwrap_or_else(PoisonError::into_inner),
+                &*state_root_verifier,
+                &skip_blocks,
+            )
+        })
+        .await
+    }
+
     /// See [`Self::verify_apply`].
     pub async fn verify_apply_pending(
         &self,
@@ -75,12 +154,48 @@ pub fn verify_apply_inner(
     block: PreValidatedBlock,
     validation: BlockValidationContext,
 ) -> Result<BlockImportResult, BlockImportError> {
+    verify_apply_staged(
+        backend,
+        block,
+        validation,
+        &mut StagedTrieUpdates::default(),
+        1,
+        &BonsaiStateRootVerifier,
+        None,
+    )
+}
+
+/// See [`verify_apply_inner`]. The trie updates are only committed every `trie_commit_interval` blocks, and are
+/// staged in the meantime.
+fn verify_apply_staged(
+    backend: &MadaraBackend,
+    block: PreValidatedBlock,
+    validation: BlockValidationContext,
+    staged: &mut StagedTrieUpdates,
+    trie_commit_interval: u64,
+    state_root_verifier: &dyn StateRootVerifier,
+    state_diff_dump_dir: Option<&Path>,
+) -> Result<BlockImportResult, BlockImportError> {
+    if validation.reapply_stored_blocks {
+        if let Some(stored) = stored_block_info(backend, block.unverified_block_number)? {
+            return reapply_stored_block(&block, &validation, stored);
+        }
+    }
+
     // Check block number and block hash against db
     let (block_number, parent_block_hash) =
         check_parent_hash_and_num(backend, block.header.parent_block_hash, block.unverified_block_number, &validation)?;
 
+    // Dumped before the tries are updated, so that the state diff of a block with a state root mismatch is available.
+    if let Some(dir) = state_diff_dump_dir {
+        if let Err(err) = dump_state_diff(dir, block_number, &block.state_diff) {
+            tracing::warn!("Failed to dump the state diff of block #{block_number} to {}: {err:#}", dir.display());
+        }
+    }
+
     // Update contract and its storage tries
-    let global_state_root = update_tries(backend, &block, &validation, block_number)?;
+    let global_state_root =
+        update_tries(backend, &block, &validation, block_number, staged, trie_commit_interval, state_root_verifier)?;
 
     // Block hash
     let (block_hash, header) = block_hash(&block, &validation, block_number, parent_block_hash, global_state_root)?;
@@ -107,6 +222,60 @@ pub fn verify_apply_inner(
     Ok(BlockImportResult { header, block_hash })
 }
 
+fn stored_block_info(
+    backend: &MadaraBackend,
+    block_number: Option<u64>,
+) -> Result<Option<MadaraBlockInfo>, BlockImportError> {
+    let Some(block_number) = block_number else { return Ok(None) };
+    Ok(backend
+        .get_block_info(&DbBlockId::Number(block_number))
+        .map_err(make_db_error(format!("getting block info for block #{block_number}")))?
+        .and_then(|block_info| block_info.as_nonpending_owned()))
+}
+
+/// Checks a block which is already stored against the stored one, see
+/// [`BlockValidationContext::reapply_stored_blocks`]. Its state diff is already in the global tries, so its block hash
+/// is recomputed on top of the stored global state root, and nothing is written: re-applying a block any number of
+/// times leaves the database as it was.
+fn reapply_stored_block(
+    block: &PreValidatedBlock,
+    validation: &BlockValidationContext,
+    stored: MadaraBlockInfo,
+) -> Result<BlockImportResult, BlockImportError> {
+    let MadaraBlockInfo { header: stored_header, block_hash: stored_block_hash, .. } = stored;
+    tracing::debug!("verify_apply_inner re-apply stored block {}", stored_header.block_number);
+
+    if let Some(parent_block_hash) = block.header.parent_block_hash {
+        if parent_block_hash != stored_header.parent_block_hash {
+            return Err(BlockImportError::ParentHash {
+                expected: stored_header.parent_block_hash,
+                got: parent_block_hash,
+            });
+        }
+    }
+    if let Some(global_state_root) = block.unverified_global_state_root {
+        if global_state_root != stored_header.global_state_root {
+            return Err(BlockImportError::GlobalStateRoot {
+                got: global_state_root,
+                expected: stored_header.global_state_root,
+            });
+        }
+    }
+
+    let (block_hash, header) = block_hash(
+        block,
+        validation,
+        stored_header.block_number,
+        stored_header.parent_block_hash,
+        stored_header.global_state_root,
+    )?;
+    if block_hash != stored_block_hash {
+        return Err(BlockImportError::BlockHash { got: block_hash, expected: stored_block_hash });
+    }
+
+    Ok(BlockImportResult { header, block_hash })
+}
+
 /// See [`verify_apply_inner`].
 pub fn verify_apply_pending_inner(
     backend: &MadaraBackend,
@@ -150,7 +319,9 @@ pub fn verify_apply_pending_inner(
     Ok(PendingBlockImportResult {})
 }
 
-fn make_db_error(context: impl Into<Cow<'static, str>>) -> impl FnOnce(MadaraStorageError) -> BlockImportError {
+pub(crate) fn make_db_error(
+    context: impl Into<Cow<'static, str>>,
+) -> impl FnOnce(MadaraStorageError) -> BlockImportError {
     move |error| BlockImportError::InternalDb { context: context.into(), error }
 }
 
@@ -167,6 +338,11 @@ fn check_parent_hash_and_num(
         let info =
             info.as_nonpending().ok_or_else(|| BlockImportError::Internal("Latest block cannot be pending".into()))?;
         (info.header.block_number + 1, info.block_hash)
+    } else if let Some(checkpoint) =
+        backend.get_checkpoint_block().map_err(make_db_error("getting the checkpoint block"))?
+    {
+        // importing the block after the checkpoint the database was started from
+        (checkpoint.block_number + 1, checkpoint.block_hash)
     } else {
         // importing genesis block
         (0, Felt::ZERO)
@@ -193,7 +369,7 @@ fn check_parent_hash_and_num(
 /// "STARKNET_STATE_V0"
 const STARKNET_STATE_PREFIX: Felt = Felt::from_hex_unchecked("0x535441524b4e45545f53544154455f5630");
 
-fn calculate_state_root(contracts_trie_root: Felt, classes_trie_root: Felt) -> Felt {
+pub(crate) fn calculate_state_root(contracts_trie_root: Felt, classes_trie_root: Felt) -> Felt {
     if classes_trie_root == Felt::ZERO {
         contracts_trie_root
     } else {
@@ -201,12 +377,29 @@ fn calculate_state_root(contracts_trie_root: Felt, classes_trie_root: Felt) -> F
     }
 }
 
+/// Path of the state diff of block `block_number` dumped in `dir`, see
+/// [`BlockImporter::with_state_diff_dump_dir`](crate::BlockImporter::with_state_diff_dump_dir).
+pub fn state_diff_dump_path(dir: &Path, block_number: u64) -> PathBuf {
+    dir.join(format!("{block_number}.json"))
+}
+
+/// Writes the state diff applied on the global tries for block `block_number` as JSON.
+fn dump_state_diff(dir: &Path, block_number: u64, state_diff: &StateDiff) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let file = std::fs::File::create(state_diff_dump_path(dir, block_number))?;
+    serde_json::to_writer(std::io::BufWriter::new(file), state_diff)?;
+    Ok(())
+}
+
 /// Returns the new global state root.
 fn update_tries(
     backend: &MadaraBackend,
     block: &PreValidatedBlock,
     validation: &BlockValidationContext,
     block_number: u64,
+    staged: &mut StagedTrieUpdates,
+    trie_commit_interval: u64,
+    state_root_verifier: &dyn StateRootVerifier,
 ) -> Result<Felt, BlockImportError> {
     if validation.trust_global_tries {
         let Some(global_state_root) = block.unverified_global_state_root else {
@@ -230,32 +423,271 @@ fn update_tries(
         block.state_diff.deprecated_declared_classes.iter().map(|c| c.hex_display()).format(", ")
     );
 
+    let commit = (block_number + 1) % trie_commit_interval == 0;
+    let mut first_block_n = block_number;
+    let state_root = if commit && staged.is_empty() {
+        commit_state_diff(backend, state_root_verifier, &block.state_diff, block_number)?
+    } else if !commit {
+        // The state root of a staged block cannot be computed, it is checked when the tries are committed.
+        let Some(global_state_root) = block.unverified_global_state_root else {
+            return Err(BlockImportError::Internal(
+                "Trying to stage the trie updates of a block without a global state root".into(),
+            ));
+        };
+        staged.stage(block_number, &block.state_diff);
+        return Ok(global_state_root);
+    } else {
+        staged.stage(block_number, &block.state_diff);
+        first_block_n = staged.first_block_n().expect("Trie updates have just been staged");
+        let (block_number, state_diff) = staged.take().expect("Trie updates have just been staged");
+        commit_state_diff(backend, state_root_verifier, &state_diff, block_number)?
+    };
+
+    if let Some(expected) = block.unverified_global_state_root {
+        if expected != state_root {
+            if validation.skip_blocks.contains(&block_number) {
+                tracing::warn!(
+                    "⚠️ Ignoring the global state root mismatch of block #{block_number} (computed {state_root:#x}, \
+                     expected {expected:#x}) as it is listed in --skip-blocks"
+                );
+                return Ok(expected);
+            }
+            return Err(state_root_mismatch(state_root, expected, first_block_n, block_number));
+        }
+    }
+
+    Ok(state_root)
+}
+
+/// A mismatch after committing the trie updates of several blocks at once flags the whole range.
+fn state_root_mismatch(got: Felt, expected: Felt, first_block_n: u64, last_block_n: u64) -> BlockImportError {
+    if first_block_n == last_block_n {
+        BlockImportError::GlobalStateRoot { got, expected }
+    } else {
+        BlockImportError::StagedGlobalStateRoot { got, expected, first_block_n, last_block_n }
+    }
+}
+
+/// Computation of the global state root of a block, from its state diff.
+///
+/// The default implementation is [`BonsaiStateRootVerifier`]. Alternative implementations can be injected with
+/// [`BlockImporter::with_state_root_verifier`](crate::BlockImporter::with_state_root_verifier), for example to
+/// benchmark another trie implementation against the same blocks. The returned root is checked against the one of
+/// the block the same way, whichever implementation is used.
+pub trait StateRootVerifier: Send + Sync {
+    /// Applies `state_diff` on top of the state at the previous block and commits it at `block_number`, returning
+    /// the new global state root.
+    fn commit_state_diff(
+        &self,
+        backend: &MadaraBackend,
+        state_diff: &StateDiff,
+        block_number: u64,
+    ) -> Result<Felt, BlockImportError>;
+
+    /// The global state root of the last committed state, used to find which block the tries are at when recovering
+    /// them. Returns `None` when this cannot be known, in which case the last block recorded for the tries is trusted.
+    fn committed_state_root(&self, backend: &MadaraBackend) -> Result<Option<Felt>, BlockImportError>;
+}
+
+/// Global tries stored in the database using bonsai, hashing contracts with Pedersen and classes with Poseidon.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BonsaiStateRootVerifier;
+
+impl StateRootVerifier for BonsaiStateRootVerifier {
+    fn commit_state_diff(
+        &self,
+        backend: &MadaraBackend,
+        state_diff: &StateDiff,
+        block_number: u64,
+    ) -> Result<Felt, BlockImportError> {
+        commit_tries(backend, state_diff, block_number)
+    }
+
+    fn committed_state_root(&self, backend: &MadaraBackend) -> Result<Option<Felt>, BlockImportError> {
+        let contract_trie_root = backend
+            .contract_trie()
+            .root_hash(mc_db::bonsai_identifier::CONTRACT)
+            .map_err(|err| BlockImportError::Internal(format!("Getting the contract trie root: {err:#}").into()))?;
+        let class_trie_root = backend
+            .class_trie()
+            .root_hash(mc_db::bonsai_identifier::CLASS)
+            .map_err(|err| BlockImportError::Internal(format!("Getting the class trie root: {err:#}").into()))?;
+        Ok(Some(calculate_state_root(contract_trie_root, class_trie_root)))
+    }
+}
+
+/// Applies a state diff to the global tries and commits them at `block_number`, returning the new global state root.
+fn commit_tries(backend: &MadaraBackend, state_diff: &StateDiff, block_number: u64) -> Result<Felt, BlockImportError> {
     let (contract_trie_root, class_trie_root) = rayon::join(
         || {
             contracts::contract_trie_root(
                 backend,
-                &block.state_diff.deployed_contracts,
-                &block.state_diff.replaced_classes,
-                &block.state_diff.nonces,
-                &block.state_diff.storage_diffs,
+                &state_diff.deployed_contracts,
+                &state_diff.replaced_classes,
+                &state_diff.nonces,
+                &state_diff.storage_diffs,
                 block_number,
             )
         },
-        || classes::class_trie_root(backend, &block.state_diff.declared_classes, block_number),
+        || classes::class_trie_root(backend, &state_diff.declared_classes, block_number),
     );
 
-    let state_root = calculate_state_root(
+    Ok(calculate_state_root(
         contract_trie_root.map_err(make_db_error("updating contract trie root"))?,
         class_trie_root.map_err(make_db_error("updating class trie root"))?,
-    );
+    ))
+}
 
-    if let Some(expected) = block.unverified_global_state_root {
-        if expected != state_root {
-            return Err(BlockImportError::GlobalStateRoot { got: state_root, expected });
+/// Commits `state_diff` on the global tries at `block_number`, and records it as the last block of the tries.
+fn commit_state_diff(
+    backend: &MadaraBackend,
+    state_root_verifier: &dyn StateRootVerifier,
+    state_diff: &StateDiff,
+    block_number: u64,
+) -> Result<Felt, BlockImportError> {
+    let state_root = state_root_verifier.commit_state_diff(backend, state_diff, block_number)?;
+    backend.write_tries_block_n(block_number).map_err(make_db_error("recording the last block of the global tries"))?;
+    Ok(state_root)
+}
+
+/// See [`VerifyApply::commit_staged_tries`].
+fn commit_staged_tries(
+    backend: &MadaraBackend,
+    staged: &mut StagedTrieUpdates,
+    state_root_verifier: &dyn StateRootVerifier,
+    skip_blocks: &BTreeSet<u64>,
+) -> Result<(), BlockImportError> {
+    let Some(first_block_n) = staged.first_block_n() else { return Ok(()) };
+    let Some((block_number, state_diff)) = staged.take() else { return Ok(()) };
+
+    tracing::debug!("Committing the trie updates staged up to block #{block_number}");
+    let state_root = commit_state_diff(backend, state_root_verifier, &state_diff, block_number)?;
+
+    let expected = block_global_state_root(backend, block_number)?;
+    if expected != state_root {
+        if skip_blocks.contains(&block_number) {
+            tracing::warn!(
+                "⚠️ Ignoring the global state root mismatch of block #{block_number} (computed {state_root:#x}, \
+                 expected {expected:#x}) as it is listed in --skip-blocks"
+            );
+            return Ok(());
         }
+        return Err(state_root_mismatch(state_root, expected, first_block_n, block_number));
     }
 
-    Ok(state_root)
+    Ok(())
+}
+
+fn block_global_state_root(backend: &MadaraBackend, block_number: u64) -> Result<Felt, BlockImportError> {
+    Ok(backend
+        .get_block_info(&DbBlockId::Number(block_number))
+        .map_err(make_db_error(format!("getting block info for block #{block_number}")))?
+        .and_then(|block_info| block_info.as_nonpending_owned())
+        .ok_or_else(|| BlockImportError::Internal(format!("Block #{block_number} not found in database").into()))?
+        .header
+        .global_state_root)
+}
+
+/// See [`VerifyApply::recover_tries`].
+///
+/// The last block recorded for the tries is written after they are committed, so the tries may be ahead of it when
+/// the node was killed in between. The block they are actually at is found by matching the committed state root
+/// against the ones of the stored blocks after the recorded one. When no block is recorded, the database either
+/// never committed its tries or predates the record, and every stored block is a candidate. The stored state root of
+/// a block listed in `skip_blocks` is not the one of the tries, so when nothing matches the tries can still be at the
+/// only skipped candidate.
+fn recover_tries(
+    backend: &MadaraBackend,
+    staged: &mut StagedTrieUpdates,
+    state_root_verifier: &dyn StateRootVerifier,
+    skip_blocks: &BTreeSet<u64>,
+) -> Result<(), BlockImportError> {
+    let Some(latest_block_n) =
+        backend.get_latest_block_n().map_err(make_db_error("getting the latest block number"))?
+    else {
+        return Ok(());
+    };
+    let recorded_block_n =
+        backend.get_tries_block_n().map_err(make_db_error("getting the last block of the global tries"))?;
+    let first_candidate_block_n = match recorded_block_n {
+        Some(tries_block_n) => tries_block_n + 1,
+        None => backend
+            .get_checkpoint_block()
+            .map_err(make_db_error("getting the checkpoint block"))?
+            .map_or(0, |checkpoint| checkpoint.block_number + 1),
+    };
+    if first_candidate_block_n > latest_block_n {
+        return Ok(());
+    }
+
+    let first_missing_block_n = match state_root_verifier.committed_state_root(backend)? {
+        // The tries cannot be inspected: trust the recorded block, if any.
+        None if recorded_block_n.is_some() => first_candidate_block_n,
+        None => return Ok(()),
+        Some(committed_state_root) => {
+            let mut tries_block_n = None;
+            for block_n in (first_candidate_block_n..=latest_block_n).rev() {
+                if block_global_state_root(backend, block_n)? == committed_state_root {
+                    tries_block_n = Some(block_n);
+                    break;
+                }
+            }
+            match tries_block_n {
+                Some(tries_block_n) => tries_block_n + 1,
+                None => {
+                    let base_state_root = match recorded_block_n {
+                        Some(recorded_block_n) => block_global_state_root(backend, recorded_block_n)?,
+                        None => Felt::ZERO,
+                    };
+                    if committed_state_root == base_state_root {
+                        first_candidate_block_n
+                    } else {
+                        let mut skipped = skip_blocks.range(first_candidate_block_n..=latest_block_n);
+                        match (skipped.next(), skipped.next()) {
+                            (Some(&skipped_block_n), None) => {
+                                tracing::warn!(
+                                    "⚠️ The global tries do not match the state root of any block, assuming they are \
+                                     at block #{skipped_block_n} as it is listed in --skip-blocks"
+                                );
+                                skipped_block_n + 1
+                            }
+                            _ => {
+                                return Err(BlockImportError::Internal(
+                                    format!(
+                                        "The global tries do not match the state root of any block from \
+                                         #{first_candidate_block_n} to #{latest_block_n}"
+                                    )
+                                    .into(),
+                                ))
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    if first_missing_block_n > latest_block_n {
+        tracing::debug!("The global tries are already at block #{latest_block_n}");
+        backend
+            .write_tries_block_n(latest_block_n)
+            .map_err(make_db_error("recording the last block of the global tries"))?;
+        return Ok(());
+    }
+
+    tracing::warn!(
+        "⚠️ The global tries are missing blocks #{first_missing_block_n} to #{latest_block_n}, re-applying their state \
+         diffs"
+    );
+    for block_n in first_missing_block_n..=latest_block_n {
+        let state_diff = backend
+            .get_block_state_diff(&DbBlockId::Number(block_n))
+            .map_err(make_db_error(format!("getting the state diff of block #{block_n}")))?
+            .ok_or_else(|| {
+                BlockImportError::Internal(format!("State diff of block #{block_n} not found in database").into())
+            })?;
+        staged.stage(block_n, &state_diff);
+    }
+    commit_staged_tries(backend, staged, state_root_verifier, skip_blocks)
 }
 
 /// Returns the block hash and header.
@@ -333,6 +765,7 @@ mod verify_apply_tests {
 
     use rstest::*;
     use starknet_api::{core::ChainId, felt};
+    use std::collections::BTreeSet;
     use std::sync::Arc;
 
     /// Sets up a test backend.
@@ -424,6 +857,44 @@ mod verify_apply_tests {
         }
     }
 
+    /// A database started from a checkpoint expects the block after it, built on top of it.
+    #[rstest]
+    #[case::success(Some(felt!("0x12345")), Some(11), Ok((11, felt!("0x12345"))))]
+    #[case::no_block_number(Some(felt!("0x12345")), None, Ok((11, felt!("0x12345"))))]
+    #[case::mismatch_block_number(Some(felt!("0x12345")), Some(0), Err(BlockImportError::LatestBlockN { expected: 11, got: 0 }))]
+    #[case::mismatch_parent_hash(
+        Some(felt!("0x1")),
+        Some(11),
+        Err(BlockImportError::ParentHash { expected: felt!("0x12345"), got: felt!("0x1") })
+    )]
+    fn test_check_parent_hash_and_num_after_checkpoint(
+        #[case] parent_block_hash: Option<Felt>,
+        #[case] unverified_block_number: Option<u64>,
+        #[case] expected_result: Result<(u64, Felt), BlockImportError>,
+        setup_test_backend: Arc<MadaraBackend>,
+    ) {
+        let backend = setup_test_backend;
+        backend
+            .write_checkpoint_block(&mc_db::block_db::CheckpointBlock {
+                block_number: 10,
+                block_hash: felt!("0x12345"),
+                global_root: felt!("0x789"),
+            })
+            .unwrap();
+
+        let result = check_parent_hash_and_num(
+            &backend,
+            parent_block_hash,
+            unverified_block_number,
+            &create_validation_context(false),
+        );
+        match (result, expected_result) {
+            (Ok(actual), Ok(expected)) => assert_eq!(actual, expected),
+            (Err(actual), Err(expected)) => assert_eq!(format!("{:?}", actual), format!("{:?}", expected)),
+            _ => panic!("Result types do not match"),
+        }
+    }
+
     /// Test cases for the `calculate_state_root` function.
     ///
     /// This test uses `rstest` to parameterize different scenarios for calculating
@@ -526,10 +997,20 @@ mod verify_apply_tests {
             trust_global_tries,
             trust_transaction_hashes: false,
             trust_class_hashes: false,
+            skip_blocks: Default::default(),
+            reapply_stored_blocks: false,
         };
 
         // WHEN: We call update_tries with these parameters
-        let result = update_tries(&backend, &block, &validation, 1);
+        let result = update_tries(
+            &backend,
+            &block,
+            &validation,
+            1,
+            &mut StagedTrieUpdates::default(),
+            1,
+            &BonsaiStateRootVerifier,
+        );
 
         // THEN: The result should match the expected outcome
         match (result, expected_result) {
@@ -549,6 +1030,31 @@ mod verify_apply_tests {
         }
     }
 
+    /// A state root mismatch is ignored for the blocks listed in `skip_blocks` only.
+    #[rstest]
+    #[case::listed([1].into(), Ok(felt!("0xb")))]
+    #[case::unlisted([2].into(), Err(BlockImportError::GlobalStateRoot { got: felt!("0x0"), expected: felt!("0xb") }))]
+    fn test_update_tries_skip_blocks(
+        #[case] skip_blocks: BTreeSet<u64>,
+        #[case] expected_result: Result<Felt, BlockImportError>,
+        setup_test_backend: Arc<MadaraBackend>,
+    ) {
+        let mut block = create_dummy_block();
+        block.unverified_global_state_root = Some(felt!("0xb"));
+        let validation = BlockValidationContext::new(ChainId::Mainnet).skip_blocks(skip_blocks);
+
+        let result = update_tries(
+            &setup_test_backend,
+            &block,
+            &validation,
+            1,
+            &mut StagedTrieUpdates::default(),
+            1,
+            &BonsaiStateRootVerifier,
+        );
+        assert_eq!(format!("{result:?}"), format!("{expected_result:?}"));
+    }
+
     #[rstest]
     // Case 1: Successful block hash calculation
     #[case::success(
@@ -589,6 +1095,8 @@ mod verify_apply_tests {
                 trust_global_tries: false,
                 trust_transaction_hashes: false,
                 trust_class_hashes: false,
+                skip_blocks: Default::default(),
+                reapply_stored_blocks: false,
             },
             1466,
             felt!("0x1"),
@@ -807,5 +1315,455 @@ mod verify_apply_tests {
                 "Unexpected pending block should not be stored"
             );
         }
+
+        /// The pending block is a speculative head on top of the latest block: it never touches the global tries, and
+        /// is replaced by the confirmed block once it is imported.
+        #[rstest]
+        #[tokio::test]
+        async fn test_pending_block_replaced_by_confirmed_block(setup_test_backend: Arc<MadaraBackend>) {
+            let backend = setup_test_backend;
+            let validation = create_validation_context(false);
+            let root = || {
+                calculate_state_root(
+                    backend.contract_trie().root_hash(mc_db::bonsai_identifier::CONTRACT).unwrap(),
+                    backend.class_trie().root_hash(mc_db::bonsai_identifier::CLASS).unwrap(),
+                )
+            };
+            let block_0 =
+                verify_apply_inner(&backend, trie_commit_test_block(0, Felt::ZERO, None), validation.clone()).unwrap();
+            let confirmed = trie_commit_test_block(1, block_0.block_hash, None);
+
+            // A pending block which does not build on the latest block is not stored
+            let mut pending_block = create_dummy_pending_block();
+            pending_block.state_diff = confirmed.state_diff.clone();
+            assert!(verify_apply_pending_inner(&backend, pending_block.clone(), validation.clone()).is_err());
+            assert_eq!(backend.get_pending_block_state_update().unwrap(), StateDiff::default());
+
+            pending_block.header.parent_block_hash = Some(block_0.block_hash);
+            verify_apply_pending_inner(&backend, pending_block, validation.clone()).unwrap();
+            assert_eq!(backend.get_pending_block_state_update().unwrap(), confirmed.state_diff);
+            assert_eq!(root(), block_0.header.global_state_root);
+
+            let block_1 = verify_apply_inner(&backend, confirmed, validation).unwrap();
+            assert_ne!(block_1.header.global_state_root, block_0.header.global_state_root);
+            assert_eq!(root(), block_1.header.global_state_root);
+            assert_eq!(backend.get_pending_block_state_update().unwrap(), StateDiff::default());
+            let pending_info = backend.get_block_info(&BLOCK_ID_PENDING).unwrap().unwrap();
+            assert_eq!(pending_info.as_pending().unwrap().header.parent_block_hash, block_1.block_hash);
+            assert_eq!(backend.get_latest_block_n().unwrap(), Some(1));
+        }
+    }
+
+    /// Before Sierra classes, blocks have no class commitment: the class trie stays empty and the global state root
+    /// is the contract trie root.
+    #[rstest]
+    fn test_update_tries_without_declared_classes(setup_test_backend: Arc<MadaraBackend>) {
+        let backend = setup_test_backend;
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+        let mut block = create_dummy_block();
+        block.unverified_global_state_root = None;
+        block.state_diff = StateDiff {
+            storage_diffs: vec![ContractStorageDiffItem {
+                address: felt!("0x1"),
+                storage_entries: vec![StorageEntry { key: felt!("0x10"), value: felt!("0x1") }],
+            }],
+            deployed_contracts: vec![DeployedContractItem { address: felt!("0x1"), class_hash: felt!("0xc") }],
+            ..Default::default()
+        };
+
+        let state_root = update_tries(
+            &backend,
+            &block,
+            &validation,
+            0,
+            &mut StagedTrieUpdates::default(),
+            1,
+            &BonsaiStateRootVerifier,
+        )
+        .unwrap();
+
+        assert_eq!(backend.class_trie().root_hash(mc_db::bonsai_identifier::CLASS).unwrap(), Felt::ZERO);
+        let contract_trie_root = backend.contract_trie().root_hash(mc_db::bonsai_identifier::CONTRACT).unwrap();
+        assert_ne!(contract_trie_root, Felt::ZERO);
+        assert_eq!(state_root, contract_trie_root);
+    }
+
+    fn trie_commit_test_block(
+        block_n: u64,
+        parent_block_hash: Felt,
+        global_state_root: Option<Felt>,
+    ) -> PreValidatedBlock {
+        let mut block = create_dummy_block();
+        block.unverified_block_number = Some(block_n);
+        block.header.parent_block_hash = Some(parent_block_hash);
+        block.unverified_global_state_root = global_state_root;
+        block.unverified_block_hash = None;
+        block.state_diff = StateDiff {
+            storage_diffs: vec![ContractStorageDiffItem {
+                address: felt!("0x1"),
+                storage_entries: vec![
+                    StorageEntry { key: felt!("0x10"), value: Felt::from(block_n) },
+                    StorageEntry { key: Felt::from(0x100 + block_n), value: felt!("0x1") },
+                ],
+            }],
+            deployed_contracts: vec![DeployedContractItem {
+                address: Felt::from(0x200 + block_n),
+                class_hash: felt!("0xc"),
+            }],
+            ..Default::default()
+        };
+        block
+    }
+
+    /// Committing the tries every few blocks must result in the same state as committing them on every block.
+    #[rstest]
+    #[case::every_two_blocks(2, 6)]
+    #[case::last_block_staged(4, 6)]
+    #[tokio::test]
+    async fn test_trie_commit_interval(#[case] trie_commit_interval: u64, #[case] n_blocks: u64) {
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+
+        let reference = setup_test_backend();
+        let mut parent_block_hash = Felt::ZERO;
+        let mut state_roots = vec![];
+        for block_n in 0..n_blocks {
+            let block = trie_commit_test_block(block_n, parent_block_hash, None);
+            let res = verify_apply_inner(&reference, block, validation.clone()).unwrap();
+            parent_block_hash = res.block_hash;
+            state_roots.push(res.header.global_state_root);
+        }
+
+        let backend = setup_test_backend();
+        let verify_apply = VerifyApply { trie_commit_interval, ..VerifyApply::new(Arc::clone(&backend)) };
+        let mut parent_block_hash = Felt::ZERO;
+        for block_n in 0..n_blocks {
+            let block = trie_commit_test_block(block_n, parent_block_hash, Some(state_roots[block_n as usize]));
+            let res = verify_apply.verify_apply(block, validation.clone()).await.unwrap();
+            assert_eq!(res.header.global_state_root, state_roots[block_n as usize]);
+            parent_block_hash = res.block_hash;
+        }
+        verify_apply.commit_staged_tries(&BTreeSet::new()).await.unwrap();
+
+        let root = |backend: &MadaraBackend| {
+            calculate_state_root(
+                backend.contract_trie().root_hash(mc_db::bonsai_identifier::CONTRACT).unwrap(),
+                backend.class_trie().root_hash(mc_db::bonsai_identifier::CLASS).unwrap(),
+            )
+        };
+        assert_eq!(root(&backend), root(&reference));
+        assert_eq!(root(&backend), *state_roots.last().unwrap());
+    }
+
+    /// When the node is killed before its staged trie updates are committed, the state diffs of the stored blocks
+    /// missing from the tries are re-applied on the next start.
+    #[rstest]
+    #[case::after_a_commit(4, 6, Some(3))]
+    #[case::never_committed(10, 3, None)]
+    #[tokio::test]
+    async fn test_recover_tries(
+        #[case] trie_commit_interval: u64,
+        #[case] n_blocks: u64,
+        #[case] tries_block_n: Option<u64>,
+    ) {
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+
+        let reference = setup_test_backend();
+        let mut parent_block_hash = Felt::ZERO;
+        let mut state_roots = vec![];
+        for block_n in 0..n_blocks {
+            let block = trie_commit_test_block(block_n, parent_block_hash, None);
+            let res = verify_apply_inner(&reference, block, validation.clone()).unwrap();
+            parent_block_hash = res.block_hash;
+            state_roots.push(res.header.global_state_root);
+        }
+
+        let backend = setup_test_backend();
+        let verify_apply = VerifyApply { trie_commit_interval, ..VerifyApply::new(Arc::clone(&backend)) };
+        let mut parent_block_hash = Felt::ZERO;
+        for block_n in 0..n_blocks {
+            let block = trie_commit_test_block(block_n, parent_block_hash, Some(state_roots[block_n as usize]));
+            parent_block_hash = verify_apply.verify_apply(block, validation.clone()).await.unwrap().block_hash;
+        }
+        // The node is killed: the staged trie updates are lost
+        drop(verify_apply);
+        assert_eq!(backend.get_tries_block_n().unwrap(), tries_block_n);
+
+        let verify_apply = VerifyApply::new(Arc::clone(&backend));
+        verify_apply.recover_tries(&BTreeSet::new()).await.unwrap();
+        assert_eq!(backend.get_tries_block_n().unwrap(), Some(n_blocks - 1));
+        let root = calculate_state_root(
+            backend.contract_trie().root_hash(mc_db::bonsai_identifier::CONTRACT).unwrap(),
+            backend.class_trie().root_hash(mc_db::bonsai_identifier::CLASS).unwrap(),
+        );
+        assert_eq!(root, *state_roots.last().unwrap());
+
+        // The tries are up to date: recovering them again does nothing
+        verify_apply.recover_tries(&BTreeSet::new()).await.unwrap();
+        assert_eq!(backend.get_tries_block_n().unwrap(), Some(n_blocks - 1));
+    }
+
+    /// When the node is killed after committing the tries but before recording their last block, the blocks already
+    /// in the tries are not re-applied on top of them.
+    #[rstest]
+    #[case::recorded_block_behind(Some(1))]
+    #[case::no_recorded_block(None)]
+    #[tokio::test]
+    async fn test_recover_tries_ahead_of_recorded_block(#[case] recorded_block_n: Option<u64>) {
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+
+        let reference = setup_test_backend();
+        let mut parent_block_hash = Felt::ZERO;
+        let mut state_roots = vec![];
+        for block_n in 0..4 {
+            let block = trie_commit_test_block(block_n, parent_block_hash, None);
+            let res = verify_apply_inner(&reference, block, validation.clone()).unwrap();
+            parent_block_hash = res.block_hash;
+            state_roots.push(res.header.global_state_root);
+        }
+
+        // The tries are committed up to block #2, and block #3 is stored without being committed
+        let backend = setup_test_backend();
+        let verify_apply = VerifyApply { trie_commit_interval: 3, ..VerifyApply::new(Arc::clone(&backend)) };
+        let mut parent_block_hash = Felt::ZERO;
+        for block_n in 0..4 {
+            let block = trie_commit_test_block(block_n, parent_block_hash, Some(state_roots[block_n as usize]));
+            parent_block_hash = verify_apply.verify_apply(block, validation.clone()).await.unwrap().block_hash;
+        }
+        drop(verify_apply);
+        assert_eq!(backend.get_tries_block_n().unwrap(), Some(2));
+
+        // The node was killed before recording that the tries are at block #2
+        match recorded_block_n {
+            Some(recorded_block_n) => backend.write_tries_block_n(recorded_block_n).unwrap(),
+            None => backend.clear_tries_block_n_for_testing().unwrap(),
+        }
+
+        let verify_apply = VerifyApply::new(Arc::clone(&backend));
+        verify_apply.recover_tries(&BTreeSet::new()).await.unwrap();
+        assert_eq!(backend.get_tries_block_n().unwrap(), Some(3));
+        let root = calculate_state_root(
+            backend.contract_trie().root_hash(mc_db::bonsai_identifier::CONTRACT).unwrap(),
+            backend.class_trie().root_hash(mc_db::bonsai_identifier::CLASS).unwrap(),
+        );
+        assert_eq!(root, state_roots[3]);
+    }
+
+    /// The tries committed at a block listed in `skip_blocks` do not match its stored state root, they are still
+    /// found when recovering them.
+    #[rstest]
+    #[tokio::test]
+    async fn test_recover_tries_at_skipped_block() {
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+
+        let reference = setup_test_backend();
+        let mut parent_block_hash = Felt::ZERO;
+        let mut state_roots = vec![];
+        for block_n in 0..4 {
+            let block = trie_commit_test_block(block_n, parent_block_hash, None);
+            let res = verify_apply_inner(&reference, block, validation.clone()).unwrap();
+            parent_block_hash = res.block_hash;
+            state_roots.push(res.header.global_state_root);
+        }
+
+        // Block #2 is stored with a wrong state root, the tries are committed at it and block #3 is not committed
+        let skip_blocks = BTreeSet::from([2]);
+        let validation = validation.skip_blocks(skip_blocks.clone());
+        let backend = setup_test_backend();
+        let verify_apply = VerifyApply { trie_commit_interval: 3, ..VerifyApply::new(Arc::clone(&backend)) };
+        let mut parent_block_hash = Felt::ZERO;
+        for block_n in 0..4 {
+            let state_root = if block_n == 2 { felt!("0xbad") } else { state_roots[block_n as usize] };
+            let block = trie_commit_test_block(block_n, parent_block_hash, Some(state_root));
+            parent_block_hash = verify_apply.verify_apply(block, validation.clone()).await.unwrap().block_hash;
+        }
+        drop(verify_apply);
+        backend.write_tries_block_n(1).unwrap();
+
+        let verify_apply = VerifyApply::new(Arc::clone(&backend));
+        assert!(verify_apply.recover_tries(&BTreeSet::new()).await.is_err());
+        verify_apply.recover_tries(&skip_blocks).await.unwrap();
+        assert_eq!(backend.get_tries_block_n().unwrap(), Some(3));
+        let root = calculate_state_root(
+            backend.contract_trie().root_hash(mc_db::bonsai_identifier::CONTRACT).unwrap(),
+            backend.class_trie().root_hash(mc_db::bonsai_identifier::CLASS).unwrap(),
+        );
+        assert_eq!(root, state_roots[3]);
+    }
+
+    /// Re-processing an imported block, as after a retry, is rejected before its state diff touches the tries: the
+    /// global state root stays the one of the block applied once.
+    #[rstest]
+    #[tokio::test]
+    async fn test_reimport_block_keeps_state_root(setup_test_backend: Arc<MadaraBackend>) {
+        let backend = setup_test_backend;
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+        let root = || {
+            calculate_state_root(
+                backend.contract_trie().root_hash(mc_db::bonsai_identifier::CONTRACT).unwrap(),
+                backend.class_trie().root_hash(mc_db::bonsai_identifier::CLASS).unwrap(),
+            )
+        };
+
+        let res =
+            verify_apply_inner(&backend, trie_commit_test_block(0, Felt::ZERO, None), validation.clone()).unwrap();
+        assert_eq!(root(), res.header.global_state_root);
+
+        assert!(matches!(
+            verify_apply_inner(&backend, trie_commit_test_block(0, Felt::ZERO, None), validation),
+            Err(BlockImportError::LatestBlockN { expected: 1, got: 0 })
+        ));
+        assert_eq!(root(), res.header.global_state_root);
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(0));
+    }
+
+    /// With `reapply_stored_blocks`, a stored block is checked again against the stored one without touching the
+    /// database, and a block which differs from the stored one is rejected.
+    #[rstest]
+    #[tokio::test]
+    async fn test_reapply_stored_blocks(setup_test_backend: Arc<MadaraBackend>) {
+        let backend = setup_test_backend;
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+        let root = || {
+            calculate_state_root(
+                backend.contract_trie().root_hash(mc_db::bonsai_identifier::CONTRACT).unwrap(),
+                backend.class_trie().root_hash(mc_db::bonsai_identifier::CLASS).unwrap(),
+            )
+        };
+
+        let first =
+            verify_apply_inner(&backend, trie_commit_test_block(0, Felt::ZERO, None), validation.clone()).unwrap();
+        let second =
+            verify_apply_inner(&backend, trie_commit_test_block(1, first.block_hash, None), validation.clone())
+                .unwrap();
+
+        let validation = validation.reapply_stored_blocks(true);
+        for _ in 0..2 {
+            let res =
+                verify_apply_inner(&backend, trie_commit_test_block(0, Felt::ZERO, None), validation.clone()).unwrap();
+            assert_eq!(res.block_hash, first.block_hash);
+            assert_eq!(res.header, first.header);
+        }
+        assert_eq!(root(), second.header.global_state_root);
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(1));
+
+        assert!(matches!(
+            verify_apply_inner(&backend, trie_commit_test_block(1, Felt::ZERO, None), validation.clone()),
+            Err(BlockImportError::ParentHash { expected, got: Felt::ZERO }) if expected == first.block_hash
+        ));
+        assert!(matches!(
+            verify_apply_inner(&backend, trie_commit_test_block(1, first.block_hash, Some(felt!("0xbad"))), validation),
+            Err(BlockImportError::GlobalStateRoot { got, .. }) if got == felt!("0xbad")
+        ));
+        assert_eq!(root(), second.header.global_state_root);
+    }
+
+    /// The dumped state diff is the one applied on the tries, including for a block with a state root mismatch.
+    #[rstest]
+    #[tokio::test]
+    async fn test_state_diff_dump(setup_test_backend: Arc<MadaraBackend>) {
+        let dir = tempfile::tempdir().unwrap();
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+        let verify_apply =
+            VerifyApply { state_diff_dump_dir: Some(dir.path().to_path_buf()), ..VerifyApply::new(setup_test_backend) };
+
+        let block = trie_commit_test_block(0, Felt::ZERO, Some(felt!("0xbad")));
+        let state_diff = block.state_diff.clone();
+        assert!(matches!(
+            verify_apply.verify_apply(block, validation).await,
+            Err(BlockImportError::GlobalStateRoot { expected, .. }) if expected == felt!("0xbad")
+        ));
+
+        let dumped = std::fs::read(state_diff_dump_path(dir.path(), 0)).unwrap();
+        assert_eq!(serde_json::from_slice::<StateDiff>(&dumped).unwrap(), state_diff);
+    }
+
+    /// A staged block with a wrong state root is caught when the tries are committed.
+    #[rstest]
+    #[tokio::test]
+    async fn test_trie_commit_interval_mismatch(setup_test_backend: Arc<MadaraBackend>) {
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+        let verify_apply = VerifyApply { trie_commit_interval: 10, ..VerifyApply::new(setup_test_backend) };
+
+        let block = trie_commit_test_block(0, Felt::ZERO, Some(felt!("0xdead")));
+        verify_apply.verify_apply(block, validation).await.unwrap();
+
+        assert!(matches!(
+            verify_apply.commit_staged_tries(&BTreeSet::new()).await,
+            Err(BlockImportError::GlobalStateRoot { expected, .. }) if expected == felt!("0xdead")
+        ));
+    }
+
+    /// The state root mismatch of a staged block listed in `skip_blocks` is ignored when the tries are committed.
+    #[rstest]
+    #[tokio::test]
+    async fn test_trie_commit_interval_skip_blocks(setup_test_backend: Arc<MadaraBackend>) {
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+        let verify_apply = VerifyApply { trie_commit_interval: 10, ..VerifyApply::new(setup_test_backend) };
+
+        let block = trie_commit_test_block(0, Felt::ZERO, Some(felt!("0xdead")));
+        verify_apply.verify_apply(block, validation).await.unwrap();
+
+        verify_apply.commit_staged_tries(&BTreeSet::from([0])).await.unwrap();
+        assert_eq!(verify_apply.backend.get_tries_block_n().unwrap(), Some(0));
+    }
+
+    /// With a commit interval, only the state roots of the commit points are compared. A mismatch there flags all
+    /// the blocks committed together.
+    #[rstest]
+    #[tokio::test]
+    async fn test_trie_commit_interval_only_checks_commit_points(setup_test_backend: Arc<MadaraBackend>) {
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+        let verify_apply = VerifyApply { trie_commit_interval: 3, ..VerifyApply::new(setup_test_backend) };
+
+        // The wrong state roots of the blocks in between are not checked
+        let mut parent_block_hash = Felt::ZERO;
+        for block_n in 0..2 {
+            let block = trie_commit_test_block(block_n, parent_block_hash, Some(felt!("0xdead")));
+            let res = verify_apply.verify_apply(block, validation.clone()).await.unwrap();
+            parent_block_hash = res.block_hash;
+        }
+
+        let block = trie_commit_test_block(2, parent_block_hash, Some(felt!("0xdead")));
+        assert!(matches!(
+            verify_apply.verify_apply(block, validation).await,
+            Err(BlockImportError::StagedGlobalStateRoot { expected, first_block_n: 0, last_block_n: 2, .. })
+                if expected == felt!("0xdead")
+        ));
+    }
+
+    struct FixedStateRootVerifier(Felt);
+
+    impl StateRootVerifier for FixedStateRootVerifier {
+        fn commit_state_diff(&self, _: &MadaraBackend, _: &StateDiff, _: u64) -> Result<Felt, BlockImportError> {
+            Ok(self.0)
+        }
+
+        fn committed_state_root(&self, _: &MadaraBackend) -> Result<Option<Felt>, BlockImportError> {
+            Ok(None)
+        }
+    }
+
+    /// The state root comes from the injected verifier, and is still checked against the one of the block.
+    #[rstest]
+    #[tokio::test]
+    async fn test_custom_state_root_verifier(setup_test_backend: Arc<MadaraBackend>) {
+        let backend = setup_test_backend;
+        let validation = BlockValidationContext::new(ChainId::Other("something".to_string()));
+        let verify_apply = VerifyApply {
+            state_root_verifier: Arc::new(FixedStateRootVerifier(felt!("0x1234"))),
+            ..VerifyApply::new(Arc::clone(&backend))
+        };
+
+        let block = trie_commit_test_block(0, Felt::ZERO, Some(felt!("0xdead")));
+        assert!(matches!(
+            verify_apply.verify_apply(block, validation.clone()).await,
+            Err(BlockImportError::GlobalStateRoot { got, expected }) if got == felt!("0x1234") && expected == felt!("0xdead")
+        ));
+
+        let block = trie_commit_test_block(0, Felt::ZERO, Some(felt!("0x1234")));
+        let res = verify_apply.verify_apply(block, validation).await.unwrap();
+        assert_eq!(res.header.global_state_root, felt!("0x1234"));
+        // The bonsai tries have not been touched.
+        assert_eq!(backend.contract_trie().root_hash(mc_db::bonsai_identifier::CONTRACT).unwrap(), Felt::ZERO);
     }
 }
@@ -22,8 +22,7 @@ pub fn class_trie_root(
     let updates: Vec<_> = declared_classes
         .into_par_iter()
         .map(|DeclaredClassItem { class_hash, compiled_class_hash }| {
-            let hash = Poseidon::hash(&CONTRACT_CLASS_HASH_VERSION, compiled_class_hash);
-            (*class_hash, hash)
+            (*class_hash, class_leaf_hash(compiled_class_hash))
         })
         .collect();
 
@@ -44,6 +43,11 @@ pub fn class_trie_root(
     Ok(root_hash)
 }
 
+/// Value of the leaf of a declared class in the class trie.
+pub(crate) fn class_leaf_hash(compiled_class_hash: &Felt) -> Felt {
+    Poseidon::hash(&CONTRACT_CLASS_HASH_VERSION, compiled_class_hash)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -12,7 +12,7 @@ use starknet_types_core::hash::{Pedersen, StarkHash};
 use std::collections::HashMap;
 
 #[derive(Debug, Default)]
-struct ContractLeaf {
+pub(crate) struct ContractLeaf {
     pub class_hash: Option<Felt>,
     pub storage_root: Option<Felt>,
     pub nonce: Option<Felt>,
@@ -36,7 +36,7 @@ pub fn contract_trie_root(
     storage_diffs: &[ContractStorageDiffItem],
     block_number: u64,
 ) -> Result<Felt, MadaraStorageError> {
-    let mut contract_leafs: HashMap<Felt, ContractLeaf> = HashMap::new();
+    let contract_leafs = contract_leaves(deployed_contracts, replaced_classes, nonces, storage_diffs);
 
     let mut contract_storage_trie = backend.contract_storage_trie();
 
@@ -49,8 +49,6 @@ pub fn contract_trie_root(
             let bv: BitVec<u8, Msb0> = bytes.as_bits()[5..].to_owned();
             contract_storage_trie.insert(&address.to_bytes_be(), &bv, value)?;
         }
-        // insert the contract address in the contract_leafs to put the storage root later
-        contract_leafs.insert(*address, Default::default());
     }
 
     tracing::debug!("contract_storage_trie commit");
@@ -58,18 +56,6 @@ pub fn contract_trie_root(
     // Then we commit them
     contract_storage_trie.commit(BasicId::new(block_number))?;
 
-    for NonceUpdate { contract_address, nonce } in nonces {
-        contract_leafs.entry(*contract_address).or_default().nonce = Some(*nonce);
-    }
-
-    for DeployedContractItem { address, class_hash } in deployed_contracts {
-        contract_leafs.entry(*address).or_default().class_hash = Some(*class_hash);
-    }
-
-    for ReplacedClassItem { contract_address, class_hash } in replaced_classes {
-        contract_leafs.entry(*contract_address).or_default().class_hash = Some(*class_hash);
-    }
-
     let mut contract_trie = backend.contract_trie();
 
     let leaf_hashes: Vec<_> = contract_leafs
@@ -77,7 +63,8 @@ pub fn contract_trie_root(
         .map(|(contract_address, mut leaf)| {
             let storage_root = contract_storage_trie.root_hash(&contract_address.to_bytes_be())?;
             leaf.storage_root = Some(storage_root);
-            let leaf_hash = contract_state_leaf_hash(backend, &contract_address, &leaf)?;
+            let leaf_hash =
+                contract_state_leaf_hash(backend, &BlockId::Tag(BlockTag::Latest), &contract_address, &leaf)?;
             let bytes = contract_address.to_bytes_be();
             let bv: BitVec<u8, Msb0> = bytes.as_bits()[5..].to_owned();
             Ok((bv, leaf_hash))
@@ -98,28 +85,58 @@ pub fn contract_trie_root(
     Ok(root_hash)
 }
 
+/// The contract leaves updated by a state diff. The storage roots are left unset, they are only known once the
+/// storage diffs have been committed.
+pub(crate) fn contract_leaves(
+    deployed_contracts: &[DeployedContractItem],
+    replaced_classes: &[ReplacedClassItem],
+    nonces: &[NonceUpdate],
+    storage_diffs: &[ContractStorageDiffItem],
+) -> HashMap<Felt, ContractLeaf> {
+    let mut contract_leafs: HashMap<Felt, ContractLeaf> = HashMap::new();
+
+    // insert the contract address in the contract_leafs to put the storage root later
+    for ContractStorageDiffItem { address, .. } in storage_diffs {
+        contract_leafs.insert(*address, Default::default());
+    }
+
+    for NonceUpdate { contract_address, nonce } in nonces {
+        contract_leafs.entry(*contract_address).or_default().nonce = Some(*nonce);
+    }
+
+    for DeployedContractItem { address, class_hash } in deployed_contracts {
+        contract_leafs.entry(*address).or_default().class_hash = Some(*class_hash);
+    }
+
+    for ReplacedClassItem { contract_address, class_hash } in replaced_classes {
+        contract_leafs.entry(*contract_address).or_default().class_hash = Some(*class_hash);
+    }
+
+    contract_leafs
+}
+
 /// Computes the contract state leaf hash
 ///
 /// # Arguments
 ///
-/// * `csd`             - Commitment state diff for the current block.
+/// * `block_id`         - The block the nonce and class hash missing from the leaf are read at.
 /// * `contract_address` - The contract address.
 /// * `storage_root`     - The storage root of the contract.
 ///
 /// # Returns
 ///
 /// The contract state leaf hash.
-fn contract_state_leaf_hash(
+pub(crate) fn contract_state_leaf_hash(
     backend: &MadaraBackend,
+    block_id: &BlockId,
     contract_address: &Felt,
     contract_leaf: &ContractLeaf,
 ) -> Result<Felt, MadaraStorageError> {
-    let nonce = contract_leaf.nonce.unwrap_or(
-        backend.get_contract_nonce_at(&BlockId::Tag(BlockTag::Latest), contract_address)?.unwrap_or(Felt::ZERO),
-    );
+    let nonce =
+        contract_leaf.nonce.unwrap_or(backend.get_contract_nonce_at(block_id, contract_address)?.unwrap_or(Felt::ZERO));
 
     let class_hash = contract_leaf.class_hash.unwrap_or(
-        backend.get_contract_class_hash_at(&BlockId::Tag(BlockTag::Latest), contract_address)?.unwrap_or(Felt::ZERO), // .ok_or(MadaraStorageError::InconsistentStorage("Class hash not found".into()))?
+        backend.get_contract_class_hash_at(block_id, contract_address)?.unwrap_or(Felt::ZERO), // .ok_or(MadaraStorageError::InconsistentStorage("Class hash not found".into()))?
     );
 
     let storage_root = contract_leaf
@@ -200,7 +217,9 @@ mod contract_trie_root_tests {
         };
 
         // Call the function and print the result
-        let result = contract_state_leaf_hash(&backend, &contract_address, &contract_leaf).unwrap();
+        let result =
+            contract_state_leaf_hash(&backend, &BlockId::Tag(BlockTag::Latest), &contract_address, &contract_leaf)
+                .unwrap();
         assert_eq!(
             result,
             Felt::from_hex_unchecked("0x6bbd8d4b5692148f83c38e19091f64381b5239e2a73f53b59be3ec3efb41143")
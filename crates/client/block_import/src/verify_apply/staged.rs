@@ -0,0 +1,151 @@
+use mp_state_update::{
+    ContractStorageDiffItem, DeclaredClassItem, DeployedContractItem, NonceUpdate, StateDiff, StorageEntry,
+};
+use starknet_types_core::felt::Felt;
+use std::collections::HashMap;
+
+/// Trie updates of the blocks imported since the last trie commit, see
+/// [`BlockImporter::with_trie_commit_interval`](crate::BlockImporter::with_trie_commit_interval).
+///
+/// Only the latest value of each leaf is kept: committing the staged updates at once results in the same tries as
+/// committing them block by block.
+#[derive(Debug, Default)]
+pub(crate) struct StagedTrieUpdates {
+    first_block_n: Option<u64>,
+    last_block_n: Option<u64>,
+    storage: HashMap<Felt, HashMap<Felt, Felt>>,
+    class_hashes: HashMap<Felt, Felt>,
+    nonces: HashMap<Felt, Felt>,
+    declared_classes: HashMap<Felt, Felt>,
+}
+
+impl StagedTrieUpdates {
+    pub fn is_empty(&self) -> bool {
+        self.last_block_n.is_none()
+    }
+
+    /// First block staged since the last trie commit.
+    pub fn first_block_n(&self) -> Option<u64> {
+        self.first_block_n
+    }
+
+    pub fn stage(&mut self, block_n: u64, state_diff: &StateDiff) {
+        self.first_block_n.get_or_insert(block_n);
+        self.last_block_n = Some(block_n);
+        for ContractStorageDiffItem { address, storage_entries } in &state_diff.storage_diffs {
+            let storage = self.storage.entry(*address).or_default();
+            storage.extend(storage_entries.iter().map(|StorageEntry { key, value }| (*key, *value)));
+        }
+        self.class_hashes.extend(state_diff.deployed_contracts.iter().map(|item| (item.address, item.class_hash)));
+        self.class_hashes
+            .extend(state_diff.replaced_classes.iter().map(|item| (item.contract_address, item.class_hash)));
+        self.nonces.extend(state_diff.nonces.iter().map(|item| (item.contract_address, item.nonce)));
+        self.declared_classes
+            .extend(state_diff.declared_classes.iter().map(|item| (item.class_hash, item.compiled_class_hash)));
+    }
+
+    /// Returns the last staged block number and the trie updates to apply, leaving nothing staged.
+    ///
+    /// Replaced classes are merged into the deployed contracts, as both only update the class hash of a contract
+    /// leaf.
+    pub fn take(&mut self) -> Option<(u64, StateDiff)> {
+        let Self { first_block_n: _, last_block_n, storage, class_hashes, nonces, declared_classes } =
+            std::mem::take(self);
+        let state_diff = StateDiff {
+            storage_diffs: storage
+                .into_iter()
+                .map(|(address, storage)| ContractStorageDiffItem {
+                    address,
+                    storage_entries: storage.into_iter().map(|(key, value)| StorageEntry { key, value }).collect(),
+                })
+                .collect(),
+            deprecated_declared_classes: vec![],
+            declared_classes: declared_classes
+                .into_iter()
+                .map(|(class_hash, compiled_class_hash)| DeclaredClassItem { class_hash, compiled_class_hash })
+                .collect(),
+            deployed_contracts: class_hashes
+                .into_iter()
+                .map(|(address, class_hash)| DeployedContractItem { address, class_hash })
+                .collect(),
+            replaced_classes: vec![],
+            nonces: nonces
+                .into_iter()
+                .map(|(contract_address, nonce)| NonceUpdate { contract_address, nonce })
+                .collect(),
+        };
+        last_block_n.map(|block_n| (block_n, state_diff))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mp_state_update::ReplacedClassItem;
+    use starknet_api::felt;
+
+    #[test]
+    fn test_staged_trie_updates_keep_latest_values() {
+        let mut staged = StagedTrieUpdates::default();
+        assert!(staged.is_empty());
+        assert_eq!(staged.take(), None);
+
+        staged.stage(
+            0,
+            &StateDiff {
+                storage_diffs: vec![ContractStorageDiffItem {
+                    address: felt!("0x1"),
+                    storage_entries: vec![
+                        StorageEntry { key: felt!("0x10"), value: felt!("0x1") },
+                        StorageEntry { key: felt!("0x11"), value: felt!("0x1") },
+                    ],
+                }],
+                deployed_contracts: vec![DeployedContractItem { address: felt!("0x1"), class_hash: felt!("0xc1") }],
+                nonces: vec![NonceUpdate { contract_address: felt!("0x1"), nonce: felt!("0x1") }],
+                ..Default::default()
+            },
+        );
+        staged.stage(
+            1,
+            &StateDiff {
+                storage_diffs: vec![ContractStorageDiffItem {
+                    address: felt!("0x1"),
+                    storage_entries: vec![StorageEntry { key: felt!("0x10"), value: felt!("0x2") }],
+                }],
+                replaced_classes: vec![ReplacedClassItem { contract_address: felt!("0x1"), class_hash: felt!("0xc2") }],
+                nonces: vec![NonceUpdate { contract_address: felt!("0x1"), nonce: felt!("0x2") }],
+                declared_classes: vec![DeclaredClassItem {
+                    class_hash: felt!("0xc2"),
+                    compiled_class_hash: felt!("0xcc"),
+                }],
+                ..Default::default()
+            },
+        );
+        assert!(!staged.is_empty());
+        assert_eq!(staged.first_block_n(), Some(0));
+
+        let (block_n, mut state_diff) = staged.take().unwrap();
+        assert_eq!(block_n, 1);
+        state_diff.storage_diffs[0].storage_entries.sort_by_key(|entry| entry.key);
+        assert_eq!(
+            state_diff,
+            StateDiff {
+                storage_diffs: vec![ContractStorageDiffItem {
+                    address: felt!("0x1"),
+                    storage_entries: vec![
+                        StorageEntry { key: felt!("0x10"), value: felt!("0x2") },
+                        StorageEntry { key: felt!("0x11"), value: felt!("0x1") },
+                    ],
+                }],
+                deployed_contracts: vec![DeployedContractItem { address: felt!("0x1"), class_hash: felt!("0xc2") }],
+                nonces: vec![NonceUpdate { contract_address: felt!("0x1"), nonce: felt!("0x2") }],
+                declared_classes: vec![DeclaredClassItem {
+                    class_hash: felt!("0xc2"),
+                    compiled_class_hash: felt!("0xcc")
+                }],
+                ..Default::default()
+            }
+        );
+        assert!(staged.is_empty());
+    }
+}
@@ -26,6 +26,17 @@ const ROW_PENDING_STATE_UPDATE: &[u8] = b"pending_state_update";
 const ROW_PENDING_INNER: &[u8] = b"pending";
 const ROW_SYNC_TIP: &[u8] = b"sync_tip";
 const ROW_L1_LAST_CONFIRMED_BLOCK: &[u8] = b"l1_last";
+const ROW_CHECKPOINT: &[u8] = b"checkpoint";
+const ROW_TRIES_BLOCK_N: &[u8] = b"tries_block_n";
+
+/// Trusted block a database was started from, instead of genesis. The blocks up to it are not stored, and the global
+/// tries do not have their state.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointBlock {
+    pub block_number: u64,
+    pub block_hash: Felt,
+    pub global_root: Felt,
+}
 
 #[tracing::instrument(skip(db), fields(module = "BlockDB"))]
 pub fn get_latest_block_n(db: &DB) -> Result<Option<u64>> {
@@ -201,8 +212,50 @@ impl MadaraBackend {
         Ok(Some(res))
     }
 
+    /// The checkpoint the database was started from, `None` if it was synced from genesis.
+    #[tracing::instrument(skip(self), fields(module = "BlockDB"))]
+    pub fn get_checkpoint_block(&self) -> Result<Option<CheckpointBlock>> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        let Some(res) = self.db.get_cf(&col, ROW_CHECKPOINT)? else { return Ok(None) };
+        let res = bincode::deserialize(&res)?;
+        Ok(Some(res))
+    }
+
+    /// The last block whose state diff was committed to the global tries, `None` if the tries were never committed.
+    #[tracing::instrument(skip(self), fields(module = "BlockDB"))]
+    pub fn get_tries_block_n(&self) -> Result<Option<u64>> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        let Some(res) = self.db.get_cf(&col, ROW_TRIES_BLOCK_N)? else { return Ok(None) };
+        let res = bincode::deserialize(&res)?;
+        Ok(Some(res))
+    }
+
     // DB write
 
+    /// Records that the database starts from `checkpoint`: the next block to import is the one after it.
+    #[tracing::instrument(skip(self), fields(module = "BlockDB"))]
+    pub fn write_checkpoint_block(&self, checkpoint: &CheckpointBlock) -> Result<()> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        self.db.put_cf(&col, ROW_CHECKPOINT, bincode::serialize(checkpoint)?)?;
+        Ok(())
+    }
+
+    /// Records that the global tries contain the state diffs of every block up to `block_n`.
+    #[tracing::instrument(skip(self), fields(module = "BlockDB"))]
+    pub fn write_tries_block_n(&self, block_n: u64) -> Result<()> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        self.db.put_cf(&col, ROW_TRIES_BLOCK_N, bincode::serialize(&block_n)?)?;
+        Ok(())
+    }
+
+    /// Forgets the last block of the global tries, as in a database which predates its record.
+    #[cfg(feature = "testing")]
+    pub fn clear_tries_block_n_for_testing(&self) -> Result<()> {
+        let col = self.db.get_column(Column::BlockStorageMeta);
+        self.db.delete_cf(&col, ROW_TRIES_BLOCK_N)?;
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self), fields(module = "BlockDB"))]
     pub(crate) fn block_db_store_pending(&self, block: &MadaraPendingBlock, state_update: &StateDiff) -> Result<()> {
         let mut tx = WriteBatchWithTransaction::default();
@@ -361,6 +414,13 @@ impl MadaraBackend {
         self.sender_block_info.subscribe()
     }
 
+    /// Subscribe to changes of the pending block. The receiver holds the info of the latest pending block
+    /// stored since startup, or `None` when the pending block has been cleared.
+    #[tracing::instrument(skip(self), fields(module = "BlockDB"))]
+    pub fn subscribe_pending(&self) -> tokio::sync::watch::Receiver<Option<mp_block::MadaraPendingBlockInfo>> {
+        self.sender_pending_block_info.subscribe()
+    }
+
     #[tracing::instrument(skip(self, id), fields(module = "BlockDB"))]
     pub fn get_block_inner(&self, id: &impl DbBlockIdResolvable) -> Result<Option<MadaraBlockInner>> {
         let Some(ty) = id.resolve_db_block_id(self)? else { return Ok(None) };
@@ -9,6 +9,9 @@ use std::fmt;
 use std::sync::Arc;
 
 pub type GlobalTrie<H> = BonsaiStorage<BasicId, BonsaiDb, H>;
+/// View of a [`GlobalTrie`] at a past block, see [`BonsaiStorage::get_transactional_state`]. Updates on a view are
+/// only kept in memory, they are never written to the database.
+pub type GlobalTrieView<H> = BonsaiStorage<BasicId, BonsaiTransaction, H>;
 
 #[derive(Clone, Debug)]
 pub(crate) struct DatabaseKeyMapping {
@@ -217,6 +220,9 @@ impl BonsaiDatabase for BonsaiTransaction {
     #[tracing::instrument(skip(self, key), fields(module = "BonsaiDB"))]
     fn contains(&self, key: &DatabaseKey) -> Result<bool, Self::DatabaseError> {
         tracing::trace!("Checking if RocksDB contains: {:?}", key);
+        if let Some(val) = self.changed.get(&to_changed_key(key)) {
+            return Ok(val.is_some());
+        }
         let handle = self.snapshot.db.get_column(self.column_mapping.map(key));
         Ok(self.snapshot.get_cf(&handle, key.as_slice())?.is_some())
     }
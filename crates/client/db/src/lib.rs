@@ -33,7 +33,7 @@ mod rocksdb_options;
 pub mod storage_updates;
 pub mod tests;
 
-pub use bonsai_db::GlobalTrie;
+pub use bonsai_db::{GlobalTrie, GlobalTrieView};
 pub use bonsai_trie::{id::BasicId, MultiProof, ProofNode};
 pub use error::{BonsaiStorageError, MadaraStorageError, TrieType};
 pub type DB = DBWithThreadMode<MultiThreaded>;
@@ -268,6 +268,7 @@ pub struct MadaraBackend {
     snapshots: Arc<Snapshots>,
     trie_log_config: TrieLogConfig,
     sender_block_info: tokio::sync::broadcast::Sender<mp_block::MadaraBlockInfo>,
+    sender_pending_block_info: tokio::sync::watch::Sender<Option<mp_block::MadaraPendingBlockInfo>>,
     #[cfg(feature = "testing")]
     _temp_dir: Option<tempfile::TempDir>,
 }
@@ -346,17 +347,33 @@ impl MadaraBackend {
 
     #[cfg(feature = "testing")]
     pub fn open_for_testing(chain_config: Arc<ChainConfig>) -> Arc<MadaraBackend> {
+        Self::open_for_testing_with_trie_logs(chain_config, Default::default())
+    }
+
+    /// Same as [`Self::open_for_testing`], keeping the trie logs and snapshots configured in `trie_log_config` so
+    /// that the global tries can be read at past blocks.
+    #[cfg(feature = "testing")]
+    pub fn open_for_testing_with_trie_logs(
+        chain_config: Arc<ChainConfig>,
+        trie_log_config: TrieLogConfig,
+    ) -> Arc<MadaraBackend> {
         let temp_dir = tempfile::TempDir::with_prefix("madara-test").unwrap();
         let db = open_rocksdb(temp_dir.as_ref()).unwrap();
-        let snapshots = Arc::new(Snapshots::new(Arc::clone(&db), None, Some(0), 5));
+        let snapshots = Arc::new(Snapshots::new(
+            Arc::clone(&db),
+            None,
+            Some(trie_log_config.max_kept_snapshots),
+            trie_log_config.snapshot_interval,
+        ));
         Arc::new(Self {
             backup_handle: None,
             db,
             chain_config,
             db_metrics: DbMetrics::register().unwrap(),
             snapshots,
-            trie_log_config: Default::default(),
+            trie_log_config,
             sender_block_info: tokio::sync::broadcast::channel(100).0,
+            sender_pending_block_info: tokio::sync::watch::channel(None).0,
             _temp_dir: Some(temp_dir),
         })
     }
@@ -409,6 +426,7 @@ impl MadaraBackend {
             snapshots,
             trie_log_config,
             sender_block_info: tokio::sync::broadcast::channel(100).0,
+            sender_pending_block_info: tokio::sync::watch::channel(None).0,
             #[cfg(feature = "testing")]
             _temp_dir: None,
         });
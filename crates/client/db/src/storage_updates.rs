@@ -19,9 +19,10 @@ impl MadaraBackend {
     ) -> Result<(), MadaraStorageError> {
         let block_n = block.info.block_n();
         let state_diff_cpy = state_diff.clone();
+        let pending_info = block.info.as_pending().cloned();
 
         // Clear in every case, even when storing a pending block
-        self.clear_pending_block()?;
+        self.clear_pending_block_db()?;
 
         let task_block_db = || match block.info {
             MadaraMaybePendingBlockInfo::Pending(info) => {
@@ -79,16 +80,39 @@ impl MadaraBackend {
 
         let ((r1, r2), r3) = rayon::join(|| rayon::join(task_block_db, task_contract_db), task_class_db);
 
-        r1.and(r2).and(r3)?;
+        if let Err(err) = r1.and(r2).and(r3) {
+            // The previous pending block has been cleared either way
+            self.notify_pending_cleared();
+            return Err(err);
+        }
 
         self.snapshots.set_new_head(DbBlockId::from_block_n(block_n));
+
+        // subscribers are only woken up once the whole pending block has been written
+        match pending_info {
+            Some(info) => {
+                self.sender_pending_block_info.send_replace(Some(info));
+            }
+            None => self.notify_pending_cleared(),
+        }
         Ok(())
     }
 
     pub fn clear_pending_block(&self) -> Result<(), MadaraStorageError> {
+        self.clear_pending_block_db()?;
+        self.notify_pending_cleared();
+        Ok(())
+    }
+
+    fn clear_pending_block_db(&self) -> Result<(), MadaraStorageError> {
         self.block_db_clear_pending()?;
         self.contract_db_clear_pending()?;
         self.class_db_clear_pending()?;
         Ok(())
     }
+
+    /// Only wakes up the subscribers if there was a pending block to clear.
+    fn notify_pending_cleared(&self) {
+        self.sender_pending_block_info.send_if_modified(|pending| pending.take().is_some());
+    }
 }
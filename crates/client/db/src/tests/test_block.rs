@@ -40,6 +40,25 @@ mod block_tests {
         assert!(backend.resolve_block_id(&BlockId::Hash(felt!("0x0"))).unwrap().is_none());
     }
 
+    /// Storing a block indexes it both by number and by hash.
+    #[tokio::test]
+    async fn test_block_hash_index() {
+        let db = temp_db().await;
+        let backend = db.backend();
+
+        let blocks = [finalized_block_zero(Header::default()), finalized_block_one()];
+        for block in &blocks {
+            backend.store_block(block.clone(), finalized_state_diff_zero(), vec![]).unwrap();
+        }
+
+        for (block_n, block) in blocks.iter().enumerate() {
+            let block_hash = block.info.block_hash().unwrap();
+            assert_eq!(backend.get_block_hash(&BlockId::Number(block_n as u64)).unwrap(), Some(block_hash));
+            assert_eq!(backend.get_block_n(&BlockId::Hash(block_hash)).unwrap(), Some(block_n as u64));
+        }
+        assert_eq!(backend.get_block_hash(&BlockId::Number(2)).unwrap(), None);
+    }
+
     #[tokio::test]
     async fn test_store_block() {
         const BLOCK_ID_0: DbBlockId = DbBlockId::Number(0);
@@ -115,6 +134,26 @@ mod block_tests {
         assert_eq!(backend.get_block_state_diff(&BLOCK_ID_PENDING).unwrap().unwrap(), state_diff);
     }
 
+    #[tokio::test]
+    async fn test_subscribe_pending() {
+        let db = temp_db().await;
+        let backend = db.backend();
+        let mut pending_rx = backend.subscribe_pending();
+        assert!(pending_rx.borrow_and_update().is_none());
+
+        backend.store_block(finalized_block_zero(Header::default()), finalized_state_diff_zero(), vec![]).unwrap();
+        assert!(!pending_rx.has_changed().unwrap(), "Storing a closed block without pending should not notify");
+
+        let block = pending_block_one();
+        backend.store_block(block.clone(), pending_state_diff_one(), vec![]).unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(1), pending_rx.changed()).await.unwrap().unwrap();
+        assert_eq!(pending_rx.borrow_and_update().as_ref(), block.info.as_pending());
+
+        backend.clear_pending_block().unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(1), pending_rx.changed()).await.unwrap().unwrap();
+        assert!(pending_rx.borrow_and_update().is_none());
+    }
+
     #[tokio::test]
     async fn test_store_latest_block() {
         let db = temp_db().await;
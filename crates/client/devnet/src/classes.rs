@@ -101,6 +101,7 @@ impl InitiallyDeclaredClasses {
                     class_hash: c.class_hash,
                     contract_class: c.contract_class,
                     compiled_class_hash: c.compiled_class_hash,
+                    compiled_class: None,
                 }),
                 InitiallyDeclaredClass::Legacy(c) => DeclaredClass::Legacy(LegacyDeclaredClass {
                     class_hash: c.class_hash,
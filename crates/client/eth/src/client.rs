@@ -14,6 +14,7 @@ use opentelemetry::{global::Error, metrics::Gauge};
 
 use anyhow::{bail, Context};
 use bitvec::macros::internal::funty::Fundamental;
+use starknet_api::core::ChainId;
 use starknet_types_core::felt::Felt;
 use std::sync::Arc;
 use url::Url;
@@ -62,6 +63,47 @@ impl L1BlockMetrics {
     }
 }
 
+/// Ethereum chain id of the L1 a Starknet chain settles on, when it is known.
+///
+/// Custom chains can settle on any L1, in which case we cannot know the expected chain id and return
+/// `None`.
+pub fn l1_chain_id_for(chain_id: &ChainId) -> Option<u64> {
+    match chain_id {
+        ChainId::Mainnet => Some(ETHEREUM_MAINNET_CHAIN_ID),
+        ChainId::Sepolia | ChainId::IntegrationSepolia => Some(ETHEREUM_SEPOLIA_CHAIN_ID),
+        ChainId::Other(_) => None,
+    }
+}
+
+pub const ETHEREUM_MAINNET_CHAIN_ID: u64 = 1;
+pub const ETHEREUM_SEPOLIA_CHAIN_ID: u64 = 11155111;
+
+/// Checks that the L1 endpoint serves the expected Ethereum network (when `expected_l1_chain_id` is
+/// set), and that the L1 Core contract exists there by checking its bytecode.
+///
+/// This fails fast at startup with a clear error instead of silently syncing against the wrong L1.
+pub async fn verify_l1_endpoint(
+    provider: &RootProvider<Http<Client>>,
+    l1_core_address: Address,
+    expected_l1_chain_id: Option<u64>,
+) -> anyhow::Result<()> {
+    if let Some(expected) = expected_l1_chain_id {
+        let chain_id = provider.get_chain_id().await.context("Getting the L1 endpoint chain id")?;
+        if chain_id != expected {
+            bail!(
+                "The L1 endpoint is serving chain id {chain_id}, but chain id {expected} was expected. Check that the L2 chain matches the L1 RPC endpoint."
+            );
+        }
+    }
+
+    let l1_core_contract_bytecode =
+        provider.get_code_at(l1_core_address).await.context("Getting the L1 Core Contract bytecode")?;
+    if l1_core_contract_bytecode.is_empty() {
+        bail!("The L1 Core Contract could not be found. Check that the L2 chain matches the L1 RPC endpoint.");
+    }
+    Ok(())
+}
+
 // abi taken from: https://etherscan.io/address/0x6e0acfdc3cf17a7f99ed34be56c3dfb93f464e24#code
 // The official starknet core contract ^
 sol!(
@@ -88,27 +130,21 @@ impl Clone for EthereumClient {
 }
 
 impl EthereumClient {
-    /// Create a new EthereumClient instance with the given RPC URL
+    /// Create a new EthereumClient instance with the given RPC URL.
+    ///
+    /// This does not check that the endpoint is valid, see [`EthereumClient::verify_l1_endpoint`].
     pub async fn new(url: Url, l1_core_address: Address, l1_block_metrics: L1BlockMetrics) -> anyhow::Result<Self> {
         let provider = ProviderBuilder::new().on_http(url);
 
-        EthereumClient::assert_core_contract_exists(&provider, l1_core_address).await?;
-
         let core_contract = StarknetCoreContract::new(l1_core_address, provider.clone());
 
         Ok(Self { provider: Arc::new(provider), l1_core_contract: core_contract, l1_block_metrics })
     }
 
-    /// Assert that L1 Core contract exists by checking its bytecode.
-    async fn assert_core_contract_exists(
-        provider: &RootProvider<Http<Client>>,
-        l1_core_address: Address,
-    ) -> anyhow::Result<()> {
-        let l1_core_contract_bytecode = provider.get_code_at(l1_core_address).await?;
-        if l1_core_contract_bytecode.is_empty() {
-            bail!("The L1 Core Contract could not be found. Check that the L2 chain matches the L1 RPC endpoint.");
-        }
-        Ok(())
+    /// Startup probe which makes sure the L1 endpoint actually points to the expected Ethereum network
+    /// and that the L1 Core contract is deployed there. See [`verify_l1_endpoint`].
+    pub async fn verify_l1_endpoint(&self, expected_l1_chain_id: Option<u64>) -> anyhow::Result<()> {
+        verify_l1_endpoint(&self.provider, *self.l1_core_contract.address(), expected_l1_chain_id).await
     }
 
     /// Retrieves the latest Ethereum block number
@@ -246,8 +282,67 @@ pub mod eth_client_getter_test {
         let core_contract_address = Address::parse_checksummed(INVALID_CORE_CONTRACT_ADDRESS, None).unwrap();
         let l1_block_metrics = L1BlockMetrics::register().unwrap();
 
-        let new_client_result = EthereumClient::new(rpc_url, core_contract_address, l1_block_metrics).await;
-        assert!(new_client_result.is_err(), "EthereumClient::new should fail with an invalid core contract address");
+        let eth_client = EthereumClient::new(rpc_url, core_contract_address, l1_block_metrics).await.unwrap();
+        let verify_result = eth_client.verify_l1_endpoint(Some(ETHEREUM_MAINNET_CHAIN_ID)).await;
+        assert!(verify_result.is_err(), "verify_l1_endpoint should fail with an invalid core contract address");
+    }
+
+    fn mock_l1_endpoint(chain_id: &str, code: &str) -> httpmock::MockServer {
+        let mock_server = httpmock::MockServer::start();
+        mock_server.mock(|when, then| {
+            when.method("POST").path("/").body_contains("eth_chainId");
+            then.status(200).json_body_obj(&serde_json::json!({"jsonrpc": "2.0", "id": 0, "result": chain_id}));
+        });
+        mock_server.mock(|when, then| {
+            when.method("POST").path("/").body_contains("eth_getCode");
+            then.status(200).json_body_obj(&serde_json::json!({"jsonrpc": "2.0", "id": 0, "result": code}));
+        });
+        mock_server
+    }
+
+    #[tokio::test]
+    async fn verify_l1_endpoint_works() {
+        let mock_server = mock_l1_endpoint("0x1", "0x6080");
+        let eth_client = create_ethereum_client(Some(&mock_server.base_url()));
+
+        eth_client
+            .verify_l1_endpoint(Some(ETHEREUM_MAINNET_CHAIN_ID))
+            .await
+            .expect("verify_l1_endpoint should succeed on a matching endpoint");
+    }
+
+    #[tokio::test]
+    async fn verify_l1_endpoint_wrong_chain_id() {
+        // Sepolia chain id when mainnet is expected
+        let mock_server = mock_l1_endpoint("0xaa36a7", "0x6080");
+        let eth_client = create_ethereum_client(Some(&mock_server.base_url()));
+
+        let err = eth_client
+            .verify_l1_endpoint(Some(ETHEREUM_MAINNET_CHAIN_ID))
+            .await
+            .expect_err("verify_l1_endpoint should fail with a wrong chain id");
+        assert!(format!("{err:#}").contains("chain id 11155111"), "Unexpected error: {err:#}");
+    }
+
+    #[tokio::test]
+    async fn verify_l1_endpoint_no_core_contract() {
+        let mock_server = mock_l1_endpoint("0x1", "0x");
+        let eth_client = create_ethereum_client(Some(&mock_server.base_url()));
+
+        let err = eth_client
+            .verify_l1_endpoint(Some(ETHEREUM_MAINNET_CHAIN_ID))
+            .await
+            .expect_err("verify_l1_endpoint should fail when the core contract has no code");
+        assert!(format!("{err:#}").contains("L1 Core Contract could not be found"), "Unexpected error: {err:#}");
+    }
+
+    #[rstest::rstest]
+    #[case(ChainId::Mainnet, Some(ETHEREUM_MAINNET_CHAIN_ID))]
+    #[case(ChainId::Sepolia, Some(ETHEREUM_SEPOLIA_CHAIN_ID))]
+    #[case(ChainId::IntegrationSepolia, Some(ETHEREUM_SEPOLIA_CHAIN_ID))]
+    #[case(ChainId::Other("MADARA_DEVNET".to_string()), None)]
+    fn l1_chain_id_for_works(#[case] chain_id: ChainId, #[case] expected: Option<u64>) {
+        assert_eq!(l1_chain_id_for(&chain_id), expected);
     }
 
     #[serial]
@@ -1,6 +1,7 @@
 use crate::client::EthereumClient;
 use alloy::eips::BlockNumberOrTag;
 use alloy::providers::Provider;
+use alloy::rpc::types::FeeHistory;
 use anyhow::Context;
 use mc_mempool::{GasPriceProvider, L1DataProvider};
 use std::time::{Duration, UNIX_EPOCH};
@@ -8,14 +9,62 @@ use std::time::{Duration, UNIX_EPOCH};
 use mp_utils::{service::ServiceContext, wait_or_graceful_shutdown};
 use std::time::SystemTime;
 
+/// How the L1 gas price is derived from the Ethereum fee history.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum L1GasMode {
+    /// The base fee of the latest block, for L1s implementing EIP-1559.
+    #[default]
+    Eip1559,
+    /// The base fee plus the median priority fee paid in the latest block. On L1s which do not implement EIP-1559,
+    /// the base fee is zero and this is the median gas price paid.
+    Legacy,
+}
+
+impl L1GasMode {
+    /// Reward percentiles to request in the fee history.
+    fn reward_percentiles(&self) -> &'static [f64] {
+        match self {
+            Self::Eip1559 => &[],
+            Self::Legacy => &[50.0],
+        }
+    }
+
+    /// The L1 gas price derived from a fee history requested with [`Self::reward_percentiles`].
+    pub fn eth_gas_price(&self, fee_history: &FeeHistory) -> Option<u128> {
+        match self {
+            Self::Eip1559 => fee_history.base_fee_per_gas.last().copied(),
+            Self::Legacy => {
+                let (block_index, rewards) = fee_history.reward.as_ref()?.iter().enumerate().last()?;
+                Some(fee_history.base_fee_per_gas.get(block_index)? + rewards.first()?)
+            }
+        }
+    }
+}
+
+/// The fetch is given up after `request_timeout`, keeping the previous prices, so that a hung L1 endpoint cannot block
+/// the worker across several polls.
+///
+/// When `max_delta_pct` is set, a warning is logged whenever a fetched price differs from the previous one by more
+/// than this percentage, as this usually means that the L1 endpoint is serving bad data.
 pub async fn gas_price_worker_once(
     eth_client: &EthereumClient,
     l1_gas_provider: GasPriceProvider,
     gas_price_poll_ms: Duration,
+    request_timeout: Duration,
+    max_delta_pct: Option<f64>,
+    mode: L1GasMode,
 ) -> anyhow::Result<()> {
-    match update_gas_price(eth_client, l1_gas_provider.clone()).await {
-        Ok(_) => tracing::trace!("Updated gas prices"),
-        Err(e) => tracing::error!("Failed to update gas prices: {:?}", e),
+    match tokio::time::timeout(
+        request_timeout,
+        update_gas_price(eth_client, l1_gas_provider.clone(), max_delta_pct, mode),
+    )
+    .await
+    {
+        Ok(Ok(_)) => tracing::trace!("Updated gas prices"),
+        Ok(Err(e)) => tracing::error!("Failed to update gas prices: {:?}", e),
+        Err(_) => tracing::warn!(
+            "⚠️ Fetching the L1 gas prices timed out after {request_timeout:?}, keeping the previous gas prices"
+        ),
     }
 
     let last_update_timestamp = l1_gas_provider.get_gas_prices_last_update();
@@ -36,20 +85,48 @@ pub async fn gas_price_worker(
     eth_client: &EthereumClient,
     l1_gas_provider: GasPriceProvider,
     gas_price_poll_ms: Duration,
+    request_timeout: Duration,
+    max_delta_pct: Option<f64>,
+    mode: L1GasMode,
     ctx: ServiceContext,
 ) -> anyhow::Result<()> {
     l1_gas_provider.update_last_update_timestamp();
     let mut interval = tokio::time::interval(gas_price_poll_ms);
     interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
     while wait_or_graceful_shutdown(interval.tick(), &ctx).await.is_some() {
-        gas_price_worker_once(eth_client, l1_gas_provider.clone(), gas_price_poll_ms).await?;
+        gas_price_worker_once(
+            eth_client,
+            l1_gas_provider.clone(),
+            gas_price_poll_ms,
+            request_timeout,
+            max_delta_pct,
+            mode,
+        )
+        .await?;
     }
     Ok(())
 }
 
-async fn update_gas_price(eth_client: &EthereumClient, l1_gas_provider: GasPriceProvider) -> anyhow::Result<()> {
+/// Whether `current` differs from `previous` by more than `max_delta_pct` percent of `previous`. A change from a zero
+/// price, as before the first poll, is never reported.
+pub fn gas_price_delta_exceeded(previous: u128, current: u128, max_delta_pct: f64) -> bool {
+    if previous == 0 {
+        return false;
+    }
+    previous.abs_diff(current) as f64 / previous as f64 * 100.0 > max_delta_pct
+}
+
+async fn update_gas_price(
+    eth_client: &EthereumClient,
+    l1_gas_provider: GasPriceProvider,
+    max_delta_pct: Option<f64>,
+    mode: L1GasMode,
+) -> anyhow::Result<()> {
     let block_number = eth_client.get_latest_block_number().await?;
-    let fee_history = eth_client.provider.get_fee_history(300, BlockNumberOrTag::Number(block_number), &[]).await?;
+    let fee_history = eth_client
+        .provider
+        .get_fee_history(300, BlockNumberOrTag::Number(block_number), mode.reward_percentiles())
+        .await?;
 
     // The RPC responds with 301 elements for some reason. It's also just safer to manually
     // take the last 300. We choose 300 to get average gas caprice for last one hour (300 * 12 sec block
@@ -63,7 +140,22 @@ async fn update_gas_price(eth_client: &EthereumClient, l1_gas_provider: GasPrice
         0 // in case blob_fee_history_one_hour has 0 length
     };
 
-    let eth_gas_price = fee_history.base_fee_per_gas.last().context("Getting eth gas price")?;
+    let eth_gas_price = &mode.eth_gas_price(&fee_history).context("Getting eth gas price")?;
+
+    if let Some(max_delta_pct) = max_delta_pct {
+        let previous = l1_gas_provider.get_gas_prices();
+        for (name, previous, current) in [
+            ("gas price", previous.eth_l1_gas_price, *eth_gas_price),
+            ("data gas price", previous.eth_l1_data_gas_price, avg_blob_base_fee),
+        ] {
+            if gas_price_delta_exceeded(previous, current, max_delta_pct) {
+                tracing::warn!(
+                    "⚠️ The L1 {name} jumped from {previous} to {current} wei since the last poll, more than \
+                     {max_delta_pct}%. Check that the L1 endpoint is serving correct data"
+                );
+            }
+        }
+    }
 
     l1_gas_provider.update_eth_l1_gas_price(*eth_gas_price);
     l1_gas_provider.update_eth_l1_data_gas_price(avg_blob_base_fee);
@@ -103,6 +195,7 @@ mod eth_client_gas_price_worker_test {
     use alloy::node_bindings::Anvil;
     use httpmock::{MockServer, Regex};
     use mc_mempool::GasPriceProvider;
+    use rstest::rstest;
     use serial_test::serial;
     use std::time::SystemTime;
     use tokio::task::JoinHandle;
@@ -114,6 +207,41 @@ mod eth_client_gas_price_worker_test {
         static ref FORK_URL: String = std::env::var("ETH_FORK_URL").expect("ETH_FORK_URL not set");
     }
 
+    #[rstest]
+    #[case::unchanged(100, 100, false)]
+    #[case::within_threshold_up(100, 150, false)]
+    #[case::within_threshold_down(100, 50, false)]
+    #[case::over_threshold_up(100, 151, true)]
+    #[case::over_threshold_down(100, 49, true)]
+    #[case::first_poll(0, 1_000_000, false)]
+    fn test_gas_price_delta_exceeded(#[case] previous: u128, #[case] current: u128, #[case] exceeded: bool) {
+        assert_eq!(gas_price_delta_exceeded(previous, current, 50.0), exceeded);
+    }
+
+    #[rstest]
+    #[case::eip1559(L1GasMode::Eip1559, Some(0x30))]
+    #[case::legacy(L1GasMode::Legacy, Some(0x20 + 0x5))]
+    fn test_l1_gas_mode(#[case] mode: L1GasMode, #[case] expected: Option<u128>) {
+        let fee_history: FeeHistory = serde_json::from_value(serde_json::json!({
+            "oldestBlock": "0x137368c",
+            "baseFeePerGas": ["0x10", "0x20", "0x30"],
+            "gasUsedRatio": [0.5, 0.6],
+            "baseFeePerBlobGas": ["0x1", "0x1", "0x1"],
+            "blobGasUsedRatio": [0.0, 0.0],
+            "reward": [["0x3"], ["0x5"]]
+        }))
+        .unwrap();
+        assert_eq!(mode.eth_gas_price(&fee_history), expected);
+    }
+
+    /// Without the requested rewards, there is no legacy gas price to derive.
+    #[test]
+    fn test_l1_gas_mode_legacy_without_rewards() {
+        let fee_history = FeeHistory { base_fee_per_gas: vec![0x10, 0x20], ..Default::default() };
+        assert_eq!(L1GasMode::Legacy.eth_gas_price(&fee_history), None);
+        assert_eq!(L1GasMode::Eip1559.eth_gas_price(&fee_history), Some(0x20));
+    }
+
     #[serial]
     #[tokio::test]
     async fn gas_price_worker_when_infinite_loop_true_works() {
@@ -135,6 +263,9 @@ mod eth_client_gas_price_worker_test {
                     &eth_client,
                     l1_gas_provider,
                     Duration::from_millis(200),
+                    Duration::from_secs(10),
+                    None,
+                    L1GasMode::default(),
                     ServiceContext::new_for_testing(),
                 )
                 .await
@@ -176,7 +307,14 @@ mod eth_client_gas_price_worker_test {
         let l1_gas_provider = GasPriceProvider::new();
 
         // Run the worker for a short time
-        let worker_handle = gas_price_worker_once(&eth_client, l1_gas_provider.clone(), Duration::from_millis(200));
+        let worker_handle = gas_price_worker_once(
+            &eth_client,
+            l1_gas_provider.clone(),
+            Duration::from_millis(200),
+            Duration::from_secs(10),
+            None,
+            L1GasMode::default(),
+        );
 
         // Wait for the worker to complete
         worker_handle.await.expect("issue with the gas worker");
@@ -202,7 +340,14 @@ mod eth_client_gas_price_worker_test {
         l1_gas_provider.set_gas_price_sync_enabled(false);
 
         // Run the worker for a short time
-        let worker_handle = gas_price_worker_once(&eth_client, l1_gas_provider.clone(), Duration::from_millis(200));
+        let worker_handle = gas_price_worker_once(
+            &eth_client,
+            l1_gas_provider.clone(),
+            Duration::from_millis(200),
+            Duration::from_secs(10),
+            None,
+            L1GasMode::default(),
+        );
 
         // Wait for the worker to complete
         worker_handle.await.expect("issue with the gas worker");
@@ -228,7 +373,14 @@ mod eth_client_gas_price_worker_test {
         l1_gas_provider.set_data_gas_price_sync_enabled(false);
 
         // Run the worker for a short time
-        let worker_handle = gas_price_worker_once(&eth_client, l1_gas_provider.clone(), Duration::from_millis(200));
+        let worker_handle = gas_price_worker_once(
+            &eth_client,
+            l1_gas_provider.clone(),
+            Duration::from_millis(200),
+            Duration::from_secs(10),
+            None,
+            L1GasMode::default(),
+        );
 
         // Wait for the worker to complete
         worker_handle.await.expect("issue with the gas worker");
@@ -280,6 +432,9 @@ mod eth_client_gas_price_worker_test {
                 &eth_client,
                 l1_gas_provider.clone(),
                 Duration::from_millis(200),
+                Duration::from_secs(10),
+                None,
+                L1GasMode::default(),
                 ServiceContext::new_for_testing(),
             ),
         )
@@ -303,6 +458,45 @@ mod eth_client_gas_price_worker_test {
         mock.assert();
     }
 
+    /// A hung L1 endpoint makes the fetch time out, and the last known gas prices are kept.
+    #[serial]
+    #[tokio::test]
+    async fn gas_price_worker_when_request_times_out_keeps_prices() {
+        let mock_server = MockServer::start();
+        let eth_client = create_ethereum_client(Some(&mock_server.base_url()));
+        let mock = mock_server.mock(|when, then| {
+            when.method("POST").path("/").body_contains("eth_blockNumber");
+            then.status(200)
+                .delay(Duration::from_secs(2))
+                .json_body_obj(&serde_json::json!({"jsonrpc":"2.0","id":0,"result":"0x0137368e"}));
+        });
+
+        let l1_gas_provider = GasPriceProvider::new();
+        l1_gas_provider.update_eth_l1_gas_price(20);
+        l1_gas_provider.update_eth_l1_data_gas_price(30);
+        l1_gas_provider.update_last_update_timestamp();
+
+        timeout(
+            Duration::from_secs(1),
+            gas_price_worker_once(
+                &eth_client,
+                l1_gas_provider.clone(),
+                Duration::from_secs(10),
+                Duration::from_millis(100),
+                None,
+                L1GasMode::default(),
+            ),
+        )
+        .await
+        .expect("The gas price fetch should have timed out")
+        .expect("The worker should keep running after a timeout");
+
+        let prices = l1_gas_provider.get_gas_prices();
+        assert_eq!(prices.eth_l1_gas_price, 20);
+        assert_eq!(prices.eth_l1_data_gas_price, 30);
+        mock.assert();
+    }
+
     #[serial]
     #[tokio::test]
     async fn update_gas_price_works() {
@@ -318,7 +512,9 @@ mod eth_client_gas_price_worker_test {
         l1_gas_provider.update_last_update_timestamp();
 
         // Update gas prices
-        update_gas_price(&eth_client, l1_gas_provider.clone()).await.expect("Failed to update gas prices");
+        update_gas_price(&eth_client, l1_gas_provider.clone(), None, L1GasMode::default())
+            .await
+            .expect("Failed to update gas prices");
 
         // Access the updated gas prices
         let updated_prices = l1_gas_provider.get_gas_prices();
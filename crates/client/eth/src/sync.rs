@@ -1,36 +1,242 @@
 use crate::client::EthereumClient;
-use crate::l1_gas_price::gas_price_worker;
+use crate::l1_gas_price::{gas_price_worker, L1GasMode};
 use crate::l1_messaging::sync;
 use crate::state_update::state_update_worker;
 use mc_mempool::{GasPriceProvider, Mempool};
 use mp_utils::service::ServiceContext;
+use mp_utils::wait_or_graceful_shutdown;
 use starknet_api::core::ChainId;
+use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
 
 use mc_db::MadaraBackend;
 
+/// How often the primary L1 endpoint is checked while the workers run on the fallback endpoint.
+pub const PRIMARY_RECOVERY_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
 #[allow(clippy::too_many_arguments)]
 pub async fn l1_sync_worker(
     backend: &MadaraBackend,
     eth_client: &EthereumClient,
+    fallback_eth_client: Option<&EthereumClient>,
     chain_id: ChainId,
     l1_gas_provider: GasPriceProvider,
     gas_price_sync_disabled: bool,
     gas_price_poll_ms: Duration,
+    gas_price_request_timeout: Duration,
+    gas_price_max_delta_pct: Option<f64>,
+    l1_gas_mode: L1GasMode,
     mempool: Arc<Mempool>,
     ctx: ServiceContext,
 ) -> anyhow::Result<()> {
-    tokio::try_join!(
-        state_update_worker(backend, eth_client, chain_id.clone(), ctx.clone()),
-        async {
-            if !gas_price_sync_disabled {
-                gas_price_worker(eth_client, l1_gas_provider, gas_price_poll_ms, ctx.clone()).await?;
-            }
+    run_with_fallback(eth_client, fallback_eth_client, PRIMARY_RECOVERY_CHECK_INTERVAL, &ctx, |eth_client| {
+        let (chain_id, l1_gas_provider, mempool, ctx) =
+            (chain_id.clone(), l1_gas_provider.clone(), Arc::clone(&mempool), ctx.clone());
+        async move {
+            tokio::try_join!(
+                state_update_worker(backend, &eth_client, chain_id.clone(), ctx.clone()),
+                async {
+                    if !gas_price_sync_disabled {
+                        gas_price_worker(
+                            &eth_client,
+                            l1_gas_provider,
+                            gas_price_poll_ms,
+                            gas_price_request_timeout,
+                            gas_price_max_delta_pct,
+                            l1_gas_mode,
+                            ctx.clone(),
+                        )
+                        .await?;
+                    }
+                    Ok(())
+                },
+                sync(backend, &eth_client, &chain_id, mempool, ctx.clone())
+            )?;
             Ok(())
-        },
-        sync(backend, eth_client, &chain_id, mempool, ctx.clone())
-    )?;
+        }
+    })
+    .await
+}
+
+/// Runs the L1 workers with `primary`. When they fail and a `fallback` endpoint is set, they are restarted with the
+/// fallback endpoint, and restarted with the primary endpoint again once it answers. When they fail on the fallback
+/// endpoint too, they are restarted with the primary endpoint after `recovery_check_interval`, alternating between
+/// both endpoints until one of them works. Without a fallback endpoint, a failure is returned.
+pub async fn run_with_fallback<F, Fut>(
+    primary: &EthereumClient,
+    fallback: Option<&EthereumClient>,
+    recovery_check_interval: Duration,
+    ctx: &ServiceContext,
+    run: F,
+) -> anyhow::Result<()>
+where
+    F: Fn(EthereumClient) -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    loop {
+        let err = match run(primary.clone()).await {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+        let Some(fallback) = fallback else { return Err(err) };
+        tracing::warn!("⚠️ The L1 workers failed on the primary L1 endpoint, switching to the fallback: {err:#}");
+
+        tokio::select! {
+            res = run(fallback.clone()) => {
+                let Err(err) = res else { return Ok(()) };
+                tracing::warn!(
+                    "⚠️ The L1 workers failed on the fallback L1 endpoint, switching back to the primary: {err:#}"
+                );
+                if wait_or_graceful_shutdown(tokio::time::sleep(recovery_check_interval), ctx).await.is_none() {
+                    return Ok(());
+                }
+            }
+            recovered = wait_for_recovery(primary, recovery_check_interval, ctx) => {
+                if !recovered {
+                    return Ok(());
+                }
+                tracing::info!("🔁 The primary L1 endpoint answers again, switching back to it");
+            }
+        }
+    }
+}
+
+/// Polls `eth_client` until it answers. Returns `false` if the node is shutting down.
+async fn wait_for_recovery(eth_client: &EthereumClient, check_interval: Duration, ctx: &ServiceContext) -> bool {
+    let mut interval = tokio::time::interval(check_interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    // The first tick completes immediately, while the primary endpoint has just failed
+    interval.tick().await;
+    while wait_or_graceful_shutdown(interval.tick(), ctx).await.is_some() {
+        if eth_client.get_latest_block_number().await.is_ok() {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::eth_client_getter_test::create_ethereum_client;
+    use httpmock::MockServer;
+    use std::sync::Mutex;
+
+    fn mock_block_number(mock_server: &MockServer, block_number: Option<&str>) {
+        mock_server.mock(|when, then| {
+            when.method("POST").path("/").body_contains("eth_blockNumber");
+            match block_number {
+                Some(block_number) => {
+                    then.status(200).json_body(serde_json::json!({"jsonrpc":"2.0","id":0,"result":block_number}))
+                }
+                None => then.status(500).body("Internal Server Error"),
+            };
+        });
+    }
+
+    /// The workers are restarted on the fallback endpoint after failing on the primary endpoint.
+    #[tokio::test]
+    async fn test_run_with_fallback() {
+        let (primary_server, fallback_server) = (MockServer::start(), MockServer::start());
+        mock_block_number(&primary_server, None);
+        mock_block_number(&fallback_server, Some("0x2"));
+        let primary = create_ethereum_client(Some(&primary_server.base_url()));
+        let fallback = create_ethereum_client(Some(&fallback_server.base_url()));
+
+        let results = Mutex::new(vec![]);
+        let run = |eth_client: EthereumClient| {
+            let results = &results;
+            async move {
+                let res = eth_client.get_latest_block_number().await;
+                results.lock().unwrap().push(res.as_ref().ok().copied());
+                res.map(|_| ())
+            }
+        };
+
+        let ctx = ServiceContext::new_for_testing();
+        run_with_fallback(&primary, None, Duration::from_millis(10), &ctx, run).await.unwrap_err();
+        assert_eq!(*results.lock().unwrap(), [None]);
+
+        results.lock().unwrap().clear();
+        run_with_fallback(&primary, Some(&fallback), Duration::from_millis(10), &ctx, run).await.unwrap();
+        assert_eq!(*results.lock().unwrap(), [None, Some(2)]);
+    }
+
+    /// A failure on the fallback endpoint is not fatal: the workers are restarted with the primary endpoint.
+    #[tokio::test]
+    async fn test_run_with_fallback_alternates() {
+        let (primary_server, fallback_server) = (MockServer::start(), MockServer::start());
+        mock_block_number(&primary_server, None);
+        mock_block_number(&fallback_server, Some("0x2"));
+        let primary = create_ethereum_client(Some(&primary_server.base_url()));
+        let fallback = create_ethereum_client(Some(&fallback_server.base_url()));
+
+        // The workers fail on both endpoints once, then work
+        let results = Mutex::new(vec![]);
+        let run = |eth_client: EthereumClient| {
+            let results = &results;
+            async move {
+                let res = eth_client.get_latest_block_number().await.ok();
+                let mut results = results.lock().unwrap();
+                results.push(res);
+                anyhow::ensure!(results.len() > 2, "The L1 workers failed");
+                Ok(())
+            }
+        };
+
+        let ctx = ServiceContext::new_for_testing();
+        run_with_fallback(&primary, Some(&fallback), Duration::from_millis(10), &ctx, run).await.unwrap();
+        assert_eq!(*results.lock().unwrap(), [None, Some(2), None]);
+    }
+
+    /// The workers run on the fallback endpoint until the primary endpoint answers again, and are then restarted with
+    /// the primary endpoint.
+    #[tokio::test]
+    async fn test_run_with_fallback_primary_recovers() {
+        let (primary_server, fallback_server) = (MockServer::start(), MockServer::start());
+        let mut failing = primary_server.mock(|when, then| {
+            when.method("POST").path("/").body_contains("eth_blockNumber");
+            then.status(500).body("Internal Server Error");
+        });
+        mock_block_number(&fallback_server, Some("0x2"));
+        let primary = create_ethereum_client(Some(&primary_server.base_url()));
+        let fallback = create_ethereum_client(Some(&fallback_server.base_url()));
+
+        // The workers fail on the primary endpoint while it is down, and run until the node stops on the fallback
+        let results = Mutex::new(vec![]);
+        let run = |eth_client: EthereumClient| {
+            let results = &results;
+            async move {
+                let res = eth_client.get_latest_block_number().await;
+                results.lock().unwrap().push(res.as_ref().ok().copied());
+                match res? {
+                    2 => std::future::pending().await,
+                    _ => Ok(()),
+                }
+            }
+        };
 
-    Ok(())
+        let ctx = ServiceContext::new_for_testing();
+        let recover_primary = async {
+            tokio::time::timeout(Duration::from_secs(10), async {
+                while results.lock().unwrap().len() < 2 {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("The workers were not restarted on the fallback endpoint");
+            failing.delete();
+            mock_block_number(&primary_server, Some("0x1"));
+        };
+        let (res, ()) = tokio::join!(
+            tokio::time::timeout(
+                Duration::from_secs(10),
+                run_with_fallback(&primary, Some(&fallback), Duration::from_millis(10), &ctx, run)
+            ),
+            recover_primary
+        );
+        res.expect("The workers were not restarted on the primary endpoint").unwrap();
+        assert_eq!(*results.lock().unwrap(), [None, Some(2), Some(1)]);
+    }
 }
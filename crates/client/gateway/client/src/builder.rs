@@ -9,18 +9,42 @@ use hyper_util::client::legacy::Client;
 use hyper_util::rt::TokioExecutor;
 use std::error::Error;
 use std::future::Future;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::PathBuf;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError};
 use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, Semaphore};
 use tower::retry;
 use tower::Service;
 use tower::{retry::Retry, timeout::Timeout};
 use url::Url;
 
+use crate::health::FeederHealth;
+use crate::record::{RecordFormat, RecordMode, Recorder};
+
+/// Default timeout for a single request to the (feeder) gateway.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Connection pool of the HTTP client. Reusing the connections to the gateway saves a TCP and TLS handshake per
+/// request, which adds up when downloading the many classes declared in a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+    /// Maximum number of idle connections kept open to the gateway.
+    pub max_idle: usize,
+    /// Idle connections are closed after this long.
+    pub keepalive: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self { max_idle: usize::MAX, keepalive: Duration::from_secs(90) }
+    }
+}
+
 type HttpsClient = Client<HttpsConnector<HttpConnector>, String>;
-type TimeoutRetryClient = Retry<RetryPolicy, Timeout<HttpsClient>>;
+type TimeoutRetryClient = Retry<RetryPolicy, RateLimitMiddleware<Timeout<HttpsClient>>>;
 pub type PausedClient = PauseLayerMiddleware<TimeoutRetryClient>;
 #[derive(Debug, Clone)]
 pub struct GatewayProvider {
@@ -28,24 +52,80 @@ pub struct GatewayProvider {
     pub(crate) gateway_url: Url,
     pub(crate) feeder_gateway_url: Url,
     pub(crate) headers: HeaderMap,
+    pub(crate) recorder: Option<Recorder>,
+    pub(crate) record_format: RecordFormat,
+    pub(crate) class_request_limit: Option<Arc<Semaphore>>,
+    pub(crate) pool: PoolConfig,
+    pub(crate) feeder_health: Arc<FeederHealth>,
 }
 
 impl GatewayProvider {
     pub fn new(gateway_url: Url, feeder_gateway_url: Url) -> Self {
+        Self::new_with_timeout(gateway_url, feeder_gateway_url, DEFAULT_REQUEST_TIMEOUT)
+    }
+
+    /// Each request will fail with [`SequencerError::Timeout`](mp_gateway::error::SequencerError::Timeout)
+    /// if it takes longer than `request_timeout`, after being retried.
+    pub fn new_with_timeout(gateway_url: Url, feeder_gateway_url: Url, request_timeout: Duration) -> Self {
+        Self::new_with_pool(gateway_url, feeder_gateway_url, request_timeout, PoolConfig::default())
+    }
+
+    /// Like [`Self::new_with_timeout`], with the connection pool of the HTTP client configured by `pool`.
+    pub fn new_with_pool(
+        gateway_url: Url,
+        feeder_gateway_url: Url,
+        request_timeout: Duration,
+        pool: PoolConfig,
+    ) -> Self {
         let pause_until = Arc::new(RwLock::new(None));
         let connector = HttpsConnector::new();
-        let base_client = Client::builder(TokioExecutor::new()).build::<_, String>(connector);
+        let base_client = Client::builder(TokioExecutor::new())
+            .pool_max_idle_per_host(pool.max_idle)
+            .pool_idle_timeout(pool.keepalive)
+            .build::<_, String>(connector);
 
-        let timeout_layer = Timeout::new(base_client, Duration::from_secs(20)); // Timeout after 20 seconds
+        let timeout_layer = Timeout::new(base_client, request_timeout);
+        let rate_limit_layer = RateLimitMiddleware::new(timeout_layer);
         let retry_policy = RetryPolicy::new(5, Duration::from_secs(1), Arc::clone(&pause_until)); // Retry 5 times with 1 second backoff
-        let retry_layer = Retry::new(retry_policy, timeout_layer);
+        let retry_layer = Retry::new(retry_policy, rate_limit_layer);
         let client = PauseLayerMiddleware::new(retry_layer, Arc::clone(&pause_until));
 
-        Self { client, gateway_url, feeder_gateway_url, headers: HeaderMap::new() }
+        Self {
+            client,
+            gateway_url,
+            feeder_gateway_url,
+            headers: HeaderMap::new(),
+            recorder: None,
+            record_format: RecordFormat::default(),
+            class_request_limit: None,
+            pool,
+            feeder_health: Arc::default(),
+        }
+    }
+
+    pub fn pool_config(&self) -> PoolConfig {
+        self.pool
     }
 
-    pub fn new_with_headers(gateway_url: Url, feeder_gateway_url: Url, headers: &[(HeaderName, HeaderValue)]) -> Self {
-        let feeder_client = Self::new(gateway_url, feeder_gateway_url);
+    /// Health of the feeder gateway, updated by every request to it.
+    pub fn feeder_health(&self) -> &Arc<FeederHealth> {
+        &self.feeder_health
+    }
+
+    /// Records the health of the feeder gateway in `feeder_health`, so that it can be shared with other services.
+    pub fn with_feeder_health(mut self, feeder_health: Arc<FeederHealth>) -> Self {
+        self.feeder_health = feeder_health;
+        self
+    }
+
+    /// Like [`Self::new_with_timeout`], with `headers` sent on every request.
+    pub fn new_with_headers(
+        gateway_url: Url,
+        feeder_gateway_url: Url,
+        headers: &[(HeaderName, HeaderValue)],
+        request_timeout: Duration,
+    ) -> Self {
+        let feeder_client = Self::new_with_timeout(gateway_url, feeder_gateway_url, request_timeout);
         let headers = headers.iter().cloned().collect();
 
         Self { headers, ..feeder_client }
@@ -59,6 +139,60 @@ impl GatewayProvider {
         self.headers.remove(name)
     }
 
+    /// Writes every block, state update, signature and class fetched from the feeder gateway to `dir`, see
+    /// [`Self::with_replay_dir`].
+    pub fn with_record_dir(mut self, dir: PathBuf) -> Self {
+        self.recorder = Some(Recorder::new(RecordMode::Record, dir, self.record_format));
+        self
+    }
+
+    /// Reads the blocks, state updates, signatures and classes recorded with [`Self::with_record_dir`] from `dir`
+    /// instead of the network. Missing recordings are reported as not found.
+    pub fn with_replay_dir(mut self, dir: PathBuf) -> Self {
+        self.recorder = Some(Recorder::new(RecordMode::Replay, dir, self.record_format));
+        self
+    }
+
+    /// Format of the recordings written by [`Self::with_record_dir`], JSON by default. Replaying reads the recordings
+    /// in either format.
+    pub fn with_record_format(mut self, format: RecordFormat) -> Self {
+        self.record_format = format;
+        if let Some(recorder) = &mut self.recorder {
+            recorder.format = format;
+        }
+        self
+    }
+
+    /// Limits the requests made by this provider, and all of its clones, to `requests_per_second`. Retried requests
+    /// are limited too.
+    pub fn with_rate_limit(mut self, requests_per_second: NonZeroU32) -> Self {
+        self.client.inner.get_mut().rate_limiter = Some(Arc::new(RateLimiter::new(requests_per_second)));
+        self
+    }
+
+    /// Limits the number of class requests in flight at once, for this provider and all of its clones. Other requests
+    /// are not affected: blocks are few and large, whereas a single block may declare many small classes.
+    pub fn with_max_concurrent_class_requests(mut self, max_concurrent_class_requests: NonZeroUsize) -> Self {
+        self.class_request_limit = Some(Arc::new(Semaphore::new(max_concurrent_class_requests.get())));
+        self
+    }
+
+    /// Rejects responses with a body larger than `max_response_bytes` with
+    /// [`SequencerError::ResponseTooLarge`](mp_gateway::error::SequencerError::ResponseTooLarge), instead of buffering
+    /// them whole. Responses announcing a larger `Content-Length` are rejected before reading the body.
+    pub fn with_max_response_bytes(mut self, max_response_bytes: NonZeroUsize) -> Self {
+        self.client.max_response_bytes = Some(max_response_bytes.get());
+        self
+    }
+
+    /// Asks the gateway for gzip compressed responses, which are decompressed transparently. This saves a lot of
+    /// bandwidth on classes and state updates. [`Self::with_max_response_bytes`] then also limits the size of the
+    /// decompressed body.
+    pub fn with_compression(mut self) -> Self {
+        self.client.compression = true;
+        self
+    }
+
     pub fn starknet_alpha_mainnet() -> Self {
         Self::new(
             Url::parse("https://alpha-mainnet.starknet.io/gateway/")
@@ -170,15 +304,89 @@ fn get_retry_after(response: &Response<Incoming>) -> Option<Duration> {
     None
 }
 
+/// Token bucket shared by the requests of a [`GatewayProvider`] and all of its clones.
+///
+/// The bucket holds a single token, so that requests are evenly spaced out.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next_slot: std::sync::Mutex<tokio::time::Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: NonZeroU32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / requests_per_second.get(),
+            next_slot: std::sync::Mutex::new(tokio::time::Instant::now()),
+        }
+    }
+
+    /// Waits until the next request can be sent.
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap_or_else(PoisonError::into_inner);
+            let slot = (*next_slot).max(tokio::time::Instant::now());
+            *next_slot = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// Waits for the [`RateLimiter`], if any, before each request. This sits below the [`Retry`] layer, so that every
+/// attempt sent to the gateway is paced, including the retries.
+#[derive(Clone, Debug)]
+pub struct RateLimitMiddleware<S> {
+    inner: S,
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+impl<S> RateLimitMiddleware<S> {
+    pub fn new(inner: S) -> Self {
+        RateLimitMiddleware { inner, rate_limiter: None }
+    }
+}
+
+impl<S> Service<Request<String>> for RateLimitMiddleware<S>
+where
+    S: Service<Request<String>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<String>) -> Self::Future {
+        let rate_limiter = self.rate_limiter.clone();
+        let mut inner = self.inner.clone();
+
+        async move {
+            if let Some(rate_limiter) = rate_limiter {
+                rate_limiter.acquire().await;
+            }
+            inner.call(req).await
+        }
+        .boxed()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct PauseLayerMiddleware<S> {
     inner: S,
     pause_until: Arc<RwLock<Option<Instant>>>,
+    /// Read by the [`RequestBuilder`](crate::request_builder::RequestBuilder) when buffering a response body.
+    pub(crate) max_response_bytes: Option<usize>,
+    /// Whether the [`RequestBuilder`](crate::request_builder::RequestBuilder) asks for gzip compressed responses.
+    pub(crate) compression: bool,
 }
 
 impl<S> PauseLayerMiddleware<S> {
     pub fn new(inner: S, pause_until: Arc<RwLock<Option<Instant>>>) -> Self {
-        PauseLayerMiddleware { inner, pause_until }
+        PauseLayerMiddleware { inner, pause_until, max_response_bytes: None, compression: false }
     }
 }
 
@@ -228,3 +436,207 @@ where
         .boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use httpmock::MockServer;
+    use mp_block::{BlockId, BlockTag};
+    use mp_gateway::error::SequencerError;
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let mock_server = MockServer::start();
+        mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_block");
+            then.status(200).delay(Duration::from_secs(5)).body("{}");
+        });
+
+        let url = Url::parse(&mock_server.base_url()).unwrap();
+        let provider = GatewayProvider::new_with_timeout(
+            url.join("/gateway/").unwrap(),
+            url.join("/feeder_gateway/").unwrap(),
+            Duration::from_millis(50),
+        );
+
+        let start = Instant::now();
+        let res = provider.get_block(BlockId::Tag(BlockTag::Latest)).await;
+        assert!(matches!(res, Err(SequencerError::Timeout)), "Expected a timeout, got {res:?}");
+        // 6 attempts with 1s backoff, the requests themselves must have been aborted.
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_with_headers() {
+        let mock_server = MockServer::start();
+        let mock = mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_block").header("x-test", "value");
+            then.status(200).delay(Duration::from_secs(5)).body("{}");
+        });
+
+        let url = Url::parse(&mock_server.base_url()).unwrap();
+        let provider = GatewayProvider::new_with_headers(
+            url.join("/gateway/").unwrap(),
+            url.join("/feeder_gateway/").unwrap(),
+            &[(HeaderName::from_static("x-test"), HeaderValue::from_static("value"))],
+            Duration::from_millis(50),
+        );
+
+        let res = provider.get_block(BlockId::Tag(BlockTag::Latest)).await;
+        assert!(matches!(res, Err(SequencerError::Timeout)), "Expected a timeout, got {res:?}");
+        assert!(mock.hits() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_max_response_bytes() {
+        let mock_server = MockServer::start();
+        mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_block_traces").query_param("blockNumber", "0");
+            then.status(200).json_body(serde_json::json!({ "traces": [] }));
+        });
+        mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_block_traces").query_param("blockNumber", "1");
+            then.status(200).json_body(serde_json::json!({ "traces": ["0".repeat(2048)] }));
+        });
+
+        let url = Url::parse(&mock_server.base_url()).unwrap();
+        let provider = GatewayProvider::new(url.join("/gateway/").unwrap(), url.join("/feeder_gateway/").unwrap())
+            .with_max_response_bytes(NonZeroUsize::new(1024).unwrap());
+
+        provider.get_block_traces(BlockId::Number(0)).await.unwrap();
+        let res = provider.get_block_traces(BlockId::Number(1)).await;
+        assert!(
+            matches!(res, Err(SequencerError::ResponseTooLarge { max_response_bytes: 1024 })),
+            "Expected the response to be rejected, got {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        let body = serde_json::json!({ "traces": ["0".repeat(2048)] });
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body.to_string().as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        assert!(gzipped.len() < 1024);
+
+        let mock_server = MockServer::start();
+        mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_block_traces").header("accept-encoding", "gzip");
+            then.status(200).header("content-encoding", "gzip").body(&gzipped);
+        });
+
+        let url = Url::parse(&mock_server.base_url()).unwrap();
+        let provider = GatewayProvider::new(url.join("/gateway/").unwrap(), url.join("/feeder_gateway/").unwrap())
+            .with_compression();
+        assert_eq!(provider.get_block_traces(BlockId::Number(0)).await.unwrap(), body);
+
+        // The size limit applies to the decompressed body
+        let provider = provider.with_max_response_bytes(NonZeroUsize::new(1024).unwrap());
+        let res = provider.get_block_traces(BlockId::Number(0)).await;
+        assert!(
+            matches!(res, Err(SequencerError::ResponseTooLarge { max_response_bytes: 1024 })),
+            "Expected the decompressed response to be rejected, got {res:?}"
+        );
+    }
+
+    /// Every request to the feeder gateway updates its health, which is shared by the clones of the provider.
+    #[tokio::test]
+    async fn test_feeder_health() {
+        let mock_server = MockServer::start();
+        let mut failing = mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_block_traces");
+            then.status(502).body("Bad Gateway");
+        });
+
+        let url = Url::parse(&mock_server.base_url()).unwrap();
+        let provider = GatewayProvider::new(url.join("/gateway/").unwrap(), url.join("/feeder_gateway/").unwrap())
+            .with_feeder_health(Arc::new(FeederHealth::new(2)));
+        let health = Arc::clone(provider.feeder_health());
+
+        provider.get_block_traces(BlockId::Number(0)).await.unwrap_err();
+        assert_eq!(health.record().consecutive_failures, 1);
+        assert!(health.is_up());
+        provider.clone().get_block_traces(BlockId::Number(0)).await.unwrap_err();
+        assert_eq!(health.record().consecutive_failures, 2);
+        assert!(!health.is_up());
+
+        failing.delete();
+        mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_block_traces");
+            then.status(200).json_body(serde_json::json!({ "traces": [] }));
+        });
+        provider.get_block_traces(BlockId::Number(0)).await.unwrap();
+        let record = health.record();
+        assert!(record.up);
+        assert_eq!(record.consecutive_failures, 0);
+        assert!(record.last_success_timestamp.is_some());
+    }
+
+    /// The rate limit spaces out every request sent to the gateway, including the ones retried by the client.
+    #[tokio::test]
+    async fn test_rate_limit_failing_requests() {
+        let mock_server = MockServer::start();
+        let rate_limited = mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_block_traces").query_param("blockNumber", "0");
+            then.status(429).header("Retry-After", "0").body("Too Many Requests");
+        });
+        mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_block_traces").query_param("blockNumber", "1");
+            then.status(503).body("Service Unavailable");
+        });
+
+        let url = Url::parse(&mock_server.base_url()).unwrap();
+        let provider = GatewayProvider::new(url.join("/gateway/").unwrap(), url.join("/feeder_gateway/").unwrap())
+            .with_rate_limit(NonZeroU32::new(10).unwrap());
+
+        // Rate limited requests are retried by the client itself, right away as asked by the gateway
+        let start = Instant::now();
+        let retried = tokio::spawn({
+            let provider = provider.clone();
+            async move { provider.get_block_traces(BlockId::Number(0)).await }
+        });
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while rate_limited.hits() < 5 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("The rate limited request was not retried");
+        assert!(start.elapsed() >= Duration::from_millis(400), "5 attempts sent in {:?}", start.elapsed());
+        retried.abort();
+
+        // Server errors are retried by the caller
+        let start = Instant::now();
+        for _ in 0..5 {
+            provider.get_block_traces(BlockId::Number(1)).await.unwrap_err();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(400), "5 requests sent in {:?}", start.elapsed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_pacing() {
+        let rate_limiter = Arc::new(RateLimiter::new(NonZeroU32::new(10).unwrap()));
+
+        let start = tokio::time::Instant::now();
+        let mut handles = vec![];
+        for _ in 0..20 {
+            let rate_limiter = Arc::clone(&rate_limiter);
+            handles.push(tokio::spawn(async move {
+                rate_limiter.acquire().await;
+                tokio::time::Instant::now()
+            }));
+        }
+        let mut sent_at = futures::future::try_join_all(handles).await.unwrap();
+        sent_at.sort();
+
+        // The first request is sent right away, the next ones every 100ms.
+        assert_eq!(sent_at[0], start);
+        for window in sent_at.windows(2) {
+            assert!(window[1] - window[0] >= Duration::from_millis(100));
+        }
+        assert!(sent_at[19] - start >= Duration::from_millis(1900));
+    }
+}
@@ -0,0 +1,128 @@
+//! Health of the feeder gateway endpoint, as seen by the requests of a [`GatewayProvider`](crate::GatewayProvider).
+use mp_gateway::error::SequencerError;
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default number of consecutive failed requests after which the feeder gateway is reported as down.
+pub const DEFAULT_DOWN_AFTER_FAILURES: u32 = 3;
+
+/// Snapshot of the [`FeederHealth`] of a feeder gateway endpoint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeederHealthRecord {
+    /// Number of failed requests since the last successful one.
+    pub consecutive_failures: u32,
+    /// Unix time of the last successful request, in seconds. `None` until a request succeeds.
+    pub last_success_timestamp: Option<u64>,
+    /// `false` once the consecutive failures reach the threshold, until a request succeeds again.
+    pub up: bool,
+}
+
+/// Keeps track of the outcome of the requests to the feeder gateway, shared by a provider and all of its clones.
+///
+/// A request fails when the feeder gateway cannot be reached or does not answer with a valid response. Errors
+/// returned by the feeder gateway itself, such as a block not being found, mean that it is up.
+#[derive(Debug)]
+pub struct FeederHealth {
+    down_after_failures: u32,
+    record: Mutex<FeederHealthRecord>,
+}
+
+impl Default for FeederHealth {
+    fn default() -> Self {
+        Self::new(DEFAULT_DOWN_AFTER_FAILURES)
+    }
+}
+
+impl FeederHealth {
+    /// The endpoint is reported as down after `down_after_failures` consecutive failed requests. It is assumed to be
+    /// up until then.
+    pub fn new(down_after_failures: u32) -> Self {
+        Self {
+            down_after_failures: down_after_failures.max(1),
+            record: Mutex::new(FeederHealthRecord { up: true, ..Default::default() }),
+        }
+    }
+
+    pub fn record(&self) -> FeederHealthRecord {
+        *self.record.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.record().up
+    }
+
+    pub fn on_success(&self) {
+        let last_success_timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).ok();
+        let mut record = self.record.lock().unwrap_or_else(PoisonError::into_inner);
+        if !record.up {
+            tracing::info!("🟢 The feeder gateway is back up");
+        }
+        *record = FeederHealthRecord { consecutive_failures: 0, last_success_timestamp, up: true };
+    }
+
+    pub fn on_failure(&self) {
+        let mut record = self.record.lock().unwrap_or_else(PoisonError::into_inner);
+        record.consecutive_failures = record.consecutive_failures.saturating_add(1);
+        if record.up && record.consecutive_failures >= self.down_after_failures {
+            tracing::warn!(
+                "🔴 The feeder gateway is down, {} consecutive requests failed",
+                record.consecutive_failures
+            );
+            record.up = false;
+        }
+    }
+
+    /// Records the outcome of a request, see [`FeederHealth`].
+    pub(crate) fn on_result<T>(&self, result: &Result<T, SequencerError>) {
+        match result {
+            Ok(_) | Err(SequencerError::StarknetError(_)) => self.on_success(),
+            Err(_) => self.on_failure(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mp_gateway::error::StarknetError;
+
+    #[test]
+    fn test_feeder_health_threshold() {
+        let health = FeederHealth::new(3);
+        assert_eq!(
+            health.record(),
+            FeederHealthRecord { consecutive_failures: 0, last_success_timestamp: None, up: true }
+        );
+
+        health.on_failure();
+        health.on_failure();
+        assert_eq!(health.record().consecutive_failures, 2);
+        assert!(health.is_up());
+        health.on_failure();
+        assert!(!health.is_up());
+        health.on_failure();
+        assert_eq!(health.record().consecutive_failures, 4);
+
+        health.on_success();
+        let record = health.record();
+        assert!(record.up);
+        assert_eq!(record.consecutive_failures, 0);
+        assert!(record.last_success_timestamp.is_some());
+
+        // The failures are counted again from the last success
+        health.on_failure();
+        assert!(health.is_up());
+    }
+
+    #[test]
+    fn test_feeder_health_starknet_error_is_up() {
+        let health = FeederHealth::new(1);
+        health.on_result::<()>(&Err(StarknetError::block_not_found().into()));
+        assert!(health.is_up());
+        health.on_result::<()>(&Err(SequencerError::Timeout));
+        assert!(!health.is_up());
+        health.on_result(&Ok(()));
+        assert!(health.is_up());
+    }
+}
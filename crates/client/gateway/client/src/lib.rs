@@ -1,5 +1,9 @@
 mod builder;
+mod health;
 mod methods;
+mod record;
 mod request_builder;
 
-pub use builder::GatewayProvider;
+pub use builder::{GatewayProvider, PoolConfig, DEFAULT_REQUEST_TIMEOUT};
+pub use health::{FeederHealth, FeederHealthRecord, DEFAULT_DOWN_AFTER_FAILURES};
+pub use record::RecordFormat;
@@ -14,16 +14,18 @@ use mp_gateway::{
     },
 };
 use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use serde_json::Value;
 use starknet_core::types::contract::legacy::LegacyContractClass;
 use starknet_types_core::felt::Felt;
 use starknet_types_rpc::{AddInvokeTransactionResult, ClassAndTxnHash, ContractAndTxnHash};
 
-use super::{builder::GatewayProvider, request_builder::RequestBuilder};
+use super::{builder::GatewayProvider, record::class_not_found, request_builder::RequestBuilder};
 
 impl GatewayProvider {
     pub async fn get_block(&self, block_id: BlockId) -> Result<ProviderBlockPendingMaybe, SequencerError> {
         let request = RequestBuilder::new(&self.client, self.feeder_gateway_url.clone(), self.headers.clone())
+            .with_health(&self.feeder_health)
             .add_uri_segment("get_block")
             .expect("Failed to add URI segment. This should not fail in prod.")
             .with_block_id(&block_id);
@@ -36,8 +38,27 @@ impl GatewayProvider {
         }
     }
 
+    /// Number of the latest block, requested with `headerOnly` so that the feeder gateway does not send the whole
+    /// block. This is meant to be polled to follow the tip of the chain, it is never recorded nor replayed.
+    pub async fn get_latest_block_number(&self) -> Result<u64, SequencerError> {
+        #[derive(Deserialize)]
+        struct BlockHeaderOnly {
+            block_number: u64,
+        }
+
+        let request = RequestBuilder::new(&self.client, self.feeder_gateway_url.clone(), self.headers.clone())
+            .with_health(&self.feeder_health)
+            .add_uri_segment("get_block")
+            .expect("Failed to add URI segment. This should not fail in prod.")
+            .with_block_id(&BlockId::Tag(BlockTag::Latest))
+            .add_param(Cow::from("headerOnly"), "true");
+
+        Ok(request.send_get::<BlockHeaderOnly>().await?.block_number)
+    }
+
     pub async fn get_state_update(&self, block_id: BlockId) -> Result<ProviderStateUpdatePendingMaybe, SequencerError> {
         let request = RequestBuilder::new(&self.client, self.feeder_gateway_url.clone(), self.headers.clone())
+            .with_health(&self.feeder_health)
             .add_uri_segment("get_state_update")
             .expect("Failed to add URI segment. This should not fail in prod")
             .with_block_id(&block_id);
@@ -54,7 +75,20 @@ impl GatewayProvider {
         &self,
         block_id: BlockId,
     ) -> Result<ProviderStateUpdateWithBlockPendingMaybe, SequencerError> {
+        let recorder = match (&self.recorder, &block_id) {
+            (Some(recorder), BlockId::Number(block_n)) => Some((recorder, recorder.block_path(*block_n))),
+            _ => None,
+        };
+        if let Some((recorder, path)) = &recorder {
+            if recorder.is_replay() {
+                return Ok(ProviderStateUpdateWithBlockPendingMaybe::NonPending(
+                    recorder.replay(path, StarknetError::block_not_found()).await?,
+                ));
+            }
+        }
+
         let request = RequestBuilder::new(&self.client, self.feeder_gateway_url.clone(), self.headers.clone())
+            .with_health(&self.feeder_health)
             .add_uri_segment("get_state_update")
             .expect("Failed to add URI segment. This should not fail in prod")
             .with_block_id(&block_id)
@@ -64,9 +98,13 @@ impl GatewayProvider {
             BlockId::Tag(BlockTag::Pending) => Ok(ProviderStateUpdateWithBlockPendingMaybe::Pending(
                 request.send_get::<ProviderStateUpdateWithBlockPending>().await?,
             )),
-            _ => Ok(ProviderStateUpdateWithBlockPendingMaybe::NonPending(
-                request.send_get::<ProviderStateUpdateWithBlock>().await?,
-            )),
+            _ => {
+                let state_update = request.send_get::<ProviderStateUpdateWithBlock>().await?;
+                if let Some((recorder, path)) = &recorder {
+                    recorder.record(path, &state_update).await;
+                }
+                Ok(ProviderStateUpdateWithBlockPendingMaybe::NonPending(state_update))
+            }
         }
     }
 
@@ -74,13 +112,52 @@ impl GatewayProvider {
         if matches!(block_id, BlockId::Tag(BlockTag::Pending)) {
             return Err(StarknetError::no_signature_for_pending_block().into());
         }
+        let recorder = match (&self.recorder, &block_id) {
+            (Some(recorder), BlockId::Number(block_n)) => Some((recorder, recorder.signature_path(*block_n))),
+            _ => None,
+        };
+        if let Some((recorder, path)) = &recorder {
+            if recorder.is_replay() {
+                return recorder.replay(path, StarknetError::block_not_found()).await;
+            }
+        }
 
         let request = RequestBuilder::new(&self.client, self.feeder_gateway_url.clone(), self.headers.clone())
+            .with_health(&self.feeder_health)
             .add_uri_segment("get_signature")
             .expect("Failed to add URI segment. This should not fail in prod")
             .with_block_id(&block_id);
 
-        request.send_get::<ProviderBlockSignature>().await
+        let signature = request.send_get::<ProviderBlockSignature>().await?;
+        if let Some((recorder, path)) = &recorder {
+            recorder.record(path, &signature).await;
+        }
+        Ok(signature)
+    }
+
+    /// Execution traces of the transactions of a block, as returned by the feeder gateway.
+    pub async fn get_block_traces(&self, block_id: BlockId) -> Result<Value, SequencerError> {
+        let recorder = match (&self.recorder, &block_id) {
+            (Some(recorder), BlockId::Number(block_n)) => Some((recorder, recorder.traces_path(*block_n))),
+            _ => None,
+        };
+        if let Some((recorder, path)) = &recorder {
+            if recorder.is_replay() {
+                return recorder.replay(path, StarknetError::block_not_found()).await;
+            }
+        }
+
+        let request = RequestBuilder::new(&self.client, self.feeder_gateway_url.clone(), self.headers.clone())
+            .with_health(&self.feeder_health)
+            .add_uri_segment("get_block_traces")
+            .expect("Failed to add URI segment. This should not fail in prod")
+            .with_block_id(&block_id);
+
+        let traces = request.send_get::<Value>().await?;
+        if let Some((recorder, path)) = &recorder {
+            recorder.record(path, &traces).await;
+        }
+        Ok(traces)
     }
 
     pub async fn get_class_by_hash(
@@ -89,12 +166,28 @@ impl GatewayProvider {
         block_id: BlockId,
     ) -> Result<ContractClass, SequencerError> {
         let request = RequestBuilder::new(&self.client, self.feeder_gateway_url.clone(), self.headers.clone())
+            .with_health(&self.feeder_health)
             .add_uri_segment("get_class_by_hash")
             .expect("Failed to add URI segment. This should not fail in prod.")
             .with_block_id(&block_id)
             .with_class_hash(class_hash);
 
-        let value = request.send_get::<Value>().await?;
+        let value = match &self.recorder {
+            Some(recorder) if recorder.is_replay() => {
+                recorder.replay(&recorder.class_path(class_hash), class_not_found(class_hash)).await?
+            }
+            _ => {
+                let _permit = match &self.class_request_limit {
+                    Some(limit) => Some(limit.acquire().await.expect("The class request semaphore is never closed")),
+                    None => None,
+                };
+                let value = request.send_get::<Value>().await?;
+                if let Some(recorder) = &self.recorder {
+                    recorder.record(&recorder.class_path(class_hash), &value).await;
+                }
+                value
+            }
+        };
 
         if value.get("sierra_program").is_some() {
             let sierra: FlattenedSierraClass = serde_json::from_value(value)?;
@@ -108,6 +201,36 @@ impl GatewayProvider {
         }
     }
 
+    /// Value of the storage slot `key` of contract `contract_address`, after the block `block_id`.
+    pub async fn get_storage_at(
+        &self,
+        contract_address: Felt,
+        key: Felt,
+        block_id: BlockId,
+    ) -> Result<Felt, SequencerError> {
+        let request = RequestBuilder::new(&self.client, self.feeder_gateway_url.clone(), self.headers.clone())
+            .with_health(&self.feeder_health)
+            .add_uri_segment("get_storage_at")
+            .expect("Failed to add URI segment. This should not fail in prod.")
+            .with_block_id(&block_id)
+            .add_param(Cow::from("contractAddress"), &format!("0x{contract_address:x}"))
+            .add_param(Cow::from("key"), &format!("0x{key:x}"));
+
+        request.send_get::<Felt>().await
+    }
+
+    /// Nonce of contract `contract_address`, after the block `block_id`.
+    pub async fn get_nonce(&self, contract_address: Felt, block_id: BlockId) -> Result<Felt, SequencerError> {
+        let request = RequestBuilder::new(&self.client, self.feeder_gateway_url.clone(), self.headers.clone())
+            .with_health(&self.feeder_health)
+            .add_uri_segment("get_nonce")
+            .expect("Failed to add URI segment. This should not fail in prod.")
+            .with_block_id(&block_id)
+            .add_param(Cow::from("contractAddress"), &format!("0x{contract_address:x}"));
+
+        request.send_get::<Felt>().await
+    }
+
     async fn add_transaction<T>(&self, transaction: UserTransaction) -> Result<T, SequencerError>
     where
         T: DeserializeOwned,
@@ -155,10 +278,14 @@ mod tests {
     use starknet_types_core::felt::Felt;
     use std::fs::{remove_file, File};
     use std::io::{BufReader, BufWriter, Read, Write};
+    use std::num::NonZeroUsize;
     use std::ops::Drop;
     use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
 
     use super::*;
+    use crate::RecordFormat;
 
     const CLASS_BLOCK_0: &str = "0x010455c752b86932ce552f2b0fe81a880746649b9aee7e0d842bf3f52378f9f8";
 
@@ -547,4 +674,219 @@ mod tests {
             }))
         ))
     }
+
+    /// The recordings are replayed whichever format they were written in.
+    #[rstest]
+    #[case::json(RecordFormat::Json, "blocks/0.json")]
+    #[case::bincode(RecordFormat::Bincode, "blocks/0.bin")]
+    #[tokio::test]
+    async fn record_replay_round_trip(#[case] format: RecordFormat, #[case] recorded_path: &str) {
+        let state_update_and_block =
+            load_from_file_compressed::<serde_json::Value>("src/mocks/state_update_and_block_0.gz");
+        let reference = serde_json::from_value::<ProviderStateUpdateWithBlock>(state_update_and_block.clone()).unwrap();
+        let mock_server = httpmock::MockServer::start();
+        let mock = mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_state_update").query_param("blockNumber", "0");
+            then.status(200).json_body(state_update_and_block);
+        });
+        let url = url::Url::parse(&mock_server.base_url()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let recording = GatewayProvider::new(url.join("/gateway/").unwrap(), url.join("/feeder_gateway/").unwrap())
+            .with_record_dir(dir.path().to_owned())
+            .with_record_format(format);
+        recording.get_state_update_with_block(BlockId::Number(0)).await.unwrap();
+        mock.assert_hits(1);
+        assert!(dir.path().join(recorded_path).exists());
+
+        // Replaying never hits the network
+        let replaying = GatewayProvider::new(url.join("/gateway/").unwrap(), url.join("/feeder_gateway/").unwrap())
+            .with_replay_dir(dir.path().to_owned());
+        let ProviderStateUpdateWithBlockPendingMaybe::NonPending(replayed) =
+            replaying.get_state_update_with_block(BlockId::Number(0)).await.unwrap()
+        else {
+            panic!("Expected a non-pending state update")
+        };
+        assert_eq!(replayed, reference);
+        mock.assert_hits(1);
+
+        assert!(matches!(
+            replaying.get_state_update_with_block(BlockId::Number(1)).await,
+            Err(SequencerError::StarknetError(StarknetError { code: StarknetErrorCode::BlockNotFound, .. }))
+        ));
+    }
+
+    /// Recording a block with bincode takes less space than with JSON.
+    #[tokio::test]
+    async fn record_bincode_is_smaller_than_json() {
+        let state_update_and_block =
+            load_from_file_compressed::<serde_json::Value>("src/mocks/state_update_and_block_0.gz");
+        let mock_server = httpmock::MockServer::start();
+        mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_state_update").query_param("blockNumber", "0");
+            then.status(200).json_body(state_update_and_block);
+        });
+        let url = url::Url::parse(&mock_server.base_url()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        for format in [RecordFormat::Json, RecordFormat::Bincode] {
+            GatewayProvider::new(url.join("/gateway/").unwrap(), url.join("/feeder_gateway/").unwrap())
+                .with_record_dir(dir.path().to_owned())
+                .with_record_format(format)
+                .get_state_update_with_block(BlockId::Number(0))
+                .await
+                .unwrap();
+        }
+
+        let json_len = std::fs::metadata(dir.path().join("blocks/0.json")).unwrap().len();
+        let bincode_len = std::fs::metadata(dir.path().join("blocks/0.bin")).unwrap().len();
+        assert!(bincode_len < json_len, "bincode recording is {bincode_len} bytes, json recording is {json_len} bytes");
+    }
+
+    #[tokio::test]
+    async fn get_latest_block_number_header_only() {
+        let mock_server = httpmock::MockServer::start();
+        let mock = mock_server.mock(|when, then| {
+            when.method("GET")
+                .path_contains("get_block")
+                .query_param("blockNumber", "latest")
+                .query_param("headerOnly", "true");
+            then.status(200).json_body(serde_json::json!({ "block_hash": "0x1", "block_number": 42 }));
+        });
+        let url = url::Url::parse(&mock_server.base_url()).unwrap();
+        let provider = GatewayProvider::new(url.join("/gateway/").unwrap(), url.join("/feeder_gateway/").unwrap());
+
+        assert_eq!(provider.get_latest_block_number().await.unwrap(), 42);
+        mock.assert_hits(1);
+    }
+
+    #[rstest]
+    #[case::json(RecordFormat::Json, "signatures/0.json")]
+    #[case::bincode(RecordFormat::Bincode, "signatures/0.bin")]
+    #[tokio::test]
+    async fn record_replay_signature(#[case] format: RecordFormat, #[case] recorded_path: &str) {
+        let signature = load_from_file_compressed::<serde_json::Value>("src/mocks/signature_block_0.gz");
+        let reference = serde_json::from_value::<ProviderBlockSignature>(signature.clone()).unwrap();
+        let mock_server = httpmock::MockServer::start();
+        let mock = mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_signature").query_param("blockNumber", "0");
+            then.status(200).json_body(signature);
+        });
+        let url = url::Url::parse(&mock_server.base_url()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let recording = GatewayProvider::new(url.join("/gateway/").unwrap(), url.join("/feeder_gateway/").unwrap())
+            .with_record_dir(dir.path().to_owned())
+            .with_record_format(format);
+        recording.get_signature(BlockId::Number(0)).await.unwrap();
+        mock.assert_hits(1);
+        assert!(dir.path().join(recorded_path).exists());
+
+        // Replaying never hits the network
+        let replaying = GatewayProvider::new(url.join("/gateway/").unwrap(), url.join("/feeder_gateway/").unwrap())
+            .with_replay_dir(dir.path().to_owned());
+        assert_eq!(replaying.get_signature(BlockId::Number(0)).await.unwrap(), reference);
+        mock.assert_hits(1);
+
+        assert!(matches!(
+            replaying.get_signature(BlockId::Number(1)).await,
+            Err(SequencerError::StarknetError(StarknetError { code: StarknetErrorCode::BlockNotFound, .. }))
+        ));
+    }
+
+    /// Number of requests being answered by [`in_flight_server`], and the most it has seen at once.
+    #[derive(Default)]
+    struct InFlight {
+        current: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    impl InFlight {
+        fn enter(&self) {
+            let current = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max.fetch_max(current, Ordering::SeqCst);
+        }
+
+        fn exit(&self) {
+            self.current.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// HTTP server answering `class` to the class requests and `state_update` to the other ones, each after `delay`.
+    /// It counts the class requests and the other ones in flight separately.
+    fn in_flight_server(
+        class: serde_json::Value,
+        state_update: serde_json::Value,
+        delay: Duration,
+    ) -> (url::Url, Arc<InFlight>, Arc<InFlight>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = url::Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let (class, state_update) = (Arc::new(class.to_string()), Arc::new(state_update.to_string()));
+        let (classes_in_flight, others_in_flight) = (Arc::new(InFlight::default()), Arc::new(InFlight::default()));
+        let (classes_in_flight_, others_in_flight_) = (Arc::clone(&classes_in_flight), Arc::clone(&others_in_flight));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { return };
+                let (class, state_update) = (Arc::clone(&class), Arc::clone(&state_update));
+                let (classes_in_flight, others_in_flight) =
+                    (Arc::clone(&classes_in_flight_), Arc::clone(&others_in_flight_));
+                std::thread::spawn(move || {
+                    let mut buf = Vec::new();
+                    let mut chunk = [0u8; 1024];
+                    while let Ok(n) = stream.read(&mut chunk) {
+                        if n == 0 {
+                            return;
+                        }
+                        buf.extend_from_slice(&chunk[..n]);
+                        while let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+                            let request: Vec<u8> = buf.drain(..end + 4).collect();
+                            let is_class = String::from_utf8_lossy(&request).contains("get_class_by_hash");
+                            let (in_flight, body) = if is_class {
+                                (&classes_in_flight, &class)
+                            } else {
+                                (&others_in_flight, &state_update)
+                            };
+                            in_flight.enter();
+                            std::thread::sleep(delay);
+                            in_flight.exit();
+                            let response = format!(
+                                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+                                body.len()
+                            );
+                            if stream.write_all(response.as_bytes()).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        (url, classes_in_flight, others_in_flight)
+    }
+
+    #[tokio::test]
+    async fn max_concurrent_class_requests() {
+        let class =
+            load_from_file_compressed::<serde_json::Value>(&format!("src/mocks/class_block_0_{CLASS_BLOCK_0}.gz"));
+        let state_update_and_block =
+            load_from_file_compressed::<serde_json::Value>("src/mocks/state_update_and_block_0.gz");
+        let (url, classes_in_flight, others_in_flight) =
+            in_flight_server(class, state_update_and_block, Duration::from_millis(200));
+        let provider = GatewayProvider::new(url.join("/gateway/").unwrap(), url.join("/feeder_gateway/").unwrap())
+            .with_max_concurrent_class_requests(NonZeroUsize::new(2).unwrap());
+
+        // 4 class requests, 2 at a time
+        futures::future::try_join_all(
+            (0..4u64).map(|i| provider.get_class_by_hash(Felt::from(i), BlockId::Tag(BlockTag::Latest))),
+        )
+        .await
+        .unwrap();
+        assert_eq!(classes_in_flight.max.load(Ordering::SeqCst), 2);
+
+        // Block requests are not limited
+        futures::future::try_join_all((0..4u64).map(|i| provider.get_state_update_with_block(BlockId::Number(i))))
+            .await
+            .unwrap();
+        assert!(others_in_flight.max.load(Ordering::SeqCst) > 2);
+    }
 }
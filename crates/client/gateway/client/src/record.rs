@@ -0,0 +1,215 @@
+//! Recording of feeder gateway responses, so that a sync can be reproduced offline.
+//!
+//! Responses are stored in a [`RecordFormat`], keyed by block number for blocks, state updates, signatures and
+//! traces, and by class hash for classes:
+//!
+//! ```text
+//! <dir>/blocks/<block_n>.<json|bin>
+//! <dir>/classes/<class_hash>.<json|bin>
+//! <dir>/signatures/<block_n>.<json|bin>
+//! <dir>/traces/<block_n>.<json|bin>
+//! ```
+use bincode::Options;
+use mp_gateway::error::{SequencerError, StarknetError, StarknetErrorCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use starknet_types_core::felt::Felt;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Encoding of the recorded responses. The recordings are replayed whichever format they are in, so a directory can
+/// mix both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordFormat {
+    /// Human readable, in the same format as the responses of the feeder gateway.
+    #[default]
+    Json,
+    /// Compact binary encoding, better suited to large historical recordings.
+    Bincode,
+}
+
+impl RecordFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            RecordFormat::Json => "json",
+            RecordFormat::Bincode => "bin",
+        }
+    }
+
+    fn encode(self, value: &impl Serialize) -> std::io::Result<Vec<u8>> {
+        match self {
+            RecordFormat::Json => serde_json::to_vec(value).map_err(std::io::Error::from),
+            RecordFormat::Bincode => {
+                let value = BincodeValue::from(serde_json::to_value(value)?);
+                bincode_options().serialize(&value).map_err(std::io::Error::other)
+            }
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, SequencerError> {
+        let value = match self {
+            RecordFormat::Json => return serde_json::from_slice(bytes).map_err(deserialize_error),
+            RecordFormat::Bincode => bincode_options()
+                .deserialize::<BincodeValue>(bytes)
+                .map_err(|err| SequencerError::Replay(std::io::Error::new(ErrorKind::InvalidData, err)))?,
+        };
+        serde_json::from_value(value.into()).map_err(deserialize_error)
+    }
+}
+
+fn deserialize_error(serde_error: serde_json::Error) -> SequencerError {
+    SequencerError::DeserializeBody { serde_error }
+}
+
+/// Integers and lengths are varint encoded, unlike with [`bincode::serialize`].
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new().with_varint_encoding()
+}
+
+/// A JSON value, encoded with bincode. The responses of the feeder gateway cannot be encoded with bincode directly,
+/// as they use self-describing representations such as internally tagged transactions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum BincodeValue {
+    Null,
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    String(String),
+    /// A felt in its canonical `0x`-prefixed lowercase hex representation, which most of the strings of the
+    /// responses are, as its big-endian bytes without the leading zeros.
+    Felt(Vec<u8>),
+    Array(Vec<BincodeValue>),
+    Object(Vec<(String, BincodeValue)>),
+}
+
+impl From<serde_json::Value> for BincodeValue {
+    fn from(value: serde_json::Value) -> Self {
+        use serde_json::Value;
+        match value {
+            Value::Null => Self::Null,
+            Value::Bool(b) => Self::Bool(b),
+            Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+                (Some(n), _) => Self::U64(n),
+                (None, Some(n)) => Self::I64(n),
+                (None, None) => Self::F64(n.as_f64().unwrap_or_default()),
+            },
+            Value::String(s) => match Felt::from_hex(&s) {
+                Ok(felt) if s.starts_with("0x") && format!("{felt:#x}") == s => {
+                    let bytes = felt.to_bytes_be();
+                    let leading_zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+                    Self::Felt(bytes[leading_zeros..].to_vec())
+                }
+                _ => Self::String(s),
+            },
+            Value::Array(values) => Self::Array(values.into_iter().map(Into::into).collect()),
+            Value::Object(map) => Self::Object(map.into_iter().map(|(key, value)| (key, value.into())).collect()),
+        }
+    }
+}
+
+impl From<BincodeValue> for serde_json::Value {
+    fn from(value: BincodeValue) -> Self {
+        match value {
+            BincodeValue::Null => Self::Null,
+            BincodeValue::Bool(b) => Self::Bool(b),
+            BincodeValue::U64(n) => n.into(),
+            BincodeValue::I64(n) => n.into(),
+            BincodeValue::F64(n) => n.into(),
+            BincodeValue::String(s) => Self::String(s),
+            BincodeValue::Felt(bytes) => Self::String(format!("{:#x}", Felt::from_bytes_be_slice(&bytes))),
+            BincodeValue::Array(values) => Self::Array(values.into_iter().map(Into::into).collect()),
+            BincodeValue::Object(entries) => {
+                Self::Object(entries.into_iter().map(|(key, value)| (key, value.into())).collect())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RecordMode {
+    /// Responses from the feeder gateway are written to the record directory.
+    Record,
+    /// Responses are read from the record directory instead of the network.
+    Replay,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Recorder {
+    pub(crate) mode: RecordMode,
+    dir: PathBuf,
+    /// Format of the new recordings.
+    pub(crate) format: RecordFormat,
+}
+
+impl Recorder {
+    pub(crate) fn new(mode: RecordMode, dir: PathBuf, format: RecordFormat) -> Self {
+        Self { mode, dir, format }
+    }
+
+    pub(crate) fn is_replay(&self) -> bool {
+        self.mode == RecordMode::Replay
+    }
+
+    /// Paths are given without extension, which depends on the [`RecordFormat`].
+    pub(crate) fn block_path(&self, block_n: u64) -> PathBuf {
+        self.dir.join("blocks").join(block_n.to_string())
+    }
+
+    pub(crate) fn signature_path(&self, block_n: u64) -> PathBuf {
+        self.dir.join("signatures").join(block_n.to_string())
+    }
+
+    pub(crate) fn traces_path(&self, block_n: u64) -> PathBuf {
+        self.dir.join("traces").join(block_n.to_string())
+    }
+
+    pub(crate) fn class_path(&self, class_hash: Felt) -> PathBuf {
+        self.dir.join("classes").join(format!("{class_hash:#x}"))
+    }
+
+    /// Recording is best effort: failures are logged, and never fail the request.
+    pub(crate) async fn record(&self, path: &Path, value: &impl Serialize) {
+        if self.mode != RecordMode::Record {
+            return;
+        }
+        let path = path.with_extension(self.format.extension());
+        let res = async {
+            let bytes = self.format.encode(value)?;
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&path, bytes).await
+        }
+        .await;
+        if let Err(err) = res {
+            tracing::warn!("Failed to record feeder gateway response to {}: {err:#}", path.display());
+        }
+    }
+
+    /// A missing recording is reported as `not_found`, the same way the feeder gateway would answer. The recording
+    /// is looked up in the configured [`RecordFormat`] first, then in the other one.
+    pub(crate) async fn replay<T: DeserializeOwned>(
+        &self,
+        path: &Path,
+        not_found: StarknetError,
+    ) -> Result<T, SequencerError> {
+        let formats = match self.format {
+            RecordFormat::Json => [RecordFormat::Json, RecordFormat::Bincode],
+            RecordFormat::Bincode => [RecordFormat::Bincode, RecordFormat::Json],
+        };
+        for format in formats {
+            match tokio::fs::read(path.with_extension(format.extension())).await {
+                Ok(bytes) => return format.decode(&bytes),
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(SequencerError::Replay(err)),
+            }
+        }
+        Err(not_found.into())
+    }
+}
+
+pub(crate) fn class_not_found(class_hash: Felt) -> StarknetError {
+    StarknetError::new(StarknetErrorCode::UndeclaredClass, format!("Class with hash {class_hash:#x} was not recorded"))
+}
@@ -1,10 +1,12 @@
+use std::io::Read;
 use std::{borrow::Cow, collections::HashMap};
 
-use bytes::Buf;
+use bytes::{Buf, Bytes};
+use flate2::read::GzDecoder;
 use http::Method;
-use http_body_util::BodyExt;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
 use hyper::body::Incoming;
-use hyper::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use hyper::header::{HeaderName, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE};
 use hyper::{HeaderMap, Request, Response, StatusCode, Uri};
 use mp_block::{BlockId, BlockTag};
 use mp_gateway::error::{SequencerError, StarknetError};
@@ -15,6 +17,7 @@ use tower::Service;
 use url::Url;
 
 use super::builder::PausedClient;
+use super::health::FeederHealth;
 
 #[derive(Debug)]
 pub struct RequestBuilder<'a> {
@@ -22,11 +25,12 @@ pub struct RequestBuilder<'a> {
     url: Url,
     params: HashMap<Cow<'static, str>, String>,
     headers: HeaderMap,
+    health: Option<&'a FeederHealth>,
 }
 
 impl<'a> RequestBuilder<'a> {
     pub fn new(client: &'a PausedClient, base_url: Url, headers: HeaderMap) -> Self {
-        Self { client, url: base_url, params: HashMap::new(), headers }
+        Self { client, url: base_url, params: HashMap::new(), headers, health: None }
     }
 
     pub fn add_uri_segment(mut self, segment: &str) -> Result<Self, url::ParseError> {
@@ -64,6 +68,12 @@ impl<'a> RequestBuilder<'a> {
         self
     }
 
+    /// Records the outcome of the request in `health`.
+    pub fn with_health(mut self, health: &'a FeederHealth) -> Self {
+        self.health = Some(health);
+        self
+    }
+
     pub fn with_class_hash(mut self, class_hash: Felt) -> Self {
         self = self.add_param(Cow::from("classHash"), &format!("0x{class_hash:x}"));
         self
@@ -73,7 +83,16 @@ impl<'a> RequestBuilder<'a> {
     where
         T: DeserializeOwned,
     {
-        unpack(self.send_get_raw().await?).await
+        let max_response_bytes = self.client.max_response_bytes;
+        let health = self.health;
+        let res = match self.send_get_raw().await {
+            Ok(response) => unpack(response, max_response_bytes).await,
+            Err(err) => Err(err),
+        };
+        if let Some(health) = health {
+            health.on_result(&res);
+        }
+        res
     }
 
     pub async fn send_get_raw(self) -> Result<Response<Incoming>, SequencerError> {
@@ -82,11 +101,13 @@ impl<'a> RequestBuilder<'a> {
         let mut req_builder = Request::builder().method(Method::GET).uri(uri);
 
         req_builder.headers_mut().expect("Failed to get mutable reference to request headers").extend(self.headers);
+        if self.client.compression {
+            req_builder = req_builder.header(ACCEPT_ENCODING, "gzip");
+        }
 
         let req = req_builder.body(String::new())?;
 
-        let response: Response<Incoming> =
-            self.client.clone().call(req).await.map_err(SequencerError::HttpCallError)?;
+        let response: Response<Incoming> = self.client.clone().call(req).await.map_err(call_error)?;
         Ok(response)
     }
 
@@ -100,13 +121,22 @@ impl<'a> RequestBuilder<'a> {
         let mut req_builder = Request::builder().method(Method::POST).uri(uri);
 
         req_builder.headers_mut().expect("Failed to get mutable reference to request headers").extend(self.headers);
+        if self.client.compression {
+            req_builder = req_builder.header(ACCEPT_ENCODING, "gzip");
+        }
 
         let body = serde_json::to_string(&body).map_err(SequencerError::SerializeRequest)?;
 
         let req = req_builder.header(CONTENT_TYPE, "application/json").body(body)?;
 
-        let response = self.client.clone().call(req).await.map_err(SequencerError::HttpCallError)?;
-        unpack(response).await
+        let res = match self.client.clone().call(req).await.map_err(call_error) {
+            Ok(response) => unpack(response, self.client.max_response_bytes).await,
+            Err(err) => Err(err),
+        };
+        if let Some(health) = self.health {
+            health.on_result(&res);
+        }
+        res
     }
 
     fn build_uri(&self) -> Result<Uri, SequencerError> {
@@ -123,12 +153,46 @@ impl<'a> RequestBuilder<'a> {
     }
 }
 
-async fn unpack<T>(response: Response<Incoming>) -> Result<T, SequencerError>
+fn call_error(err: Box<dyn std::error::Error + Send + Sync>) -> SequencerError {
+    if err.is::<tower::timeout::error::Elapsed>() {
+        SequencerError::Timeout
+    } else {
+        SequencerError::HttpCallError(err)
+    }
+}
+
+async fn unpack<T>(response: Response<Incoming>, max_response_bytes: Option<usize>) -> Result<T, SequencerError>
 where
     T: ::serde::de::DeserializeOwned,
 {
     let http_status = response.status();
-    let whole_body = response.collect().await?.aggregate();
+    let gzipped =
+        response.headers().get(CONTENT_ENCODING).is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"gzip"));
+    let whole_body = match max_response_bytes {
+        Some(max_response_bytes) => {
+            let too_large = || SequencerError::ResponseTooLarge { max_response_bytes };
+            let content_length = response
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<usize>().ok());
+            if content_length.is_some_and(|content_length| content_length > max_response_bytes) {
+                return Err(too_large());
+            }
+
+            // The body may also be chunked, in which case the limit is checked while reading it
+            Limited::new(response.into_body(), max_response_bytes)
+                .collect()
+                .await
+                .map_err(
+                    |err| if err.is::<LengthLimitError>() { too_large() } else { SequencerError::HttpCallError(err) },
+                )?
+                .aggregate()
+        }
+        None => response.collect().await?.aggregate(),
+    };
+    let whole_body: Box<dyn Buf + Send> =
+        if gzipped { Box::new(gunzip(whole_body, max_response_bytes)?) } else { Box::new(whole_body) };
 
     if http_status == StatusCode::TOO_MANY_REQUESTS {
         return Err(SequencerError::StarknetError(StarknetError::rate_limited()));
@@ -144,3 +208,25 @@ where
 
     Ok(res)
 }
+
+/// Decompresses a gzip response body. The limit on the response size also applies to the decompressed body, so that a
+/// small compressed response cannot expand into an unbounded allocation.
+fn gunzip(body: impl Buf, max_response_bytes: Option<usize>) -> Result<Bytes, SequencerError> {
+    let mut decoder = GzDecoder::new(body.reader());
+    let mut decompressed = Vec::new();
+    match max_response_bytes {
+        Some(max_response_bytes) => {
+            decoder
+                .take(max_response_bytes as u64 + 1)
+                .read_to_end(&mut decompressed)
+                .map_err(|err| SequencerError::HttpCallError(err.into()))?;
+            if decompressed.len() > max_response_bytes {
+                return Err(SequencerError::ResponseTooLarge { max_response_bytes });
+            }
+        }
+        None => {
+            decoder.read_to_end(&mut decompressed).map_err(|err| SequencerError::HttpCallError(err.into()))?;
+        }
+    }
+    Ok(decompressed.into())
+}
@@ -41,11 +41,11 @@ impl GasPriceProvider {
     }
 
     pub fn set_strk_gas_price_sync_enabled(&self, enabled: bool) {
-        self.gas_price_sync_enabled.store(enabled, Ordering::Relaxed);
+        self.strk_gas_price_sync_enabled.store(enabled, Ordering::Relaxed);
     }
 
     pub fn set_strk_data_gas_price_sync_enabled(&self, enabled: bool) {
-        self.data_gas_price_sync_enabled.store(enabled, Ordering::Relaxed);
+        self.strk_data_gas_price_sync_enabled.store(enabled, Ordering::Relaxed);
     }
 
     pub fn update_last_update_timestamp(&self) {
@@ -115,3 +115,39 @@ impl L1DataProvider for GasPriceProvider {
         L1DataAvailabilityMode::Blob
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Disabling the sync of a STRK gas price only fixes that price, and leaves the ETH ones in sync.
+    #[test]
+    fn test_strk_gas_price_sync_disabled() {
+        let provider = GasPriceProvider::new();
+        provider.set_gas_prices(GasPrices {
+            eth_l1_gas_price: 1,
+            strk_l1_gas_price: 2,
+            eth_l1_data_gas_price: 3,
+            strk_l1_data_gas_price: 4,
+        });
+
+        provider.set_strk_gas_price_sync_enabled(false);
+        provider.set_strk_data_gas_price_sync_enabled(false);
+        provider.set_gas_prices(GasPrices {
+            eth_l1_gas_price: 10,
+            strk_l1_gas_price: 20,
+            eth_l1_data_gas_price: 30,
+            strk_l1_data_gas_price: 40,
+        });
+
+        assert_eq!(
+            provider.get_gas_prices(),
+            GasPrices {
+                eth_l1_gas_price: 10,
+                strk_l1_gas_price: 2,
+                eth_l1_data_gas_price: 30,
+                strk_l1_data_gas_price: 4
+            }
+        );
+    }
+}
@@ -14,6 +14,7 @@ pub mod versions;
 use jsonrpsee::RpcModule;
 use mc_db::db_block_id::DbBlockIdResolvable;
 use mc_db::MadaraBackend;
+use mc_gateway_client::FeederHealth;
 use mp_block::{BlockId, BlockTag, MadaraMaybePendingBlock, MadaraMaybePendingBlockInfo};
 use mp_chain_config::ChainConfig;
 use mp_convert::ToFelt;
@@ -48,6 +49,8 @@ pub struct Starknet {
     backend: Arc<MadaraBackend>,
     pub(crate) add_transaction_provider: Arc<dyn AddTransactionProvider>,
     storage_proof_config: StorageProofConfig,
+    /// Only set on full nodes, which sync from a feeder gateway.
+    feeder_health: Option<Arc<FeederHealth>>,
     pub ctx: ServiceContext,
 }
 
@@ -58,7 +61,13 @@ impl Starknet {
         storage_proof_config: StorageProofConfig,
         ctx: ServiceContext,
     ) -> Self {
-        Self { backend, add_transaction_provider, storage_proof_config, ctx }
+        Self { backend, add_transaction_provider, storage_proof_config, feeder_health: None, ctx }
+    }
+
+    /// Reports the health of the feeder gateway the node syncs from.
+    pub fn with_feeder_health(mut self, feeder_health: Arc<FeederHealth>) -> Self {
+        self.feeder_health = Some(feeder_health);
+        self
     }
 
     pub fn clone_backend(&self) -> Arc<MadaraBackend> {
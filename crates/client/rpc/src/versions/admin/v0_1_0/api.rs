@@ -1,5 +1,6 @@
 use jsonrpsee::core::RpcResult;
 use m_proc_macros::versioned_rpc;
+use mc_gateway_client::FeederHealthRecord;
 use mp_transactions::BroadcastedDeclareTransactionV0;
 use starknet_types_core::felt::Felt;
 use starknet_types_rpc::ClassAndTxnHash;
@@ -33,6 +34,15 @@ pub trait MadaraStatusRpcApi {
     #[method(name = "shutdown")]
     async fn shutdown(&self) -> RpcResult<u64>;
 
+    /// Health of the feeder gateway the node syncs from.
+    ///
+    /// # Returns
+    ///
+    /// * Consecutive failed requests, unix time of the last successful request, and whether the feeder gateway is
+    ///   up. `null` when the node does not sync from a feeder gateway.
+    #[method(name = "feederHealth")]
+    async fn feeder_health(&self) -> RpcResult<Option<FeederHealthRecord>>;
+
     /// Periodically sends a signal that the node is alive.
     ///
     /// # Sends
@@ -1,6 +1,7 @@
 use std::time::{Duration, SystemTime};
 
 use jsonrpsee::core::async_trait;
+use mc_gateway_client::FeederHealthRecord;
 
 use crate::{errors::ErrorExtWs, versions::admin::v0_1_0::MadaraStatusRpcApiV0_1_0Server, Starknet};
 
@@ -17,6 +18,10 @@ impl MadaraStatusRpcApiV0_1_0Server for Starknet {
         Ok(unix_now())
     }
 
+    async fn feeder_health(&self) -> jsonrpsee::core::RpcResult<Option<FeederHealthRecord>> {
+        Ok(self.feeder_health.as_ref().map(|feeder_health| feeder_health.record()))
+    }
+
     async fn pulse(
         &self,
         subscription_sink: jsonrpsee::PendingSubscriptionSink,
@@ -0,0 +1,288 @@
+//! Trusted checkpoint loaded with `--checkpoint`, to start a fresh database from a recent block instead of syncing
+//! the whole chain from genesis. The checkpoint is signed by a key the operator trusts, and the blocks up to it are
+//! never fetched nor verified.
+//!
+//! The checkpoint is recorded in the database it starts, and the block import then expects the block after it to be
+//! built on the checkpoint block hash.
+use crate::l2::next_block_to_sync;
+use anyhow::Context;
+use mc_db::block_db::CheckpointBlock;
+use mc_db::db_block_id::DbBlockId;
+use mc_db::MadaraBackend;
+use serde::{Deserialize, Serialize};
+use starknet_types_core::felt::Felt;
+use starknet_types_core::hash::{Poseidon, StarkHash};
+use std::path::{Path, PathBuf};
+
+/// Checkpoint to start the sync from.
+#[derive(Debug, Clone)]
+pub struct CheckpointConfig {
+    /// Path of the JSON [`Checkpoint`].
+    pub path: PathBuf,
+    /// Public key the checkpoint signature is checked against.
+    pub public_key: Felt,
+}
+
+/// A block trusted as the verified base of the chain, signed over [`Checkpoint::message_hash`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Checkpoint {
+    pub block_number: u64,
+    pub global_root: Felt,
+    pub block_hash: Felt,
+    /// `[r, s]` signature.
+    pub signature: Vec<Felt>,
+}
+
+/// Where the sync starts from when it is given a [`Checkpoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointStart {
+    pub starting_block: u64,
+    pub ignore_block_order: bool,
+    /// The database was started from the checkpoint, so its global tries do not have the state of the blocks before
+    /// it and the state roots of the following blocks cannot be verified.
+    pub trust_global_tries: bool,
+}
+
+impl CheckpointStart {
+    /// Where the sync starts from without a checkpoint: the database may still have been started from one before.
+    pub fn without_checkpoint(
+        backend: &MadaraBackend,
+        starting_block: u64,
+        ignore_block_order: bool,
+    ) -> anyhow::Result<Self> {
+        let trust_global_tries = backend.get_checkpoint_block().context("Getting the checkpoint block")?.is_some();
+        Ok(Self { starting_block, ignore_block_order, trust_global_tries })
+    }
+}
+
+impl Checkpoint {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("Opening checkpoint {}", path.display()))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("Parsing checkpoint {}", path.display()))
+    }
+
+    /// Hash of the `(block_number, global_root, block_hash)` tuple, which is what the signature signs.
+    pub fn message_hash(&self) -> Felt {
+        Poseidon::hash_array(&[Felt::from(self.block_number), self.global_root, self.block_hash])
+    }
+
+    pub fn block(&self) -> CheckpointBlock {
+        CheckpointBlock { block_number: self.block_number, block_hash: self.block_hash, global_root: self.global_root }
+    }
+
+    pub fn verify(&self, public_key: &Felt) -> anyhow::Result<()> {
+        if !mp_utils::crypto::verify_signature(public_key, &self.message_hash(), &self.signature) {
+            anyhow::bail!(
+                "Invalid signature for the checkpoint at block #{}, it is not signed by {public_key:#x}",
+                self.block_number
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns where the sync starts from with this checkpoint.
+    ///
+    /// A fresh database is started from the checkpoint, which is recorded in it. A database started from a
+    /// checkpoint must have been started from this one. A synced database which already has the checkpoint block
+    /// must agree with it, and a database which is behind the checkpoint cannot be started from it.
+    pub fn starting_block(&self, backend: &MadaraBackend) -> anyhow::Result<CheckpointStart> {
+        if let Some(started_from) = backend.get_checkpoint_block().context("Getting the checkpoint block")? {
+            if started_from != self.block() {
+                anyhow::bail!(
+                    "The database was started from the checkpoint at block #{} ({:#x}), not from the checkpoint at block #{} ({:#x})",
+                    started_from.block_number,
+                    started_from.block_hash,
+                    self.block_number,
+                    self.block_hash
+                );
+            }
+            return Ok(CheckpointStart {
+                starting_block: next_block_to_sync(backend)?,
+                ignore_block_order: false,
+                trust_global_tries: true,
+            });
+        }
+
+        let Some(latest_block_n) = backend.get_latest_block_n().context("Getting the latest block number")? else {
+            tracing::info!(
+                "📍 Starting from the checkpoint at block #{} ({:#x}) with state root {:#x}",
+                self.block_number,
+                self.block_hash,
+                self.global_root
+            );
+            backend.write_checkpoint_block(&self.block()).context("Recording the checkpoint in the database")?;
+            return Ok(CheckpointStart {
+                starting_block: self.block_number + 1,
+                ignore_block_order: false,
+                trust_global_tries: true,
+            });
+        };
+        if latest_block_n < self.block_number {
+            anyhow::bail!(
+                "The database is at block #{latest_block_n}, before the checkpoint at block #{}. A checkpoint can only start a fresh database",
+                self.block_number
+            );
+        }
+
+        let block_info = backend
+            .get_block_info(&DbBlockId::Number(self.block_number))
+            .context("Getting the checkpoint block")?
+            .and_then(|block_info| block_info.as_nonpending_owned());
+        let Some(block_info) = block_info else {
+            anyhow::bail!(
+                "The database does not have the checkpoint block #{} and was not started from a checkpoint",
+                self.block_number
+            );
+        };
+        if block_info.block_hash != self.block_hash || block_info.header.global_state_root != self.global_root {
+            anyhow::bail!(
+                "The checkpoint at block #{} does not match the database: checkpoint block hash {:#x} and state root {:#x}, stored block hash {:#x} and state root {:#x}",
+                self.block_number,
+                self.block_hash,
+                self.global_root,
+                block_info.block_hash,
+                block_info.header.global_state_root
+            );
+        }
+        Ok(CheckpointStart { starting_block: latest_block_n + 1, ignore_block_order: false, trust_global_tries: false })
+    }
+}
+
+/// Loads the checkpoint and checks its signature, then returns where the sync starts from.
+pub fn load_checkpoint(backend: &MadaraBackend, config: &CheckpointConfig) -> anyhow::Result<CheckpointStart> {
+    let checkpoint = Checkpoint::from_file(&config.path)?;
+    checkpoint.verify(&config.public_key)?;
+    checkpoint.starting_block(backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::utils::gateway::test_setup;
+    use mc_block_import::tests::block_import_utils::create_dummy_unverified_full_block;
+    use mc_block_import::{
+        BlockImportError, BlockImporter, BlockValidationContext, UnverifiedFullBlock, UnverifiedHeader,
+    };
+    use mp_utils::crypto::ZeroingPrivateKey;
+    use rstest::rstest;
+    use std::sync::Arc;
+
+    fn signed_checkpoint(
+        key: &ZeroingPrivateKey,
+        block_number: u64,
+        global_root: Felt,
+        block_hash: Felt,
+    ) -> Checkpoint {
+        let mut checkpoint = Checkpoint { block_number, global_root, block_hash, signature: vec![] };
+        let signature = key.sign(&checkpoint.message_hash()).unwrap();
+        checkpoint.signature = vec![signature.r, signature.s];
+        checkpoint
+    }
+
+    fn write_checkpoint(dir: &tempfile::TempDir, checkpoint: &Checkpoint) -> PathBuf {
+        let path = dir.path().join("checkpoint.json");
+        std::fs::write(&path, serde_json::to_string(checkpoint).unwrap()).unwrap();
+        path
+    }
+
+    /// A fresh database starts right after a valid checkpoint.
+    #[rstest]
+    #[tokio::test]
+    async fn test_load_checkpoint(test_setup: Arc<MadaraBackend>) {
+        let key = ZeroingPrivateKey::default();
+        let checkpoint = signed_checkpoint(&key, 1000, Felt::from(0x123), Felt::from(0x456));
+        let dir = tempfile::tempdir().unwrap();
+        let config = CheckpointConfig { path: write_checkpoint(&dir, &checkpoint), public_key: key.public };
+
+        assert_eq!(
+            load_checkpoint(&test_setup, &config).unwrap(),
+            CheckpointStart { starting_block: 1001, ignore_block_order: false, trust_global_tries: true }
+        );
+        assert_eq!(test_setup.get_checkpoint_block().unwrap(), Some(checkpoint.block()));
+
+        // On restart, the database is known to have been started from the checkpoint
+        assert_eq!(
+            load_checkpoint(&test_setup, &config).unwrap(),
+            CheckpointStart { starting_block: 1001, ignore_block_order: false, trust_global_tries: true }
+        );
+        assert_eq!(
+            CheckpointStart::without_checkpoint(&test_setup, 1001, false).unwrap(),
+            CheckpointStart { starting_block: 1001, ignore_block_order: false, trust_global_tries: true }
+        );
+        let other = signed_checkpoint(&key, 1000, Felt::from(0x123), Felt::from(0x789));
+        let config = CheckpointConfig { path: write_checkpoint(&dir, &other), public_key: key.public };
+        let err = load_checkpoint(&test_setup, &config).unwrap_err();
+        assert!(format!("{err:#}").contains("was started from the checkpoint"), "Unexpected error {err:#}");
+    }
+
+    /// The block after the checkpoint must be built on the checkpoint block hash, so that a checkpoint with a forged
+    /// block hash cannot start the sync on a chain it does not belong to.
+    #[rstest]
+    #[tokio::test]
+    async fn test_checkpoint_forged_block_hash(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        let key = ZeroingPrivateKey::default();
+        let forged = signed_checkpoint(&key, 10, Felt::from(0x123), Felt::from(0xbad));
+        let start = forged.starting_block(&backend).unwrap();
+        assert_eq!(start, CheckpointStart { starting_block: 11, ignore_block_order: false, trust_global_tries: true });
+
+        let block_import = BlockImporter::new(backend.clone(), None).unwrap();
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone())
+            .trust_global_tries(start.trust_global_tries);
+        let block = UnverifiedFullBlock {
+            unverified_block_number: Some(11),
+            header: UnverifiedHeader {
+                parent_block_hash: Some(Felt::from(0x456)),
+                ..create_dummy_unverified_full_block().header
+            },
+            ..create_dummy_unverified_full_block()
+        };
+        let block = block_import.pre_validate(block, validation.clone()).await.unwrap();
+        let err = block_import.verify_apply(block, validation).await.unwrap_err();
+        assert!(
+            matches!(&err, BlockImportError::ParentHash { expected, got } if *expected == Felt::from(0xbad) && *got == Felt::from(0x456)),
+            "Expected a parent hash mismatch, got {err:#}"
+        );
+        assert_eq!(backend.get_latest_block_n().unwrap(), None);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_load_checkpoint_bad_signature(test_setup: Arc<MadaraBackend>) {
+        let checkpoint = signed_checkpoint(&ZeroingPrivateKey::default(), 1000, Felt::from(0x123), Felt::from(0x456));
+        let dir = tempfile::tempdir().unwrap();
+        let config = CheckpointConfig {
+            path: write_checkpoint(&dir, &checkpoint),
+            public_key: ZeroingPrivateKey::default().public,
+        };
+
+        let err = load_checkpoint(&test_setup, &config).unwrap_err();
+        assert!(format!("{err:#}").contains("Invalid signature"), "Expected an invalid signature, got {err:#}");
+    }
+
+    /// A database which already has the checkpoint block keeps syncing from its tip, if it agrees with the checkpoint.
+    #[rstest]
+    #[tokio::test]
+    async fn test_checkpoint_starting_block_synced_database(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        let block_import = BlockImporter::new(backend.clone(), None).unwrap();
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let block = block_import.pre_validate(create_dummy_unverified_full_block(), validation.clone()).await.unwrap();
+        let imported = block_import.verify_apply(block, validation).await.unwrap();
+
+        let key = ZeroingPrivateKey::default();
+        let checkpoint = signed_checkpoint(&key, 0, imported.header.global_state_root, imported.block_hash);
+        assert_eq!(
+            checkpoint.starting_block(&backend).unwrap(),
+            CheckpointStart { starting_block: 1, ignore_block_order: false, trust_global_tries: false }
+        );
+
+        let other_chain = signed_checkpoint(&key, 0, imported.header.global_state_root, Felt::ONE);
+        assert!(other_chain.starting_block(&backend).is_err());
+        let ahead = signed_checkpoint(&key, 5, Felt::ONE, Felt::ONE);
+        assert!(ahead.starting_block(&backend).is_err());
+        assert_eq!(backend.get_checkpoint_block().unwrap(), None);
+    }
+}
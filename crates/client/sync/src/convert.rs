@@ -0,0 +1,214 @@
+//! Conversion of the blocks, state updates and classes returned by the feeder gateway into the types imported by
+//! [`mc_block_import`]. These are the conversions used by the sync, so that other tools such as explorers or test
+//! harnesses do not have to reimplement them.
+use crate::l2::L2SyncError;
+use mc_block_import::{
+    BlockImportError, DeclaredClass, UnverifiedCommitments, UnverifiedFullBlock, UnverifiedPendingFullBlock,
+};
+use mp_class::class_update::{ClassUpdate, LegacyClassUpdate, SierraClassUpdate};
+use mp_class::ContractClass;
+use mp_gateway::block::{ProviderBlock, ProviderBlockPending};
+use mp_gateway::state_update::{ProviderStateUpdate, ProviderStateUpdatePending};
+use mp_state_update::StateUpdate;
+use starknet_types_core::felt::Felt;
+use std::sync::Arc;
+
+/// Converts a closed block and its state update, along with the classes it declares, into a block ready to be
+/// imported. The block hash and global state root of the feeder gateway are kept as the commitments to verify.
+///
+/// # Example
+/// ```rust
+/// use mp_gateway::block::ProviderBlock;
+/// use mp_gateway::state_update::ProviderStateUpdate;
+/// use starknet_types_core::felt::Felt;
+///
+/// let block: ProviderBlock = serde_json::from_str(
+///     r#"{
+///         "block_hash": "0x42",
+///         "block_number": 1,
+///         "parent_block_hash": "0x41",
+///         "timestamp": 1725950824,
+///         "sequencer_address": "0x1",
+///         "state_root": "0x123",
+///         "transaction_commitment": "0x0",
+///         "event_commitment": "0x0",
+///         "status": "ACCEPTED_ON_L2",
+///         "l1_da_mode": "BLOB",
+///         "l1_gas_price": { "price_in_wei": "0x10", "price_in_fri": "0x20" },
+///         "l1_data_gas_price": { "price_in_wei": "0x1", "price_in_fri": "0x2" },
+///         "transactions": [],
+///         "transaction_receipts": [],
+///         "starknet_version": "0.13.2"
+///     }"#,
+/// )
+/// .unwrap();
+/// let state_update: ProviderStateUpdate = serde_json::from_str(
+///     r#"{
+///         "block_hash": "0x42",
+///         "new_root": "0x123",
+///         "old_root": "0x122",
+///         "state_diff": {
+///             "storage_diffs": { "0x100": [{ "key": "0x1", "value": "0x2" }] },
+///             "deployed_contracts": [],
+///             "old_declared_contracts": [],
+///             "declared_classes": [],
+///             "nonces": {},
+///             "replaced_classes": []
+///         }
+///     }"#,
+/// )
+/// .unwrap();
+///
+/// let block = mc_sync::convert::block(block, state_update, vec![]).unwrap();
+/// assert_eq!(block.unverified_block_number, Some(1));
+/// assert_eq!(block.header.parent_block_hash, Some(Felt::from_hex_unchecked("0x41")));
+/// assert_eq!(block.header.l1_gas_price.strk_l1_gas_price, 0x20);
+/// assert_eq!(block.commitments.block_hash, Some(Felt::from_hex_unchecked("0x42")));
+/// assert_eq!(block.commitments.global_state_root, Some(Felt::from_hex_unchecked("0x123")));
+/// assert_eq!(block.state_diff.storage_diffs[0].address, Felt::from_hex_unchecked("0x100"));
+/// ```
+pub fn block(
+    block: ProviderBlock,
+    state_update: ProviderStateUpdate,
+    class_update: Vec<ClassUpdate>,
+) -> anyhow::Result<UnverifiedFullBlock> {
+    // Verify against these commitments.
+    let commitments = UnverifiedCommitments {
+        // TODO: these commitments are wrong for mainnet from block 0 to unknown. We need to figure out
+        // which blocks and handle the case directly in the block import crate.
+        // transaction_commitment: Some(block.transaction_commitment.context("No transaction commitment")?),
+        // event_commitment: Some(block.event_commitment.context("No event commitment")?),
+        state_diff_commitment: None,
+        receipt_commitment: None,
+        global_state_root: Some(block.state_root),
+        block_hash: Some(block.block_hash),
+        ..Default::default()
+    };
+    Ok(UnverifiedFullBlock {
+        unverified_block_number: Some(block.block_number),
+        header: block.header()?,
+        state_diff: state_update.state_diff.into(),
+        receipts: block
+            .transaction_receipts
+            .into_iter()
+            .zip(&block.transactions)
+            .map(|(receipt, tx)| receipt.into_mp(tx))
+            .collect(),
+        transactions: block.transactions.into_iter().map(Into::into).collect(),
+        declared_classes: class_update.into_iter().map(Into::into).collect(),
+        commitments,
+        ..Default::default()
+    })
+}
+
+/// Same as [`block`], for the pending block. The pending block has no commitments, and its Starknet version must be
+/// set as it cannot be deduced from a block number.
+pub fn pending_block(
+    block: ProviderBlockPending,
+    state_update: ProviderStateUpdatePending,
+    class_update: Vec<ClassUpdate>,
+) -> anyhow::Result<UnverifiedPendingFullBlock> {
+    Ok(UnverifiedPendingFullBlock {
+        header: block.header()?,
+        state_diff: state_update.state_diff.into(),
+        receipts: block
+            .transaction_receipts
+            .into_iter()
+            .zip(&block.transactions)
+            .map(|(receipt, tx)| receipt.into_mp(tx))
+            .collect(),
+        transactions: block.transactions.into_iter().map(Into::into).collect(),
+        declared_classes: class_update.into_iter().map(Into::into).collect(),
+    })
+}
+
+/// Converts a state update of the feeder gateway, keeping its block hash and state roots.
+///
+/// # Example
+/// ```rust
+/// use mp_gateway::state_update::{ProviderStateUpdate, StateDiff};
+/// use starknet_types_core::felt::Felt;
+///
+/// let mut state_diff = StateDiff::default();
+/// state_diff.nonces.insert(Felt::from_hex_unchecked("0x100"), Felt::ONE);
+/// let state_update = ProviderStateUpdate {
+///     block_hash: Felt::from_hex_unchecked("0x42"),
+///     new_root: Felt::TWO,
+///     old_root: Felt::ONE,
+///     state_diff,
+/// };
+///
+/// let state_update = mc_sync::convert::state_update(state_update);
+/// assert_eq!(state_update.block_hash, Felt::from_hex_unchecked("0x42"));
+/// assert_eq!(state_update.state_diff.nonces[0].contract_address, Felt::from_hex_unchecked("0x100"));
+/// ```
+pub fn state_update(state_update: ProviderStateUpdate) -> StateUpdate {
+    state_update.into()
+}
+
+/// Converts a class downloaded from the feeder gateway into a class update. `compiled_class_hash` is the one declared
+/// in the state diff, and is only set for Sierra classes: a class which does not have the type declared in the state
+/// diff is rejected.
+pub fn class(
+    class_hash: Felt,
+    contract_class: ContractClass,
+    compiled_class_hash: Option<Felt>,
+) -> Result<ClassUpdate, L2SyncError> {
+    match (contract_class, compiled_class_hash) {
+        (ContractClass::Legacy(contract_class), None) => Ok(ClassUpdate::Legacy(LegacyClassUpdate {
+            class_hash,
+            contract_class: Arc::unwrap_or_clone(contract_class),
+        })),
+        (ContractClass::Sierra(contract_class), Some(compiled_class_hash)) => {
+            Ok(ClassUpdate::Sierra(SierraClassUpdate {
+                class_hash,
+                contract_class: Arc::unwrap_or_clone(contract_class),
+                compiled_class_hash,
+            }))
+        }
+        _ => Err(L2SyncError::UnexpectedClassType { class_hash }),
+    }
+}
+
+/// Compiles the Sierra classes of `declared_classes` to CASM, and attaches the result to them. The classes are
+/// compiled with [`FlattenedSierraClass::compile_to_casm`](mp_class::FlattenedSierraClass::compile_to_casm), as when
+/// verifying their compiled class hash on import, which then uses the attached CASM instead of compiling them again.
+pub fn compile_classes(declared_classes: &mut [DeclaredClass]) -> Result<(), L2SyncError> {
+    for declared_class in declared_classes {
+        let DeclaredClass::Sierra(sierra) = declared_class else { continue };
+        let (_, compiled_class) = sierra
+            .contract_class
+            .compile_to_casm()
+            .map_err(|error| BlockImportError::CompilationClassError { class_hash: sierra.class_hash, error })?;
+        sierra.compiled_class = Some(compiled_class);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::utils::gateway::{test_setup, TestContext};
+    use mc_db::MadaraBackend;
+    use mp_block::BlockId;
+    use rstest::rstest;
+
+    /// The CASM attached to a Sierra class is the one of its declared compiled class hash.
+    #[rstest]
+    #[tokio::test]
+    async fn test_compile_classes(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+        let class_hash = Felt::from_hex_unchecked("0x1234");
+        let contract_class = ctx.provider.get_class_by_hash(class_hash, BlockId::Number(0)).await.unwrap();
+        let ContractClass::Sierra(sierra) = &contract_class else { panic!("Expected a sierra class") };
+        let (compiled_class_hash, _) = sierra.compile_to_casm().unwrap();
+
+        let mut declared_classes = vec![class(class_hash, contract_class, Some(compiled_class_hash)).unwrap().into()];
+        compile_classes(&mut declared_classes).unwrap();
+
+        let [DeclaredClass::Sierra(sierra)] = declared_classes.as_slice() else { panic!("Expected a sierra class") };
+        let compiled_class = sierra.compiled_class.as_ref().expect("The CASM is attached");
+        assert_eq!(compiled_class.compiled_class_hash().unwrap(), sierra.compiled_class_hash);
+    }
+}
@@ -0,0 +1,75 @@
+//! Events emitted by the sync, so that embedders can follow its progress without scraping metrics or logs.
+use starknet_types_core::felt::Felt;
+use tokio::sync::broadcast;
+
+/// Number of events kept for receivers which are behind. Slower receivers skip the oldest events.
+pub const SYNC_EVENTS_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncEvent {
+    /// A block and its classes have been fetched from the feeder gateway, and are waiting to be imported.
+    BlockFetched { block_number: u64 },
+    /// A block has been verified and stored in the database.
+    BlockVerified { block_number: u64, block_hash: Felt, global_state_root: Felt },
+    /// The parent hash of a fetched block does not match the local tip: the chain of the feeder gateway has
+    /// diverged from the local one. `block_hash` is the hash of the new head, and `rollback_depth` the number of
+    /// local blocks it replaces, when its parent is one of them.
+    ReorgDetected {
+        block_number: Option<u64>,
+        block_hash: Option<Felt>,
+        local_tip_hash: Felt,
+        parent_block_hash: Felt,
+        rollback_depth: Option<u64>,
+    },
+    /// Fetching from the feeder gateway failed, which stops the sync.
+    FetchError { message: String },
+}
+
+/// Broadcasts the [`SyncEvent`]s to every subscriber. Clones share the same channel.
+#[derive(Debug, Clone)]
+pub struct SyncEvents {
+    sender: broadcast::Sender<SyncEvent>,
+}
+
+impl Default for SyncEvents {
+    fn default() -> Self {
+        Self::new(SYNC_EVENTS_CAPACITY)
+    }
+}
+
+impl SyncEvents {
+    pub fn new(capacity: usize) -> Self {
+        Self { sender: broadcast::channel(capacity).0 }
+    }
+
+    /// Subscribes to the events emitted from now on. A receiver lagging more than the channel capacity behind gets
+    /// a [`RecvError::Lagged`](broadcast::error::RecvError::Lagged) with the number of skipped events, and then
+    /// resumes from the oldest event still retained.
+    pub fn subscribe_sync_events(&self) -> broadcast::Receiver<SyncEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Never blocks the sync: the event is dropped when there is no subscriber.
+    pub(crate) fn emit(&self, event: SyncEvent) {
+        let _ = self.sender.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use broadcast::error::RecvError;
+
+    #[tokio::test]
+    async fn test_lagged_receiver_resumes() {
+        let events = SyncEvents::new(2);
+        let mut receiver = events.subscribe_sync_events();
+        for block_number in 0..3 {
+            events.emit(SyncEvent::BlockFetched { block_number });
+        }
+
+        assert_eq!(receiver.recv().await, Err(RecvError::Lagged(1)));
+        assert_eq!(receiver.recv().await, Ok(SyncEvent::BlockFetched { block_number: 1 }));
+        assert_eq!(receiver.recv().await, Ok(SyncEvent::BlockFetched { block_number: 2 }));
+    }
+}
@@ -1,43 +1,72 @@
 //! Contains the code required to fetch data from the network efficiently.
 use super::FetchError;
+use crate::convert;
 use crate::l2::L2SyncError;
+use crate::metrics::fetch_metrics::FetchMetrics;
+use crate::timing::BlockTiming;
 use anyhow::Context;
 use core::time::Duration;
-use futures::FutureExt;
-use mc_block_import::{UnverifiedCommitments, UnverifiedFullBlock, UnverifiedPendingFullBlock};
-use mc_gateway_client::GatewayProvider;
+use futures::{stream, StreamExt, TryStreamExt};
+use mc_block_import::{UnverifiedFullBlock, UnverifiedPendingFullBlock};
+use mc_gateway_client::{GatewayProvider, RecordFormat};
 use mp_block::{BlockId, BlockTag};
-use mp_class::class_update::{ClassUpdate, LegacyClassUpdate, SierraClassUpdate};
+use mp_class::class_update::ClassUpdate;
 use mp_class::{ContractClass, MISSED_CLASS_HASHES};
-use mp_gateway::block::{ProviderBlock, ProviderBlockPending};
+use mp_gateway::block::ProviderBlock;
 use mp_gateway::error::{SequencerError, StarknetError, StarknetErrorCode};
 use mp_gateway::state_update::ProviderStateUpdateWithBlockPendingMaybe::{self};
-use mp_gateway::state_update::{ProviderStateUpdate, ProviderStateUpdatePending, StateDiff};
+use mp_gateway::state_update::{ProviderStateUpdate, StateDiff};
+use mp_utils::crypto::verify_signature;
+use mp_utils::serde::{
+    serialize_duration, serialize_optional_duration, serialize_optional_url_origin, serialize_redacted,
+    serialize_url_origin,
+};
 use mp_utils::service::ServiceContext;
 use mp_utils::{stopwatch_end, wait_or_graceful_shutdown, PerfStopwatch};
+use rand::seq::IteratorRandom;
+use serde::Serialize;
 use starknet_api::core::ChainId;
 use starknet_types_core::felt::Felt;
-use std::sync::Arc;
+use std::collections::{BTreeSet, HashMap};
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Instant;
 use url::Url;
 
 const MAX_RETRY: u32 = 15;
 const BASE_DELAY: Duration = Duration::from_secs(1);
+/// Delay before retrying the classes of a block which could not be downloaded, see [`fetch_class_updates`].
+const DEFERRED_CLASS_DELAY: Duration = Duration::from_secs(1);
 
 /// The configuration of the worker responsible for fetching new blocks and state updates from the
-/// feeder.
-#[derive(Clone, Debug)]
+/// feeder. It serializes with its secrets redacted, so that it can be shown to the operator.
+#[derive(Clone, Debug, Serialize)]
 pub struct FetchConfig {
     /// The URL of the sequencer gateway.
+    #[serde(serialize_with = "serialize_url_origin")]
     pub gateway: Url,
     /// The URL of the feeder gateway.
+    #[serde(serialize_with = "serialize_url_origin")]
     pub feeder_gateway: Url,
+    /// Path prefix inserted before the path of `gateway`, when it is hosted under a prefix behind a reverse proxy.
+    pub gateway_path_prefix: Option<String>,
+    /// Same as `gateway_path_prefix`, for `feeder_gateway`.
+    pub feeder_gateway_path_prefix: Option<String>,
     /// The ID of the chain served by the sequencer gateway.
     pub chain_id: ChainId,
     /// Whether to check the root of the state update.
     pub verify: bool,
     /// The optional API_KEY to avoid rate limiting from the sequencer gateway.
+    #[serde(serialize_with = "serialize_redacted")]
     pub api_key: Option<String>,
+    /// The optional API key sent with every request, for gateways behind an authentication proxy.
+    #[serde(serialize_with = "serialize_redacted")]
+    pub feeder_api_key: Option<String>,
+    /// Name of the header in which the feeder API key is sent.
+    pub feeder_api_key_header: String,
     /// Polling interval.
+    #[serde(serialize_with = "serialize_optional_duration")]
     pub sync_polling_interval: Option<Duration>,
     /// Number of blocks to sync (for testing purposes).
     pub n_blocks_to_sync: Option<u64>,
@@ -45,16 +74,73 @@ pub struct FetchConfig {
     pub flush_every_n_blocks: u64,
     /// Number of seconds between db flushes
     pub flush_every_n_seconds: u64,
+    /// Wait this long after each imported block before importing the next one.
+    #[serde(serialize_with = "serialize_duration")]
+    pub block_commit_throttle: Duration,
     /// Stops the node once all blocks have been synced (for testing purposes)
     pub stop_on_sync: bool,
     /// Number of blocks to fetch in parallel during the sync process
     pub sync_parallelism: u8,
+    /// Maximum number of classes downloaded at once, across all the blocks being fetched.
+    pub class_parallelism: Option<NonZeroUsize>,
+    /// Fetch the blocks by windows of this many blocks, downloading the classes of a window at once.
+    pub class_prefetch_window: Option<NonZeroUsize>,
+    /// Number of fetched blocks which can wait to be verified and imported
+    pub fetch_buffer_size: usize,
     /// True if the node is called with `--warp-update-receiver`
     pub warp_update: bool,
     /// The port used for nodes to make rpc calls during a warp update.
     pub warp_update_port_rpc: u16,
     /// The port used for nodes to send blocks during a warp update.
     pub warp_update_port_fgw: u16,
+    /// Play a sound every time a new block is imported.
+    pub sound: bool,
+    /// URL to POST to every time a new block is imported.
+    #[serde(serialize_with = "serialize_optional_url_origin")]
+    pub block_webhook_url: Option<Url>,
+    /// Timeout of a single request to the feeder gateway.
+    #[serde(serialize_with = "serialize_duration")]
+    pub request_timeout: Duration,
+    /// Maximum number of idle connections kept open to the feeder gateway, unlimited when `None`.
+    pub pool_max_idle: Option<usize>,
+    /// Idle connections to the feeder gateway are closed after this long.
+    #[serde(serialize_with = "serialize_duration")]
+    pub keepalive: Duration,
+    /// Maximum number of requests per second made to the feeder gateway.
+    pub feeder_rps: Option<NonZeroU32>,
+    /// Feeder gateway responses larger than this are rejected instead of being buffered.
+    pub max_response_bytes: Option<NonZeroUsize>,
+    /// Ask the feeder gateway for gzip compressed responses.
+    pub feeder_compression: bool,
+    /// Trust the class hashes and global state roots from the feeder gateway instead of verifying them.
+    pub trust_feeder: bool,
+    /// Blocks for which a global state root mismatch is ignored.
+    pub skip_blocks: BTreeSet<u64>,
+    /// Write the fetched blocks, state updates and classes to this directory.
+    pub record_dir: Option<PathBuf>,
+    /// Read the blocks, state updates and classes recorded in this directory instead of fetching them.
+    pub replay_dir: Option<PathBuf>,
+    /// Format of the files written to `record_dir`.
+    pub record_format: RecordFormat,
+    /// Also fetch the execution traces of each block.
+    pub fetch_traces: bool,
+    /// Log the time spent in each sync phase for every block.
+    pub sync_timing: bool,
+    /// Log the catch-up progress every this many imported blocks.
+    pub progress_every_n_blocks: u64,
+    /// Log the catch-up progress at least this often while blocks are imported.
+    #[serde(serialize_with = "serialize_duration")]
+    pub progress_interval: Duration,
+    /// Fetch the signature of each block, and check it against the public key of the sequencer.
+    pub verify_signatures: bool,
+    /// Public key of the sequencer. The key of the Starknet sequencer is used by default on the Starknet networks.
+    pub sequencer_public_key: Option<Felt>,
+    /// Cross-check the storage and nonces of this many contracts of each block against the feeder gateway.
+    pub verify_sample_size: Option<NonZeroUsize>,
+    /// Stop the sync when the feeder gateway fails more than this many requests in a row.
+    pub max_consecutive_failures: Option<u32>,
+    /// Compile the Sierra classes to CASM as soon as they are fetched, instead of when the block is imported.
+    pub compile_classes: bool,
 }
 
 pub async fn fetch_pending_block_and_updates(
@@ -99,25 +185,186 @@ pub async fn fetch_pending_block_and_updates(
         );
         return Ok(None);
     }
-    let class_update = fetch_class_updates(chain_id, &state_update.state_diff, block_id.clone(), provider, ctx).await?;
+    let class_update =
+        fetch_class_updates(chain_id, &state_update.state_diff, block_id.clone(), provider, ctx, None).await?;
 
     stopwatch_end!(sw, "fetching {:?}: {:?}", block_id);
 
-    let converted = convert_sequencer_block_pending(block, state_update, class_update)
-        .context("Parsing the FGW pending block format")?;
+    let converted =
+        convert::pending_block(block, state_update, class_update).context("Parsing the FGW pending block format")?;
 
     Ok(Some(converted))
 }
 
+/// Fetches the execution traces of a block. Traces are optional: failures are logged, and never stop the sync.
+pub async fn fetch_block_traces(block_n: u64, provider: &GatewayProvider) -> Option<serde_json::Value> {
+    match provider.get_block_traces(BlockId::Number(block_n)).await {
+        Ok(traces) => Some(traces),
+        Err(err) => {
+            tracing::warn!("Failed to fetch the traces of block #{block_n}: {err:#}");
+            None
+        }
+    }
+}
+
+/// Returns the hash and number of the latest block of the chain, according to the feeder gateway.
+pub async fn fetch_highest_block_hash_and_number(
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+) -> Result<(Felt, u64), FetchError> {
+    let block = retry(|| provider.get_block(BlockId::Tag(BlockTag::Latest)), MAX_RETRY, BASE_DELAY, ctx).await?;
+    let block = block.non_pending().context("Block called on tag 'latest' should not be pending")?;
+    Ok((block.block_hash, block.block_number))
+}
+
+/// Fetches a block, its state update and the classes it declares, converted into the block import format. Nothing
+/// is sent to the rest of the sync, so this can be used on its own by tools and tests.
 pub async fn fetch_block_and_updates(
     chain_id: &ChainId,
     block_n: u64,
     provider: &GatewayProvider,
     ctx: &ServiceContext,
 ) -> Result<UnverifiedFullBlock, FetchError> {
-    let block_id = BlockId::Number(block_n);
+    Ok(fetch_block_and_updates_by_id(chain_id, BlockId::Number(block_n), provider, ctx, None).await?.0)
+}
 
-    let sw = PerfStopwatch::new();
+/// Same as [`fetch_block_and_updates`], also returning the time spent fetching the block and its classes, and
+/// recording its class lookups in `metrics`.
+pub async fn fetch_block_and_updates_timed(
+    chain_id: &ChainId,
+    block_n: u64,
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+    metrics: &FetchMetrics,
+) -> Result<(UnverifiedFullBlock, BlockTiming), FetchError> {
+    fetch_block_and_updates_by_id(chain_id, BlockId::Number(block_n), provider, ctx, Some(metrics)).await
+}
+
+/// Same as [`fetch_block_and_updates`], for the block with hash `block_hash`. This is used to re-fetch a specific
+/// block, such as an ancestor of the local tip after a reorg, when its block number may refer to another block.
+pub async fn fetch_block_and_updates_by_hash(
+    chain_id: &ChainId,
+    block_hash: Felt,
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+) -> Result<UnverifiedFullBlock, FetchError> {
+    Ok(fetch_block_and_updates_by_id(chain_id, BlockId::Hash(block_hash), provider, ctx, None).await?.0)
+}
+
+/// Fetches the state update of the block with hash `block_hash`, without its block nor its classes.
+pub async fn fetch_state_update_by_hash(
+    block_hash: Felt,
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+) -> Result<ProviderStateUpdate, FetchError> {
+    let state_update =
+        retry(|| provider.get_state_update(BlockId::Hash(block_hash)), MAX_RETRY, BASE_DELAY, ctx).await?;
+    Ok(state_update.non_pending_ownded().context("State update called on block hash should not be pending")?)
+}
+
+/// Fetches the state update of block `block_n`, converted into the database format. The state update is neither
+/// verified nor stored, and the classes it declares are not downloaded: this is meant for tools comparing state
+/// updates.
+pub async fn fetch_and_convert_state_update(
+    provider: &GatewayProvider,
+    block_n: u64,
+    ctx: &ServiceContext,
+) -> Result<mp_state_update::StateUpdate, FetchError> {
+    let (_, state_update) = fetch_state_update_with_block(BlockId::Number(block_n), provider, ctx).await?;
+    Ok(convert::state_update(state_update))
+}
+
+/// Fetches the blocks of `blocks` with their state updates, and then all the classes they declare at once. A class
+/// declared by several blocks of the window is only downloaded once, with the first block declaring it.
+///
+/// This returns the result of each block, in order.
+pub async fn fetch_block_window_and_updates(
+    chain_id: &ChainId,
+    blocks: Range<u64>,
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+    metrics: &FetchMetrics,
+) -> Vec<Result<(UnverifiedFullBlock, BlockTiming), FetchError>> {
+    let fetched = futures::future::join_all(blocks.map(|block_n| async move {
+        let start = Instant::now();
+        let res = fetch_state_update_with_block(BlockId::Number(block_n), provider, ctx).await;
+        (res, start.elapsed())
+    }))
+    .await;
+
+    let declared_classes: Vec<_> = fetched
+        .iter()
+        .map(|(res, _)| {
+            res.as_ref().ok().map(|(block, state_update)| {
+                DeclaredClasses::new(chain_id, &state_update.state_diff, &BlockId::Number(block.block_number))
+            })
+        })
+        .collect();
+
+    // Number of blocks of the window declaring each class, so that the last one can take the class instead of
+    // cloning it.
+    let mut class_uses: HashMap<Felt, usize> = HashMap::new();
+    let mut classes = vec![];
+    for ((block, _), declared_classes) in fetched
+        .iter()
+        .zip(&declared_classes)
+        .filter_map(|((res, _), declared)| Some((res.as_ref().ok()?, declared.as_ref()?)))
+    {
+        for class_hash in declared_classes.class_hashes() {
+            let uses = class_uses.entry(class_hash).or_default();
+            if *uses == 0 {
+                classes.push((class_hash, BlockId::Number(block.block_number)));
+            }
+            *uses += 1;
+        }
+    }
+
+    let start = Instant::now();
+    let mut contract_classes: HashMap<_, _> =
+        classes.iter().map(|(class_hash, _)| *class_hash).zip(fetch_classes(&classes, provider, ctx).await).collect();
+    let fetch_classes = start.elapsed();
+
+    let declarations: usize = class_uses.values().sum();
+    let downloaded = contract_classes.values().filter(|res| res.is_ok()).count();
+    metrics.record_class_lookups((declarations - classes.len()) as u64, classes.len() as u64, downloaded as u64);
+
+    fetched
+        .into_iter()
+        .zip(declared_classes)
+        .map(|((res, fetch_block), declared_classes)| {
+            let (block, state_update) = res?;
+            let declared_classes = declared_classes.expect("Classes are listed for every fetched block");
+            // The error of a class is only reported once, for the first block declaring it. The sync stops there.
+            if let Some(class_hash) = declared_classes.class_hashes().find(|hash| !contract_classes.contains_key(hash))
+            {
+                return Err(anyhow::anyhow!("Class {class_hash:#x} could not be fetched for a previous block").into());
+            }
+            let block_contract_classes = declared_classes
+                .class_hashes()
+                .map(|class_hash| {
+                    let uses = class_uses.get_mut(&class_hash).expect("Class uses are counted for every class");
+                    *uses -= 1;
+                    match contract_classes.get(&class_hash) {
+                        Some(Ok(contract_class)) if *uses > 0 => Ok(contract_class.clone()),
+                        _ => contract_classes.remove(&class_hash).expect("Checked above").map_err(Into::into),
+                    }
+                })
+                .collect();
+            let class_update =
+                declared_classes.into_class_updates(block_contract_classes).map_err(anyhow::Error::from)?;
+
+            let converted =
+                convert::block(block, state_update, class_update).context("Parsing the FGW full block format")?;
+            Ok((converted, BlockTiming { fetch_block, fetch_classes, ..Default::default() }))
+        })
+        .collect()
+}
+
+async fn fetch_state_update_with_block(
+    block_id: BlockId,
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+) -> Result<(ProviderBlock, ProviderStateUpdate), FetchError> {
     let (state_update, block) = retry(
         || async {
             provider
@@ -130,19 +377,61 @@ pub async fn fetch_block_and_updates(
         ctx,
     )
     .await?;
-    let class_update = fetch_class_updates(chain_id, state_update.state_diff(), block_id, provider, ctx).await?;
+    let block = block.non_pending_owned().expect("Block called on block number or hash should not be pending");
+    let state_update =
+        state_update.non_pending_ownded().expect("State update called on block number or hash should not be pending");
+    // The block hash is verified when importing the block, but the state update is only tied to it by its hash.
+    if state_update.block_hash != block.block_hash {
+        return Err(FetchError::BlockHashMismatch {
+            block_number: block.block_number,
+            block_hash: block.block_hash,
+            state_update_block_hash: state_update.block_hash,
+        });
+    }
+    Ok((block, state_update))
+}
 
-    stopwatch_end!(sw, "fetching {:?}: {:?}", block_n);
+async fn fetch_block_and_updates_by_id(
+    chain_id: &ChainId,
+    block_id: BlockId,
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+    metrics: Option<&FetchMetrics>,
+) -> Result<(UnverifiedFullBlock, BlockTiming), FetchError> {
+    let sw = PerfStopwatch::new();
+    let (block, state_update) = fetch_state_update_with_block(block_id.clone(), provider, ctx).await?;
+    let fetch_block = sw.elapsed();
+
+    // Classes are always fetched by block number, as some mainnet classes are looked up by block number.
+    let class_update = fetch_class_updates(
+        chain_id,
+        &state_update.state_diff,
+        BlockId::Number(block.block_number),
+        provider,
+        ctx,
+        metrics,
+    )
+    .await?;
+    let fetch_classes = sw.elapsed() - fetch_block;
 
-    let converted = convert_sequencer_block_non_pending(
-        block.non_pending_owned().expect("Block called on block number should not be pending"),
-        state_update.non_pending_ownded().expect("State update called on block number should not be pending"),
-        class_update,
+    stopwatch_end!(sw, "fetching {:?}: {:?}", block_id);
+
+    let converted = convert::block(block, state_update, class_update).context("Parsing the FGW full block format")?;
+    Ok((converted, BlockTiming { fetch_block, fetch_classes, ..Default::default() }))
+}
+
+/// Whether a failed request may succeed when sent again. Network errors, timeouts and rate limiting are retryable,
+/// but the feeder gateway will keep answering the same to a request for an undeclared class, and with the same
+/// response past `--max-response-bytes`.
+fn is_retryable(err: &SequencerError) -> bool {
+    !matches!(
+        err,
+        SequencerError::StarknetError(StarknetError { code: StarknetErrorCode::UndeclaredClass, .. })
+            | SequencerError::ResponseTooLarge { .. }
     )
-    .context("Parsing the FGW full block format")?;
-    Ok(converted)
 }
 
+/// Retries `f` with an exponential backoff, unless the error is not [retryable](is_retryable).
 async fn retry<F, Fut, T>(
     mut f: F,
     max_retries: u32,
@@ -160,6 +449,7 @@ where
             Err(SequencerError::StarknetError(StarknetError { code: StarknetErrorCode::BlockNotFound, .. })) => {
                 break Err(SequencerError::StarknetError(StarknetError::block_not_found()));
             }
+            Err(err) if !is_retryable(&err) => break Err(err),
             Err(err) => {
                 let delay = base_delay * 2_u32.pow(attempt).min(6); // Cap to prevent overly long delays
                 attempt += 1;
@@ -181,6 +471,194 @@ where
     }
 }
 
+/// Fetches the signature of a block from the feeder gateway, and checks that its block hash has been signed with
+/// `public_key`, the key of the sequencer. The block hash itself is checked against the block content when the
+/// block is imported.
+pub async fn verify_block_signature(
+    block: &UnverifiedFullBlock,
+    public_key: Felt,
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+) -> Result<(), FetchError> {
+    let block_number = block.unverified_block_number.context("Verifying the signature of a block without number")?;
+    let block_hash = block.commitments.block_hash.context("Verifying the signature of a block without hash")?;
+    let signature = retry(|| provider.get_signature(BlockId::Number(block_number)), MAX_RETRY, BASE_DELAY, ctx).await?;
+
+    if signature.block_hash != block_hash || !verify_signature(&public_key, &block_hash, &signature.signature) {
+        return Err(FetchError::InvalidSignature { block_number, block_hash });
+    }
+    Ok(())
+}
+
+/// Storage entries checked per sampled contract by [`verify_state_diff_sample`], picked at random when the block sets
+/// more of them.
+const SAMPLED_STORAGE_ENTRIES_PER_CONTRACT: usize = 16;
+/// Requests to the feeder gateway in flight at once in [`verify_state_diff_sample`].
+const MAX_CONCURRENT_SAMPLE_REQUESTS: usize = 8;
+
+/// A value set by the state diff of a block for a sampled contract.
+enum SampledValue {
+    Storage { key: Felt, value: Felt },
+    Nonce(Felt),
+}
+
+/// Cross-checks the state diff of a block against the feeder gateway, for `sample_size` contracts picked at random
+/// among the contracts with a storage or nonce update. Up to [`SAMPLED_STORAGE_ENTRIES_PER_CONTRACT`] storage values
+/// and the nonce set by the block for these contracts must be the ones returned by `get_storage_at` and `get_nonce`
+/// after the block. The values are requested concurrently.
+///
+/// This is a probabilistic check of the state diff, much cheaper than recomputing the global state root.
+pub async fn verify_state_diff_sample(
+    block: &UnverifiedFullBlock,
+    sample_size: NonZeroUsize,
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+) -> Result<(), FetchError> {
+    let block_number = block.unverified_block_number.context("Verifying the state diff of a block without number")?;
+    let state_diff = &block.state_diff;
+    let contracts: BTreeSet<Felt> = state_diff
+        .storage_diffs
+        .iter()
+        .map(|diff| diff.address)
+        .chain(state_diff.nonces.iter().map(|nonce| nonce.contract_address))
+        .collect();
+    // The thread rng cannot be held across an await point
+    let values = {
+        let mut rng = rand::thread_rng();
+        let sampled = contracts.into_iter().choose_multiple(&mut rng, sample_size.get());
+        sampled_values(state_diff, sampled, &mut rng)
+    };
+
+    stream::iter(values)
+        .map(|(contract_address, value)| verify_sampled_value(block_number, contract_address, value, provider, ctx))
+        .buffer_unordered(MAX_CONCURRENT_SAMPLE_REQUESTS)
+        .try_collect()
+        .await
+}
+
+/// Picks up to [`SAMPLED_STORAGE_ENTRIES_PER_CONTRACT`] storage values, and the nonce, set by the state diff for each
+/// of the `sampled` contracts.
+fn sampled_values(
+    state_diff: &mp_state_update::StateDiff,
+    sampled: Vec<Felt>,
+    rng: &mut impl rand::Rng,
+) -> Vec<(Felt, SampledValue)> {
+    let mut values = vec![];
+    for contract_address in sampled {
+        let storage_entries = state_diff
+            .storage_diffs
+            .iter()
+            .filter(|diff| diff.address == contract_address)
+            .flat_map(|diff| &diff.storage_entries)
+            .choose_multiple(rng, SAMPLED_STORAGE_ENTRIES_PER_CONTRACT);
+        values.extend(
+            storage_entries
+                .into_iter()
+                .map(|entry| (contract_address, SampledValue::Storage { key: entry.key, value: entry.value })),
+        );
+        if let Some(nonce) = state_diff.nonces.iter().find(|nonce| nonce.contract_address == contract_address) {
+            values.push((contract_address, SampledValue::Nonce(nonce.nonce)));
+        }
+    }
+    values
+}
+
+async fn verify_sampled_value(
+    block_number: u64,
+    contract_address: Felt,
+    value: SampledValue,
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+) -> Result<(), FetchError> {
+    match value {
+        SampledValue::Storage { key, value } => {
+            let feeder_value = retry(
+                || provider.get_storage_at(contract_address, key, BlockId::Number(block_number)),
+                MAX_RETRY,
+                BASE_DELAY,
+                ctx,
+            )
+            .await?;
+            if feeder_value != value {
+                return Err(FetchError::StorageMismatch {
+                    block_number,
+                    contract_address,
+                    key,
+                    state_diff_value: value,
+                    feeder_value,
+                });
+            }
+        }
+        SampledValue::Nonce(nonce) => {
+            let feeder_nonce = retry(
+                || provider.get_nonce(contract_address, BlockId::Number(block_number)),
+                MAX_RETRY,
+                BASE_DELAY,
+                ctx,
+            )
+            .await?;
+            if feeder_nonce != nonce {
+                return Err(FetchError::NonceMismatch {
+                    block_number,
+                    contract_address,
+                    state_diff_nonce: nonce,
+                    feeder_nonce,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Classes declared by a block, in the order they are fetched: legacy classes first, and then sierra classes with
+/// their compiled class hash.
+struct DeclaredClasses {
+    legacy: Vec<Felt>,
+    sierra: Vec<(Felt, Felt)>,
+}
+
+impl DeclaredClasses {
+    fn new(chain_id: &ChainId, state_diff: &StateDiff, block_id: &BlockId) -> Self {
+        // for blocks before 2597 on mainnet new classes are not declared in the state update
+        // https://github.com/madara-alliance/madara/issues/233
+        let legacy = match (chain_id, block_id) {
+            (ChainId::Mainnet, &BlockId::Number(block_n)) if block_n < 2597 => {
+                MISSED_CLASS_HASHES.get(&block_n).cloned().unwrap_or_default()
+            }
+            _ => state_diff.old_declared_contracts.clone(),
+        };
+        let sierra = state_diff
+            .declared_classes
+            .iter()
+            .map(|declared_class| (declared_class.class_hash, declared_class.compiled_class_hash))
+            .collect();
+        Self { legacy, sierra }
+    }
+
+    fn class_hashes(&self) -> impl Iterator<Item = Felt> + '_ {
+        self.legacy.iter().copied().chain(self.sierra.iter().map(|(class_hash, _)| *class_hash))
+    }
+
+    /// `contract_classes` are the classes downloaded for [`Self::class_hashes`], in the same order.
+    fn into_class_updates(
+        self,
+        mut contract_classes: Vec<Result<(Felt, ContractClass), L2SyncError>>,
+    ) -> Result<Vec<ClassUpdate>, L2SyncError> {
+        let sierra_contract_classes = contract_classes.split_off(self.legacy.len());
+        let legacy_class_updates = contract_classes.into_iter().map(|res| {
+            let (class_hash, contract_class) = res?;
+            convert::class(class_hash, contract_class, None)
+        });
+        let sierra_class_updates =
+            sierra_contract_classes.into_iter().zip(self.sierra).map(|(res, (_, compiled_class_hash))| {
+                let (class_hash, contract_class) = res?;
+                convert::class(class_hash, contract_class, Some(compiled_class_hash))
+            });
+
+        legacy_class_updates.chain(sierra_class_updates).collect()
+    }
+}
+
 /// retrieves class updates from Starknet sequencer
 async fn fetch_class_updates(
     chain_id: &ChainId,
@@ -188,65 +666,63 @@ async fn fetch_class_updates(
     block_id: BlockId,
     provider: &GatewayProvider,
     ctx: &ServiceContext,
+    metrics: Option<&FetchMetrics>,
 ) -> anyhow::Result<Vec<ClassUpdate>> {
-    // for blocks before 2597 on mainnet new classes are not declared in the state update
-    // https://github.com/madara-alliance/madara/issues/233
-    let legacy_classes: Vec<_> = match (chain_id, &block_id) {
-        (ChainId::Mainnet, &BlockId::Number(block_n)) if block_n < 2597 => {
-            MISSED_CLASS_HASHES.get(&block_n).cloned().unwrap_or_default()
-        }
-        _ => state_diff.old_declared_contracts.clone(),
-    };
+    let declared_classes = DeclaredClasses::new(chain_id, state_diff, &block_id);
+    let classes: Vec<_> = declared_classes.class_hashes().map(|class_hash| (class_hash, block_id.clone())).collect();
+    let contract_classes = fetch_classes(&classes, provider, ctx).await;
+    if let Some(metrics) = metrics {
+        // Classes are not shared between blocks here: every declaration is downloaded.
+        let downloaded = contract_classes.iter().filter(|res| res.is_ok()).count();
+        metrics.record_class_lookups(0, classes.len() as u64, downloaded as u64);
+    }
 
-    let sierra_classes: Vec<_> = state_diff
-        .declared_classes
-        .iter()
-        .map(|declared_class| (declared_class.class_hash, &declared_class.compiled_class_hash))
-        .collect();
+    Ok(declared_classes
+        .into_class_updates(contract_classes.into_iter().map(|res| res.map_err(Into::into)).collect())?)
+}
 
-    let legacy_class_futures = legacy_classes.into_iter().map(|class_hash| {
+/// Downloads each class with the block it is declared in.
+///
+/// Classes which could not be downloaded are retried once all the other classes are done, as the failure may clear
+/// in the meantime, for example when a feeder gateway node is lagging behind.
+async fn fetch_classes(
+    classes: &[(Felt, BlockId)],
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+) -> Vec<Result<(Felt, ContractClass), SequencerError>> {
+    let fetch = |class_hash, block_id: &BlockId| {
         let block_id = block_id.clone();
-        async move {
-            let (class_hash, contract_class) =
-                retry(|| fetch_class(class_hash, block_id.clone(), provider), MAX_RETRY, BASE_DELAY, ctx).await?;
-
-            let ContractClass::Legacy(contract_class) = contract_class else {
-                return Err(L2SyncError::UnexpectedClassType { class_hash });
-            };
-            let contract_class = Arc::try_unwrap(contract_class)
-                .expect("Contract class should only have one referenced when it is fetched");
+        async move { retry(|| fetch_class(class_hash, block_id.clone(), provider), MAX_RETRY, BASE_DELAY, ctx).await }
+    };
 
-            Ok::<_, L2SyncError>(ClassUpdate::Legacy(LegacyClassUpdate { class_hash, contract_class }))
+    let mut contract_classes =
+        futures::future::join_all(classes.iter().map(|(class_hash, block_id)| fetch(*class_hash, block_id))).await;
+    let deferred: Vec<_> =
+        contract_classes.iter().enumerate().filter(|(_, res)| res.is_err()).map(|(index, _)| index).collect();
+    if !deferred.is_empty() {
+        for &index in &deferred {
+            if let Err(err) = &contract_classes[index] {
+                tracing::debug!("Failed to fetch class {:#x}, retrying it later: {err:#}", classes[index].0);
+            }
         }
-        .boxed()
-    });
-
-    let sierra_class_futures = sierra_classes.into_iter().map(|(class_hash, &compiled_class_hash)| {
-        let block_id = block_id.clone();
-        async move {
-            let (class_hash, contract_class) =
-                retry(|| fetch_class(class_hash, block_id.clone(), provider), MAX_RETRY, BASE_DELAY, ctx).await?;
-
-            let ContractClass::Sierra(contract_class) = contract_class else {
-                return Err(L2SyncError::UnexpectedClassType { class_hash });
-            };
-            let contract_class = Arc::try_unwrap(contract_class)
-                .expect("Contract class should only have one referenced when it is fetchd");
-
-            Ok::<_, L2SyncError>(ClassUpdate::Sierra(SierraClassUpdate {
-                class_hash,
-                contract_class,
-                compiled_class_hash,
+        if wait_or_graceful_shutdown(tokio::time::sleep(DEFERRED_CLASS_DELAY), ctx).await.is_some() {
+            let retried = futures::future::join_all(deferred.iter().map(|&index| {
+                let (class_hash, block_id) = &classes[index];
+                fetch(*class_hash, block_id)
             }))
+            .await;
+            for (index, contract_class) in deferred.into_iter().zip(retried) {
+                contract_classes[index] = contract_class;
+            }
         }
-        .boxed()
-    });
-
-    Ok(futures::future::try_join_all(legacy_class_futures.chain(sierra_class_futures)).await?)
+    }
+    contract_classes
 }
 
 /// Downloads a class definition from the Starknet sequencer. Note that because
 /// of the current type hell we decided to deal with raw JSON data instead of starknet-providers `DeployedContract`.
+/// The returned class is not checked against `class_hash` here: the class hash is recomputed from the class
+/// definition when the block is pre-validated, see [`mc_block_import::BlockImporter::pre_validate`].
 async fn fetch_class(
     class_hash: Felt,
     block_id: BlockId,
@@ -257,59 +733,6 @@ async fn fetch_class(
     Ok((class_hash, contract_class))
 }
 
-fn convert_sequencer_block_pending(
-    block: ProviderBlockPending,
-    state_update: ProviderStateUpdatePending,
-    class_update: Vec<ClassUpdate>,
-) -> anyhow::Result<UnverifiedPendingFullBlock> {
-    Ok(UnverifiedPendingFullBlock {
-        header: block.header()?,
-        state_diff: state_update.state_diff.into(),
-        receipts: block
-            .transaction_receipts
-            .into_iter()
-            .zip(&block.transactions)
-            .map(|(receipt, tx)| receipt.into_mp(tx))
-            .collect(),
-        transactions: block.transactions.into_iter().map(Into::into).collect(),
-        declared_classes: class_update.into_iter().map(Into::into).collect(),
-    })
-}
-
-fn convert_sequencer_block_non_pending(
-    block: ProviderBlock,
-    state_update: ProviderStateUpdate,
-    class_update: Vec<ClassUpdate>,
-) -> anyhow::Result<UnverifiedFullBlock> {
-    // Verify against these commitments.
-    let commitments = UnverifiedCommitments {
-        // TODO: these commitments are wrong for mainnet from block 0 to unknown. We need to figure out
-        // which blocks and handle the case directly in the block import crate.
-        // transaction_commitment: Some(block.transaction_commitment.context("No transaction commitment")?),
-        // event_commitment: Some(block.event_commitment.context("No event commitment")?),
-        state_diff_commitment: None,
-        receipt_commitment: None,
-        global_state_root: Some(block.state_root),
-        block_hash: Some(block.block_hash),
-        ..Default::default()
-    };
-    Ok(UnverifiedFullBlock {
-        unverified_block_number: Some(block.block_number),
-        header: block.header()?,
-        state_diff: state_update.state_diff.into(),
-        receipts: block
-            .transaction_receipts
-            .into_iter()
-            .zip(&block.transactions)
-            .map(|(receipt, tx)| receipt.into_mp(tx))
-            .collect(),
-        transactions: block.transactions.into_iter().map(Into::into).collect(),
-        declared_classes: class_update.into_iter().map(Into::into).collect(),
-        commitments,
-        ..Default::default()
-    })
-}
-
 #[cfg(test)]
 #[path = "fetchers_real_fgw_test.rs"]
 mod fetchers_real_fgw_test;
@@ -323,6 +746,8 @@ mod test_l2_fetchers {
     use mp_block::header::L1DataAvailabilityMode;
     use mp_chain_config::StarknetVersion;
     use mp_gateway::block::BlockStatus;
+    use mp_state_update::DeclaredClassItem;
+    use mp_utils::crypto::ZeroingPrivateKey;
     use rstest::*;
     use starknet_api::felt;
     use std::sync::Arc;
@@ -649,6 +1074,7 @@ mod test_l2_fetchers {
             BlockId::Number(5),
             &ctx.provider,
             &ServiceContext::new_for_testing(),
+            None,
         )
         .await
         .expect("Failed to fetch class updates");
@@ -686,6 +1112,7 @@ mod test_l2_fetchers {
             BlockId::Number(5),
             &ctx.provider,
             &ServiceContext::new_for_testing(),
+            None,
         )
         .await;
 
@@ -697,6 +1124,69 @@ mod test_l2_fetchers {
         ));
     }
 
+    /// A class which fails to download is retried once the other classes of the block are done.
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_class_updates_deferred_retry(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+
+        ctx.mock_block(5);
+        let state_update = ctx
+            .provider
+            .get_state_update_with_block(BlockId::Number(5))
+            .await
+            .expect("Failed to fetch state update at block number 5")
+            .state_update();
+        let state_diff = state_update.state_diff();
+
+        let not_found = ctx.mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_class_by_hash");
+            then.status(400).header("content-type", "application/json").json_body(serde_json::json!({
+                "code": "StarknetErrorCode.UNDECLARED_CLASS",
+                "message": "Class hash is not declared."
+            }));
+        });
+
+        let task = tokio::spawn({
+            let chain_id = ctx.backend.chain_config().chain_id.clone();
+            let state_diff = state_diff.clone();
+            let provider = Arc::clone(&ctx.provider);
+            async move {
+                fetch_class_updates(
+                    &chain_id,
+                    &state_diff,
+                    BlockId::Number(5),
+                    &provider,
+                    &ServiceContext::new_for_testing(),
+                    None,
+                )
+                .await
+            }
+        });
+
+        // The class becomes available once the first attempt has failed
+        tokio::time::timeout(Duration::from_secs(30), async {
+            while not_found.hits_async().await == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("The class should have been requested");
+        not_found.delete_async().await;
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+
+        let class_updates = tokio::time::timeout(Duration::from_secs(30), task)
+            .await
+            .expect("Timeout reached while waiting for the deferred retry")
+            .unwrap()
+            .expect("The deferred retry should have succeeded");
+        assert_eq!(class_updates.len(), 1);
+        assert_eq!(
+            class_updates[0].class_hash(),
+            felt!("0x40fe2533528521fc49a8ad8440f8a1780c50337a94d0fce43756015fa816a8a")
+        );
+    }
+
     /// Test fetching of individual class definitions.
     ///
     /// Verifies that:
@@ -821,4 +1311,486 @@ mod test_l2_fetchers {
         assert!(block.transaction_receipts.is_empty());
         assert_eq!(block.starknet_version, Some("0.13.2.1".to_string()));
     }
+
+    /// The genesis block is fetched like any other block, with the provider shared by the whole sync.
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_genesis_block(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_block(0);
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+        ctx.mock_signature();
+
+        let block = fetch_block_and_updates(
+            &ctx.backend.chain_config().chain_id,
+            0,
+            &ctx.provider,
+            &ServiceContext::new_for_testing(),
+        )
+        .await
+        .expect("Failed to fetch the genesis block");
+        assert_eq!(block.unverified_block_number, Some(0));
+    }
+
+    /// Fetching a block by hash results in the same block as fetching it by number.
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_block_and_updates_by_hash(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_block(5);
+        ctx.mock_block_by_hash("0x541112d5d5937a66ff09425a0256e53ac5c4f554be7e24917fc21a71aa3cf32", 5);
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+        let chain_id = &ctx.backend.chain_config().chain_id;
+        let service_ctx = ServiceContext::new_for_testing();
+
+        let block = fetch_block_and_updates_by_hash(
+            chain_id,
+            felt!("0x541112d5d5937a66ff09425a0256e53ac5c4f554be7e24917fc21a71aa3cf32"),
+            &ctx.provider,
+            &service_ctx,
+        )
+        .await
+        .expect("Failed to fetch block by hash");
+
+        assert_eq!(block.unverified_block_number, Some(5));
+        assert_eq!(
+            block.commitments.block_hash,
+            Some(felt!("0x541112d5d5937a66ff09425a0256e53ac5c4f554be7e24917fc21a71aa3cf32"))
+        );
+        assert_eq!(block, fetch_block_and_updates(chain_id, 5, &ctx.provider, &service_ctx).await.unwrap());
+    }
+
+    /// The state update is converted without downloading the classes it declares.
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_and_convert_state_update(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_block(5);
+
+        let state_update = fetch_and_convert_state_update(&ctx.provider, 5, &ServiceContext::new_for_testing())
+            .await
+            .expect("Failed to fetch state update");
+
+        assert_eq!(state_update.block_hash, felt!("0x541112d5d5937a66ff09425a0256e53ac5c4f554be7e24917fc21a71aa3cf32"));
+        assert_eq!(state_update.old_root, felt!("0x6152bda357cb522337756c71bcab298d88c5d829a479ad8247b82b969912713"));
+        assert_eq!(state_update.new_root, felt!("0x704b7fe29fa070cf3737173acd1d0790fe318f68cc07a49ddfa9c1cd94c804f"));
+        let state_diff = state_update.state_diff;
+        assert_eq!(state_diff.storage_diffs.len(), 6);
+        assert_eq!(state_diff.nonces.len(), 2);
+        assert_eq!(
+            state_diff.declared_classes,
+            vec![DeclaredClassItem {
+                class_hash: felt!("0x40fe2533528521fc49a8ad8440f8a1780c50337a94d0fce43756015fa816a8a"),
+                compiled_class_hash: felt!("0x7d24ab3a5277e064c65b37f2bd4b118050a9f1864bd3f74beeb3e84b2213692"),
+            }]
+        );
+        assert!(state_diff.deployed_contracts.is_empty() && state_diff.replaced_classes.is_empty());
+    }
+
+    /// The signature of the feeder gateway is checked against the public key of the sequencer, a signature from
+    /// another key is rejected.
+    #[rstest]
+    #[case::valid(true)]
+    #[case::forged(false)]
+    #[tokio::test]
+    async fn test_verify_block_signature(test_setup: Arc<MadaraBackend>, #[case] valid: bool) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_block(5);
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+        let service_ctx = ServiceContext::new_for_testing();
+        let block = fetch_block_and_updates(&ctx.backend.chain_config().chain_id, 5, &ctx.provider, &service_ctx)
+            .await
+            .expect("Failed to fetch block");
+        let block_hash = block.commitments.block_hash.unwrap();
+
+        let sequencer_key = ZeroingPrivateKey::default();
+        let forger_key = ZeroingPrivateKey::default();
+        let signature = if valid { &sequencer_key } else { &forger_key }.sign(&block_hash).unwrap();
+        ctx.mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_signature").query_param("blockNumber", "5");
+            then.status(200).header("content-type", "application/json").json_body(serde_json::json!({
+                "block_hash": block_hash,
+                "signature": [signature.r, signature.s],
+            }));
+        });
+
+        let res = verify_block_signature(&block, sequencer_key.public, &ctx.provider, &service_ctx).await;
+        if valid {
+            res.expect("Signature should be valid");
+        } else {
+            assert!(
+                matches!(res, Err(FetchError::InvalidSignature { block_number: 5, block_hash: hash }) if hash == block_hash),
+                "Forged signature should be rejected, got {res:?}"
+            );
+        }
+    }
+
+    /// Mocks the storage and nonce endpoints with the values set by `state_diff`, except for the storage slots `key`
+    /// which are set to another value when `tampered_key` is `Some(key)`.
+    fn mock_contract_state(ctx: &TestContext, state_diff: &mp_state_update::StateDiff, tampered_key: Option<Felt>) {
+        for diff in &state_diff.storage_diffs {
+            for entry in &diff.storage_entries {
+                let value = if tampered_key == Some(entry.key) { entry.value + Felt::ONE } else { entry.value };
+                ctx.mock_server.mock(|when, then| {
+                    when.method("GET")
+                        .path_contains("get_storage_at")
+                        .query_param("contractAddress", format!("{:#x}", diff.address))
+                        .query_param("key", format!("{:#x}", entry.key));
+                    then.status(200).header("content-type", "application/json").json_body(format!("{value:#x}"));
+                });
+            }
+        }
+        for nonce in &state_diff.nonces {
+            ctx.mock_server.mock(|when, then| {
+                when.method("GET")
+                    .path_contains("get_nonce")
+                    .query_param("contractAddress", format!("{:#x}", nonce.contract_address));
+                then.status(200).header("content-type", "application/json").json_body(format!("{:#x}", nonce.nonce));
+            });
+        }
+    }
+
+    /// A storage value of the state diff which is not the one of the feeder gateway is caught once its contract is
+    /// sampled.
+    #[rstest]
+    #[tokio::test]
+    async fn test_verify_state_diff_sample(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_block(5);
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+        let service_ctx = ServiceContext::new_for_testing();
+        let block = fetch_block_and_updates(&ctx.backend.chain_config().chain_id, 5, &ctx.provider, &service_ctx)
+            .await
+            .expect("Failed to fetch block");
+
+        // Sample all the contracts, so that the tampered one is always checked
+        let contracts: BTreeSet<Felt> = block
+            .state_diff
+            .storage_diffs
+            .iter()
+            .map(|diff| diff.address)
+            .chain(block.state_diff.nonces.iter().map(|nonce| nonce.contract_address))
+            .collect();
+        let sample_size = NonZeroUsize::new(contracts.len()).unwrap();
+
+        mock_contract_state(&ctx, &block.state_diff, None);
+        verify_state_diff_sample(&block, sample_size, &ctx.provider, &service_ctx).await.unwrap();
+
+        let tampered = TestContext::new(Arc::clone(&ctx.backend));
+        let entry = block.state_diff.storage_diffs[0].storage_entries[0].clone();
+        mock_contract_state(&tampered, &block.state_diff, Some(entry.key));
+        let err = verify_state_diff_sample(&block, sample_size, &tampered.provider, &service_ctx).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                FetchError::StorageMismatch { block_number: 5, key, state_diff_value, feeder_value, .. }
+                    if key == entry.key && state_diff_value == entry.value && feeder_value == entry.value + Felt::ONE
+            ),
+            "Expected a storage mismatch, got {err:#}"
+        );
+    }
+
+    /// The tip of the chain is the latest closed block, so it is known on chains which do not expose a pending
+    /// block.
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_highest_block_without_pending_block(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        let pending_mock = ctx.mock_pending_block_unavailable();
+        ctx.mock_latest_block(42);
+
+        let (block_hash, block_number) =
+            fetch_highest_block_hash_and_number(&ctx.provider, &ServiceContext::new_for_testing())
+                .await
+                .expect("Failed to fetch the highest block");
+
+        assert_eq!(block_number, 42);
+        assert_eq!(block_hash, felt!("0x541112d5d5937a66ff09425a0256e53ac5c4f554be7e24917fc21a71aa3cf32"));
+        // The pending block is never requested
+        pending_mock.assert_hits(0);
+    }
+
+    /// Both the block and the class downloads are timed, and the class lookups are recorded.
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_block_and_updates_timed(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_block_with_delay(5, Duration::from_millis(20));
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+        let metrics = FetchMetrics::register().unwrap();
+
+        let (block, timing) = fetch_block_and_updates_timed(
+            &ctx.backend.chain_config().chain_id,
+            5,
+            &ctx.provider,
+            &ServiceContext::new_for_testing(),
+            &metrics,
+        )
+        .await
+        .expect("Failed to fetch block");
+
+        assert_eq!(block.unverified_block_number, Some(5));
+        assert!(timing.fetch_block >= Duration::from_millis(20));
+        assert!(timing.fetch_classes > Duration::ZERO);
+        assert_eq!(metrics.class_cache_totals(), (0, 1));
+    }
+
+    /// A class declared by several blocks of a window is only downloaded once, and the blocks past the tip are
+    /// reported as not found.
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_block_window_and_updates(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_block(5);
+        ctx.mock_block(6);
+        ctx.mock_block_not_found(7);
+        let class_mock = ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+
+        let metrics = FetchMetrics::register().unwrap();
+
+        let results = fetch_block_window_and_updates(
+            &ctx.backend.chain_config().chain_id,
+            5..8,
+            &ctx.provider,
+            &ServiceContext::new_for_testing(),
+            &metrics,
+        )
+        .await;
+
+        assert_eq!(results.len(), 3);
+        for (block_n, res) in (5..7).zip(&results) {
+            let (block, _timing) = res.as_ref().expect("Failed to fetch block");
+            assert_eq!(block.unverified_block_number, Some(block_n));
+            assert_eq!(block.declared_classes.len(), 1);
+        }
+        assert!(matches!(
+            results[2],
+            Err(FetchError::Sequencer(SequencerError::StarknetError(StarknetError {
+                code: StarknetErrorCode::BlockNotFound,
+                ..
+            })))
+        ));
+        class_mock.assert_hits(1);
+        // Block 6 reuses the class downloaded for block 5
+        assert_eq!(metrics.class_cache_totals(), (1, 1));
+    }
+
+    /// [`fetch_block_and_updates`] does not dispatch anything, and can be used on its own to get a block in the
+    /// block import format.
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_block_and_updates_converts_block(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_block(5);
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+
+        let block = fetch_block_and_updates(
+            &ctx.backend.chain_config().chain_id,
+            5,
+            &ctx.provider,
+            &ServiceContext::new_for_testing(),
+        )
+        .await
+        .expect("Failed to fetch block");
+
+        assert_eq!(block.unverified_block_number, Some(5));
+        assert_eq!(
+            block.header.parent_block_hash,
+            Some(felt!("0x6dc4eb6311529b941e3963f477b1d13928b38dd4c6ec0206bfba73c8a87198d"))
+        );
+        assert_eq!(
+            block.header.sequencer_address,
+            felt!("0x1176a1bd84444c89232ec27754698e5d2e7e1a7f1539f12027f28b23ec9f3d8")
+        );
+        assert_eq!(block.header.block_timestamp, 1725974819);
+        assert_eq!(block.header.protocol_version, StarknetVersion::new(0, 13, 2, 1));
+        assert_eq!(block.header.l1_gas_price.eth_l1_gas_price, 0x3bf1322e5);
+        assert_eq!(block.header.l1_gas_price.strk_l1_data_gas_price, 0x5b269552db6fa);
+        assert_eq!(block.header.l1_da_mode, L1DataAvailabilityMode::Calldata);
+
+        assert_eq!(
+            block.commitments.block_hash,
+            Some(felt!("0x541112d5d5937a66ff09425a0256e53ac5c4f554be7e24917fc21a71aa3cf32"))
+        );
+        assert_eq!(
+            block.commitments.global_state_root,
+            Some(felt!("0x704b7fe29fa070cf3737173acd1d0790fe318f68cc07a49ddfa9c1cd94c804f"))
+        );
+        assert_eq!(block.commitments.state_diff_length, Some(43));
+
+        assert_eq!(block.state_diff.nonces.len(), 2);
+        assert_eq!(block.state_diff.declared_classes.len(), 1);
+        assert_eq!(block.declared_classes.len(), 1);
+        assert!(block.transactions.is_empty());
+        assert!(block.receipts.is_empty());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_genesis_block_not_found(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_block_not_found(0);
+
+        let result = fetch_block_and_updates(
+            &ctx.backend.chain_config().chain_id,
+            0,
+            &ctx.provider,
+            &ServiceContext::new_for_testing(),
+        )
+        .await;
+        assert!(
+            matches!(
+                result,
+                Err(FetchError::Sequencer(SequencerError::StarknetError(StarknetError {
+                    code: StarknetErrorCode::BlockNotFound,
+                    ..
+                })))
+            ),
+            "Expected BlockNotFound error, but got: {result:?}"
+        );
+    }
+
+    /// A state update returned for another block than its block is rejected, instead of importing the block with the
+    /// wrong state diff.
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_block_with_mismatched_state_update(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_block_with_mismatched_state_update(5);
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+
+        let result = fetch_block_and_updates(
+            &ctx.backend.chain_config().chain_id,
+            5,
+            &ctx.provider,
+            &ServiceContext::new_for_testing(),
+        )
+        .await;
+        assert!(
+            matches!(
+                result,
+                Err(FetchError::BlockHashMismatch { block_number: 5, block_hash, state_update_block_hash })
+                    if block_hash == felt!("0x541112d5d5937a66ff09425a0256e53ac5c4f554be7e24917fc21a71aa3cf32")
+                        && state_update_block_hash == felt!("0x1234")
+            ),
+            "Expected a block hash mismatch, but got: {result:?}"
+        );
+    }
+
+    /// Past the tip of the chain, the feeder gateway answers with a block not found error: this is not retried, so
+    /// that the sync goes back to polling right away. Server errors are retried with a backoff.
+    #[rstest]
+    #[case::block_not_found(400, r#"{"code": "StarknetErrorCode.BLOCK_NOT_FOUND", "message": "Block not found"}"#, 1)]
+    #[case::server_error(500, "Internal Server Error", 4)]
+    #[tokio::test]
+    async fn test_retry_block_not_found_vs_server_error(
+        test_setup: Arc<MadaraBackend>,
+        #[case] status: u16,
+        #[case] body: &str,
+        #[case] expected_hits: usize,
+    ) {
+        let ctx = TestContext::new(test_setup);
+        let mock = ctx.mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_state_update").query_param("blockNumber", "5");
+            then.status(status).body(body);
+        });
+
+        let result = retry(
+            || ctx.provider.get_state_update_with_block(BlockId::Number(5)),
+            3,
+            Duration::from_millis(1),
+            &ServiceContext::new_for_testing(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        mock.assert_hits(expected_hits);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_class_retries_network_errors(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        let class_hash = Felt::from_hex_unchecked("0x78401746828463e2c3f92ebb261fc82f7d4d4c8d9a80a356c44580dab124cb0");
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+
+        // The first two requests time out
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let result = retry(
+            || {
+                let attempt = attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let provider = &ctx.provider;
+                async move {
+                    if attempt < 2 {
+                        return Err(SequencerError::Timeout);
+                    }
+                    fetch_class(class_hash, BlockId::Number(5), provider).await
+                }
+            },
+            MAX_RETRY,
+            Duration::from_millis(1),
+            &ServiceContext::new_for_testing(),
+        )
+        .await;
+
+        let (fetched_hash, _contract_class) = result.expect("Class should be fetched after retrying");
+        assert_eq!(fetched_hash, class_hash);
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_class_does_not_retry_undeclared_class(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        let mock = ctx.mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_class_by_hash").query_param("classHash", "0x1234");
+            then.status(400).header("content-type", "application/json").json_body(serde_json::json!({
+                "code": "StarknetErrorCode.UNDECLARED_CLASS",
+                "message": "Class hash is not declared."
+            }));
+        });
+
+        let result = retry(
+            || fetch_class(felt!("0x1234"), BlockId::Number(5), &ctx.provider),
+            MAX_RETRY,
+            Duration::from_millis(1),
+            &ServiceContext::new_for_testing(),
+        )
+        .await;
+
+        assert!(
+            matches!(
+                result,
+                Err(SequencerError::StarknetError(StarknetError { code: StarknetErrorCode::UndeclaredClass, .. }))
+            ),
+            "Expected UndeclaredClass error, but got: {result:?}"
+        );
+        mock.assert_hits(1);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_fetch_class_does_not_retry_response_too_large(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        let mock = ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+        let provider = GatewayProvider::new(
+            format!("{}/gateway/", ctx.mock_server.base_url()).parse().unwrap(),
+            format!("{}/feeder_gateway/", ctx.mock_server.base_url()).parse().unwrap(),
+        )
+        .with_max_response_bytes(NonZeroUsize::new(16).unwrap());
+
+        let result = retry(
+            || fetch_class(felt!("0x1234"), BlockId::Number(5), &provider),
+            MAX_RETRY,
+            Duration::from_millis(1),
+            &ServiceContext::new_for_testing(),
+        )
+        .await;
+
+        assert!(
+            matches!(result, Err(SequencerError::ResponseTooLarge { max_response_bytes: 16 })),
+            "Expected ResponseTooLarge error, but got: {result:?}"
+        );
+        mock.assert_hits(1);
+    }
 }
@@ -1,20 +1,44 @@
+use std::ops::Range;
 use std::time::Duration;
 use std::{num::NonZeroUsize, sync::Arc};
 
+use anyhow::Context;
 use futures::prelude::*;
 use mc_block_import::UnverifiedFullBlock;
 use mc_db::MadaraBackend;
-use mc_gateway_client::GatewayProvider;
+use mc_gateway_client::{FeederHealth, GatewayProvider};
 use mc_rpc::versions::admin::v0_1_0::MadaraStatusRpcApiV0_1_0Client;
 use mp_gateway::error::{SequencerError, StarknetError, StarknetErrorCode};
 use mp_utils::{channel_wait_or_graceful_shutdown, service::ServiceContext, wait_or_graceful_shutdown};
+use starknet_types_core::felt::Felt;
 use tokio::sync::{mpsc, oneshot};
 use url::Url;
 
-use crate::fetch::fetchers::fetch_block_and_updates;
+use crate::convert;
+use crate::events::{SyncEvent, SyncEvents};
+use crate::fetch::fetchers::{
+    fetch_block_and_updates_timed, fetch_block_traces, fetch_block_window_and_updates, verify_block_signature,
+    verify_state_diff_sample,
+};
+use crate::health::SyncHealthTracker;
+use crate::metrics::fetch_metrics::FetchMetrics;
+use crate::timing::SyncTimings;
 
 pub mod fetchers;
 
+/// Interval at which the tip of the chain is polled to report the sync health.
+const HIGHEST_BLOCK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Interval at which the consecutive failures of the feeder gateway are checked, see
+/// [`L2FetchConfig::max_consecutive_failures`].
+const FAILURE_BUDGET_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Execution traces of a block, as returned by the feeder gateway.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTraces {
+    pub block_number: u64,
+    pub traces: serde_json::Value,
+}
+
 pub struct L2FetchConfig {
     pub first_block: u64,
     pub fetch_stream_sender: mpsc::Sender<UnverifiedFullBlock>,
@@ -23,17 +47,80 @@ pub struct L2FetchConfig {
     pub n_blocks_to_sync: Option<u64>,
     pub stop_on_sync: bool,
     pub sync_parallelism: usize,
+    /// When set, blocks are fetched by windows of this many blocks, and the classes declared in a window are
+    /// downloaded at once. See [`fetch_block_window_and_updates`].
+    pub class_prefetch_window: Option<NonZeroUsize>,
+    /// When set, the fetch task stops with [`FetchError::TooManyFailures`] once the feeder gateway has failed more
+    /// than this many requests in a row. A successful request resets the count, see [`FeederHealth`].
+    pub max_consecutive_failures: Option<u32>,
+    /// When set, the signature of each fetched block is checked against this sequencer public key.
+    pub sequencer_public_key: Option<Felt>,
+    /// When set, the state diff of each fetched block is cross-checked against the feeder gateway for this many
+    /// contracts, see [`verify_state_diff_sample`].
+    pub verify_sample_size: Option<NonZeroUsize>,
+    /// Compile the Sierra classes of each fetched block to CASM, see [`convert::compile_classes`].
+    pub compile_classes: bool,
     pub warp_update: bool,
     pub warp_update_port_rpc: u16,
     pub warp_update_port_fgw: u16,
+    pub health: Arc<SyncHealthTracker>,
+    /// When set, the traces of each fetched block are fetched too and sent here.
+    pub trace_sender: Option<mpsc::Sender<BlockTraces>>,
+    /// When set, the time spent fetching each block is recorded here.
+    pub timings: Option<Arc<SyncTimings>>,
+    pub events: SyncEvents,
 }
 
+/// Fetches blocks and sends them to the block import. The task stops without error once the block import has
+/// stopped, as the block import reports its own error if it stopped because of one.
 pub async fn l2_fetch_task(
     backend: Arc<MadaraBackend>,
     provider: Arc<GatewayProvider>,
     ctx: ServiceContext,
-    mut config: L2FetchConfig,
+    config: L2FetchConfig,
 ) -> anyhow::Result<()> {
+    let events = config.events.clone();
+    let metrics = FetchMetrics::register().context("Registering metrics for the fetch task")?;
+    // The tip is polled in its own task, so that a block fetch waiting on its retries does not make the sync health
+    // go stale. This must not delay the sync either.
+    let highest_block_task = tokio::spawn(poll_highest_block(
+        Arc::clone(&provider),
+        Arc::clone(&config.health),
+        metrics.clone(),
+        ctx.clone(),
+    ));
+    let res = match config.max_consecutive_failures {
+        Some(max_consecutive_failures) => {
+            let feeder_health = Arc::clone(provider.feeder_health());
+            tokio::select! {
+                res = fetch_blocks(backend, provider, ctx, config, metrics) => res,
+                err = failure_budget_exceeded(&feeder_health, max_consecutive_failures) => Err(err),
+            }
+        }
+        None => fetch_blocks(backend, provider, ctx, config, metrics).await,
+    };
+    highest_block_task.abort();
+
+    match res {
+        Err(FetchError::ChannelClosed) => {
+            tracing::warn!("The block import has stopped, stopping the fetch task");
+            Ok(())
+        }
+        Err(err) => {
+            events.emit(SyncEvent::FetchError { message: format!("{err:#}") });
+            Err(err.into())
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
+async fn fetch_blocks(
+    backend: Arc<MadaraBackend>,
+    provider: Arc<GatewayProvider>,
+    ctx: ServiceContext,
+    mut config: L2FetchConfig,
+    metrics: FetchMetrics,
+) -> Result<(), FetchError> {
     // First, catch up with the chain
     // let backend = &backend;
 
@@ -62,7 +149,7 @@ pub async fn l2_fetch_task(
             .unwrap_or(NonZeroUsize::new(1usize).expect("1 should always be in usize bound"));
         config.sync_parallelism = Into::<usize>::into(available_parallelism) * 2;
 
-        let next_block = match sync_blocks(backend.as_ref(), &provider, &ctx, &config).await? {
+        let next_block = match sync_blocks(backend.as_ref(), &provider, &ctx, &config, &metrics).await? {
             SyncStatus::Full(next_block) => next_block,
             SyncStatus::UpTo(next_block) => next_block,
         };
@@ -78,7 +165,7 @@ pub async fn l2_fetch_task(
         config.sync_parallelism = save;
     }
 
-    let mut next_block = match sync_blocks(backend.as_ref(), &provider, &ctx, &config).await? {
+    let mut next_block = match sync_blocks(backend.as_ref(), &provider, &ctx, &config, &metrics).await? {
         SyncStatus::Full(next_block) => {
             tracing::info!("🥳 The sync process has caught up with the tip of the chain");
             next_block
@@ -87,14 +174,25 @@ pub async fn l2_fetch_task(
     };
 
     if config.stop_on_sync {
-        return anyhow::Ok(());
+        return Ok(());
     }
 
-    let L2FetchConfig { fetch_stream_sender, once_caught_up_sender, sync_polling_interval, stop_on_sync, .. } = config;
+    let checks = FetchedBlockChecks::new(&config);
+    let L2FetchConfig {
+        fetch_stream_sender,
+        once_caught_up_sender,
+        sync_polling_interval,
+        stop_on_sync,
+        health,
+        trace_sender,
+        timings,
+        events,
+        ..
+    } = config;
 
     // We do not call cancellation here as we still want the blocks to be stored
     if stop_on_sync {
-        return anyhow::Ok(());
+        return Ok(());
     }
 
     // TODO: replace this with a tokio::sync::Notify
@@ -107,17 +205,23 @@ pub async fn l2_fetch_task(
         interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
         while wait_or_graceful_shutdown(interval.tick(), &ctx).await.is_some() {
             loop {
-                match fetch_block_and_updates(&backend.chain_config().chain_id, next_block, &provider, &ctx).await {
+                let block = fetch_block(&backend, next_block, &provider, &ctx, timings.as_deref(), checks).await;
+                match block {
                     Err(FetchError::Sequencer(SequencerError::StarknetError(StarknetError {
                         code: StarknetErrorCode::BlockNotFound,
                         ..
                     }))) => {
+                        if let Some(highest_block_n) = next_block.checked_sub(1) {
+                            health.set_highest_block_number(highest_block_n);
+                        }
                         break;
                     }
                     val => {
-                        if fetch_stream_sender.send(val?).await.is_err() {
-                            // stream closed
-                            break;
+                        fetch_stream_sender.send(val?).await.map_err(|_| FetchError::ChannelClosed)?;
+                        events.emit(SyncEvent::BlockFetched { block_number: next_block });
+                        metrics.fetch_channel_capacity.record(fetch_stream_sender.capacity() as u64, &[]);
+                        if let Some(trace_sender) = &trace_sender {
+                            send_block_traces(next_block, &provider, trace_sender).await;
                         }
                     }
                 }
@@ -129,6 +233,41 @@ pub async fn l2_fetch_task(
     Ok(())
 }
 
+/// Resolves once the feeder gateway has failed more than `max_consecutive_failures` requests in a row. Every request
+/// to the feeder gateway counts, including the ones of the pending block and tip polling.
+async fn failure_budget_exceeded(feeder_health: &FeederHealth, max_consecutive_failures: u32) -> FetchError {
+    let mut interval = tokio::time::interval(FAILURE_BUDGET_POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    loop {
+        interval.tick().await;
+        let failures = feeder_health.record().consecutive_failures;
+        if failures > max_consecutive_failures {
+            return FetchError::TooManyFailures { failures };
+        }
+    }
+}
+
+/// Updates the highest block number of `health` every [`HIGHEST_BLOCK_POLL_INTERVAL`], until the fetch task stops,
+/// and records the resulting sync lag. A failed request is not retried, the next tick will try again.
+async fn poll_highest_block(
+    provider: Arc<GatewayProvider>,
+    health: Arc<SyncHealthTracker>,
+    metrics: FetchMetrics,
+    ctx: ServiceContext,
+) {
+    let mut interval = tokio::time::interval(HIGHEST_BLOCK_POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    while wait_or_graceful_shutdown(interval.tick(), &ctx).await.is_some() {
+        match provider.get_latest_block_number().await {
+            Ok(block_n) => health.set_highest_block_number(block_n),
+            Err(err) => tracing::debug!("Could not get the tip of the chain: {err:#}"),
+        }
+        if let Some(sync_lag) = health.sync_lag() {
+            metrics.sync_lag.record(sync_lag, &[]);
+        }
+    }
+}
+
 /// Whether a chain has been caught up to the tip or only a certain block number
 ///
 /// This is mostly relevant in the context of the `--n-blocks-to-sync` cli
@@ -151,31 +290,76 @@ enum SyncStatus {
 ///
 /// Fetch config, including number of blocks to fetch and fetch parallelism,
 /// is defined in [L2FetchConfig].
+///
+/// A block is only sent once its state update and all of its classes have
+/// been downloaded, and the next block is only polled once it has been sent.
+/// When the import falls behind and the channel fills up, at most
+/// `sync_parallelism` blocks are held in memory by the fetch task, rounded
+/// up to whole windows (and at least two) when fetching by windows.
 async fn sync_blocks(
     backend: &MadaraBackend,
     provider: &Arc<GatewayProvider>,
     ctx: &ServiceContext,
     config: &L2FetchConfig,
-) -> anyhow::Result<SyncStatus> {
-    let L2FetchConfig { first_block, fetch_stream_sender, n_blocks_to_sync, sync_parallelism, .. } = config;
-
-    // Fetch blocks and updates in parallel one time before looping
-    let fetch_stream =
-        (*first_block..).take(n_blocks_to_sync.unwrap_or(u64::MAX) as _).map(|block_n| {
-            let provider = Arc::clone(provider);
-            let ctx = ctx.clone();
-            async move {
-                (block_n, fetch_block_and_updates(&backend.chain_config().chain_id, block_n, &provider, &ctx).await)
-            }
-        });
+    metrics: &FetchMetrics,
+) -> Result<SyncStatus, FetchError> {
+    let L2FetchConfig {
+        first_block,
+        fetch_stream_sender,
+        n_blocks_to_sync,
+        sync_parallelism,
+        class_prefetch_window,
+        health,
+        trace_sender,
+        events,
+        ..
+    } = config;
+
+    let fetch_traces = trace_sender.is_some();
+    let checks = FetchedBlockChecks::new(config);
+    let last_block = first_block.saturating_add(n_blocks_to_sync.unwrap_or(u64::MAX));
+    let mut fetch_stream = match class_prefetch_window {
+        None => {
+            // Fetch blocks and updates in parallel one time before looping
+            let fetch_stream = (*first_block..last_block).map(|block_n| {
+                let provider = Arc::clone(provider);
+                let ctx = ctx.clone();
+                let timings = config.timings.clone();
+                async move {
+                    let block =
+                        fetch_block(backend, block_n, &provider, &ctx, timings.as_deref(), checks, metrics).await;
+                    let traces = match &block {
+                        Ok(_) if fetch_traces => fetch_block_traces(block_n, &provider).await,
+                        _ => None,
+                    };
+                    (block_n, block, traces)
+                }
+            });
 
-    // Have `sync_parallelism` fetches in parallel at once, using futures Buffered
+            // Have `sync_parallelism` fetches in parallel at once, using futures Buffered
+            stream::iter(fetch_stream).buffered(*sync_parallelism).boxed()
+        }
+        Some(window) => {
+            let windows = (*first_block..last_block)
+                .step_by(window.get())
+                .map(|start| start..start.saturating_add(window.get() as u64).min(last_block));
+            let fetch_stream = windows.map(|blocks| {
+                let provider = Arc::clone(provider);
+                let ctx = ctx.clone();
+                async move { fetch_window(backend, blocks, &provider, &ctx, config, metrics).await }
+            });
+
+            // Have about `sync_parallelism` blocks in flight at once, and always fetch the next window while the
+            // blocks of the current one are sent
+            let windows_in_flight = sync_parallelism.div_ceil(window.get()).max(2);
+            stream::iter(fetch_stream).buffered(windows_in_flight).flat_map(stream::iter).boxed()
+        }
+    };
     let mut next_block = *first_block;
-    let mut fetch_stream = stream::iter(fetch_stream).buffered(*sync_parallelism);
 
     loop {
-        let Some((block_n, val)) = channel_wait_or_graceful_shutdown(fetch_stream.next(), ctx).await else {
-            return anyhow::Ok(SyncStatus::UpTo(next_block));
+        let Some((block_n, val, traces)) = channel_wait_or_graceful_shutdown(fetch_stream.next(), ctx).await else {
+            return Ok(SyncStatus::UpTo(next_block));
         };
 
         match val {
@@ -183,12 +367,17 @@ async fn sync_blocks(
                 code: StarknetErrorCode::BlockNotFound,
                 ..
             }))) => {
-                return anyhow::Ok(SyncStatus::Full(next_block));
+                if let Some(highest_block_n) = block_n.checked_sub(1) {
+                    health.set_highest_block_number(highest_block_n);
+                }
+                return Ok(SyncStatus::Full(next_block));
             }
             val => {
-                if fetch_stream_sender.send(val?).await.is_err() {
-                    // join error
-                    return anyhow::Ok(SyncStatus::UpTo(next_block));
+                fetch_stream_sender.send(val?).await.map_err(|_| FetchError::ChannelClosed)?;
+                events.emit(SyncEvent::BlockFetched { block_number: block_n });
+                metrics.fetch_channel_capacity.record(fetch_stream_sender.capacity() as u64, &[]);
+                if let (Some(trace_sender), Some(traces)) = (trace_sender, traces) {
+                    let _ = trace_sender.send(BlockTraces { block_number: block_n, traces }).await;
                 }
             }
         }
@@ -197,22 +386,192 @@ async fn sync_blocks(
     }
 }
 
+/// Fetches a window of blocks with [`fetch_block_window_and_updates`], recording the time spent when `timings` is
+/// set.
+async fn fetch_window(
+    backend: &MadaraBackend,
+    blocks: Range<u64>,
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+    config: &L2FetchConfig,
+    metrics: &FetchMetrics,
+) -> Vec<(u64, Result<UnverifiedFullBlock, FetchError>, Option<serde_json::Value>)> {
+    let L2FetchConfig { timings, trace_sender, .. } = config;
+    let checks = FetchedBlockChecks::new(config);
+    let fetch_traces = trace_sender.is_some();
+    let results =
+        fetch_block_window_and_updates(&backend.chain_config().chain_id, blocks.clone(), provider, ctx, metrics).await;
+    let mut fetched = Vec::with_capacity(results.len());
+    for (block_n, res) in blocks.zip(results) {
+        let block = async {
+            let (block, timing) = res?;
+            if let Some(timings) = timings {
+                timings.update(block_n, |block_timing| {
+                    block_timing.fetch_block = timing.fetch_block;
+                    block_timing.fetch_classes = timing.fetch_classes;
+                });
+            }
+            checks.run(block, provider, ctx).await
+        }
+        .await;
+        let traces = match &block {
+            Ok(_) if fetch_traces => fetch_block_traces(block_n, provider).await,
+            _ => None,
+        };
+        fetched.push((block_n, block, traces));
+    }
+    fetched
+}
+
+/// Fetches a block, recording the time spent when `timings` is set.
+async fn fetch_block(
+    backend: &MadaraBackend,
+    block_n: u64,
+    provider: &GatewayProvider,
+    ctx: &ServiceContext,
+    timings: Option<&SyncTimings>,
+    checks: FetchedBlockChecks,
+    metrics: &FetchMetrics,
+) -> Result<UnverifiedFullBlock, FetchError> {
+    let (block, timing) =
+        fetch_block_and_updates_timed(&backend.chain_config().chain_id, block_n, provider, ctx, metrics).await?;
+    if let Some(timings) = timings {
+        timings.update(block_n, |block_timing| {
+            block_timing.fetch_block = timing.fetch_block;
+            block_timing.fetch_classes = timing.fetch_classes;
+        });
+    }
+    checks.run(block, provider, ctx).await
+}
+
+/// Checks and processing applied to each fetched block, from the [`L2FetchConfig`].
+#[derive(Debug, Clone, Copy)]
+struct FetchedBlockChecks {
+    sequencer_public_key: Option<Felt>,
+    verify_sample_size: Option<NonZeroUsize>,
+    compile_classes: bool,
+}
+
+impl FetchedBlockChecks {
+    fn new(config: &L2FetchConfig) -> Self {
+        Self {
+            sequencer_public_key: config.sequencer_public_key,
+            verify_sample_size: config.verify_sample_size,
+            compile_classes: config.compile_classes,
+        }
+    }
+
+    async fn run(
+        self,
+        block: UnverifiedFullBlock,
+        provider: &GatewayProvider,
+        ctx: &ServiceContext,
+    ) -> Result<UnverifiedFullBlock, FetchError> {
+        if let Some(public_key) = self.sequencer_public_key {
+            verify_block_signature(&block, public_key, provider, ctx).await?;
+        }
+        if let Some(sample_size) = self.verify_sample_size {
+            verify_state_diff_sample(&block, sample_size, provider, ctx).await?;
+        }
+        if self.compile_classes {
+            return compile_block_classes(block).await;
+        }
+        Ok(block)
+    }
+}
+
+/// Attaches the CASM of the Sierra classes declared by `block`, see [`convert::compile_classes`]. The compilation is
+/// done on the rayon thread pool, as it is compute heavy.
+async fn compile_block_classes(mut block: UnverifiedFullBlock) -> Result<UnverifiedFullBlock, FetchError> {
+    mp_utils::spawn_rayon_task(move || convert::compile_classes(&mut block.declared_classes).map(|()| block))
+        .await
+        .map_err(|err| FetchError::Internal(err.into()))
+}
+
+async fn send_block_traces(block_n: u64, provider: &GatewayProvider, trace_sender: &mpsc::Sender<BlockTraces>) {
+    if let Some(traces) = fetch_block_traces(block_n, provider).await {
+        let _ = trace_sender.send(BlockTraces { block_number: block_n, traces }).await;
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum FetchError {
     #[error(transparent)]
     Sequencer(#[from] SequencerError),
     #[error(transparent)]
     Internal(#[from] anyhow::Error),
+    /// The receiving end of the fetch channel has been dropped, which means that the block import has stopped.
+    /// This is fatal: the fetched blocks cannot be imported anymore.
+    #[error("The fetch channel is closed")]
+    ChannelClosed,
+    /// The block has not been signed by the sequencer.
+    #[error("Invalid signature for block #{block_number} with hash {block_hash:#x}")]
+    InvalidSignature { block_number: u64, block_hash: Felt },
+    /// The state update returned with a block is the state update of another block.
+    #[error(
+        "Block #{block_number} has hash {block_hash:#x}, but its state update is for block hash {state_update_block_hash:#x}"
+    )]
+    BlockHashMismatch { block_number: u64, block_hash: Felt, state_update_block_hash: Felt },
+    /// The feeder gateway has failed too many requests in a row, see [`L2FetchConfig::max_consecutive_failures`].
+    /// The node exits with [`FetchError::TOO_MANY_FAILURES_EXIT_CODE`], so that it can be restarted cleanly.
+    #[error("The feeder gateway failed {failures} consecutive requests, giving up")]
+    TooManyFailures { failures: u32 },
+    /// A storage value of the state diff of a block is not the one of the feeder gateway, see
+    /// [`verify_state_diff_sample`].
+    #[error(
+        "Block #{block_number} sets the storage {key:#x} of contract {contract_address:#x} to {state_diff_value:#x}, but the feeder gateway has {feeder_value:#x}"
+    )]
+    StorageMismatch { block_number: u64, contract_address: Felt, key: Felt, state_diff_value: Felt, feeder_value: Felt },
+    /// A nonce of the state diff of a block is not the one of the feeder gateway, see [`verify_state_diff_sample`].
+    #[error(
+        "Block #{block_number} sets the nonce of contract {contract_address:#x} to {state_diff_nonce:#x}, but the feeder gateway has {feeder_nonce:#x}"
+    )]
+    NonceMismatch { block_number: u64, contract_address: Felt, state_diff_nonce: Felt, feeder_nonce: Felt },
+}
+
+impl FetchError {
+    /// Exit code of the node when the sync stops with [`FetchError::TooManyFailures`].
+    pub const TOO_MANY_FAILURES_EXIT_CODE: i32 = 3;
 }
 
 #[cfg(test)]
 mod test_l2_fetch_task {
     use super::*;
+    use crate::fetch::fetchers::fetch_block_and_updates;
     use crate::tests::utils::gateway::{test_setup, TestContext};
     use rstest::*;
     use std::sync::Arc;
     use std::time::Duration;
 
+    /// Configuration of the fetch task used by the tests, which override the fields they exercise.
+    fn test_fetch_config(
+        backend: &Arc<MadaraBackend>,
+        fetch_stream_sender: mpsc::Sender<UnverifiedFullBlock>,
+        once_caught_up_sender: oneshot::Sender<()>,
+    ) -> L2FetchConfig {
+        L2FetchConfig {
+            first_block: 0,
+            fetch_stream_sender,
+            once_caught_up_sender,
+            sync_polling_interval: None,
+            n_blocks_to_sync: None,
+            stop_on_sync: true,
+            sync_parallelism: 2,
+            class_prefetch_window: None,
+            max_consecutive_failures: None,
+            sequencer_public_key: None,
+            verify_sample_size: None,
+            compile_classes: false,
+            warp_update: false,
+            warp_update_port_rpc: 9943,
+            warp_update_port_fgw: 8080,
+            health: Arc::new(SyncHealthTracker::new(Arc::clone(backend), 0)),
+            trace_sender: None,
+            timings: None,
+            events: SyncEvents::default(),
+        }
+    }
+
     /// Test the comprehensive functionality of the l2_fetch_task.
     ///
     /// This test verifies that:
@@ -236,28 +595,17 @@ mod test_l2_fetch_task {
         let task = tokio::spawn({
             let backend = Arc::clone(&ctx.backend);
             let provider = Arc::clone(&ctx.provider);
-            let fetch_stream_sender = ctx.fetch_stream_sender.clone();
-            let once_caught_up_sender = ctx.once_caught_up_sender;
+            let config = L2FetchConfig {
+                sync_polling_interval: Some(polling_interval),
+                n_blocks_to_sync: Some(5),
+                stop_on_sync: false,
+                sync_parallelism: 10,
+                ..test_fetch_config(&ctx.backend, ctx.fetch_stream_sender.clone(), ctx.once_caught_up_sender)
+            };
             async move {
                 tokio::time::timeout(
                     Duration::from_secs(5),
-                    l2_fetch_task(
-                        backend,
-                        provider,
-                        ServiceContext::new_for_testing(),
-                        L2FetchConfig {
-                            first_block: 0,
-                            fetch_stream_sender,
-                            once_caught_up_sender,
-                            sync_polling_interval: Some(polling_interval),
-                            n_blocks_to_sync: Some(5),
-                            stop_on_sync: false,
-                            sync_parallelism: 10,
-                            warp_update: false,
-                            warp_update_port_rpc: 9943,
-                            warp_update_port_fgw: 8080,
-                        },
-                    ),
+                    l2_fetch_task(backend, provider, ServiceContext::new_for_testing(), config),
                 )
                 .await
             }
@@ -295,4 +643,355 @@ mod test_l2_fetch_task {
 
         task.abort();
     }
+
+    /// The tip of the chain keeps being polled while a block fetch is stalled, and the polling stops with the fetch
+    /// task.
+    #[rstest]
+    #[tokio::test]
+    async fn test_l2_fetch_task_polls_highest_block(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_block_with_delay(0, Duration::from_secs(30));
+        let mut latest_block = ctx.mock_latest_block(10);
+
+        let health = Arc::new(SyncHealthTracker::new(Arc::clone(&ctx.backend), 0));
+        let service_ctx = ServiceContext::new_for_testing();
+        let (once_caught_up_sender, _once_caught_up_receiver) = oneshot::channel();
+        let task = tokio::spawn(l2_fetch_task(
+            Arc::clone(&ctx.backend),
+            Arc::clone(&ctx.provider),
+            service_ctx.clone(),
+            L2FetchConfig {
+                stop_on_sync: false,
+                sync_parallelism: 1,
+                health: Arc::clone(&health),
+                ..test_fetch_config(&ctx.backend, ctx.fetch_stream_sender.clone(), once_caught_up_sender)
+            },
+        ));
+
+        let wait_for_highest_block = |block_n| {
+            let health = Arc::clone(&health);
+            tokio::time::timeout(Duration::from_secs(5), async move {
+                while health.highest_block_number() != Some(block_n) {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+            })
+        };
+        wait_for_highest_block(10).await.expect("The tip was not polled");
+        latest_block.delete();
+        ctx.mock_latest_block(12);
+        wait_for_highest_block(12).await.expect("The tip was not polled while the block fetch is stalled");
+        assert!(!task.is_finished());
+
+        service_ctx.cancel_global();
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("The fetch task did not stop")
+            .expect("Task panicked")
+            .expect("Task failed");
+    }
+
+    /// A slow consumer must stall the fetch task instead of letting fetched blocks pile up.
+    #[rstest]
+    #[tokio::test]
+    async fn test_sync_blocks_backpressure(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        for block_number in 0..10 {
+            ctx.mock_block(block_number);
+        }
+        ctx.mock_block_not_found(10);
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+        ctx.mock_signature();
+
+        let (fetch_stream_sender, mut fetch_stream_receiver) = mpsc::channel(1);
+        let (once_caught_up_sender, _once_caught_up_receiver) = oneshot::channel();
+        let config = test_fetch_config(&ctx.backend, fetch_stream_sender, once_caught_up_sender);
+
+        let task = tokio::spawn({
+            let backend = Arc::clone(&ctx.backend);
+            let provider = Arc::clone(&ctx.provider);
+            async move {
+                let metrics = FetchMetrics::register().unwrap();
+                sync_blocks(&backend, &provider, &ServiceContext::new_for_testing(), &config, &metrics).await
+            }
+        });
+
+        // Nothing is consumed: the fetch task fills the channel and then waits
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(!task.is_finished());
+        assert_eq!(fetch_stream_receiver.len(), 1);
+
+        for expected_block_number in 0..10 {
+            let block = tokio::time::timeout(Duration::from_secs(1), fetch_stream_receiver.recv())
+                .await
+                .expect("Timeout waiting for block")
+                .expect("Channel closed unexpectedly");
+            assert_eq!(block.unverified_block_number, Some(expected_block_number));
+        }
+
+        let status = tokio::time::timeout(Duration::from_secs(1), task).await.unwrap().unwrap().unwrap();
+        assert!(matches!(status, SyncStatus::Full(10)));
+    }
+
+    /// Windowed fetching sends the blocks in order, across window boundaries, and stops at the tip of the chain.
+    #[rstest]
+    #[tokio::test]
+    async fn test_sync_blocks_class_prefetch_window(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        for block_number in 0..5 {
+            ctx.mock_block(block_number);
+        }
+        ctx.mock_block_not_found(5);
+        let class_mock = ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+
+        let (fetch_stream_sender, mut fetch_stream_receiver) = mpsc::channel(10);
+        let (once_caught_up_sender, _once_caught_up_receiver) = oneshot::channel();
+        let config = L2FetchConfig {
+            class_prefetch_window: NonZeroUsize::new(3),
+            ..test_fetch_config(&ctx.backend, fetch_stream_sender, once_caught_up_sender)
+        };
+        let metrics = FetchMetrics::register().unwrap();
+
+        let status = sync_blocks(&ctx.backend, &ctx.provider, &ServiceContext::new_for_testing(), &config, &metrics)
+            .await
+            .unwrap();
+        assert!(matches!(status, SyncStatus::Full(5)));
+        for expected_block_number in 0..5 {
+            let block = fetch_stream_receiver.recv().await.unwrap();
+            assert_eq!(block.unverified_block_number, Some(expected_block_number));
+        }
+        // Every mocked block declares the same class: it is downloaded once per window
+        class_mock.assert_hits(2);
+    }
+
+    /// Blocks recorded with `--record-dir` can be synced again without access to the feeder gateway.
+    #[rstest]
+    #[tokio::test]
+    async fn test_sync_blocks_from_replay_dir(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        for block_number in 0..3 {
+            ctx.mock_block(block_number);
+        }
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+
+        let dir = tempfile::tempdir().unwrap();
+        let chain_id = ctx.backend.chain_config().chain_id.clone();
+        let recording = GatewayProvider::new(
+            Url::parse(&format!("{}/gateway/", ctx.mock_server.base_url())).unwrap(),
+            Url::parse(&format!("{}/feeder_gateway/", ctx.mock_server.base_url())).unwrap(),
+        )
+        .with_record_dir(dir.path().to_owned());
+        let mut recorded = vec![];
+        for block_n in 0..3 {
+            recorded.push(
+                fetch_block_and_updates(&chain_id, block_n, &recording, &ServiceContext::new_for_testing())
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        // Nothing listens on this port: every request would fail
+        let replaying = Arc::new(
+            GatewayProvider::new(
+                Url::parse("http://127.0.0.1:1/gateway/").unwrap(),
+                Url::parse("http://127.0.0.1:1/feeder_gateway/").unwrap(),
+            )
+            .with_replay_dir(dir.path().to_owned()),
+        );
+        let (fetch_stream_sender, mut fetch_stream_receiver) = mpsc::channel(10);
+        let (once_caught_up_sender, _once_caught_up_receiver) = oneshot::channel();
+        let config = test_fetch_config(&ctx.backend, fetch_stream_sender, once_caught_up_sender);
+        let metrics = FetchMetrics::register().unwrap();
+
+        let status =
+            sync_blocks(&ctx.backend, &replaying, &ServiceContext::new_for_testing(), &config, &metrics).await.unwrap();
+        assert!(matches!(status, SyncStatus::Full(3)));
+        for expected in recorded {
+            assert_eq!(fetch_stream_receiver.recv().await.unwrap(), expected);
+        }
+    }
+
+    #[rstest]
+    #[case::enabled(true, 200, true)]
+    #[case::disabled(false, 200, false)]
+    #[case::failure_is_not_fatal(true, 500, false)]
+    #[tokio::test]
+    async fn test_sync_blocks_fetch_traces(
+        test_setup: Arc<MadaraBackend>,
+        #[case] fetch_traces: bool,
+        #[case] traces_status: u16,
+        #[case] expect_traces: bool,
+    ) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_block(0);
+        ctx.mock_block_not_found(1);
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+        let traces = serde_json::json!({ "traces": [] });
+        let traces_mock = ctx.mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_block_traces").query_param("blockNumber", "0");
+            then.status(traces_status).header("content-type", "application/json").json_body(traces.clone());
+        });
+
+        let (fetch_stream_sender, mut fetch_stream_receiver) = mpsc::channel(10);
+        let (once_caught_up_sender, _once_caught_up_receiver) = oneshot::channel();
+        let (trace_sender, mut trace_receiver) = mpsc::channel(10);
+        let config = L2FetchConfig {
+            trace_sender: fetch_traces.then_some(trace_sender),
+            ..test_fetch_config(&ctx.backend, fetch_stream_sender, once_caught_up_sender)
+        };
+        let metrics = FetchMetrics::register().unwrap();
+
+        let status = sync_blocks(&ctx.backend, &ctx.provider, &ServiceContext::new_for_testing(), &config, &metrics)
+            .await
+            .unwrap();
+        drop(config);
+
+        // The block is sent whether or not its traces could be fetched
+        assert!(matches!(status, SyncStatus::Full(1)));
+        assert_eq!(fetch_stream_receiver.recv().await.unwrap().unverified_block_number, Some(0));
+
+        traces_mock.assert_hits(if fetch_traces { 1 } else { 0 });
+        let received = trace_receiver.recv().await;
+        if expect_traces {
+            assert_eq!(received, Some(BlockTraces { block_number: 0, traces }));
+        } else {
+            assert_eq!(received, None);
+        }
+    }
+
+    /// Blocks are fetched ahead of the import, and are delivered in order even when later blocks are fetched first.
+    #[rstest]
+    #[tokio::test]
+    async fn test_sync_blocks_preserves_order(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        for block_number in 0..6 {
+            // Later blocks come back faster
+            ctx.mock_block_with_delay(block_number, Duration::from_millis(50 * (6 - block_number)));
+        }
+        ctx.mock_block_not_found(6);
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+
+        let (fetch_stream_sender, mut fetch_stream_receiver) = mpsc::channel(4);
+        let (once_caught_up_sender, _once_caught_up_receiver) = oneshot::channel();
+        let config = L2FetchConfig {
+            sync_parallelism: 6,
+            ..test_fetch_config(&ctx.backend, fetch_stream_sender, once_caught_up_sender)
+        };
+
+        let task = tokio::spawn({
+            let backend = Arc::clone(&ctx.backend);
+            let provider = Arc::clone(&ctx.provider);
+            async move {
+                let metrics = FetchMetrics::register().unwrap();
+                sync_blocks(&backend, &provider, &ServiceContext::new_for_testing(), &config, &metrics).await
+            }
+        });
+
+        // Nothing has been imported yet, but the fetch task already filled the buffer
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while fetch_stream_receiver.len() < 4 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("The fetch task did not fetch ahead");
+
+        for expected_block_number in 0..6 {
+            let block = fetch_stream_receiver.recv().await.expect("Channel closed unexpectedly");
+            assert_eq!(block.unverified_block_number, Some(expected_block_number));
+        }
+        let status = tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap().unwrap();
+        assert!(matches!(status, SyncStatus::Full(6)));
+    }
+
+    /// Once the block import has stopped, the fetch task stops instead of polling for new blocks forever.
+    #[rstest]
+    #[tokio::test]
+    async fn test_l2_fetch_task_stops_on_closed_channel(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        for block_number in 0..5 {
+            ctx.mock_block(block_number);
+        }
+        ctx.mock_class_hash(m_cairo_test_contracts::TEST_CONTRACT_SIERRA);
+
+        let (fetch_stream_sender, fetch_stream_receiver) = mpsc::channel(1);
+        drop(fetch_stream_receiver);
+        let (once_caught_up_sender, _once_caught_up_receiver) = oneshot::channel();
+        let config = L2FetchConfig {
+            sync_polling_interval: Some(Duration::from_millis(10)),
+            stop_on_sync: false,
+            ..test_fetch_config(&ctx.backend, fetch_stream_sender, once_caught_up_sender)
+        };
+
+        let res = tokio::time::timeout(
+            Duration::from_secs(5),
+            l2_fetch_task(
+                Arc::clone(&ctx.backend),
+                Arc::clone(&ctx.provider),
+                ServiceContext::new_for_testing(),
+                config,
+            ),
+        )
+        .await
+        .expect("The fetch task did not stop");
+        assert!(res.is_ok());
+    }
+
+    /// The failure budget is only exceeded by consecutive failures: a success in between resets it.
+    #[tokio::test(start_paused = true)]
+    async fn test_failure_budget_exceeded() {
+        async fn exceeded(feeder_health: &FeederHealth) -> Option<FetchError> {
+            tokio::time::timeout(Duration::from_secs(1), failure_budget_exceeded(feeder_health, 2)).await.ok()
+        }
+        let feeder_health = FeederHealth::new(u32::MAX);
+
+        feeder_health.on_failure();
+        feeder_health.on_failure();
+        assert!(exceeded(&feeder_health).await.is_none());
+        feeder_health.on_success();
+        feeder_health.on_failure();
+        feeder_health.on_failure();
+        assert!(exceeded(&feeder_health).await.is_none());
+
+        feeder_health.on_failure();
+        let err = exceeded(&feeder_health).await;
+        assert!(
+            matches!(err, Some(FetchError::TooManyFailures { failures: 3 })),
+            "Expected too many failures, got {err:?}"
+        );
+    }
+
+    /// The fetch task gives up with a fatal error once the feeder gateway keeps failing, instead of retrying.
+    #[rstest]
+    #[tokio::test]
+    async fn test_l2_fetch_task_max_consecutive_failures(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_server.mock(|when, then| {
+            when.any_request();
+            then.status(502).body("Bad Gateway");
+        });
+
+        let config = L2FetchConfig {
+            stop_on_sync: false,
+            sync_parallelism: 1,
+            max_consecutive_failures: Some(1),
+            ..test_fetch_config(&ctx.backend, ctx.fetch_stream_sender, ctx.once_caught_up_sender)
+        };
+
+        let err = tokio::time::timeout(
+            Duration::from_secs(10),
+            l2_fetch_task(
+                Arc::clone(&ctx.backend),
+                Arc::clone(&ctx.provider),
+                ServiceContext::new_for_testing(),
+                config,
+            ),
+        )
+        .await
+        .expect("The fetch task did not give up")
+        .unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<FetchError>(), Some(FetchError::TooManyFailures { failures }) if *failures > 1),
+            "Expected too many failures, got {err:#}"
+        );
+    }
 }
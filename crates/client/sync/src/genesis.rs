@@ -0,0 +1,296 @@
+//! Genesis state imported from a dump file with `--genesis-dump`. Forked chains often start from a large exported
+//! state, which the feeder gateway cannot return as a single block 0.
+use anyhow::Context;
+use mc_block_import::{
+    BlockImporter, BlockValidationContext, UnverifiedCommitments, UnverifiedFullBlock, UnverifiedHeader,
+};
+use mc_db::MadaraBackend;
+use mp_block::header::{GasPrices, L1DataAvailabilityMode};
+use mp_chain_config::{deserialize_starknet_version, StarknetVersion};
+use mp_class::class_update::{ClassUpdate, LegacyClassUpdate, SierraClassUpdate};
+use mp_class::ContractClass;
+use mp_state_update::{
+    ContractStorageDiffItem, DeclaredClassItem, DeployedContractItem, NonceUpdate, StateDiff, StorageEntry,
+};
+use serde::Deserialize;
+use starknet_types_core::felt::Felt;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Genesis dump to import when the database is empty.
+#[derive(Debug, Clone)]
+pub struct GenesisDumpConfig {
+    /// Path of the JSON [`GenesisDump`].
+    pub path: PathBuf,
+    /// Global state root of the chain once the dump is imported. A dump resulting in another state root is rejected.
+    pub state_root: Felt,
+}
+
+/// Full state of a chain at its genesis: the deployed contracts with their storage, and the declared classes.
+///
+/// The header and hash of block 0 on the network come along with the state: the imported block must have the same
+/// hash, otherwise block 1 could not be synced on top of it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisDump {
+    pub header: GenesisHeader,
+    pub block_hash: Felt,
+    #[serde(default)]
+    pub contracts: Vec<GenesisContract>,
+    #[serde(default)]
+    pub classes: Vec<GenesisClass>,
+}
+
+/// Header of block 0 on the network.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisHeader {
+    #[serde(default)]
+    pub parent_block_hash: Felt,
+    pub sequencer_address: Felt,
+    pub block_timestamp: u64,
+    #[serde(deserialize_with = "deserialize_starknet_version")]
+    pub protocol_version: StarknetVersion,
+    #[serde(default)]
+    pub l1_gas_price: GasPrices,
+    pub l1_da_mode: L1DataAvailabilityMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisContract {
+    pub address: Felt,
+    pub class_hash: Felt,
+    #[serde(default)]
+    pub nonce: Felt,
+    #[serde(default)]
+    pub storage: BTreeMap<Felt, Felt>,
+}
+
+/// A declared class. Sierra classes also come with their compiled class hash.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisClass {
+    pub class_hash: Felt,
+    #[serde(default)]
+    pub compiled_class_hash: Option<Felt>,
+    pub contract_class: ContractClass,
+}
+
+impl GenesisDump {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("Opening genesis dump {}", path.display()))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("Parsing genesis dump {}", path.display()))
+    }
+
+    /// Block 0 holding the whole state of the dump, with the header of the dump. Its hash is checked against the one
+    /// of the dump on import. When set, `state_root` is checked against the global state root computed on import.
+    pub fn into_genesis_block(self, state_root: Option<Felt>) -> anyhow::Result<UnverifiedFullBlock> {
+        let mut state_diff = StateDiff::default();
+        for GenesisContract { address, class_hash, nonce, storage } in self.contracts {
+            state_diff.deployed_contracts.push(DeployedContractItem { address, class_hash });
+            if nonce != Felt::ZERO {
+                state_diff.nonces.push(NonceUpdate { contract_address: address, nonce });
+            }
+            if !storage.is_empty() {
+                state_diff.storage_diffs.push(ContractStorageDiffItem {
+                    address,
+                    storage_entries: storage.into_iter().map(|(key, value)| StorageEntry { key, value }).collect(),
+                });
+            }
+        }
+
+        let mut declared_classes = Vec::with_capacity(self.classes.len());
+        for GenesisClass { class_hash, compiled_class_hash, contract_class } in self.classes {
+            let class_update = match (contract_class, compiled_class_hash) {
+                (ContractClass::Legacy(contract_class), None) => {
+                    state_diff.deprecated_declared_classes.push(class_hash);
+                    ClassUpdate::Legacy(LegacyClassUpdate {
+                        class_hash,
+                        contract_class: Arc::unwrap_or_clone(contract_class),
+                    })
+                }
+                (ContractClass::Sierra(contract_class), Some(compiled_class_hash)) => {
+                    state_diff.declared_classes.push(DeclaredClassItem { class_hash, compiled_class_hash });
+                    ClassUpdate::Sierra(SierraClassUpdate {
+                        class_hash,
+                        contract_class: Arc::unwrap_or_clone(contract_class),
+                        compiled_class_hash,
+                    })
+                }
+                (ContractClass::Legacy(_), Some(_)) => {
+                    anyhow::bail!("Legacy class {class_hash:#x} cannot have a compiled class hash")
+                }
+                (ContractClass::Sierra(_), None) => {
+                    anyhow::bail!("Sierra class {class_hash:#x} is missing its compiled class hash")
+                }
+            };
+            declared_classes.push(class_update.into());
+        }
+
+        let GenesisHeader {
+            parent_block_hash,
+            sequencer_address,
+            block_timestamp,
+            protocol_version,
+            l1_gas_price,
+            l1_da_mode,
+        } = self.header;
+        Ok(UnverifiedFullBlock {
+            unverified_block_number: Some(0),
+            header: UnverifiedHeader {
+                parent_block_hash: Some(parent_block_hash),
+                sequencer_address,
+                block_timestamp,
+                protocol_version,
+                l1_gas_price,
+                l1_da_mode,
+            },
+            state_diff,
+            declared_classes,
+            commitments: UnverifiedCommitments {
+                global_state_root: state_root,
+                block_hash: Some(self.block_hash),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+}
+
+/// Imports the genesis dump as block 0, instead of fetching block 0 from the feeder gateway. Nothing is done when the
+/// database already has blocks.
+pub async fn import_genesis_dump(
+    backend: &MadaraBackend,
+    block_importer: &BlockImporter,
+    config: &GenesisDumpConfig,
+) -> anyhow::Result<()> {
+    if backend.get_latest_block_n().context("Getting the latest block number")?.is_some() {
+        tracing::debug!("The database already has blocks, skipping the genesis dump");
+        return Ok(());
+    }
+
+    tracing::info!("📦 Importing the genesis state from {}", config.path.display());
+    // The dump can be large, it is read and parsed outside of the async runtime
+    let path = config.path.clone();
+    let dump = mp_utils::spawn_rayon_task(move || GenesisDump::from_file(&path)).await?;
+    let block = dump.into_genesis_block(Some(config.state_root)).context("Building the genesis block from the dump")?;
+    let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+    let res = block_importer.add_block(block, validation).await.context("Importing the genesis dump")?;
+
+    tracing::info!(
+        "✅ Imported the genesis dump as block #0 ({:#x}) with state root {:#x}",
+        res.block_hash,
+        res.header.global_state_root
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::utils::gateway::test_setup;
+    use mc_block_import::tests::block_import_utils::create_dummy_unverified_full_block;
+    use mc_block_import::BlockImportError;
+    use mc_db::db_block_id::DbBlockId;
+    use mp_chain_config::ChainConfig;
+    use rstest::rstest;
+
+    fn dump_json(block_hash: Felt) -> String {
+        format!(
+            r#"{{
+                "header": {{
+                    "sequencer_address": "0x1",
+                    "block_timestamp": 1700000000,
+                    "protocol_version": "0.13.2",
+                    "l1_da_mode": "BLOB"
+                }},
+                "block_hash": "{block_hash:#x}",
+                "contracts": [
+                    {{ "address": "0x100", "class_hash": "0xc1", "nonce": "0x1", "storage": {{ "0x1": "0x2", "0x3": "0x4" }} }},
+                    {{ "address": "0x200", "class_hash": "0xc2" }}
+                ]
+            }}"#
+        )
+    }
+
+    /// The state root and block hash of the imported dump must be the expected ones, and the sync then goes on with
+    /// block 1 on top of it.
+    #[rstest]
+    #[tokio::test]
+    async fn test_import_genesis_dump(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        let dump: GenesisDump = serde_json::from_str(&dump_json(Felt::ZERO)).unwrap();
+        assert_eq!(dump.contracts[0].storage.len(), 2);
+
+        // State root and hash of block 0 on the network, computed by a node importing the same block without
+        // checking them
+        let reference = MadaraBackend::open_for_testing(Arc::new(ChainConfig::madara_test()));
+        let validation = BlockValidationContext::new(reference.chain_config().chain_id.clone());
+        let mut block = dump.into_genesis_block(None).unwrap();
+        block.commitments.block_hash = None;
+        let reference_block = BlockImporter::new(Arc::clone(&reference), None)
+            .unwrap()
+            .add_block(block, validation.clone())
+            .await
+            .unwrap();
+        let (state_root, block_hash) = (reference_block.header.global_state_root, reference_block.block_hash);
+        assert_ne!(state_root, Felt::ZERO);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("genesis.json");
+        std::fs::write(&path, dump_json(block_hash)).unwrap();
+
+        let wrong_root = GenesisDumpConfig { path: path.clone(), state_root: Felt::ONE };
+        let other_backend = MadaraBackend::open_for_testing(Arc::new(ChainConfig::madara_test()));
+        let other_importer = BlockImporter::new(Arc::clone(&other_backend), None).unwrap();
+        let err = import_genesis_dump(&other_backend, &other_importer, &wrong_root).await.unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<BlockImportError>(),
+                Some(BlockImportError::GlobalStateRoot { got, expected }) if *got == state_root && *expected == Felt::ONE
+            ),
+            "Expected a state root mismatch, got {err:#}"
+        );
+        assert_eq!(other_backend.get_latest_block_n().unwrap(), None);
+
+        // A dump which does not give the block 0 of the network is rejected
+        let wrong_hash_path = dir.path().join("wrong_hash.json");
+        std::fs::write(&wrong_hash_path, dump_json(Felt::ONE)).unwrap();
+        let wrong_hash = GenesisDumpConfig { path: wrong_hash_path, state_root };
+        let err = import_genesis_dump(&other_backend, &other_importer, &wrong_hash).await.unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<BlockImportError>(),
+                Some(BlockImportError::BlockHash { got, expected }) if *got == block_hash && *expected == Felt::ONE
+            ),
+            "Expected a block hash mismatch, got {err:#}"
+        );
+        assert_eq!(other_backend.get_latest_block_n().unwrap(), None);
+
+        let block_importer = BlockImporter::new(Arc::clone(&backend), None).unwrap();
+        let config = GenesisDumpConfig { path, state_root };
+        import_genesis_dump(&backend, &block_importer, &config).await.unwrap();
+        let block_info = backend.get_block_info(&DbBlockId::Number(0)).unwrap().unwrap();
+        assert_eq!(block_info.as_nonpending().unwrap().header.global_state_root, state_root);
+
+        assert_eq!(crate::l2::next_block_to_sync(&backend).unwrap(), 1);
+
+        // Block 1 of the network extends the imported block 0
+        let block = UnverifiedFullBlock {
+            unverified_block_number: Some(1),
+            header: UnverifiedHeader {
+                parent_block_hash: Some(block_hash),
+                ..create_dummy_unverified_full_block().header
+            },
+            ..create_dummy_unverified_full_block()
+        };
+        block_importer.add_block(block, validation).await.unwrap();
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(1));
+
+        // The dump is only imported in an empty database
+        import_genesis_dump(&backend, &block_importer, &wrong_root).await.unwrap();
+    }
+}
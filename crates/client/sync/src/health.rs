@@ -0,0 +1,397 @@
+//! Sync health, used as a readiness signal by orchestrators.
+use crate::utils::{lock, read, write};
+use mc_db::MadaraBackend;
+use mp_utils::service::{MadaraService, ServiceContext};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+/// How far the local chain is from the tip of the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncHealth {
+    /// The tip of the network is not known yet, or no block has been imported.
+    Bootstrapping,
+    /// The node is more than `synced_threshold` blocks behind the tip.
+    SyncingBehind { lag: u64 },
+    /// The node is at most `synced_threshold` blocks behind the tip.
+    Synced,
+    /// The node has been more than the maximum sync lag behind the tip, without catching up, for longer than the
+    /// timeout. This usually means that the feeder gateway or the block verification is stuck.
+    Stalled { lag: u64 },
+    /// The L2 sync has been deactivated with `madara_syncDisable`, and no block is imported until
+    /// `madara_syncEnable`.
+    Paused,
+}
+
+impl SyncHealth {
+    /// Whether the node is caught up enough to serve traffic.
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Self::Synced)
+    }
+}
+
+/// Computes the [`SyncHealth`] from the latest imported block and the highest block of the network.
+pub fn sync_health(
+    current_block_number: Option<u64>,
+    highest_block_number: Option<u64>,
+    synced_threshold: u64,
+) -> SyncHealth {
+    let sync_lag =
+        current_block_number.zip(highest_block_number).map(|(current, highest)| highest.saturating_sub(current));
+    lag_health(sync_lag, synced_threshold)
+}
+
+/// Same as [`sync_health`], from the sync lag returned by [`SyncHealthTracker::sync_lag`].
+fn lag_health(sync_lag: Option<u64>, synced_threshold: u64) -> SyncHealth {
+    match sync_lag {
+        None => SyncHealth::Bootstrapping,
+        Some(lag) if lag > synced_threshold => SyncHealth::SyncingBehind { lag },
+        Some(_) => SyncHealth::Synced,
+    }
+}
+
+/// The node is reported as [`SyncHealth::Stalled`] when it stays more than `blocks` behind the tip for longer than
+/// `timeout` without its lag decreasing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxSyncLag {
+    pub blocks: u64,
+    pub timeout: Duration,
+}
+
+/// Last lag seen, and when it last decreased.
+struct LagTracking {
+    lag: Option<u64>,
+    decreased_at: Instant,
+    stalled: bool,
+}
+
+/// Keeps track of the highest block of the network as seen by the sync, so that other services can report on the
+/// sync health.
+pub struct SyncHealthTracker {
+    backend: Arc<MadaraBackend>,
+    highest_block_number: RwLock<Option<u64>>,
+    /// Latest block imported by the sync, as `block_n + 1`. `0` until the sync publishes it, in which case the
+    /// latest block of the database is used.
+    current_block_number: AtomicU64,
+    synced_threshold: u64,
+    max_lag: Option<MaxSyncLag>,
+    lag: Mutex<LagTracking>,
+    caught_up_polls: u32,
+    /// Number of consecutive polls of the tip for which the node has been caught up.
+    caught_up_streak: Mutex<u32>,
+    /// Services of the node, whose L2 sync is deactivated while the sync is paused.
+    services: OnceLock<ServiceContext>,
+}
+
+impl SyncHealthTracker {
+    pub fn new(backend: Arc<MadaraBackend>, synced_threshold: u64) -> Self {
+        Self {
+            backend,
+            highest_block_number: RwLock::new(None),
+            current_block_number: AtomicU64::new(0),
+            synced_threshold,
+            max_lag: None,
+            lag: Mutex::new(LagTracking { lag: None, decreased_at: Instant::now(), stalled: false }),
+            caught_up_polls: 0,
+            caught_up_streak: Mutex::new(0),
+            services: OnceLock::new(),
+        }
+    }
+
+    /// Reports the sync as [`SyncHealth::Paused`] while the L2 sync service of `ctx` is deactivated, which is how
+    /// `madara_syncDisable` and `madara_syncEnable` pause and resume it. Only the first context is kept.
+    pub fn watch_services(&self, ctx: &ServiceContext) {
+        let _ = self.services.set(ctx.clone());
+    }
+
+    /// Whether the L2 sync service is deactivated, see [`Self::watch_services`].
+    pub fn is_paused(&self) -> bool {
+        self.services.get().is_some_and(|ctx| !ctx.service_check(MadaraService::L2Sync as u8))
+    }
+
+    /// Reports the node as [`SyncHealth::Stalled`] when it does not catch up, see [`MaxSyncLag`].
+    pub fn with_max_lag(mut self, max_lag: MaxSyncLag) -> Self {
+        self.max_lag = Some(max_lag);
+        self
+    }
+
+    /// The node is only reported as [`SyncHealth::Synced`] once it has been caught up for `polls` consecutive polls
+    /// of the tip, so that a tip briefly matching the local chain, for example during a reorg, is not trusted.
+    pub fn with_caught_up_polls(mut self, polls: u32) -> Self {
+        self.caught_up_polls = polls;
+        self
+    }
+
+    /// Whether the node has been caught up for the consecutive polls of the tip required by
+    /// [`Self::with_caught_up_polls`]. This is always the case when no polls are required.
+    pub fn is_caught_up_confirmed(&self) -> bool {
+        *lock(&self.caught_up_streak) >= self.caught_up_polls
+    }
+
+    pub fn highest_block_number(&self) -> Option<u64> {
+        *read(&self.highest_block_number)
+    }
+
+    /// The highest block number only ever advances: a lagging feeder gateway response must not make the
+    /// node look further behind than it is. Use [`Self::reset_highest_block_number`] after a reorg.
+    pub fn set_highest_block_number(&self, block_n: u64) {
+        {
+            let mut highest_block_number = write(&self.highest_block_number);
+            if highest_block_number.map_or(true, |highest| block_n > highest) {
+                *highest_block_number = Some(block_n);
+            }
+        }
+        self.update_caught_up_streak();
+        // The sync polls the tip regularly, which keeps the lag tracking up to date between `/ready` requests.
+        self.sync_health();
+    }
+
+    /// Overwrites the highest block number, even if it goes backwards. This is meant for reorgs, where the tip of
+    /// the network really moved back.
+    pub fn reset_highest_block_number(&self, block_n: u64) {
+        *write(&self.highest_block_number) = Some(block_n);
+        self.update_caught_up_streak();
+    }
+
+    /// Published by the sync after each imported block, so that the sync health does not have to read the latest
+    /// block number from the database. It can move backwards after a reorg.
+    pub fn set_current_block_number(&self, block_n: u64) {
+        self.current_block_number.store(block_n.saturating_add(1), Ordering::Release);
+    }
+
+    /// Number of blocks the node is behind the tip of the network, or `None` when either block number is not known
+    /// yet. The sync health, the `sync_lag` metric and the progress log are all computed from it.
+    pub fn sync_lag(&self) -> Option<u64> {
+        let (current, highest) = (self.current_block_number(), self.highest_block_number());
+        Some(highest?.saturating_sub(current?))
+    }
+
+    /// Called on every poll of the tip, see [`Self::with_caught_up_polls`].
+    fn update_caught_up_streak(&self) {
+        let caught_up = self.current_sync_health() == SyncHealth::Synced;
+        let mut caught_up_streak = lock(&self.caught_up_streak);
+        *caught_up_streak = if caught_up { caught_up_streak.saturating_add(1) } else { 0 };
+    }
+
+    /// Current sync health. A database error is reported as [`SyncHealth::Bootstrapping`].
+    pub fn sync_health(&self) -> SyncHealth {
+        if self.is_paused() {
+            // The node is not expected to catch up while paused: the stall timeout starts over once it resumes.
+            lock(&self.lag).decreased_at = Instant::now();
+            return SyncHealth::Paused;
+        }
+        let sync_lag = self.sync_lag();
+        let health = match (lag_health(sync_lag, self.synced_threshold), sync_lag) {
+            (SyncHealth::Synced, Some(lag)) if !self.is_caught_up_confirmed() => SyncHealth::SyncingBehind { lag },
+            (health, _) => health,
+        };
+        self.check_stalled(health)
+    }
+
+    fn current_block_number(&self) -> Option<u64> {
+        if let Some(block_n) = self.current_block_number.load(Ordering::Acquire).checked_sub(1) {
+            return Some(block_n);
+        }
+        self.backend.get_latest_block_n().unwrap_or_else(|err| {
+            tracing::warn!("Failed to get the latest block number: {err:#}");
+            None
+        })
+    }
+
+    /// Sync health from the latest imported block, before the caught-up polls and stall checks.
+    fn current_sync_health(&self) -> SyncHealth {
+        lag_health(self.sync_lag(), self.synced_threshold)
+    }
+
+    /// Turns a [`SyncHealth::SyncingBehind`] health into [`SyncHealth::Stalled`] when the lag has stayed above the
+    /// maximum sync lag for too long.
+    fn check_stalled(&self, health: SyncHealth) -> SyncHealth {
+        let Some(MaxSyncLag { blocks, timeout }) = self.max_lag else { return health };
+        let lag = match health {
+            SyncHealth::SyncingBehind { lag } | SyncHealth::Stalled { lag } => lag,
+            SyncHealth::Synced => 0,
+            SyncHealth::Bootstrapping | SyncHealth::Paused => return health,
+        };
+
+        let mut tracking = lock(&self.lag);
+        if tracking.lag.map_or(true, |last_lag| lag < last_lag) || lag <= blocks {
+            tracking.decreased_at = Instant::now();
+        }
+        tracking.lag = Some(lag);
+
+        let stalled_for = tracking.decreased_at.elapsed();
+        let stalled = lag > blocks && stalled_for > timeout;
+        if stalled && !tracking.stalled {
+            tracing::warn!("⚠️ The sync has been {lag} blocks behind the tip for {stalled_for:?} without catching up");
+        }
+        tracking.stalled = stalled;
+        if stalled {
+            SyncHealth::Stalled { lag }
+        } else {
+            health
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::utils::gateway::test_setup;
+    use mc_block_import::tests::block_import_utils::create_dummy_unverified_full_block;
+    use mc_block_import::{BlockImporter, BlockValidationContext};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::no_local_block(None, Some(10), SyncHealth::Bootstrapping)]
+    #[case::unknown_tip(Some(10), None, SyncHealth::Bootstrapping)]
+    #[case::at_tip(Some(10), Some(10), SyncHealth::Synced)]
+    #[case::within_threshold(Some(5), Some(10), SyncHealth::Synced)]
+    #[case::beyond_threshold(Some(4), Some(10), SyncHealth::SyncingBehind { lag: 6 })]
+    #[case::far_behind(Some(0), Some(1000), SyncHealth::SyncingBehind { lag: 1000 })]
+    #[case::ahead_of_tip(Some(12), Some(10), SyncHealth::Synced)]
+    fn test_sync_health(#[case] current: Option<u64>, #[case] highest: Option<u64>, #[case] expected: SyncHealth) {
+        assert_eq!(sync_health(current, highest, 5), expected);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_sync_health_tracker(test_setup: Arc<MadaraBackend>) {
+        let tracker = SyncHealthTracker::new(Arc::clone(&test_setup), 0);
+        assert_eq!(tracker.sync_health(), SyncHealth::Bootstrapping);
+
+        tracker.set_highest_block_number(1);
+        assert_eq!(tracker.sync_health(), SyncHealth::Bootstrapping);
+
+        let block_import = BlockImporter::new(Arc::clone(&test_setup), None).unwrap();
+        let validation = BlockValidationContext::new(test_setup.chain_config().chain_id.clone());
+        let block = block_import.pre_validate(create_dummy_unverified_full_block(), validation.clone()).await.unwrap();
+        block_import.verify_apply(block, validation).await.unwrap();
+        assert_eq!(tracker.sync_health(), SyncHealth::SyncingBehind { lag: 1 });
+
+        tracker.reset_highest_block_number(0);
+        assert_eq!(tracker.sync_health(), SyncHealth::Synced);
+        assert!(tracker.sync_health().is_ready());
+    }
+
+    /// The node is reported as stalled once its lag stays above the maximum for the whole timeout, and recovers as
+    /// soon as the lag decreases.
+    #[rstest]
+    #[tokio::test]
+    async fn test_sync_health_tracker_stalled(test_setup: Arc<MadaraBackend>) {
+        let timeout = Duration::from_millis(50);
+        let tracker =
+            SyncHealthTracker::new(Arc::clone(&test_setup), 0).with_max_lag(MaxSyncLag { blocks: 2, timeout });
+
+        let block_import = BlockImporter::new(Arc::clone(&test_setup), None).unwrap();
+        let validation = BlockValidationContext::new(test_setup.chain_config().chain_id.clone());
+        let block = block_import.pre_validate(create_dummy_unverified_full_block(), validation.clone()).await.unwrap();
+        block_import.verify_apply(block, validation).await.unwrap();
+
+        // The fetch is stalled: the tip moves away and no block is imported anymore
+        tracker.set_highest_block_number(5);
+        assert_eq!(tracker.sync_health(), SyncHealth::SyncingBehind { lag: 5 });
+        tokio::time::sleep(timeout * 2).await;
+        assert_eq!(tracker.sync_health(), SyncHealth::Stalled { lag: 5 });
+        assert!(!tracker.sync_health().is_ready());
+        tracker.set_highest_block_number(6);
+        assert_eq!(tracker.sync_health(), SyncHealth::Stalled { lag: 6 });
+
+        tracker.reset_highest_block_number(4);
+        assert_eq!(tracker.sync_health(), SyncHealth::SyncingBehind { lag: 4 });
+    }
+
+    /// The node is only reported as synced once the tip has matched the local chain for several consecutive polls:
+    /// a tip which flickers back and forth, as during a reorg, is not trusted.
+    #[rstest]
+    #[tokio::test]
+    async fn test_sync_health_tracker_caught_up_polls(test_setup: Arc<MadaraBackend>) {
+        let tracker = SyncHealthTracker::new(Arc::clone(&test_setup), 0).with_caught_up_polls(3);
+
+        let block_import = BlockImporter::new(Arc::clone(&test_setup), None).unwrap();
+        let validation = BlockValidationContext::new(test_setup.chain_config().chain_id.clone());
+        let block = block_import.pre_validate(create_dummy_unverified_full_block(), validation.clone()).await.unwrap();
+        block_import.verify_apply(block, validation).await.unwrap();
+
+        tracker.set_highest_block_number(0);
+        tracker.set_highest_block_number(0);
+        assert_eq!(tracker.sync_health(), SyncHealth::SyncingBehind { lag: 0 });
+        assert!(!tracker.is_caught_up_confirmed());
+
+        // The tip moves away and comes back, the node has to be caught up for 3 polls again
+        tracker.set_highest_block_number(1);
+        assert_eq!(tracker.sync_health(), SyncHealth::SyncingBehind { lag: 1 });
+        tracker.reset_highest_block_number(0);
+        tracker.set_highest_block_number(0);
+        assert!(!tracker.sync_health().is_ready());
+
+        tracker.set_highest_block_number(0);
+        assert!(tracker.is_caught_up_confirmed());
+        assert_eq!(tracker.sync_health(), SyncHealth::Synced);
+    }
+
+    /// The sync is reported as paused between `madara_syncDisable` and `madara_syncEnable`, and the stall timeout
+    /// does not run meanwhile.
+    #[rstest]
+    #[tokio::test]
+    async fn test_sync_health_tracker_paused(test_setup: Arc<MadaraBackend>) {
+        let timeout = Duration::from_millis(50);
+        let tracker =
+            SyncHealthTracker::new(Arc::clone(&test_setup), 0).with_max_lag(MaxSyncLag { blocks: 2, timeout });
+        let ctx = ServiceContext::new_for_testing();
+        tracker.watch_services(&ctx);
+
+        let block_import = BlockImporter::new(Arc::clone(&test_setup), None).unwrap();
+        let validation = BlockValidationContext::new(test_setup.chain_config().chain_id.clone());
+        let block = block_import.pre_validate(create_dummy_unverified_full_block(), validation.clone()).await.unwrap();
+        block_import.verify_apply(block, validation).await.unwrap();
+        tracker.set_highest_block_number(5);
+        assert_eq!(tracker.sync_health(), SyncHealth::SyncingBehind { lag: 5 });
+
+        // madara_syncDisable
+        ctx.service_remove(MadaraService::L2Sync);
+        assert!(tracker.is_paused());
+        tokio::time::sleep(timeout * 2).await;
+        assert_eq!(tracker.sync_health(), SyncHealth::Paused);
+        assert!(!tracker.sync_health().is_ready());
+
+        // madara_syncEnable
+        ctx.service_add(MadaraService::L2Sync);
+        assert!(!tracker.is_paused());
+        assert_eq!(tracker.sync_health(), SyncHealth::SyncingBehind { lag: 5 });
+    }
+
+    #[rstest]
+    fn test_highest_block_number_is_monotonic(test_setup: Arc<MadaraBackend>) {
+        let tracker = SyncHealthTracker::new(test_setup, 0);
+        assert_eq!(tracker.highest_block_number(), None);
+
+        tracker.set_highest_block_number(10);
+        tracker.set_highest_block_number(8);
+        assert_eq!(tracker.highest_block_number(), Some(10));
+        tracker.set_highest_block_number(12);
+        assert_eq!(tracker.highest_block_number(), Some(12));
+
+        tracker.reset_highest_block_number(8);
+        assert_eq!(tracker.highest_block_number(), Some(8));
+    }
+
+    #[rstest]
+    fn test_sync_lag(test_setup: Arc<MadaraBackend>) {
+        let tracker = SyncHealthTracker::new(test_setup, 0);
+        // Nothing has been imported yet
+        tracker.set_highest_block_number(10);
+        assert_eq!(tracker.sync_lag(), None);
+
+        tracker.set_current_block_number(4);
+        assert_eq!(tracker.sync_lag(), Some(6));
+        tracker.set_current_block_number(7);
+        assert_eq!(tracker.sync_lag(), Some(3));
+        assert_eq!(tracker.sync_health(), SyncHealth::SyncingBehind { lag: 3 });
+        tracker.set_current_block_number(10);
+        assert_eq!(tracker.sync_lag(), Some(0));
+        assert_eq!(tracker.sync_health(), SyncHealth::Synced);
+        // A node ahead of the tip it knows about is not behind
+        tracker.set_current_block_number(12);
+        assert_eq!(tracker.sync_lag(), Some(0));
+    }
+}
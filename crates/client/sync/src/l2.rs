@@ -1,12 +1,19 @@
 //! Contains the code required to sync data from the feeder efficiently.
+use crate::events::{SyncEvent, SyncEvents};
 use crate::fetch::fetchers::fetch_pending_block_and_updates;
 use crate::fetch::l2_fetch_task;
+use crate::fetch::BlockTraces;
 use crate::fetch::L2FetchConfig;
-use crate::utils::trim_hash;
+use crate::health::SyncHealthTracker;
+use crate::metrics::reorg_metrics::ReorgMetrics;
+use crate::notifier::BlockNotifier;
+use crate::progress::{SyncProgressConfig, SyncProgressLog};
+use crate::timing::SyncTimings;
+use crate::utils::{lock, trim_hash};
 use anyhow::Context;
 use futures::{stream, StreamExt};
 use mc_block_import::{
-    BlockImportResult, BlockImporter, BlockValidationContext, PreValidatedBlock, UnverifiedFullBlock,
+    BlockImportError, BlockImportResult, BlockImporter, BlockValidationContext, PreValidatedBlock, UnverifiedFullBlock,
 };
 use mc_db::MadaraBackend;
 use mc_db::MadaraStorageError;
@@ -19,8 +26,10 @@ use mp_utils::service::ServiceContext;
 use mp_utils::{channel_wait_or_graceful_shutdown, wait_or_graceful_shutdown, PerfStopwatch};
 use starknet_api::core::ChainId;
 use starknet_types_core::felt::Felt;
+use std::collections::{BTreeSet, VecDeque};
+use std::num::NonZeroUsize;
 use std::pin::pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tokio::sync::{mpsc, oneshot};
 use tokio::task::JoinSet;
 use tokio::time::Duration;
@@ -39,22 +48,167 @@ pub enum L2SyncError {
 }
 
 /// Contains the latest Starknet verified state on L2
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct L2StateUpdate {
     pub block_number: u64,
     pub global_root: Felt,
     pub block_hash: Felt,
 }
 
+/// Default number of [`L2StateUpdate`]s kept by [`RecentStateUpdates`].
+pub const DEFAULT_RECENT_STATE_UPDATES: usize = 32;
+
+/// Ring buffer of the last verified [`L2StateUpdate`]s, so that the recent state roots can be looked up without
+/// reading the database. Shared by the sync and its embedders.
+#[derive(Debug)]
+pub struct RecentStateUpdates {
+    capacity: usize,
+    updates: Mutex<VecDeque<L2StateUpdate>>,
+}
+
+impl Default for RecentStateUpdates {
+    fn default() -> Self {
+        Self::new(DEFAULT_RECENT_STATE_UPDATES)
+    }
+}
+
+impl RecentStateUpdates {
+    /// Keeps the last `capacity` state updates, none when it is zero.
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, updates: Mutex::new(VecDeque::with_capacity(capacity)) }
+    }
+
+    /// Drops the oldest state update once the buffer is full.
+    pub fn push(&self, state_update: L2StateUpdate) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut updates = lock(&self.updates);
+        if updates.len() == self.capacity {
+            updates.pop_front();
+        }
+        updates.push_back(state_update);
+    }
+
+    /// The retained state updates, from the oldest to the latest.
+    pub fn recent_state_updates(&self) -> Vec<L2StateUpdate> {
+        lock(&self.updates).iter().cloned().collect()
+    }
+
+    /// The state update of block `block_number`, if it is still retained.
+    pub fn get(&self, block_number: u64) -> Option<L2StateUpdate> {
+        lock(&self.updates).iter().rev().find(|update| update.block_number == block_number).cloned()
+    }
+}
+
+/// Returns the latest verified state stored in the database, or `None` if no block has been
+/// imported yet.
+pub fn get_last_state_update(backend: &MadaraBackend) -> Result<Option<L2StateUpdate>, L2SyncError> {
+    let Some(block_info) = backend.get_block_info(&BlockId::Tag(BlockTag::Latest))? else { return Ok(None) };
+    let Some(block_info) = block_info.as_nonpending_owned() else { return Ok(None) };
+
+    Ok(Some(L2StateUpdate {
+        block_number: block_info.header.block_number,
+        global_root: block_info.header.global_state_root,
+        block_hash: block_info.block_hash,
+    }))
+}
+
+/// Returns the block after the tip of the database. If no block has been imported yet, this is the block after the
+/// checkpoint the database was started from, or the genesis block.
+pub fn next_block_to_sync(backend: &MadaraBackend) -> Result<u64, L2SyncError> {
+    if let Some(block_n) = backend.get_latest_block_n()? {
+        return Ok(block_n + 1);
+    }
+    Ok(backend.get_checkpoint_block()?.map_or(0, |checkpoint| checkpoint.block_number + 1))
+}
+
 pub struct L2VerifyApplyConfig {
     block_import: Arc<BlockImporter>,
     backup_every_n_blocks: Option<u64>,
     flush_every_n_blocks: u64,
     flush_every_n_seconds: u64,
+    /// Wait this long after each imported block, to pace the sync.
+    block_commit_throttle: Duration,
     stop_on_sync: bool,
     telemetry: TelemetryHandle,
     validation: BlockValidationContext,
     block_conv_receiver: mpsc::Receiver<PreValidatedBlock>,
+    notifier: Arc<dyn BlockNotifier>,
+    timings: Option<Arc<SyncTimings>>,
+    events: SyncEvents,
+    progress: Option<SyncProgressLog>,
+    /// Source of the sync lag for the progress log, the imported block numbers are published to it.
+    health: Arc<SyncHealthTracker>,
+    metrics: ReorgMetrics,
+    recent_state_updates: Arc<RecentStateUpdates>,
+    /// Feeder gateway whose parent blocks are walked to find the rollback depth of a reorg, unknown without it.
+    provider: Option<Arc<GatewayProvider>>,
+}
+
+/// Reorgs deeper than this many blocks are reported with an unknown rollback depth.
+const MAX_ROLLBACK_DEPTH: u64 = 1024;
+
+/// Number of local blocks after the common ancestor of the local chain and the one of the feeder gateway, found by
+/// walking the parents of `parent_block_hash` on the feeder gateway until one of them is a local block.
+async fn rollback_depth(
+    backend: &MadaraBackend,
+    provider: Option<&GatewayProvider>,
+    parent_block_hash: Felt,
+) -> Option<u64> {
+    let local_tip = backend.get_latest_block_n().ok().flatten()?;
+    let mut block_hash = parent_block_hash;
+    for _ in 0..=MAX_ROLLBACK_DEPTH.min(local_tip) {
+        if let Some(ancestor) = backend.get_block_n(&BlockId::Hash(block_hash)).ok().flatten() {
+            return Some(local_tip.saturating_sub(ancestor));
+        }
+        let block = match provider?.get_block(BlockId::Hash(block_hash)).await {
+            Ok(block) => block.non_pending_owned()?,
+            Err(err) => {
+                tracing::debug!("Getting the parent of the reorged block {block_hash:#x}: {err:#}");
+                return None;
+            }
+        };
+        block_hash = block.parent_block_hash;
+    }
+    None
+}
+
+/// Reports a block of the feeder gateway whose parent is not the local tip. The sync stops on a reorg, the rollback
+/// depth is the number of local blocks replaced by the chain of the feeder gateway. The tip of the network may have
+/// moved back, so the highest block number is reset to the new head.
+#[allow(clippy::too_many_arguments)]
+async fn report_reorg(
+    backend: &MadaraBackend,
+    provider: Option<&GatewayProvider>,
+    health: &SyncHealthTracker,
+    events: &SyncEvents,
+    metrics: &ReorgMetrics,
+    block_number: Option<u64>,
+    block_hash: Option<Felt>,
+    local_tip_hash: Felt,
+    parent_block_hash: Felt,
+) {
+    let rollback_depth = rollback_depth(backend, provider, parent_block_hash).await;
+    if let Some(block_number) = block_number {
+        health.reset_highest_block_number(block_number);
+    }
+    metrics.record_reorg();
+    let new_head = block_hash.map_or_else(|| "unknown".to_string(), |hash| format!("{hash:#x}"));
+    tracing::warn!(
+        block_number = ?block_number,
+        old_head = %format_args!("{local_tip_hash:#x}"),
+        new_head = %new_head,
+        rollback_depth = ?rollback_depth,
+        "🔀 Reorg detected: new head {new_head} has parent {parent_block_hash:#x}, but the local tip is {local_tip_hash:#x}"
+    );
+    events.emit(SyncEvent::ReorgDetected {
+        block_number,
+        block_hash,
+        local_tip_hash,
+        parent_block_hash,
+        rollback_depth,
+    });
 }
 
 #[tracing::instrument(skip(backend, ctx, config), fields(module = "Sync"))]
@@ -62,24 +216,87 @@ async fn l2_verify_and_apply_task(
     backend: Arc<MadaraBackend>,
     ctx: ServiceContext,
     config: L2VerifyApplyConfig,
+) -> anyhow::Result<()> {
+    let block_import = Arc::clone(&config.block_import);
+    let stop_on_sync = config.stop_on_sync;
+    let skip_blocks = config.validation.skip_blocks.clone();
+
+    let res = verify_and_apply_blocks(&backend, &ctx, config).await;
+
+    // The blocks imported so far are stored even when the import failed midway: their staged trie updates have to be
+    // committed on every exit path, or the tries would lag behind the stored blocks.
+    let commit = block_import.commit_staged_tries(&skip_blocks).await.context("Committing the staged trie updates");
+    match (res, commit) {
+        (Err(err), Err(commit_err)) => {
+            tracing::error!("❗ Failed to commit the staged trie updates: {commit_err:#}");
+            return Err(err);
+        }
+        (res, commit) => {
+            res?;
+            commit?;
+        }
+    }
+    backend.flush().context("Flushing database")?;
+
+    if stop_on_sync {
+        ctx.cancel_global()
+    }
+
+    Ok(())
+}
+
+async fn verify_and_apply_blocks(
+    backend: &Arc<MadaraBackend>,
+    ctx: &ServiceContext,
+    config: L2VerifyApplyConfig,
 ) -> anyhow::Result<()> {
     let L2VerifyApplyConfig {
         block_import,
         backup_every_n_blocks,
         flush_every_n_blocks,
         flush_every_n_seconds,
-        stop_on_sync,
+        block_commit_throttle,
+        stop_on_sync: _,
         telemetry,
         validation,
         mut block_conv_receiver,
+        notifier,
+        timings,
+        events,
+        mut progress,
+        health,
+        metrics,
+        recent_state_updates,
+        provider,
     } = config;
 
     let mut last_block_n = 0;
     let mut instant = std::time::Instant::now();
     let target_duration = std::time::Duration::from_secs(flush_every_n_seconds);
 
-    while let Some(block) = channel_wait_or_graceful_shutdown(pin!(block_conv_receiver.recv()), &ctx).await {
-        let BlockImportResult { header, block_hash } = block_import.verify_apply(block, validation.clone()).await?;
+    while let Some(block) = channel_wait_or_graceful_shutdown(pin!(block_conv_receiver.recv()), ctx).await {
+        let verify_apply_start = std::time::Instant::now();
+        let (block_number, new_block_hash) = (block.unverified_block_number, block.unverified_block_hash);
+        let res = block_import.verify_apply(block, validation.clone()).await;
+        if let Err(BlockImportError::ParentHash { got, expected }) = &res {
+            report_reorg(
+                backend,
+                provider.as_deref(),
+                &health,
+                &events,
+                &metrics,
+                block_number,
+                new_block_hash,
+                *expected,
+                *got,
+            )
+            .await;
+        }
+        let BlockImportResult { header, block_hash } = res?;
+        if let Some(timings) = &timings {
+            timings.update(header.block_number, |timing| timing.verify_apply = verify_apply_start.elapsed());
+            timings.finish(header.block_number);
+        }
 
         if header.block_number - last_block_n >= flush_every_n_blocks || instant.elapsed() >= target_duration {
             last_block_n = header.block_number;
@@ -99,6 +316,10 @@ async fn l2_verify_and_apply_task(
             block_hash,
             header.global_state_root
         );
+        health.set_current_block_number(header.block_number);
+        if let Some(progress) = &mut progress {
+            progress.on_block(header.block_number, health.sync_lag());
+        }
 
         telemetry.send(
             VerbosityLevel::Info,
@@ -110,16 +331,28 @@ async fn l2_verify_and_apply_task(
             }),
         );
 
+        events.emit(SyncEvent::BlockVerified {
+            block_number: header.block_number,
+            block_hash,
+            global_state_root: header.global_state_root,
+        });
+        let state_update =
+            L2StateUpdate { block_number: header.block_number, global_root: header.global_state_root, block_hash };
+        notifier.on_new_block(&state_update);
+        recent_state_updates.push(state_update);
+
         if backup_every_n_blocks.is_some_and(|backup_every_n_blocks| header.block_number % backup_every_n_blocks == 0) {
             tracing::info!("⏳ Backing up database at block {}...", header.block_number);
             let sw = PerfStopwatch::new();
             backend.backup().await.context("backing up database")?;
             tracing::info!("✅ Database backup is done ({:?})", sw.elapsed());
         }
-    }
 
-    if stop_on_sync {
-        ctx.cancel_global()
+        if !block_commit_throttle.is_zero()
+            && wait_or_graceful_shutdown(tokio::time::sleep(block_commit_throttle), ctx).await.is_none()
+        {
+            break;
+        }
     }
 
     Ok(())
@@ -130,19 +363,29 @@ async fn l2_block_conversion_task(
     output: mpsc::Sender<PreValidatedBlock>,
     block_import: Arc<BlockImporter>,
     validation: BlockValidationContext,
+    timings: Option<Arc<SyncTimings>>,
     ctx: ServiceContext,
 ) -> anyhow::Result<()> {
     // Items of this stream are futures that resolve to blocks, which becomes a regular stream of blocks
     // using futures buffered.
     let conversion_stream = stream::unfold(
-        (updates_receiver, block_import, validation.clone(), ctx.clone()),
-        |(mut updates_recv, block_import, validation, ctx)| async move {
+        (updates_receiver, block_import, validation.clone(), timings, ctx.clone()),
+        |(mut updates_recv, block_import, validation, timings, ctx)| async move {
             channel_wait_or_graceful_shutdown(updates_recv.recv(), &ctx).await.map(|block| {
                 let block_import_ = Arc::clone(&block_import);
                 let validation_ = validation.clone();
+                let timings_ = timings.clone();
                 (
-                    async move { block_import_.pre_validate(block, validation_).await },
-                    (updates_recv, block_import, validation, ctx),
+                    async move {
+                        let block_n = block.unverified_block_number;
+                        let start = std::time::Instant::now();
+                        let block = block_import_.pre_validate(block, validation_).await;
+                        if let (Some(timings), Some(block_n)) = (timings_, block_n) {
+                            timings.update(block_n, |timing| timing.pre_validate = start.elapsed());
+                        }
+                        block
+                    },
+                    (updates_recv, block_import, validation, timings, ctx),
                 )
             })
         },
@@ -158,11 +401,28 @@ async fn l2_block_conversion_task(
     Ok(())
 }
 
+/// Hands the fetched block traces over to the notifier.
+async fn l2_traces_task(
+    mut trace_receiver: mpsc::Receiver<BlockTraces>,
+    notifier: Arc<dyn BlockNotifier>,
+    ctx: ServiceContext,
+) -> anyhow::Result<()> {
+    while let Some(traces) = channel_wait_or_graceful_shutdown(trace_receiver.recv(), &ctx).await {
+        notifier.on_block_traces(&traces);
+    }
+    Ok(())
+}
+
 struct L2PendingBlockConfig {
     block_import: Arc<BlockImporter>,
     once_caught_up_receiver: oneshot::Receiver<()>,
     pending_block_poll_interval: Duration,
+    /// Do not poll the pending block at all, the database then only holds the closed blocks.
+    disable_pending: bool,
     validation: BlockValidationContext,
+    /// The pending block is only trusted once the caught-up condition is confirmed, see
+    /// [`SyncHealthTracker::with_caught_up_polls`].
+    health: Arc<SyncHealthTracker>,
 }
 
 async fn l2_pending_block_task(
@@ -171,8 +431,14 @@ async fn l2_pending_block_task(
     ctx: ServiceContext,
     config: L2PendingBlockConfig,
 ) -> anyhow::Result<()> {
-    let L2PendingBlockConfig { block_import, once_caught_up_receiver, pending_block_poll_interval, validation } =
-        config;
+    let L2PendingBlockConfig {
+        block_import,
+        once_caught_up_receiver,
+        pending_block_poll_interval,
+        disable_pending,
+        validation,
+        health,
+    } = config;
 
     // clear pending status
     {
@@ -180,6 +446,11 @@ async fn l2_pending_block_task(
         tracing::debug!("l2_pending_block_task: startup: wrote no pending");
     }
 
+    if disable_pending {
+        tracing::debug!("Pending block polling is disabled");
+        return Ok(());
+    }
+
     // we start the pending block task only once the node has been fully sync
     if once_caught_up_receiver.await.is_err() {
         // channel closed
@@ -191,18 +462,31 @@ async fn l2_pending_block_task(
     let mut interval = tokio::time::interval(pending_block_poll_interval);
     interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
     while wait_or_graceful_shutdown(interval.tick(), &ctx).await.is_some() {
+        if !health.is_caught_up_confirmed() {
+            tracing::debug!("Waiting for the tip to be confirmed before getting the pending block");
+            continue;
+        }
         tracing::debug!("Getting pending block...");
 
         let current_block_hash = backend
             .get_block_hash(&BlockId::Tag(BlockTag::Latest))
             .context("Getting latest block hash")?
             .unwrap_or(/* genesis parent block hash */ Felt::ZERO);
-        let Some(block) =
-            fetch_pending_block_and_updates(current_block_hash, &backend.chain_config().chain_id, &provider, &ctx)
-                .await
-                .context("Getting pending block from FGW")?
-        else {
-            continue;
+        // The pending block is best effort: a failure here must not stop the sync of closed blocks.
+        let block = match fetch_pending_block_and_updates(
+            current_block_hash,
+            &backend.chain_config().chain_id,
+            &provider,
+            &ctx,
+        )
+        .await
+        {
+            Ok(Some(block)) => block,
+            Ok(None) => continue,
+            Err(err) => {
+                tracing::debug!("Error while getting the pending block from the feeder gateway: {err:#}");
+                continue;
+            }
         };
 
         // HACK(see issue #239): The latest block in db may not match the pending parent block hash
@@ -222,16 +506,30 @@ async fn l2_pending_block_task(
 }
 
 pub struct L2SyncConfig {
-    pub first_block: u64,
+    /// `None` resumes from [`next_block_to_sync`], which is read once the sync starts and so is the block after the
+    /// genesis dump when one has just been imported.
+    pub first_block: Option<u64>,
     pub n_blocks_to_sync: Option<u64>,
     pub stop_on_sync: bool,
     pub sync_parallelism: u8,
+    pub class_prefetch_window: Option<NonZeroUsize>,
+    pub sequencer_public_key: Option<Felt>,
+    pub verify_sample_size: Option<NonZeroUsize>,
+    pub max_consecutive_failures: Option<u32>,
+    pub compile_classes: bool,
+    pub fetch_buffer_size: usize,
     pub verify: bool,
+    pub trust_feeder: bool,
+    pub skip_blocks: BTreeSet<u64>,
+    /// Re-fetched blocks which are already stored are checked against the stored ones, see `--resync-tail`.
+    pub reapply_stored_blocks: bool,
     pub sync_polling_interval: Option<Duration>,
     pub backup_every_n_blocks: Option<u64>,
     pub flush_every_n_blocks: u64,
     pub flush_every_n_seconds: u64,
+    pub block_commit_throttle: Duration,
     pub pending_block_poll_interval: Duration,
+    pub disable_pending: bool,
     pub ignore_block_order: bool,
     pub warp_update: bool,
     pub warp_update_port_rpc: u16,
@@ -239,6 +537,13 @@ pub struct L2SyncConfig {
     pub chain_id: ChainId,
     pub telemetry: TelemetryHandle,
     pub block_importer: Arc<BlockImporter>,
+    pub notifier: Arc<dyn BlockNotifier>,
+    pub health: Arc<SyncHealthTracker>,
+    pub recent_state_updates: Arc<RecentStateUpdates>,
+    pub fetch_traces: bool,
+    pub sync_timing: bool,
+    pub events: SyncEvents,
+    pub progress: SyncProgressConfig,
 }
 
 /// Spawns workers to fetch blocks and state updates from the feeder.
@@ -249,10 +554,21 @@ pub async fn sync(
     ctx: ServiceContext,
     config: L2SyncConfig,
 ) -> anyhow::Result<()> {
-    let (fetch_stream_sender, fetch_stream_receiver) = mpsc::channel(8);
+    let (fetch_stream_sender, fetch_stream_receiver) = mpsc::channel(config.fetch_buffer_size);
     let (block_conv_sender, block_conv_receiver) = mpsc::channel(4);
     let provider = Arc::new(provider);
     let (once_caught_up_sender, once_caught_up_receiver) = oneshot::channel();
+    let (trace_sender, trace_receiver) = if config.fetch_traces {
+        let (trace_sender, trace_receiver) = mpsc::channel(8);
+        (Some(trace_sender), Some(trace_receiver))
+    } else {
+        (None, None)
+    };
+    let timings = config.sync_timing.then(|| Arc::new(SyncTimings::default()));
+    let first_block = match config.first_block {
+        Some(first_block) => first_block,
+        None => next_block_to_sync(backend).context("Getting the next block to sync")?,
+    };
 
     // [Fetch task] ==new blocks and updates=> [Block conversion task] ======> [Verification and apply
     // task]
@@ -265,10 +581,12 @@ pub async fn sync(
     // starves the tokio worker
     let validation = BlockValidationContext {
         trust_transaction_hashes: false,
-        trust_global_tries: !config.verify,
+        trust_global_tries: !config.verify || config.trust_feeder,
         chain_id: config.chain_id,
-        trust_class_hashes: false,
+        trust_class_hashes: config.trust_feeder,
         ignore_block_order: config.ignore_block_order,
+        skip_blocks: config.skip_blocks,
+        reapply_stored_blocks: config.reapply_stored_blocks,
     };
 
     let mut join_set = JoinSet::new();
@@ -277,16 +595,25 @@ pub async fn sync(
         Arc::clone(&provider),
         ctx.clone(),
         L2FetchConfig {
-            first_block: config.first_block,
+            first_block,
             fetch_stream_sender,
             once_caught_up_sender,
             sync_polling_interval: config.sync_polling_interval,
             n_blocks_to_sync: config.n_blocks_to_sync,
             stop_on_sync: config.stop_on_sync,
             sync_parallelism: config.sync_parallelism as usize,
+            class_prefetch_window: config.class_prefetch_window,
+            sequencer_public_key: config.sequencer_public_key,
+            verify_sample_size: config.verify_sample_size,
+            max_consecutive_failures: config.max_consecutive_failures,
+            compile_classes: config.compile_classes,
             warp_update: config.warp_update,
             warp_update_port_rpc: config.warp_update_port_rpc,
             warp_update_port_fgw: config.warp_update_port_fgw,
+            health: Arc::clone(&config.health),
+            trace_sender,
+            timings: timings.clone(),
+            events: config.events.clone(),
         },
     ));
     join_set.spawn(l2_block_conversion_task(
@@ -294,6 +621,7 @@ pub async fn sync(
         block_conv_sender,
         Arc::clone(&config.block_importer),
         validation.clone(),
+        timings.clone(),
         ctx.clone(),
     ));
     join_set.spawn(l2_verify_and_apply_task(
@@ -304,12 +632,24 @@ pub async fn sync(
             backup_every_n_blocks: config.backup_every_n_blocks,
             flush_every_n_blocks: config.flush_every_n_blocks,
             flush_every_n_seconds: config.flush_every_n_seconds,
+            block_commit_throttle: config.block_commit_throttle,
             stop_on_sync: config.stop_on_sync,
             telemetry: config.telemetry,
             validation: validation.clone(),
             block_conv_receiver,
+            notifier: Arc::clone(&config.notifier),
+            timings,
+            events: config.events,
+            progress: Some(SyncProgressLog::new(config.progress)),
+            health: Arc::clone(&config.health),
+            metrics: ReorgMetrics::register().context("Registering metrics for the verify and apply task")?,
+            recent_state_updates: config.recent_state_updates,
+            provider: Some(Arc::clone(&provider)),
         },
     ));
+    if let Some(trace_receiver) = trace_receiver {
+        join_set.spawn(l2_traces_task(trace_receiver, config.notifier, ctx.clone()));
+    }
     join_set.spawn(l2_pending_block_task(
         Arc::clone(backend),
         provider,
@@ -318,7 +658,9 @@ pub async fn sync(
             block_import: Arc::clone(&config.block_importer),
             once_caught_up_receiver,
             pending_block_poll_interval: config.pending_block_poll_interval,
+            disable_pending: config.disable_pending,
             validation: validation.clone(),
+            health: config.health,
         },
     ));
 
@@ -332,20 +674,50 @@ pub async fn sync(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::notifier::NoopNotifier;
     use crate::tests::utils::gateway::{test_setup, TestContext};
     use mc_block_import::tests::block_import_utils::create_dummy_unverified_full_block;
-    use mc_block_import::BlockImporter;
-    use mc_db::{db_block_id::DbBlockId, MadaraBackend};
+    use mc_block_import::{check_global_state_roots, BlockImporter, UnverifiedCommitments, UnverifiedHeader};
+    use mc_db::{db_block_id::DbBlockId, MadaraBackend, TrieLogConfig};
 
     use mc_telemetry::TelemetryService;
     use mp_block::header::L1DataAvailabilityMode;
     use mp_block::MadaraBlock;
-    use mp_chain_config::StarknetVersion;
+    use mp_chain_config::{ChainConfig, StarknetVersion};
+    use mp_state_update::{ContractStorageDiffItem, StateDiff, StorageEntry};
     use rstest::rstest;
     use starknet_types_core::felt::Felt;
+    use std::num::NonZeroU64;
     use std::sync::Arc;
     use tokio::sync::mpsc;
 
+    /// Configuration of the verify and apply task used by the tests, which override the fields they exercise.
+    fn test_verify_apply_config(
+        backend: &Arc<MadaraBackend>,
+        block_import: Arc<BlockImporter>,
+        block_conv_receiver: mpsc::Receiver<PreValidatedBlock>,
+    ) -> L2VerifyApplyConfig {
+        L2VerifyApplyConfig {
+            block_import,
+            backup_every_n_blocks: None,
+            flush_every_n_blocks: 1,
+            flush_every_n_seconds: 10,
+            block_commit_throttle: Duration::ZERO,
+            stop_on_sync: false,
+            telemetry: TelemetryService::new(true, vec![]).unwrap().new_handle(),
+            validation: BlockValidationContext::new(backend.chain_config().chain_id.clone()),
+            block_conv_receiver,
+            notifier: Arc::new(NoopNotifier),
+            timings: None,
+            events: SyncEvents::default(),
+            progress: None,
+            health: Arc::new(SyncHealthTracker::new(backend.clone(), 0)),
+            metrics: ReorgMetrics::register().unwrap(),
+            recent_state_updates: Default::default(),
+            provider: None,
+        }
+    }
+
     /// Test the `l2_verify_and_apply_task` function.
     ///
     ///
@@ -367,10 +739,12 @@ mod tests {
     #[tokio::test]
     async fn test_l2_verify_and_apply_task(test_setup: Arc<MadaraBackend>) {
         let backend = test_setup;
+        assert_eq!(get_last_state_update(&backend).unwrap(), None);
         let (block_conv_sender, block_conv_receiver) = mpsc::channel(100);
         let block_import = Arc::new(BlockImporter::new(backend.clone(), None).unwrap());
         let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
-        let telemetry = TelemetryService::new(true, vec![]).unwrap().new_handle();
+        let timings = Arc::new(SyncTimings::default());
+        timings.update(0, |timing| timing.fetch_block = Duration::from_millis(1));
 
         let mock_block = create_dummy_unverified_full_block();
 
@@ -378,14 +752,9 @@ mod tests {
             backend.clone(),
             ServiceContext::new_for_testing(),
             L2VerifyApplyConfig {
-                block_import: block_import.clone(),
                 backup_every_n_blocks: Some(1),
-                flush_every_n_blocks: 1,
-                flush_every_n_seconds: 10,
-                stop_on_sync: false,
-                telemetry,
-                validation: validation.clone(),
-                block_conv_receiver,
+                timings: Some(Arc::clone(&timings)),
+                ..test_verify_apply_config(&backend, block_import.clone(), block_conv_receiver)
             },
         ));
 
@@ -400,6 +769,9 @@ mod tests {
             Err(_) => panic!("Timeout reached while waiting for task completion"),
         }
 
+        // The timing of the block is logged and forgotten once it has been imported
+        assert_eq!(timings.finish(0), None);
+
         let applied_block = backend.get_block(&DbBlockId::Number(0)).unwrap();
         assert!(applied_block.is_some(), "The block was not applied correctly");
         let applied_block = MadaraBlock::try_from(applied_block.unwrap()).unwrap();
@@ -417,6 +789,513 @@ mod tests {
         assert_eq!(applied_block.info.header.l1_gas_price.eth_l1_gas_price, 0, "L1 gas price (ETH) does not match");
         assert_eq!(applied_block.info.header.l1_gas_price.strk_l1_gas_price, 0, "L1 gas price (STRK) does not match");
         assert_eq!(applied_block.info.header.l1_da_mode, L1DataAvailabilityMode::Blob, "L1 DA mode does not match");
+
+        assert_eq!(
+            get_last_state_update(&backend).unwrap(),
+            Some(L2StateUpdate {
+                block_number: 0,
+                global_root: applied_block.info.header.global_state_root,
+                block_hash: applied_block.info.block_hash,
+            }),
+            "Last state update does not match the applied block"
+        );
+    }
+
+    #[derive(Default)]
+    struct RecordingNotifier(std::sync::Mutex<Vec<u64>>);
+
+    impl BlockNotifier for RecordingNotifier {
+        fn on_new_block(&self, update: &L2StateUpdate) {
+            self.0.lock().unwrap().push(update.block_number);
+        }
+    }
+
+    /// Checks that the `l2_verify_and_apply_task` calls the block notifier once the block has been
+    /// imported.
+    #[rstest]
+    #[tokio::test]
+    async fn test_l2_verify_and_apply_task_notifies(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        let (block_conv_sender, block_conv_receiver) = mpsc::channel(100);
+        let block_import = Arc::new(BlockImporter::new(backend.clone(), None).unwrap());
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let notifier = Arc::new(RecordingNotifier::default());
+        let recent_state_updates = Arc::new(RecentStateUpdates::new(4));
+
+        let task_handle = tokio::spawn(l2_verify_and_apply_task(
+            backend.clone(),
+            ServiceContext::new_for_testing(),
+            L2VerifyApplyConfig {
+                notifier: notifier.clone(),
+                recent_state_updates: recent_state_updates.clone(),
+                ..test_verify_apply_config(&backend, block_import.clone(), block_conv_receiver)
+            },
+        ));
+
+        let mock_block = create_dummy_unverified_full_block();
+        let mock_pre_validated_block = block_import.pre_validate(mock_block, validation.clone()).await.unwrap();
+        block_conv_sender.send(mock_pre_validated_block).await.unwrap();
+        drop(block_conv_sender);
+
+        tokio::time::timeout(std::time::Duration::from_secs(120), task_handle)
+            .await
+            .expect("Timeout reached while waiting for task completion")
+            .expect("Task panicked")
+            .expect("Task failed");
+
+        assert_eq!(*notifier.0.lock().unwrap(), vec![0]);
+        assert_eq!(
+            recent_state_updates.recent_state_updates(),
+            vec![get_last_state_update(&backend).unwrap().unwrap()]
+        );
+    }
+
+    /// Without a first block, the sync starts at genesis on an empty database and after the tip otherwise.
+    #[rstest]
+    #[tokio::test]
+    async fn test_next_block_to_sync(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        assert_eq!(next_block_to_sync(&backend).unwrap(), 0);
+
+        let block_import = BlockImporter::new(backend.clone(), None).unwrap();
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        for block_number in 0..3 {
+            let block = UnverifiedFullBlock {
+                unverified_block_number: Some(block_number),
+                header: UnverifiedHeader { parent_block_hash: None, ..create_dummy_unverified_full_block().header },
+                ..create_dummy_unverified_full_block()
+            };
+            let block = block_import.pre_validate(block, validation.clone()).await.unwrap();
+            block_import.verify_apply(block, validation.clone()).await.unwrap();
+        }
+        assert_eq!(next_block_to_sync(&backend).unwrap(), 3);
+    }
+
+    /// Only the `capacity` latest state updates are retained.
+    #[rstest]
+    #[case::empty(3, 0)]
+    #[case::not_full(3, 2)]
+    #[case::wrapped(3, 10)]
+    #[case::disabled(0, 5)]
+    fn test_recent_state_updates(#[case] capacity: usize, #[case] pushed: u64) {
+        let state_update = |block_number| L2StateUpdate {
+            block_number,
+            global_root: Felt::from(block_number),
+            block_hash: Felt::from(block_number + 1000),
+        };
+        let recent = RecentStateUpdates::new(capacity);
+        for block_number in 0..pushed {
+            recent.push(state_update(block_number));
+        }
+
+        let expected: Vec<_> = (pushed.saturating_sub(capacity as u64)..pushed).map(state_update).collect();
+        assert_eq!(recent.recent_state_updates(), expected);
+        if let Some(latest) = expected.last() {
+            assert_eq!(recent.get(latest.block_number).as_ref(), Some(latest));
+        }
+        assert_eq!(recent.get(pushed), None);
+        if pushed > capacity as u64 {
+            assert_eq!(recent.get(pushed - capacity as u64 - 1), None);
+        }
+    }
+
+    /// Trie updates staged by `--trie-commit-interval` are committed when the sync is stopped in the middle of a
+    /// batch, and committing them again does nothing.
+    #[rstest]
+    #[tokio::test]
+    async fn test_l2_verify_and_apply_task_commits_staged_tries_on_shutdown(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let block = UnverifiedFullBlock {
+            state_diff: StateDiff {
+                storage_diffs: vec![ContractStorageDiffItem {
+                    address: Felt::ONE,
+                    storage_entries: vec![StorageEntry { key: Felt::ONE, value: Felt::TWO }],
+                }],
+                ..Default::default()
+            },
+            ..create_dummy_unverified_full_block()
+        };
+
+        // State root of the block when the tries are committed right away
+        let reference = MadaraBackend::open_for_testing(Arc::new(ChainConfig::madara_test()));
+        let reference_import = BlockImporter::new(reference, None).unwrap();
+        let pre_validated = reference_import.pre_validate(block.clone(), validation.clone()).await.unwrap();
+        let global_state_root =
+            reference_import.verify_apply(pre_validated, validation.clone()).await.unwrap().header.global_state_root;
+
+        let (block_conv_sender, block_conv_receiver) = mpsc::channel(100);
+        let block_import = Arc::new(
+            BlockImporter::new(backend.clone(), None).unwrap().with_trie_commit_interval(NonZeroU64::new(10).unwrap()),
+        );
+        let events = SyncEvents::default();
+        let mut receiver = events.subscribe_sync_events();
+        let ctx = ServiceContext::new_for_testing();
+
+        let task_handle = tokio::spawn(l2_verify_and_apply_task(
+            backend.clone(),
+            ctx.clone(),
+            L2VerifyApplyConfig {
+                events,
+                ..test_verify_apply_config(&backend, block_import.clone(), block_conv_receiver)
+            },
+        ));
+
+        let block = UnverifiedFullBlock {
+            commitments: UnverifiedCommitments { global_state_root: Some(global_state_root), ..Default::default() },
+            ..block
+        };
+        block_conv_sender.send(block_import.pre_validate(block, validation).await.unwrap()).await.unwrap();
+        assert!(matches!(receiver.recv().await.unwrap(), SyncEvent::BlockVerified { block_number: 0, .. }));
+
+        // The block is staged: the sync is stopped while blocks can still be sent
+        ctx.cancel_global();
+        tokio::time::timeout(std::time::Duration::from_secs(120), task_handle)
+            .await
+            .expect("Timeout reached while waiting for task completion")
+            .expect("Task panicked")
+            .expect("Task failed");
+
+        assert_eq!(check_global_state_roots(&backend, 0..=0).unwrap(), None);
+        block_import.commit_staged_tries(&BTreeSet::new()).await.unwrap();
+        assert_eq!(check_global_state_roots(&backend, 0..=0).unwrap(), None);
+    }
+
+    /// When a block fails to import in the middle of a batch, the trie updates staged for the blocks stored before it
+    /// are still committed.
+    #[rstest]
+    #[tokio::test]
+    async fn test_l2_verify_and_apply_task_commits_staged_tries_on_error(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let block = UnverifiedFullBlock {
+            state_diff: StateDiff {
+                storage_diffs: vec![ContractStorageDiffItem {
+                    address: Felt::ONE,
+                    storage_entries: vec![StorageEntry { key: Felt::ONE, value: Felt::TWO }],
+                }],
+                ..Default::default()
+            },
+            ..create_dummy_unverified_full_block()
+        };
+
+        // State root of the block when the tries are committed right away
+        let reference = MadaraBackend::open_for_testing(Arc::new(ChainConfig::madara_test()));
+        let reference_import = BlockImporter::new(reference, None).unwrap();
+        let pre_validated = reference_import.pre_validate(block.clone(), validation.clone()).await.unwrap();
+        let global_state_root =
+            reference_import.verify_apply(pre_validated, validation.clone()).await.unwrap().header.global_state_root;
+
+        let (block_conv_sender, block_conv_receiver) = mpsc::channel(100);
+        let block_import = Arc::new(
+            BlockImporter::new(backend.clone(), None).unwrap().with_trie_commit_interval(NonZeroU64::new(10).unwrap()),
+        );
+
+        let task_handle = tokio::spawn(l2_verify_and_apply_task(
+            backend.clone(),
+            ServiceContext::new_for_testing(),
+            test_verify_apply_config(&backend, block_import.clone(), block_conv_receiver),
+        ));
+
+        let block = UnverifiedFullBlock {
+            commitments: UnverifiedCommitments { global_state_root: Some(global_state_root), ..Default::default() },
+            ..block
+        };
+        block_conv_sender.send(block_import.pre_validate(block, validation.clone()).await.unwrap()).await.unwrap();
+        // Block #1 does not extend block #0
+        let block = UnverifiedFullBlock {
+            unverified_block_number: Some(1),
+            header: UnverifiedHeader {
+                parent_block_hash: Some(Felt::from(0xbad)),
+                ..create_dummy_unverified_full_block().header
+            },
+            ..create_dummy_unverified_full_block()
+        };
+        block_conv_sender.send(block_import.pre_validate(block, validation).await.unwrap()).await.unwrap();
+
+        let res = tokio::time::timeout(std::time::Duration::from_secs(120), task_handle)
+            .await
+            .expect("Timeout reached while waiting for task completion")
+            .expect("Task panicked");
+        assert!(res.is_err(), "Block #1 must not be imported");
+
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(0));
+        assert_eq!(backend.get_tries_block_n().unwrap(), Some(0));
+        assert_eq!(check_global_state_roots(&backend, 0..=0).unwrap(), None);
+    }
+
+    /// With `--resync-tail`, the re-fetched tail blocks are verified again against the stored ones without changing
+    /// the state roots, and the sync goes on past the tail.
+    #[rstest]
+    #[tokio::test]
+    async fn test_l2_verify_and_apply_task_reapplies_stored_blocks() {
+        // Trie logs are kept to check the state roots of the past blocks
+        let backend = MadaraBackend::open_for_testing_with_trie_logs(
+            Arc::new(ChainConfig::madara_test()),
+            TrieLogConfig { max_saved_trie_logs: 16, ..Default::default() },
+        );
+        let block_import = Arc::new(BlockImporter::new(backend.clone(), None).unwrap());
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let block = |block_number: u64| UnverifiedFullBlock {
+            unverified_block_number: Some(block_number),
+            header: UnverifiedHeader { parent_block_hash: None, ..create_dummy_unverified_full_block().header },
+            state_diff: StateDiff {
+                storage_diffs: vec![ContractStorageDiffItem {
+                    address: Felt::ONE,
+                    storage_entries: vec![StorageEntry { key: Felt::from(block_number), value: Felt::TWO }],
+                }],
+                ..Default::default()
+            },
+            ..create_dummy_unverified_full_block()
+        };
+
+        let mut stored = vec![];
+        for block_number in 0..3 {
+            stored.push(block_import.add_block(block(block_number), validation.clone()).await.unwrap());
+        }
+
+        let (block_conv_sender, block_conv_receiver) = mpsc::channel(100);
+        let events = SyncEvents::default();
+        let mut receiver = events.subscribe_sync_events();
+        let validation = validation.reapply_stored_blocks(true);
+        let task_handle = tokio::spawn(l2_verify_and_apply_task(
+            backend.clone(),
+            ServiceContext::new_for_testing(),
+            L2VerifyApplyConfig {
+                validation: validation.clone(),
+                events,
+                ..test_verify_apply_config(&backend, block_import.clone(), block_conv_receiver)
+            },
+        ));
+
+        // The last two blocks are re-fetched, followed by a new block
+        for block_number in 1..4 {
+            let block = block_import.pre_validate(block(block_number), validation.clone()).await.unwrap();
+            block_conv_sender.send(block).await.unwrap();
+        }
+        drop(block_conv_sender);
+        tokio::time::timeout(std::time::Duration::from_secs(120), task_handle)
+            .await
+            .expect("Timeout reached while waiting for task completion")
+            .expect("Task panicked")
+            .expect("Task failed");
+
+        for expected in &stored[1..] {
+            let SyncEvent::BlockVerified { block_number, block_hash, global_state_root } =
+                receiver.recv().await.unwrap()
+            else {
+                panic!("Expected block #{} to be verified again", expected.header.block_number)
+            };
+            assert_eq!(block_number, expected.header.block_number);
+            assert_eq!(block_hash, expected.block_hash);
+            assert_eq!(global_state_root, expected.header.global_state_root);
+        }
+        assert!(matches!(receiver.recv().await.unwrap(), SyncEvent::BlockVerified { block_number: 3, .. }));
+
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(3));
+        for expected in &stored {
+            let block_info = backend.get_block_info(&DbBlockId::Number(expected.header.block_number)).unwrap().unwrap();
+            assert_eq!(block_info.as_nonpending().unwrap().header, expected.header);
+        }
+        assert_eq!(check_global_state_roots(&backend, 0..=3).unwrap(), None);
+    }
+
+    /// Subscribers get an event for each imported block, and for a block which does not extend the local chain.
+    #[rstest]
+    #[tokio::test]
+    async fn test_l2_verify_and_apply_task_events(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        let (block_conv_sender, block_conv_receiver) = mpsc::channel(100);
+        let block_import = Arc::new(BlockImporter::new(backend.clone(), None).unwrap());
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let events = SyncEvents::default();
+        let mut receiver = events.subscribe_sync_events();
+
+        let task_handle = tokio::spawn(l2_verify_and_apply_task(
+            backend.clone(),
+            ServiceContext::new_for_testing(),
+            L2VerifyApplyConfig {
+                events,
+                ..test_verify_apply_config(&backend, block_import.clone(), block_conv_receiver)
+            },
+        ));
+
+        let blocks = [
+            create_dummy_unverified_full_block(),
+            UnverifiedFullBlock {
+                unverified_block_number: Some(1),
+                header: UnverifiedHeader { parent_block_hash: None, ..create_dummy_unverified_full_block().header },
+                ..create_dummy_unverified_full_block()
+            },
+            UnverifiedFullBlock {
+                unverified_block_number: Some(2),
+                header: UnverifiedHeader {
+                    parent_block_hash: Some(Felt::from(0x1234)),
+                    ..create_dummy_unverified_full_block().header
+                },
+                ..create_dummy_unverified_full_block()
+            },
+        ];
+        for block in blocks {
+            block_conv_sender.send(block_import.pre_validate(block, validation.clone()).await.unwrap()).await.unwrap();
+        }
+        drop(block_conv_sender);
+
+        let res = tokio::time::timeout(std::time::Duration::from_secs(120), task_handle)
+            .await
+            .expect("Timeout reached while waiting for task completion")
+            .expect("Task panicked");
+        assert!(res.is_err(), "The block with the wrong parent hash must not be imported");
+
+        let mut local_tip_hash = Felt::ZERO;
+        for block_number in 0..2 {
+            let info = backend.get_block_info(&DbBlockId::Number(block_number)).unwrap().unwrap();
+            let info = info.as_nonpending().unwrap();
+            assert_eq!(
+                receiver.recv().await.unwrap(),
+                SyncEvent::BlockVerified {
+                    block_number,
+                    block_hash: info.block_hash,
+                    global_state_root: info.header.global_state_root,
+                }
+            );
+            local_tip_hash = info.block_hash;
+        }
+        assert_eq!(
+            receiver.recv().await.unwrap(),
+            SyncEvent::ReorgDetected {
+                block_number: Some(2),
+                block_hash: None,
+                local_tip_hash,
+                parent_block_hash: Felt::from(0x1234),
+                rollback_depth: None,
+            }
+        );
+    }
+
+    /// A block built on a replacement of the local tip is reported with the number of local blocks it replaces, found
+    /// by walking its parents on the feeder gateway, and counted in `reorgs_total`.
+    #[rstest]
+    #[tokio::test]
+    async fn test_l2_verify_and_apply_task_reorg(test_setup: Arc<MadaraBackend>) {
+        let ctx = TestContext::new(test_setup);
+        let backend = Arc::clone(&ctx.backend);
+        let (block_conv_sender, block_conv_receiver) = mpsc::channel(100);
+        let block_import = Arc::new(BlockImporter::new(backend.clone(), None).unwrap());
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let events = SyncEvents::default();
+        let mut receiver = events.subscribe_sync_events();
+        let metrics = ReorgMetrics::register().unwrap();
+        let health = Arc::new(SyncHealthTracker::new(backend.clone(), 0));
+        // The tip of the network before the reorg
+        health.set_highest_block_number(10);
+
+        let task_handle = tokio::spawn(l2_verify_and_apply_task(
+            backend.clone(),
+            ServiceContext::new_for_testing(),
+            L2VerifyApplyConfig {
+                events,
+                metrics: metrics.clone(),
+                provider: Some(Arc::clone(&ctx.provider)),
+                health: Arc::clone(&health),
+                ..test_verify_apply_config(&backend, block_import.clone(), block_conv_receiver)
+            },
+        ));
+
+        let mut block_hashes = vec![];
+        for block_number in 0..2 {
+            let block = UnverifiedFullBlock {
+                unverified_block_number: Some(block_number),
+                header: UnverifiedHeader { parent_block_hash: None, ..create_dummy_unverified_full_block().header },
+                ..create_dummy_unverified_full_block()
+            };
+            block_conv_sender.send(block_import.pre_validate(block, validation.clone()).await.unwrap()).await.unwrap();
+            let SyncEvent::BlockVerified { block_hash, .. } = receiver.recv().await.unwrap() else {
+                panic!("Expected block #{block_number} to be imported")
+            };
+            block_hashes.push(block_hash);
+        }
+        assert_eq!(metrics.total(), 0);
+
+        // The feeder gateway now has another block #1 on top of block #0, and block #2 on top of it
+        let replaced_block_hash = Felt::from(0xbeef);
+        let parent_mock = ctx.mock_block_header_by_hash(replaced_block_hash, block_hashes[0], 1);
+        let block = UnverifiedFullBlock {
+            unverified_block_number: Some(2),
+            header: UnverifiedHeader {
+                parent_block_hash: Some(replaced_block_hash),
+                ..create_dummy_unverified_full_block().header
+            },
+            ..create_dummy_unverified_full_block()
+        };
+        let mut block = block_import.pre_validate(block, validation.clone()).await.unwrap();
+        block.unverified_block_hash = Some(Felt::from(0xdead));
+        block_conv_sender.send(block).await.unwrap();
+        drop(block_conv_sender);
+
+        let res = tokio::time::timeout(std::time::Duration::from_secs(120), task_handle)
+            .await
+            .expect("Timeout reached while waiting for task completion")
+            .expect("Task panicked");
+        assert!(res.is_err(), "The block with the wrong parent hash must not be imported");
+        assert_eq!(
+            receiver.recv().await.unwrap(),
+            SyncEvent::ReorgDetected {
+                block_number: Some(2),
+                block_hash: Some(Felt::from(0xdead)),
+                local_tip_hash: block_hashes[1],
+                parent_block_hash: replaced_block_hash,
+                rollback_depth: Some(1),
+            }
+        );
+        parent_mock.assert();
+        assert_eq!(metrics.total(), 1);
+        assert_eq!(health.highest_block_number(), Some(2));
+    }
+
+    /// With `--block-commit-throttle`, consecutive blocks are imported at least the throttle apart.
+    #[rstest]
+    #[tokio::test(start_paused = true)]
+    async fn test_l2_verify_and_apply_task_block_commit_throttle(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        let throttle = Duration::from_secs(1);
+        let (block_conv_sender, block_conv_receiver) = mpsc::channel(100);
+        let block_import = Arc::new(BlockImporter::new(backend.clone(), None).unwrap());
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let events = SyncEvents::default();
+        let mut receiver = events.subscribe_sync_events();
+
+        let task_handle = tokio::spawn(l2_verify_and_apply_task(
+            backend.clone(),
+            ServiceContext::new_for_testing(),
+            L2VerifyApplyConfig {
+                block_commit_throttle: throttle,
+                events,
+                ..test_verify_apply_config(&backend, block_import.clone(), block_conv_receiver)
+            },
+        ));
+
+        for block_number in 0..3 {
+            let block = UnverifiedFullBlock {
+                unverified_block_number: Some(block_number),
+                header: UnverifiedHeader { parent_block_hash: None, ..create_dummy_unverified_full_block().header },
+                ..create_dummy_unverified_full_block()
+            };
+            block_conv_sender.send(block_import.pre_validate(block, validation.clone()).await.unwrap()).await.unwrap();
+        }
+        drop(block_conv_sender);
+
+        // The clock is paused: it only moves forward while the task waits for the throttle
+        let mut imported_at = Vec::new();
+        for block_number in 0..3 {
+            let event = receiver.recv().await.unwrap();
+            assert!(matches!(event, SyncEvent::BlockVerified { block_number: n, .. } if n == block_number));
+            imported_at.push(tokio::time::Instant::now());
+        }
+        for window in imported_at.windows(2) {
+            assert!(window[1] - window[0] >= throttle, "Blocks imported {:?} apart", window[1] - window[0]);
+        }
+        task_handle.await.unwrap().unwrap();
     }
 
     /// Test the `l2_block_conversion_task` function.
@@ -435,6 +1314,7 @@ mod tests {
         let (output_sender, mut output_receiver) = mpsc::channel(100);
         let block_import = Arc::new(BlockImporter::new(backend.clone(), None).unwrap());
         let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let timings = Arc::new(SyncTimings::default());
 
         let mock_block = create_dummy_unverified_full_block();
 
@@ -445,6 +1325,7 @@ mod tests {
             output_sender,
             block_import,
             validation,
+            Some(Arc::clone(&timings)),
             ServiceContext::new_for_testing(),
         ));
 
@@ -452,6 +1333,7 @@ mod tests {
         match result {
             Ok(Some(b)) => {
                 assert_eq!(b.unverified_block_number, Some(0), "Block number does not match");
+                timings.update(0, |timing| assert!(timing.pre_validate > Duration::ZERO));
             }
             Ok(None) => panic!("Channel closed without receiving a result"),
             Err(_) => panic!("Timeout reached while waiting for result"),
@@ -489,22 +1371,29 @@ mod tests {
         let ctx = TestContext::new(backend.clone());
         let block_import = Arc::new(BlockImporter::new(backend.clone(), None).unwrap());
         let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let service_ctx = ServiceContext::new_for_testing();
 
         let task_handle = tokio::spawn(l2_pending_block_task(
             backend.clone(),
             ctx.provider.clone(),
-            ServiceContext::new_for_testing(),
+            service_ctx.clone(),
             L2PendingBlockConfig {
                 block_import: block_import.clone(),
                 once_caught_up_receiver: ctx.once_caught_up_receiver,
                 pending_block_poll_interval: std::time::Duration::from_secs(5),
+                disable_pending: false,
                 validation: validation.clone(),
+                health: Arc::new(SyncHealthTracker::new(backend.clone(), 0)),
             },
         ));
 
         // Simulate the "once_caught_up" signal
         ctx.once_caught_up_sender.send(()).unwrap();
 
+        // The task polls the pending block until the node stops
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        service_ctx.cancel_global();
+
         // Wait for the task to complete
         match tokio::time::timeout(std::time::Duration::from_secs(120), task_handle).await {
             Ok(Ok(_)) => (),
@@ -512,4 +1401,120 @@ mod tests {
             Err(_) => panic!("Timeout reached while waiting for task completion"),
         }
     }
+
+    /// The pending block failures are skipped without stalling the sync health: the highest block number keeps
+    /// following the tip of the chain while every pending block request fails.
+    #[rstest]
+    #[tokio::test]
+    async fn test_highest_block_advances_while_pending_block_fails(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        let ctx = TestContext::new(backend.clone());
+        ctx.mock_block_not_found(0);
+        let mut latest_mock = ctx.mock_latest_block(10);
+        let pending_mock = ctx.mock_block_pending_internal_error();
+        let block_import = Arc::new(BlockImporter::new(backend.clone(), None).unwrap());
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let health = Arc::new(SyncHealthTracker::new(backend.clone(), 0));
+        let service_ctx = ServiceContext::new_for_testing();
+
+        let fetch_handle = tokio::spawn(l2_fetch_task(
+            backend.clone(),
+            ctx.provider.clone(),
+            service_ctx.clone(),
+            L2FetchConfig {
+                first_block: 0,
+                fetch_stream_sender: ctx.fetch_stream_sender,
+                once_caught_up_sender: ctx.once_caught_up_sender,
+                sync_polling_interval: Some(std::time::Duration::from_millis(50)),
+                n_blocks_to_sync: None,
+                stop_on_sync: false,
+                sync_parallelism: 1,
+                class_prefetch_window: None,
+                max_consecutive_failures: None,
+                sequencer_public_key: None,
+                verify_sample_size: None,
+                compile_classes: false,
+                warp_update: false,
+                warp_update_port_rpc: 9943,
+                warp_update_port_fgw: 8080,
+                health: Arc::clone(&health),
+                trace_sender: None,
+                timings: None,
+                events: SyncEvents::default(),
+            },
+        ));
+        let pending_handle = tokio::spawn(l2_pending_block_task(
+            backend.clone(),
+            ctx.provider.clone(),
+            service_ctx.clone(),
+            L2PendingBlockConfig {
+                block_import,
+                once_caught_up_receiver: ctx.once_caught_up_receiver,
+                pending_block_poll_interval: std::time::Duration::from_millis(50),
+                disable_pending: false,
+                validation,
+                health: Arc::clone(&health),
+            },
+        ));
+
+        let wait_for_highest = |block_n: u64| {
+            let health = Arc::clone(&health);
+            async move {
+                while health.highest_block_number() != Some(block_n) {
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                }
+            }
+        };
+
+        tokio::time::timeout(std::time::Duration::from_secs(10), wait_for_highest(10))
+            .await
+            .expect("The highest block number should reach the tip");
+
+        latest_mock.delete();
+        ctx.mock_latest_block(12);
+        tokio::time::timeout(std::time::Duration::from_secs(10), wait_for_highest(12))
+            .await
+            .expect("The highest block number should follow the tip while the pending block fails");
+
+        assert!(pending_mock.hits() > 0, "The pending block should have been requested");
+        if pending_handle.is_finished() {
+            panic!("Pending block task stopped: {:?}", pending_handle.await);
+        }
+
+        service_ctx.cancel_global();
+        let _ = tokio::time::timeout(std::time::Duration::from_secs(5), fetch_handle).await;
+        let _ = tokio::time::timeout(std::time::Duration::from_secs(5), pending_handle).await;
+    }
+
+    /// With `--disable-pending`, the pending block is never fetched and the database holds no pending data.
+    #[rstest]
+    #[tokio::test]
+    async fn test_l2_pending_block_task_disabled(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        let ctx = TestContext::new(backend.clone());
+        let pending_mock = ctx.mock_block_pending();
+        let block_import = Arc::new(BlockImporter::new(backend.clone(), None).unwrap());
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+
+        let task_handle = tokio::spawn(l2_pending_block_task(
+            backend.clone(),
+            ctx.provider.clone(),
+            ServiceContext::new_for_testing(),
+            L2PendingBlockConfig {
+                block_import,
+                once_caught_up_receiver: ctx.once_caught_up_receiver,
+                pending_block_poll_interval: std::time::Duration::from_millis(50),
+                disable_pending: true,
+                validation,
+                health: Arc::new(SyncHealthTracker::new(backend.clone(), 0)),
+            },
+        ));
+        // The task does not wait for the sync to catch up
+        let _ = ctx.once_caught_up_sender.send(());
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), task_handle).await.unwrap().unwrap().unwrap();
+        pending_mock.assert_hits(0);
+        assert!(backend.get_block_inner(&DbBlockId::Pending).unwrap().unwrap().transactions.is_empty());
+        assert_eq!(backend.get_pending_block_state_update().unwrap(), StateDiff::default());
+    }
 }
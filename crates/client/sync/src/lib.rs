@@ -1,20 +1,37 @@
 use crate::l2::L2SyncConfig;
 use anyhow::Context;
-use fetch::fetchers::FetchConfig;
+use checkpoint::{load_checkpoint, CheckpointConfig, CheckpointStart};
+use events::SyncEvents;
+use fetch::fetchers::{fetch_highest_block_hash_and_number, FetchConfig};
+use genesis::{import_genesis_dump, GenesisDumpConfig};
+use health::SyncHealthTracker;
 use hyper::header::{HeaderName, HeaderValue};
-use mc_block_import::BlockImporter;
+use l2::RecentStateUpdates;
+use mc_block_import::{check_global_state_roots, BlockImporter, StateRootMismatch};
 use mc_db::MadaraBackend;
-use mc_gateway_client::GatewayProvider;
+use mc_gateway_client::{FeederHealth, GatewayProvider, PoolConfig};
 use mc_telemetry::TelemetryHandle;
-use mp_block::{BlockId, BlockTag};
+use mp_chain_config::public_key;
 use mp_utils::service::ServiceContext;
-use std::{sync::Arc, time::Duration};
+use progress::SyncProgressConfig;
+use starknet_api::core::ChainId;
+use starknet_types_core::felt::Felt;
+use std::{ops::RangeInclusive, str::FromStr, sync::Arc, time::Duration};
+use url::Url;
 
+pub mod checkpoint;
+pub mod convert;
+pub mod events;
 pub mod fetch;
+pub mod genesis;
+pub mod health;
 pub mod l2;
 pub mod metrics;
+pub mod notifier;
+pub mod progress;
 #[cfg(test)]
 pub mod tests;
+pub mod timing;
 pub mod utils;
 
 pub struct SyncConfig {
@@ -23,6 +40,182 @@ pub struct SyncConfig {
     pub backup_every_n_blocks: Option<u64>,
     pub telemetry: TelemetryHandle,
     pub pending_block_poll_interval: Duration,
+    pub disable_pending: bool,
+    pub health: Arc<SyncHealthTracker>,
+    /// Health of the feeder gateway, updated by every request of the sync.
+    pub feeder_health: Arc<FeederHealth>,
+    /// Latest verified state updates, pushed by the sync after each imported block.
+    pub recent_state_updates: Arc<RecentStateUpdates>,
+    pub resync_tail: Option<u64>,
+    pub events: SyncEvents,
+    pub genesis_dump: Option<GenesisDumpConfig>,
+    /// Trusted checkpoint to start a fresh database from, instead of syncing from genesis.
+    pub checkpoint: Option<CheckpointConfig>,
+}
+
+/// Returns the block the sync should start from, and whether block order should be ignored.
+///
+/// Block 0 is the genesis block: a fresh database starts syncing from block 0.
+///
+/// A forced starting block which has already been synced is fast-forwarded to the block after the
+/// sync tip, as there is no point in fetching and verifying these blocks again.
+fn sync_starting_block(backend: &MadaraBackend, forced_starting_block: Option<u64>) -> anyhow::Result<(u64, bool)> {
+    let next_block = l2::next_block_to_sync(backend).context("getting sync tip")?;
+
+    match forced_starting_block {
+        Some(starting_block) if starting_block > next_block => {
+            tracing::warn!("Forcing unordered state. This will most probably break your database.");
+            Ok((starting_block, true))
+        }
+        Some(starting_block) if starting_block < next_block => {
+            tracing::info!("⏩ Skipping blocks {starting_block}..{} which have already been synced", next_block - 1);
+            Ok((next_block, false))
+        }
+        _ => Ok((next_block, false)),
+    }
+}
+
+/// Inserts `prefix` before the path of `url`, for a gateway hosted under a path prefix behind a reverse proxy:
+/// `https://proxy.example/feeder_gateway/` with the prefix `starknet/mainnet` becomes
+/// `https://proxy.example/starknet/mainnet/feeder_gateway/`.
+pub fn with_path_prefix(url: &Url, prefix: Option<&str>) -> anyhow::Result<Url> {
+    let Some(prefix) = prefix.map(|prefix| prefix.trim_matches('/')).filter(|prefix| !prefix.is_empty()) else {
+        return Ok(url.clone());
+    };
+    if url.cannot_be_a_base() {
+        anyhow::bail!("Cannot add the path prefix {prefix:?} to {url}");
+    }
+    if let Some(segment) =
+        prefix.split('/').find(|segment| matches!(*segment, "" | "." | "..") || segment.contains(['?', '#', ' ']))
+    {
+        anyhow::bail!("Invalid path prefix {prefix:?}: invalid segment {segment:?}");
+    }
+
+    let mut prefixed = url.clone();
+    prefixed.set_path(&format!("/{prefix}/{}", url.path().trim_start_matches('/')));
+    Ok(prefixed)
+}
+
+/// Builds the feeder gateway client, with the authentication headers and rate limit from the [`FetchConfig`].
+pub fn build_provider(fetch_config: &FetchConfig) -> anyhow::Result<GatewayProvider> {
+    let pool = PoolConfig {
+        max_idle: fetch_config.pool_max_idle.unwrap_or(PoolConfig::default().max_idle),
+        keepalive: fetch_config.keepalive,
+    };
+    let gateway = with_path_prefix(&fetch_config.gateway, fetch_config.gateway_path_prefix.as_deref())
+        .context("Invalid gateway path prefix")?;
+    let feeder_gateway =
+        with_path_prefix(&fetch_config.feeder_gateway, fetch_config.feeder_gateway_path_prefix.as_deref())
+            .context("Invalid feeder gateway path prefix")?;
+    let mut provider = GatewayProvider::new_with_pool(gateway, feeder_gateway, fetch_config.request_timeout, pool);
+    if let Some(api_key) = &fetch_config.api_key {
+        provider.add_header(
+            HeaderName::from_static("x-throttling-bypass"),
+            HeaderValue::from_str(api_key).with_context(|| "Invalid API key format")?,
+        )
+    }
+    if let Some(feeder_api_key) = &fetch_config.feeder_api_key {
+        let name = HeaderName::from_str(&fetch_config.feeder_api_key_header)
+            .with_context(|| format!("Invalid feeder API key header name: {:?}", fetch_config.feeder_api_key_header))?;
+        let mut value = HeaderValue::from_str(feeder_api_key).with_context(|| "Invalid feeder API key format")?;
+        value.set_sensitive(true);
+        provider.add_header(name, value)
+    }
+    if let Some(feeder_rps) = fetch_config.feeder_rps {
+        provider = provider.with_rate_limit(feeder_rps);
+    }
+    if let Some(max_response_bytes) = fetch_config.max_response_bytes {
+        provider = provider.with_max_response_bytes(max_response_bytes);
+    }
+    if fetch_config.feeder_compression {
+        provider = provider.with_compression();
+    }
+    if let Some(class_parallelism) = fetch_config.class_parallelism {
+        provider = provider.with_max_concurrent_class_requests(class_parallelism);
+    }
+    if let Some(record_dir) = &fetch_config.record_dir {
+        provider = provider.with_record_dir(record_dir.clone()).with_record_format(fetch_config.record_format);
+    }
+    if let Some(replay_dir) = &fetch_config.replay_dir {
+        provider = provider.with_replay_dir(replay_dir.clone());
+    }
+    Ok(provider)
+}
+
+/// The last `n_blocks` blocks of the database, clamped at the first stored block. `None` when there is nothing to
+/// resync.
+fn resync_tail_range(backend: &MadaraBackend, n_blocks: u64) -> anyhow::Result<Option<RangeInclusive<u64>>> {
+    let Some(latest_block_n) = backend.get_latest_block_n().context("Getting the latest block number")? else {
+        return Ok(None);
+    };
+    if n_blocks == 0 {
+        return Ok(None);
+    }
+    let first_stored_block_n = backend
+        .get_checkpoint_block()
+        .context("Getting the checkpoint block")?
+        .map_or(0, |checkpoint| checkpoint.block_number + 1);
+    Ok(Some((latest_block_n + 1).saturating_sub(n_blocks).max(first_stored_block_n)..=latest_block_n))
+}
+
+/// Re-verifies the global state root of the last `n_blocks` blocks of the database, in case a crash left the global
+/// tries in a partial state. The sync then re-fetches these blocks and checks them against the stored ones, see
+/// [`BlockValidationContext::reapply_stored_blocks`](mc_block_import::BlockValidationContext::reapply_stored_blocks).
+///
+/// This only reads the database, so it can be run on every startup. Returns the first block of the tail.
+fn verify_tail(backend: &MadaraBackend, n_blocks: u64) -> anyhow::Result<Option<u64>> {
+    let Some(tail) = resync_tail_range(backend, n_blocks)? else { return Ok(None) };
+    let (first_block_n, latest_block_n) = (*tail.start(), *tail.end());
+
+    tracing::info!("🔍 Verifying the state roots of blocks #{first_block_n} to #{latest_block_n}");
+    if let Some(StateRootMismatch { block_n, stored, computed }) =
+        check_global_state_roots(backend, first_block_n..=latest_block_n)?
+    {
+        anyhow::bail!(
+            "Global state root mismatch at block #{block_n}: stored {stored:#x}, computed {computed:#x}. The database is corrupted"
+        );
+    }
+    Ok(Some(first_block_n))
+}
+
+/// Warns when a forced starting block is beyond the tip of the chain, as the sync would then wait for that block
+/// to be produced. Returns whether the starting block is beyond the tip.
+async fn warn_if_beyond_chain_tip(provider: &GatewayProvider, starting_block: u64, ctx: &ServiceContext) -> bool {
+    let tip = match fetch_highest_block_hash_and_number(provider, ctx).await {
+        Ok((_, tip)) => tip,
+        Err(err) => {
+            tracing::warn!("Could not get the tip of the chain to check the starting block: {err:#}");
+            return false;
+        }
+    };
+
+    if starting_block > tip + 1 {
+        tracing::warn!(
+            "⚠️ Starting block #{starting_block} is beyond the tip of the chain (#{tip}), the sync will wait until it is produced"
+        );
+        return true;
+    }
+    false
+}
+
+/// Returns the public key the block signatures are checked against, or `None` when `--verify-signatures` is off.
+/// The key of the public networks is known, other chains have to provide it.
+fn sequencer_public_key(fetch_config: &FetchConfig, chain_id: &ChainId) -> anyhow::Result<Option<Felt>> {
+    if !fetch_config.verify_signatures {
+        return Ok(None);
+    }
+    if let Some(public_key) = fetch_config.sequencer_public_key {
+        return Ok(Some(public_key));
+    }
+    let public_key = match chain_id {
+        ChainId::Mainnet => public_key::MAINNET,
+        ChainId::Sepolia => public_key::SEPOLIA_TESTNET,
+        ChainId::IntegrationSepolia => public_key::SEPOLIA_INTEGRATION,
+        ChainId::Other(_) => anyhow::bail!(
+            "The sequencer public key of chain {chain_id} is not known, set it with --sequencer-public-key to verify the block signatures"
+        ),
+    };
+    Ok(Some(Felt::from_hex(public_key).expect("parsing a constant")))
 }
 
 #[tracing::instrument(skip(backend, ctx, fetch_config, sync_config))]
@@ -32,28 +225,51 @@ pub async fn l2_sync_worker(
     fetch_config: FetchConfig,
     sync_config: SyncConfig,
 ) -> anyhow::Result<()> {
-    let (starting_block, ignore_block_order) = if let Some(starting_block) = sync_config.starting_block {
-        tracing::warn!("Forcing unordered state. This will most probably break your database.");
-        (starting_block, true)
-    } else {
-        (
-            backend
-                .get_block_n(&BlockId::Tag(BlockTag::Latest))
-                .context("getting sync tip")?
-                .map(|block_id| block_id + 1) // next block after the tip
-                .unwrap_or_default() as _, // or genesis
-            false,
-        )
+    if fetch_config.trust_feeder {
+        tracing::warn!(
+            "⚠️ Trusting the feeder gateway: class hashes and global state roots will not be verified. Only use this with a trusted sequencer"
+        );
+    }
+
+    let sequencer_public_key = sequencer_public_key(&fetch_config, &backend.chain_config().chain_id)?;
+    if let Some(genesis_dump) = &sync_config.genesis_dump {
+        import_genesis_dump(backend, &sync_config.block_importer, genesis_dump).await?;
+    }
+    let CheckpointStart { starting_block, ignore_block_order, trust_global_tries } = match &sync_config.checkpoint {
+        Some(checkpoint) => load_checkpoint(backend, checkpoint)?,
+        None => {
+            let (starting_block, ignore_block_order) = sync_starting_block(backend, sync_config.starting_block)?;
+            CheckpointStart::without_checkpoint(backend, starting_block, ignore_block_order)?
+        }
+    };
+    if trust_global_tries && fetch_config.verify {
+        tracing::warn!(
+            "⚠️ The database was started from a checkpoint, the global state roots of the following blocks will not be verified"
+        );
+    }
+    if fetch_config.verify && !trust_global_tries {
+        // The node may have been stopped before the trie updates of its last blocks were committed.
+        sync_config
+            .block_importer
+            .recover_tries(&fetch_config.skip_blocks)
+            .await
+            .context("Recovering the global tries")?;
+    }
+    let resync_from = match sync_config.resync_tail {
+        Some(n_blocks) => verify_tail(backend, n_blocks)?,
+        None => None,
     };
+    let starting_block = resync_from.map_or(starting_block, |resync_from| resync_from.min(starting_block));
+    // Without a forced starting block, the sync resumes from the tip of the database as it is once the sync starts
+    let forced_starting_block =
+        sync_config.starting_block.is_some() || sync_config.checkpoint.is_some() || resync_from.is_some();
 
     tracing::info!("⛓️  Starting L2 sync from block {}", starting_block);
 
-    let mut provider = GatewayProvider::new(fetch_config.gateway, fetch_config.feeder_gateway);
-    if let Some(api_key) = fetch_config.api_key {
-        provider.add_header(
-            HeaderName::from_static("x-throttling-bypass"),
-            HeaderValue::from_str(&api_key).with_context(|| "Invalid API key format")?,
-        )
+    let provider = build_provider(&fetch_config)?.with_feeder_health(Arc::clone(&sync_config.feeder_health));
+
+    if ignore_block_order {
+        warn_if_beyond_chain_tip(&provider, starting_block, &ctx).await;
     }
 
     l2::sync(
@@ -61,26 +277,371 @@ pub async fn l2_sync_worker(
         provider,
         ctx,
         L2SyncConfig {
-            first_block: starting_block,
+            first_block: forced_starting_block.then_some(starting_block),
             n_blocks_to_sync: fetch_config.n_blocks_to_sync,
             stop_on_sync: fetch_config.stop_on_sync,
-            verify: fetch_config.verify,
+            verify: fetch_config.verify && !trust_global_tries,
+            trust_feeder: fetch_config.trust_feeder,
+            skip_blocks: fetch_config.skip_blocks,
+            reapply_stored_blocks: resync_from.is_some(),
             sync_polling_interval: fetch_config.sync_polling_interval,
             backup_every_n_blocks: sync_config.backup_every_n_blocks,
             flush_every_n_blocks: fetch_config.flush_every_n_blocks,
             flush_every_n_seconds: fetch_config.flush_every_n_seconds,
+            block_commit_throttle: fetch_config.block_commit_throttle,
             pending_block_poll_interval: sync_config.pending_block_poll_interval,
+            disable_pending: sync_config.disable_pending,
             ignore_block_order,
             sync_parallelism: fetch_config.sync_parallelism,
+            class_prefetch_window: fetch_config.class_prefetch_window,
+            sequencer_public_key,
+            verify_sample_size: fetch_config.verify_sample_size,
+            max_consecutive_failures: fetch_config.max_consecutive_failures,
+            compile_classes: fetch_config.compile_classes,
+            fetch_buffer_size: fetch_config.fetch_buffer_size,
             warp_update: fetch_config.warp_update,
             warp_update_port_rpc: fetch_config.warp_update_port_rpc,
             warp_update_port_fgw: fetch_config.warp_update_port_fgw,
             chain_id: backend.chain_config().chain_id.clone(),
             telemetry: sync_config.telemetry,
             block_importer: sync_config.block_importer,
+            notifier: notifier::block_notifier(fetch_config.sound, fetch_config.block_webhook_url),
+            health: sync_config.health,
+            recent_state_updates: sync_config.recent_state_updates,
+            fetch_traces: fetch_config.fetch_traces,
+            sync_timing: fetch_config.sync_timing,
+            progress: SyncProgressConfig {
+                every_n_blocks: fetch_config.progress_every_n_blocks,
+                interval: fetch_config.progress_interval,
+            },
+            events: sync_config.events,
         },
     )
     .await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests_starting_block {
+    use super::*;
+    use crate::tests::utils::gateway::{test_setup, TestContext};
+    use mc_block_import::tests::block_import_utils::create_dummy_unverified_full_block;
+    use mc_block_import::BlockValidationContext;
+    use mp_block::BlockId;
+    use rstest::rstest;
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_sync_starting_block_fresh(test_setup: Arc<MadaraBackend>) {
+        assert_eq!(sync_starting_block(&test_setup, None).unwrap(), (0, false));
+        assert_eq!(sync_starting_block(&test_setup, Some(0)).unwrap(), (0, false));
+        assert_eq!(sync_starting_block(&test_setup, Some(1)).unwrap(), (1, true));
+        assert_eq!(sync_starting_block(&test_setup, Some(5)).unwrap(), (5, true));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_sync_starting_block_skips_synced_blocks(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        let block_import = BlockImporter::new(backend.clone(), None).unwrap();
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let block = block_import.pre_validate(create_dummy_unverified_full_block(), validation.clone()).await.unwrap();
+        block_import.verify_apply(block, validation).await.unwrap();
+
+        assert_eq!(sync_starting_block(&backend, None).unwrap(), (1, false));
+        // Block 0 has already been synced, we should start past it.
+        assert_eq!(sync_starting_block(&backend, Some(0)).unwrap(), (1, false));
+        assert_eq!(sync_starting_block(&backend, Some(1)).unwrap(), (1, false));
+        assert_eq!(sync_starting_block(&backend, Some(3)).unwrap(), (3, true));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn test_verify_tail(test_setup: Arc<MadaraBackend>) {
+        let backend = test_setup;
+        // Nothing to verify on a fresh database
+        assert_eq!(verify_tail(&backend, 5).unwrap(), None);
+
+        let block_import = BlockImporter::new(backend.clone(), None).unwrap();
+        let validation = BlockValidationContext::new(backend.chain_config().chain_id.clone());
+        let block = block_import.pre_validate(create_dummy_unverified_full_block(), validation.clone()).await.unwrap();
+        let imported = block_import.verify_apply(block, validation).await.unwrap();
+
+        assert_eq!(verify_tail(&backend, 0).unwrap(), None);
+        assert_eq!(verify_tail(&backend, 1).unwrap(), Some(0));
+        // The tail is clamped at genesis
+        assert_eq!(verify_tail(&backend, 5).unwrap(), Some(0));
+
+        // Verifying the tail leaves the database untouched
+        assert_eq!(backend.get_latest_block_n().unwrap(), Some(0));
+        let block_info = backend.get_block_info(&BlockId::Number(0)).unwrap().unwrap();
+        assert_eq!(block_info.as_nonpending().unwrap().header.global_state_root, imported.header.global_state_root);
+    }
+
+    #[rstest]
+    #[case::genesis(0, false)]
+    #[case::first_block(1, false)]
+    #[case::tip(10, false)]
+    #[case::next_block(11, false)]
+    #[case::beyond_tip(1000, true)]
+    #[tokio::test]
+    async fn test_warn_if_beyond_chain_tip(
+        test_setup: Arc<MadaraBackend>,
+        #[case] starting_block: u64,
+        #[case] expected: bool,
+    ) {
+        let ctx = TestContext::new(test_setup);
+        ctx.mock_latest_block(10);
+
+        assert_eq!(
+            warn_if_beyond_chain_tip(&ctx.provider, starting_block, &ServiceContext::new_for_testing()).await,
+            expected
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests_build_provider {
+    use super::*;
+    use httpmock::MockServer;
+    use mp_block::BlockId;
+    use mp_gateway::error::{SequencerError, StarknetError, StarknetErrorCode};
+    use std::io::{Read, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use url::Url;
+
+    fn fetch_config(mock_server: &MockServer) -> FetchConfig {
+        let url = Url::parse(&mock_server.base_url()).unwrap();
+        FetchConfig {
+            gateway: url.join("/gateway/").unwrap(),
+            feeder_gateway: url.join("/feeder_gateway/").unwrap(),
+            gateway_path_prefix: None,
+            feeder_gateway_path_prefix: None,
+            chain_id: ChainId::Other("MADARA_TEST".to_string()),
+            verify: true,
+            api_key: None,
+            feeder_api_key: None,
+            feeder_api_key_header: "x-api-key".to_string(),
+            sync_polling_interval: None,
+            n_blocks_to_sync: None,
+            flush_every_n_blocks: 1,
+            flush_every_n_seconds: 1,
+            block_commit_throttle: Duration::ZERO,
+            stop_on_sync: false,
+            sync_parallelism: 1,
+            class_parallelism: None,
+            class_prefetch_window: None,
+            fetch_buffer_size: 8,
+            warp_update: false,
+            warp_update_port_rpc: 9943,
+            warp_update_port_fgw: 8080,
+            sound: false,
+            block_webhook_url: None,
+            request_timeout: Duration::from_secs(5),
+            pool_max_idle: None,
+            keepalive: Duration::from_secs(90),
+            feeder_rps: None,
+            max_response_bytes: None,
+            feeder_compression: false,
+            trust_feeder: false,
+            skip_blocks: Default::default(),
+            record_dir: None,
+            replay_dir: None,
+            record_format: mc_gateway_client::RecordFormat::Json,
+            fetch_traces: false,
+            sync_timing: false,
+            progress_every_n_blocks: 1000,
+            progress_interval: Duration::from_secs(30),
+            verify_signatures: false,
+            sequencer_public_key: None,
+            verify_sample_size: None,
+            max_consecutive_failures: None,
+            compile_classes: false,
+        }
+    }
+
+    fn mock_auth_proxy(mock_server: &MockServer) {
+        mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_block").header("x-api-key", "secret");
+            then.status(400).json_body(serde_json::json!({
+                "code": "StarknetErrorCode.BLOCK_NOT_FOUND",
+                "message": "Block not found"
+            }));
+        });
+        // Requests lacking the header are rejected by the proxy
+        mock_server.mock(|when, then| {
+            when.method("GET").path_contains("get_block").matches(|req| {
+                !req.headers.as_ref().is_some_and(|headers| headers.iter().any(|(name, _)| name == "x-api-key"))
+            });
+            then.status(401).body("Unauthorized");
+        });
+    }
+
+    #[tokio::test]
+    async fn test_build_provider_feeder_api_key() {
+        let mock_server = MockServer::start();
+        mock_auth_proxy(&mock_server);
+
+        let provider =
+            build_provider(&FetchConfig { feeder_api_key: Some("secret".into()), ..fetch_config(&mock_server) })
+                .unwrap();
+
+        let res = provider.get_block(BlockId::Number(0)).await;
+        assert!(
+            matches!(
+                res,
+                Err(SequencerError::StarknetError(StarknetError { code: StarknetErrorCode::BlockNotFound, .. }))
+            ),
+            "Request should be let through by the auth proxy, got {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_build_provider_no_feeder_api_key() {
+        let mock_server = MockServer::start();
+        mock_auth_proxy(&mock_server);
+
+        let provider = build_provider(&fetch_config(&mock_server)).unwrap();
+
+        let res = provider.get_block(BlockId::Number(0)).await;
+        assert!(
+            matches!(res, Err(SequencerError::InvalidStarknetError { .. })),
+            "Request should be rejected by the auth proxy, got {res:?}"
+        );
+    }
+
+    #[test]
+    fn test_build_provider_invalid_header_name() {
+        let mock_server = MockServer::start();
+        let config = FetchConfig {
+            feeder_api_key: Some("secret".into()),
+            feeder_api_key_header: "invalid header".into(),
+            ..fetch_config(&mock_server)
+        };
+        assert!(build_provider(&config).is_err());
+    }
+
+    /// Minimal HTTP server answering `{}` to every request, which counts the connections opened to it.
+    fn connection_counting_server() -> (Url, Arc<AtomicUsize>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let connections_ = Arc::clone(&connections);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { return };
+                connections_.fetch_add(1, Ordering::SeqCst);
+                std::thread::spawn(move || {
+                    let mut buf = Vec::new();
+                    let mut chunk = [0u8; 1024];
+                    while let Ok(n) = stream.read(&mut chunk) {
+                        if n == 0 {
+                            return;
+                        }
+                        buf.extend_from_slice(&chunk[..n]);
+                        while let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+                            buf.drain(..end + 4);
+                            let response =
+                                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 2\r\n\r\n{}";
+                            if stream.write_all(response.as_bytes()).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        (url, connections)
+    }
+
+    /// Connections to the feeder gateway are reused, unless pooling is disabled with `pool_max_idle: Some(0)`.
+    #[tokio::test]
+    async fn test_build_provider_pool_config() {
+        let mock_server = MockServer::start();
+        let (url, connections) = connection_counting_server();
+        let config = |pool_max_idle| FetchConfig {
+            gateway: url.join("/gateway/").unwrap(),
+            feeder_gateway: url.join("/feeder_gateway/").unwrap(),
+            pool_max_idle,
+            ..fetch_config(&mock_server)
+        };
+
+        let provider = build_provider(&config(None)).unwrap();
+        for _ in 0..3 {
+            assert!(matches!(
+                provider.get_block(BlockId::Number(0)).await,
+                Err(SequencerError::DeserializeBody { .. })
+            ));
+        }
+        assert_eq!(connections.swap(0, Ordering::SeqCst), 1);
+
+        let provider = build_provider(&config(Some(0))).unwrap();
+        for _ in 0..3 {
+            assert!(matches!(
+                provider.get_block(BlockId::Number(0)).await,
+                Err(SequencerError::DeserializeBody { .. })
+            ));
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 3);
+    }
+
+    /// The feeder gateway is reached under its path prefix.
+    #[tokio::test]
+    async fn test_build_provider_path_prefix() {
+        let mock_server = MockServer::start();
+        let mock = mock_server.mock(|when, then| {
+            when.method("GET").path("/proxy/starknet/feeder_gateway/get_block");
+            then.status(400).json_body(serde_json::json!({
+                "code": "StarknetErrorCode.BLOCK_NOT_FOUND",
+                "message": "Block not found"
+            }));
+        });
+
+        let config =
+            FetchConfig { feeder_gateway_path_prefix: Some("/proxy/starknet/".into()), ..fetch_config(&mock_server) };
+        let provider = build_provider(&config).unwrap();
+        let res = provider.get_block(BlockId::Number(0)).await;
+        assert!(
+            matches!(
+                res,
+                Err(SequencerError::StarknetError(StarknetError { code: StarknetErrorCode::BlockNotFound, .. }))
+            ),
+            "Request should reach the prefixed feeder gateway, got {res:?}"
+        );
+        mock.assert();
+    }
+
+    #[test]
+    fn test_with_path_prefix() {
+        let url = Url::parse("https://proxy.example/feeder_gateway/").unwrap();
+        assert_eq!(with_path_prefix(&url, None).unwrap(), url);
+        assert_eq!(with_path_prefix(&url, Some("/")).unwrap(), url);
+        assert_eq!(
+            with_path_prefix(&url, Some("starknet/mainnet")).unwrap().as_str(),
+            "https://proxy.example/starknet/mainnet/feeder_gateway/"
+        );
+
+        assert!(with_path_prefix(&url, Some("a//b")).is_err());
+        assert!(with_path_prefix(&url, Some("../admin")).is_err());
+        assert!(with_path_prefix(&url, Some("a?b=c")).is_err());
+        let mock_server = MockServer::start();
+        let config = FetchConfig { gateway_path_prefix: Some("a#b".into()), ..fetch_config(&mock_server) };
+        assert!(build_provider(&config).is_err());
+    }
+
+    #[test]
+    fn test_sequencer_public_key() {
+        let mock_server = MockServer::start();
+        let config = FetchConfig { verify_signatures: true, ..fetch_config(&mock_server) };
+
+        assert_eq!(sequencer_public_key(&fetch_config(&mock_server), &ChainId::Mainnet).unwrap(), None);
+        assert_eq!(
+            sequencer_public_key(&config, &ChainId::Mainnet).unwrap(),
+            Some(Felt::from_hex(public_key::MAINNET).unwrap())
+        );
+        assert!(sequencer_public_key(&config, &config.chain_id).is_err());
+        let config = FetchConfig { sequencer_public_key: Some(Felt::ONE), ..config };
+        assert_eq!(sequencer_public_key(&config, &config.chain_id).unwrap(), Some(Felt::ONE));
+    }
+}
@@ -0,0 +1,120 @@
+use mc_analytics::{register_counter_metric_instrument, register_gauge_metric_instrument};
+use opentelemetry::{
+    global::{self, Error},
+    metrics::{Counter, Gauge},
+    KeyValue,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[derive(Clone, Debug)]
+pub struct FetchMetrics {
+    /// Free slots in the channel between the fetch task and the block conversion task. This stays at zero when
+    /// the block import is the bottleneck of the sync.
+    pub fetch_channel_capacity: Gauge<u64>,
+    /// Number of blocks the node is behind the tip of the network, see
+    /// [`SyncHealthTracker::sync_lag`](crate::health::SyncHealthTracker::sync_lag).
+    pub sync_lag: Gauge<u64>,
+    /// Classes declared by a block of a class prefetch window which were already being downloaded for another block
+    /// of the window. This stays at zero without `--class-prefetch-window`.
+    pub class_cache_hits: Counter<u64>,
+    /// Classes declared by a fetched block which had to be downloaded.
+    pub class_cache_misses: Counter<u64>,
+    /// Classes successfully downloaded for the fetched blocks.
+    pub classes_downloaded: Counter<u64>,
+    /// Share of the classes of the windows fetched so far which did not have to be downloaded.
+    pub class_cache_hit_ratio: Gauge<f64>,
+    /// Totals since startup, from which the hit ratio is computed.
+    class_cache_totals: Arc<(AtomicU64, AtomicU64)>,
+}
+
+impl FetchMetrics {
+    pub fn register() -> Result<Self, Error> {
+        let common_scope_attributes = vec![KeyValue::new("crate", "sync")];
+        let sync_meter = global::meter_with_version(
+            "crates.sync.opentelemetry",
+            Some("0.17"),
+            Some("https://opentelemetry.io/schemas/1.2.0"),
+            Some(common_scope_attributes.clone()),
+        );
+
+        let fetch_channel_capacity = register_gauge_metric_instrument(
+            &sync_meter,
+            "fetch_channel_capacity".to_string(),
+            "Free slots in the channel of fetched blocks waiting to be imported".to_string(),
+            "".to_string(),
+        );
+
+        let sync_lag = register_gauge_metric_instrument(
+            &sync_meter,
+            "sync_lag".to_string(),
+            "Blocks between the latest imported block and the tip of the network".to_string(),
+            "".to_string(),
+        );
+
+        let class_cache_hits = register_counter_metric_instrument(
+            &sync_meter,
+            "class_cache_hits".to_string(),
+            "Classes reused from another block of the same prefetch window".to_string(),
+            "".to_string(),
+        );
+
+        let class_cache_misses = register_counter_metric_instrument(
+            &sync_meter,
+            "class_cache_misses".to_string(),
+            "Declared classes which had to be downloaded".to_string(),
+            "".to_string(),
+        );
+
+        let classes_downloaded = register_counter_metric_instrument(
+            &sync_meter,
+            "classes_downloaded".to_string(),
+            "Classes downloaded from the feeder gateway".to_string(),
+            "".to_string(),
+        );
+
+        let class_cache_hit_ratio = register_gauge_metric_instrument(
+            &sync_meter,
+            "class_cache_hit_ratio".to_string(),
+            "Share of the declared classes which did not have to be downloaded".to_string(),
+            "".to_string(),
+        );
+
+        Ok(Self {
+            fetch_channel_capacity,
+            sync_lag,
+            class_cache_hits,
+            class_cache_misses,
+            classes_downloaded,
+            class_cache_hit_ratio,
+            class_cache_totals: Default::default(),
+        })
+    }
+
+    /// Records the class lookups of a block or a window: `misses` classes were requested, of which `downloaded`
+    /// succeeded, and `hits` declarations reused one of them.
+    pub fn record_class_lookups(&self, hits: u64, misses: u64, downloaded: u64) {
+        self.class_cache_hits.add(hits, &[]);
+        self.class_cache_misses.add(misses, &[]);
+        self.classes_downloaded.add(downloaded, &[]);
+
+        let (total_hits, total_misses) = &*self.class_cache_totals;
+        let hits = total_hits.fetch_add(hits, Ordering::Relaxed) + hits;
+        let misses = total_misses.fetch_add(misses, Ordering::Relaxed) + misses;
+        if let Some(ratio) = class_cache_hit_ratio(hits, misses) {
+            self.class_cache_hit_ratio.record(ratio, &[]);
+        }
+    }
+
+    /// Class cache hits and misses since startup.
+    pub fn class_cache_totals(&self) -> (u64, u64) {
+        let (hits, misses) = &*self.class_cache_totals;
+        (hits.load(Ordering::Relaxed), misses.load(Ordering::Relaxed))
+    }
+}
+
+/// `None` when no class has been looked up yet.
+pub fn class_cache_hit_ratio(hits: u64, misses: u64) -> Option<f64> {
+    let total = hits + misses;
+    (total > 0).then(|| hits as f64 / total as f64)
+}
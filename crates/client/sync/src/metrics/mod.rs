@@ -1 +1,3 @@
 pub mod block_metrics;
+pub mod fetch_metrics;
+pub mod reorg_metrics;
@@ -0,0 +1,47 @@
+use mc_analytics::register_counter_metric_instrument;
+use opentelemetry::{
+    global::{self, Error},
+    metrics::Counter,
+    KeyValue,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[derive(Clone, Debug)]
+pub struct ReorgMetrics {
+    /// Blocks of the feeder gateway which did not extend the local chain.
+    pub reorgs_total: Counter<u64>,
+    /// Total since startup, shared by the clones.
+    total: Arc<AtomicU64>,
+}
+
+impl ReorgMetrics {
+    pub fn register() -> Result<Self, Error> {
+        let common_scope_attributes = vec![KeyValue::new("crate", "sync")];
+        let sync_meter = global::meter_with_version(
+            "crates.sync.opentelemetry",
+            Some("0.17"),
+            Some("https://opentelemetry.io/schemas/1.2.0"),
+            Some(common_scope_attributes.clone()),
+        );
+
+        let reorgs_total = register_counter_metric_instrument(
+            &sync_meter,
+            "reorgs_total".to_string(),
+            "Reorgs of the feeder gateway chain detected by the sync".to_string(),
+            "".to_string(),
+        );
+
+        Ok(Self { reorgs_total, total: Default::default() })
+    }
+
+    pub fn record_reorg(&self) {
+        self.reorgs_total.add(1, &[]);
+        self.total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reorgs detected since startup.
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+}
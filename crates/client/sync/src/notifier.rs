@@ -0,0 +1,228 @@
+//! Side effects triggered whenever the sync imports a new block.
+use crate::fetch::BlockTraces;
+use crate::l2::L2StateUpdate;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use url::Url;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Number of blocks waiting to be sent to the webhook before new ones are dropped.
+const WEBHOOK_QUEUE_CAPACITY: usize = 128;
+
+/// Called by the sync every time a new block has been verified and stored in the database.
+///
+/// Implementations must not block: they are called from the block import task, and any delay here
+/// delays the sync.
+pub trait BlockNotifier: Send + Sync {
+    fn on_new_block(&self, update: &L2StateUpdate);
+
+    /// Called with the execution traces of each fetched block, only when the sync fetches traces. Traces are
+    /// delivered once the block has been fetched, possibly before it has been imported.
+    fn on_block_traces(&self, _traces: &BlockTraces) {}
+}
+
+/// Does nothing.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopNotifier;
+
+impl BlockNotifier for NoopNotifier {
+    fn on_new_block(&self, _update: &L2StateUpdate) {}
+}
+
+/// Rings the terminal bell on each new block.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SoundNotifier;
+
+impl BlockNotifier for SoundNotifier {
+    fn on_new_block(&self, _update: &L2StateUpdate) {
+        let mut stdout = std::io::stdout().lock();
+        // This is a best effort notification, we do not care if the terminal went away.
+        let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+    }
+}
+
+/// POSTs the block number, block hash and global state root of each new block to a URL.
+///
+/// Requests are sent one at a time by a background task: delivery failures are logged and never stop the sync.
+/// When the webhook cannot keep up with the sync, the blocks which do not fit in the queue are dropped and counted
+/// in [`Self::dropped`].
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    sender: mpsc::Sender<L2StateUpdate>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl WebhookNotifier {
+    /// This spawns the task sending the requests on the current tokio runtime. The task stops once the notifier and
+    /// all of its clones are dropped.
+    pub fn new(url: Url) -> Self {
+        Self::with_queue_capacity(url, WEBHOOK_QUEUE_CAPACITY)
+    }
+
+    fn with_queue_capacity(url: Url, capacity: usize) -> Self {
+        let client = reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build().unwrap_or_default();
+        let (sender, receiver) = mpsc::channel(capacity);
+        tokio::spawn(webhook_task(client, url, receiver));
+        Self { sender, dropped: Arc::default() }
+    }
+
+    /// Number of blocks which were not sent to the webhook because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+async fn webhook_task(client: reqwest::Client, url: Url, mut receiver: mpsc::Receiver<L2StateUpdate>) {
+    while let Some(update) = receiver.recv().await {
+        let payload = serde_json::json!({
+            "block_number": update.block_number,
+            "block_hash": format!("{:#x}", update.block_hash),
+            "global_root": format!("{:#x}", update.global_root),
+        });
+        let block_n = update.block_number;
+        match client.post(url.clone()).json(&payload).send().await.and_then(|res| res.error_for_status()) {
+            Ok(_) => tracing::debug!("Sent block webhook for block #{block_n}"),
+            Err(err) => tracing::warn!("Failed to send block webhook for block #{block_n}: {err:#}"),
+        }
+    }
+}
+
+impl BlockNotifier for WebhookNotifier {
+    fn on_new_block(&self, update: &L2StateUpdate) {
+        match self.sender.try_send(update.clone()) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(update)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::warn!(
+                    "Dropped the block webhook for block #{}, as the webhook is not keeping up ({dropped} dropped so far)",
+                    update.block_number
+                );
+            }
+            Err(mpsc::error::TrySendError::Closed(update)) => {
+                tracing::warn!(
+                    "Dropped the block webhook for block #{}, as the webhook task stopped",
+                    update.block_number
+                )
+            }
+        }
+    }
+}
+
+impl BlockNotifier for Vec<Arc<dyn BlockNotifier>> {
+    fn on_new_block(&self, update: &L2StateUpdate) {
+        for notifier in self {
+            notifier.on_new_block(update);
+        }
+    }
+
+    fn on_block_traces(&self, traces: &BlockTraces) {
+        for notifier in self {
+            notifier.on_block_traces(traces);
+        }
+    }
+}
+
+/// Selects the notifiers matching the `sound` and `block_webhook_url` fields of the
+/// [`FetchConfig`](crate::fetch::fetchers::FetchConfig).
+pub fn block_notifier(sound: bool, block_webhook_url: Option<Url>) -> Arc<dyn BlockNotifier> {
+    let mut notifiers: Vec<Arc<dyn BlockNotifier>> = vec![];
+    if sound {
+        notifiers.push(Arc::new(SoundNotifier));
+    }
+    if let Some(url) = block_webhook_url {
+        notifiers.push(Arc::new(WebhookNotifier::new(url)));
+    }
+
+    match notifiers.len() {
+        0 => Arc::new(NoopNotifier),
+        1 => notifiers.remove(0),
+        _ => Arc::new(notifiers),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+    use starknet_types_core::felt::Felt;
+
+    #[tokio::test]
+    async fn test_webhook_notifier_payload() {
+        let mock_server = MockServer::start();
+        let mock = mock_server.mock(|when, then| {
+            when.method("POST").path("/new_block").json_body(serde_json::json!({
+                "block_number": 5,
+                "block_hash": "0x1234",
+                "global_root": "0xabcd",
+            }));
+            then.status(200);
+        });
+
+        let notifier = WebhookNotifier::new(mock_server.url("/new_block").parse().unwrap());
+        notifier.on_new_block(&L2StateUpdate {
+            block_number: 5,
+            global_root: Felt::from_hex_unchecked("0xabcd"),
+            block_hash: Felt::from_hex_unchecked("0x1234"),
+        });
+
+        // The request is sent in the background
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while mock.hits_async().await == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Webhook was not called");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_webhook_notifier_failure_does_not_panic() {
+        let mock_server = MockServer::start();
+        let mock = mock_server.mock(|when, then| {
+            when.method("POST").path("/new_block");
+            then.status(500);
+        });
+
+        let notifier = WebhookNotifier::new(mock_server.url("/new_block").parse().unwrap());
+        notifier.on_new_block(&L2StateUpdate { block_number: 0, global_root: Felt::ZERO, block_hash: Felt::ZERO });
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while mock.hits_async().await == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Webhook was not called");
+    }
+
+    /// Blocks are dropped and counted once the queue is full, while the webhook is still handling an older block.
+    #[tokio::test]
+    async fn test_webhook_notifier_drops_when_full() {
+        let mock_server = MockServer::start();
+        let mock = mock_server.mock(|when, then| {
+            when.method("POST").path("/new_block");
+            then.status(200).delay(Duration::from_secs(1));
+        });
+
+        let notifier = WebhookNotifier::with_queue_capacity(mock_server.url("/new_block").parse().unwrap(), 1);
+        let update = |block_number| L2StateUpdate { block_number, global_root: Felt::ZERO, block_hash: Felt::ZERO };
+        notifier.on_new_block(&update(0));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while mock.hits_async().await == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Webhook was not called");
+
+        // Block 1 is queued while block 0 is being sent, blocks 2 and 3 do not fit
+        for block_number in 1..4 {
+            notifier.on_new_block(&update(block_number));
+        }
+        assert_eq!(notifier.dropped(), 2);
+    }
+}
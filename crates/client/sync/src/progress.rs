@@ -0,0 +1,135 @@
+//! Periodic catch-up progress log, so that operators can follow a long historical sync without parsing the per-block
+//! import logs.
+use std::time::{Duration, Instant};
+
+/// How often the progress line is logged. It is logged as soon as either threshold is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncProgressConfig {
+    pub every_n_blocks: u64,
+    pub interval: Duration,
+}
+
+/// How far the local chain is from the tip of the network, and when it is expected to catch up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncProgress {
+    pub current_block_number: u64,
+    pub highest_block_number: u64,
+    pub percent: f64,
+    /// `None` when the throughput is unknown.
+    pub eta: Option<Duration>,
+}
+
+/// Computes the [`SyncProgress`] for a throughput of `blocks_per_second`.
+pub fn sync_progress(current_block_number: u64, highest_block_number: u64, blocks_per_second: f64) -> SyncProgress {
+    let highest_block_number = highest_block_number.max(current_block_number);
+    // Block numbers start at 0, block #n is the (n + 1)-th block.
+    let percent = (current_block_number + 1) as f64 / (highest_block_number + 1) as f64 * 100.0;
+    let remaining = highest_block_number - current_block_number;
+    let eta = (blocks_per_second > 0.0)
+        .then(|| Duration::try_from_secs_f64(remaining as f64 / blocks_per_second).ok())
+        .flatten();
+    SyncProgress { current_block_number, highest_block_number, percent, eta }
+}
+
+/// Logs the [`SyncProgress`] every [`SyncProgressConfig`], with an ETA based on the throughput since the last log.
+pub struct SyncProgressLog {
+    config: SyncProgressConfig,
+    last_block_number: Option<u64>,
+    last_log: Instant,
+}
+
+impl SyncProgressLog {
+    pub fn new(config: SyncProgressConfig) -> Self {
+        Self { config, last_block_number: None, last_log: Instant::now() }
+    }
+
+    /// Called for every imported block, with the [sync lag](crate::health::SyncHealthTracker::sync_lag) once it is
+    /// imported. Returns the progress when it is logged.
+    pub fn on_block(&mut self, block_number: u64, sync_lag: Option<u64>) -> Option<SyncProgress> {
+        let Some(last_block_number) = self.last_block_number else {
+            self.last_block_number = Some(block_number);
+            self.last_log = Instant::now();
+            return None;
+        };
+        let blocks = block_number.saturating_sub(last_block_number);
+        let elapsed = self.last_log.elapsed();
+        if blocks < self.config.every_n_blocks && elapsed < self.config.interval {
+            return None;
+        }
+        self.last_block_number = Some(block_number);
+        self.last_log = Instant::now();
+
+        let highest_block_number = block_number.saturating_add(sync_lag?);
+        let progress = sync_progress(block_number, highest_block_number, blocks as f64 / elapsed.as_secs_f64());
+        match progress.eta {
+            Some(eta) => tracing::info!(
+                "📈 Synced block #{block_number} of #{} ({:.2}%), ETA {}",
+                progress.highest_block_number,
+                progress.percent,
+                format_eta(eta)
+            ),
+            None => tracing::info!(
+                "📈 Synced block #{block_number} of #{} ({:.2}%)",
+                progress.highest_block_number,
+                progress.percent
+            ),
+        }
+        Some(progress)
+    }
+}
+
+fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m{s:02}s"),
+        (h, m, _) => format!("{h}h{m:02}m"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::halfway(499, 999, 10.0, 50.0, Some(Duration::from_secs(50)))]
+    #[case::caught_up(999, 999, 10.0, 100.0, Some(Duration::ZERO))]
+    #[case::ahead_of_tip(1000, 999, 10.0, 100.0, Some(Duration::ZERO))]
+    #[case::unknown_rate(499, 999, 0.0, 50.0, None)]
+    fn test_sync_progress(
+        #[case] current: u64,
+        #[case] highest: u64,
+        #[case] blocks_per_second: f64,
+        #[case] percent: f64,
+        #[case] eta: Option<Duration>,
+    ) {
+        let progress = sync_progress(current, highest, blocks_per_second);
+        assert_eq!(progress.percent, percent);
+        assert_eq!(progress.eta, eta);
+    }
+
+    #[rstest]
+    #[case(Duration::from_secs(42), "42s")]
+    #[case(Duration::from_secs(125), "2m05s")]
+    #[case(Duration::from_secs(3 * 3600 + 7 * 60 + 12), "3h07m")]
+    fn test_format_eta(#[case] eta: Duration, #[case] expected: &str) {
+        assert_eq!(format_eta(eta), expected);
+    }
+
+    #[test]
+    fn test_sync_progress_log_cadence() {
+        let mut log =
+            SyncProgressLog::new(SyncProgressConfig { every_n_blocks: 10, interval: Duration::from_secs(3600) });
+        assert_eq!(log.on_block(0, Some(100)), None);
+        assert_eq!(log.on_block(9, Some(91)), None);
+
+        let progress = log.on_block(10, Some(90)).unwrap();
+        assert_eq!((progress.current_block_number, progress.highest_block_number), (10, 100));
+        assert!(progress.eta.is_some());
+
+        // The tip of the network is not known yet
+        assert_eq!(log.on_block(20, None), None);
+        assert_eq!(log.on_block(25, Some(75)), None);
+    }
+}
@@ -0,0 +1,94 @@
+//! Per-block timing of the sync phases, enabled with `--sync-timing`. This helps telling whether the sync is bound by
+//! the network, the block verification or the database.
+use crate::utils::lock;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Time spent in each phase of the sync of a block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockTiming {
+    /// Fetching the block and its state update, which the feeder gateway returns in a single request.
+    pub fetch_block: Duration,
+    /// Downloading the classes declared in the block.
+    pub fetch_classes: Duration,
+    /// Checking the commitments and class hashes of the block, and compiling its classes.
+    pub pre_validate: Duration,
+    /// Updating the global tries, computing the block hash and storing the block.
+    pub verify_apply: Duration,
+}
+
+/// Timings of the blocks going through the sync pipeline. The timing of a block is logged once it has been imported.
+#[derive(Debug, Default)]
+pub struct SyncTimings {
+    blocks: Mutex<BTreeMap<u64, BlockTiming>>,
+}
+
+impl SyncTimings {
+    pub fn update(&self, block_n: u64, f: impl FnOnce(&mut BlockTiming)) {
+        f(lock(&self.blocks).entry(block_n).or_default())
+    }
+
+    /// Removes the timing of an imported block, and logs it. The timings of the blocks before it are removed too: they
+    /// were fetched but never imported, as when the import of a block fails and it is fetched again.
+    pub fn finish(&self, block_n: u64) -> Option<BlockTiming> {
+        let timing = {
+            let mut blocks = lock(&self.blocks);
+            let mut newer = blocks.split_off(&block_n);
+            let timing = newer.remove(&block_n);
+            *blocks = newer;
+            timing?
+        };
+        tracing::debug!(
+            block_n,
+            fetch_block = ?timing.fetch_block,
+            fetch_classes = ?timing.fetch_classes,
+            pre_validate = ?timing.pre_validate,
+            verify_apply = ?timing.verify_apply,
+            "Sync timing of block #{block_n}"
+        );
+        Some(timing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_timings() {
+        let timings = SyncTimings::default();
+        assert_eq!(timings.finish(0), None);
+
+        timings.update(0, |timing| timing.fetch_block = Duration::from_millis(1));
+        timings.update(1, |timing| timing.fetch_block = Duration::from_millis(2));
+        timings.update(0, |timing| timing.verify_apply = Duration::from_millis(3));
+
+        assert_eq!(
+            timings.finish(0),
+            Some(BlockTiming {
+                fetch_block: Duration::from_millis(1),
+                verify_apply: Duration::from_millis(3),
+                ..Default::default()
+            })
+        );
+        assert_eq!(timings.finish(0), None);
+        assert_eq!(timings.finish(1).map(|timing| timing.fetch_block), Some(Duration::from_millis(2)));
+        assert!(lock(&timings.blocks).is_empty());
+    }
+
+    /// The timings of the blocks which were never imported do not pile up.
+    #[test]
+    fn test_sync_timings_drops_older_blocks() {
+        let timings = SyncTimings::default();
+        for block_n in 0..4 {
+            timings.update(block_n, |timing| timing.fetch_block = Duration::from_millis(block_n));
+        }
+
+        assert_eq!(timings.finish(2).map(|timing| timing.fetch_block), Some(Duration::from_millis(2)));
+        assert_eq!(lock(&timings.blocks).keys().copied().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(timings.finish(0), None);
+        assert_eq!(timings.finish(3).map(|timing| timing.fetch_block), Some(Duration::from_millis(3)));
+        assert!(lock(&timings.blocks).is_empty());
+    }
+}
@@ -1,4 +1,5 @@
 use starknet_types_core::felt::Felt;
+use std::sync::{LockResult, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 pub fn trim_hash(hash: &Felt) -> String {
     let hash_str = format!("{:#x}", hash);
@@ -11,3 +12,52 @@ pub fn trim_hash(hash: &Felt) -> String {
         format!("{}...{}", prefix, suffix)
     }
 }
+
+/// The locks shared by the sync tasks only guard plain values, which stay valid if a thread panics while holding
+/// them. A poisoned lock is recovered instead of spreading the panic to every other task.
+fn recover<Guard>(res: LockResult<Guard>) -> Guard {
+    res.unwrap_or_else(|err| {
+        tracing::warn!("Recovering a lock poisoned by a panicked thread");
+        err.into_inner()
+    })
+}
+
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    recover(mutex.lock())
+}
+
+pub(crate) fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    recover(lock.read())
+}
+
+pub(crate) fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    recover(lock.write())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_poisoned_lock_is_recovered() {
+        let mutex = Arc::new(Mutex::new(1));
+        let rw_lock = Arc::new(RwLock::new(2));
+        std::thread::spawn({
+            let (mutex, rw_lock) = (Arc::clone(&mutex), Arc::clone(&rw_lock));
+            move || {
+                let _guard = mutex.lock().unwrap();
+                let _write_guard = rw_lock.write().unwrap();
+                panic!("Poisoning the locks");
+            }
+        })
+        .join()
+        .unwrap_err();
+        assert!(mutex.is_poisoned() && rw_lock.is_poisoned());
+
+        assert_eq!(*lock(&mutex), 1);
+        assert_eq!(*read(&rw_lock), 2);
+        *write(&rw_lock) = 3;
+        assert_eq!(*read(&rw_lock), 3);
+    }
+}
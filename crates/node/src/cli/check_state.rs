@@ -0,0 +1,48 @@
+use anyhow::Context;
+use mc_block_import::{check_global_state_roots, StateRootMismatch};
+use mc_db::MadaraBackend;
+use mp_block::{BlockId, BlockTag};
+
+/// Re-verifies the global state root of a range of blocks already stored in the database.
+///
+/// For each block, the stored state diff is re-applied on the global tries as they were at the previous block, and
+/// the resulting state root is compared against the one stored in the block header. Nothing is written to the
+/// database. Only blocks whose parent is still covered by the trie logs (see `--db-max-saved-trie-logs`) can be
+/// checked.
+#[derive(Clone, Debug, clap::Args)]
+pub struct CheckStateCmd {
+    /// First block of the range to check.
+    #[clap(long, value_name = "BLOCK NUMBER", default_value_t = 0)]
+    pub from: u64,
+
+    /// Last block of the range to check (inclusive). Defaults to the latest block in the database.
+    #[clap(long, value_name = "BLOCK NUMBER")]
+    pub to: Option<u64>,
+}
+
+impl CheckStateCmd {
+    pub fn run(&self, backend: &MadaraBackend) -> anyhow::Result<()> {
+        let to = match self.to {
+            Some(to) => to,
+            None => backend
+                .get_block_n(&BlockId::Tag(BlockTag::Latest))
+                .context("Getting the latest block number")?
+                .context("The database is empty, there is no block to check")?,
+        };
+        anyhow::ensure!(self.from <= to, "Invalid range: --from {} is greater than --to {to}", self.from);
+
+        tracing::info!("🔍 Re-applying the state diffs of blocks {}..={to}", self.from);
+
+        match check_global_state_roots(backend, self.from..=to).context("Checking the global state roots")? {
+            None => {
+                tracing::info!("✅ The global state roots of blocks {}..={to} are valid", self.from);
+                Ok(())
+            }
+            Some(StateRootMismatch { block_n, stored, computed }) => {
+                anyhow::bail!(
+                    "State diverges at block #{block_n}: the stored global state root is {stored:#x}, but re-applying its state diff gives {computed:#x}"
+                )
+            }
+        }
+    }
+}
@@ -1,10 +1,14 @@
 use std::time::Duration;
 
+use serde::Serialize;
 use url::Url;
 
+use mc_eth::l1_gas_price::L1GasMode;
+
 use mp_utils::parsers::{parse_duration, parse_url};
+use mp_utils::serde::{serialize_duration, serialize_optional_url_origin};
 
-#[derive(Clone, Debug, clap::Args)]
+#[derive(Clone, Debug, clap::Args, Serialize)]
 pub struct L1SyncParams {
     /// Disable L1 sync.
     #[clap(env = "MADARA_SYNC_L1_DISABLED", long, alias = "no-l1-sync", conflicts_with = "l1_endpoint")]
@@ -12,8 +16,21 @@ pub struct L1SyncParams {
 
     /// The L1 rpc endpoint url for state verification.
     #[clap(env = "MADARA_L1_ENDPOINT", long, value_parser = parse_url, value_name = "ETHEREUM RPC URL")]
+    #[serde(serialize_with = "serialize_optional_url_origin")]
     pub l1_endpoint: Option<Url>,
 
+    /// Fallback L1 rpc endpoint url. The L1 sync switches to it when the primary endpoint fails, and back to the
+    /// primary endpoint once it answers again.
+    #[clap(
+        env = "MADARA_L1_ENDPOINT_FALLBACK",
+        long,
+        value_parser = parse_url,
+        value_name = "ETHEREUM RPC URL",
+        requires = "l1_endpoint"
+    )]
+    #[serde(serialize_with = "serialize_optional_url_origin")]
+    pub l1_endpoint_fallback: Option<Url>,
+
     /// Fix the gas price. If the gas price is fixed it won't fetch the fee history from the ethereum.
     #[clap(env = "MADARA_GAS_PRICE", long, alias = "gas-price")]
     pub gas_price: Option<u64>,
@@ -37,5 +54,128 @@ pub struct L1SyncParams {
         default_value = "10s",
         value_parser = parse_duration,
     )]
+    #[serde(serialize_with = "serialize_duration")]
     pub gas_price_poll: Duration,
+
+    /// Timeout of a single gas price fetch from the Ethereum fee history. On timeout, the previous gas prices are kept
+    /// until the next poll.
+    #[clap(
+        env = "MADARA_GAS_PRICE_REQUEST_TIMEOUT",
+        long,
+        default_value = "5s",
+        value_parser = parse_duration,
+    )]
+    #[serde(serialize_with = "serialize_duration")]
+    pub gas_price_request_timeout: Duration,
+
+    /// Log a warning when a gas price fetched from the Ethereum fee history differs from the previous poll by
+    /// more than this percentage. Such a jump usually means that the L1 endpoint is serving bad data.
+    #[clap(env = "MADARA_GAS_PRICE_MAX_DELTA_PCT", long, value_name = "PERCENT")]
+    pub gas_price_max_delta_pct: Option<f64>,
+
+    /// How the L1 gas price is computed from the Ethereum fee history. The default `eip1559` mode uses the base fee,
+    /// use `legacy` for L1s which do not implement EIP-1559.
+    #[clap(env = "MADARA_L1_GAS_MODE", long, value_enum, default_value_t = L1GasModeArg::Eip1559)]
+    pub l1_gas_mode: L1GasModeArg,
+
+    /// Skip the startup check that the L1 endpoint serves the expected Ethereum chain and that the
+    /// L1 Core contract is deployed there.
+    #[clap(env = "MADARA_SKIP_L1_CHECK", long)]
+    pub skip_l1_check: bool,
+}
+
+/// See [`L1GasMode`].
+#[derive(Debug, Clone, Copy, clap::ValueEnum, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum L1GasModeArg {
+    /// The base fee of the latest block.
+    Eip1559,
+    /// The base fee plus the median priority fee paid in the latest block.
+    Legacy,
+}
+
+impl From<L1GasModeArg> for L1GasMode {
+    fn from(value: L1GasModeArg) -> Self {
+        match value {
+            L1GasModeArg::Eip1559 => L1GasMode::Eip1559,
+            L1GasModeArg::Legacy => L1GasMode::Legacy,
+        }
+    }
+}
+
+impl L1SyncParams {
+    /// Returns true when all of the L1 gas prices have been fixed from the cli. In this case there is
+    /// nothing to fetch from Ethereum and the gas price worker does not need to be started at all.
+    pub fn is_fully_fixed(&self) -> bool {
+        self.gas_price.is_some()
+            && self.blob_gas_price.is_some()
+            && self.strk_gas_price.is_some()
+            && self.strk_blob_gas_price.is_some()
+    }
+
+    /// Returns true when the gas price worker has at least one price left to fetch from the Ethereum
+    /// fee history. Prices which have been fixed are never overwritten by the worker.
+    pub fn gas_price_sync_needed(&self) -> bool {
+        !self.is_fully_fixed() && (self.gas_price.is_none() || self.blob_gas_price.is_none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn params(
+        gas_price: Option<u64>,
+        blob_gas_price: Option<u64>,
+        strk_gas_price: Option<u64>,
+        strk_blob_gas_price: Option<u64>,
+    ) -> L1SyncParams {
+        L1SyncParams {
+            sync_l1_disabled: false,
+            l1_endpoint: None,
+            l1_endpoint_fallback: None,
+            gas_price,
+            blob_gas_price,
+            strk_gas_price,
+            strk_blob_gas_price,
+            gas_price_poll: Duration::from_secs(10),
+            gas_price_request_timeout: Duration::from_secs(5),
+            gas_price_max_delta_pct: None,
+            l1_gas_mode: L1GasModeArg::Eip1559,
+            skip_l1_check: false,
+        }
+    }
+
+    #[rstest]
+    fn test_gas_prices_all_fixed() {
+        let params = params(Some(1), Some(2), Some(3), Some(4));
+        assert!(params.is_fully_fixed());
+        assert!(!params.gas_price_sync_needed());
+    }
+
+    #[rstest]
+    #[case::eth_fixed(params(Some(1), None, None, None))]
+    #[case::blob_fixed(params(None, Some(2), None, None))]
+    #[case::strk_fixed(params(None, None, Some(3), Some(4)))]
+    #[case::all_but_one_fixed(params(Some(1), None, Some(3), Some(4)))]
+    fn test_gas_prices_partially_fixed(#[case] params: L1SyncParams) {
+        assert!(!params.is_fully_fixed());
+        assert!(params.gas_price_sync_needed());
+    }
+
+    #[rstest]
+    fn test_gas_prices_eth_fixed_strk_not_fixed() {
+        // The worker only fetches eth prices from the fee history, there is nothing left to fetch.
+        let params = params(Some(1), Some(2), None, None);
+        assert!(!params.is_fully_fixed());
+        assert!(!params.gas_price_sync_needed());
+    }
+
+    #[rstest]
+    fn test_gas_prices_none_fixed() {
+        let params = params(None, None, None, None);
+        assert!(!params.is_fully_fixed());
+        assert!(params.gas_price_sync_needed());
+    }
 }
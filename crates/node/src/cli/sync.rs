@@ -1,10 +1,19 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
 use mp_chain_config::ChainConfig;
 use starknet_api::core::ChainId;
 
+use mc_gateway_client::RecordFormat;
+use mc_sync::checkpoint::CheckpointConfig;
 use mc_sync::fetch::fetchers::FetchConfig;
-use mp_utils::parsers::{parse_duration, parse_url};
+use mc_sync::genesis::GenesisDumpConfig;
+use mp_utils::parsers::{parse_duration, parse_felt, parse_url};
+use starknet_types_core::felt::Felt;
 use url::Url;
 
 use super::FGW_DEFAULT_PORT;
@@ -12,28 +21,99 @@ use super::RPC_DEFAULT_PORT_ADMIN;
 
 #[derive(Clone, Debug, clap::Args)]
 pub struct SyncParams {
-    /// Disable the sync service. The sync service is responsible for listening for new blocks on starknet and ethereum.
-    #[clap(env = "MADARA_SYNC_DISABLED", long, alias = "no-sync")]
-    pub sync_disabled: bool,
+    /// Disable the L2 sync service, which fetches new blocks from the feeder gateway. The node keeps running on the
+    /// blocks it has already synced, while the L1 sync can still run, for example for an L1-only verifier.
+    #[clap(env = "MADARA_SYNC_DISABLED", long, aliases = ["no-sync", "sync-disabled", "no-l2-sync"])]
+    pub sync_l2_disabled: bool,
 
     /// The block you want to start syncing from. This will most probably break your database.
+    /// Blocks which have already been synced are skipped.
     #[clap(env = "MADARA_UNSAFE_STARTING_BLOCK", long, value_name = "BLOCK NUMBER")]
     pub unsafe_starting_block: Option<u64>,
 
+    /// Re-verify the last N blocks of the database on startup, in case a crash left the database in a partial
+    /// state. Their stored state diffs are re-applied on the global tries and checked against their global state
+    /// roots, as `check-state` does, then the sync starts again from the first of these blocks: each one is fetched
+    /// again and checked against the stored block, leaving the database untouched. The node will not start if a
+    /// divergence is found.
+    #[clap(env = "MADARA_RESYNC_TAIL", long, value_name = "NUMBER OF BLOCKS")]
+    pub resync_tail: Option<u64>,
+
+    /// Start a fresh database from the full state in this JSON dump instead of fetching block 0 from the feeder
+    /// gateway, as forked chains often start from a state which is too large for a single gateway block. The dump
+    /// also holds the header and hash of block 0 on the network, and is rejected if the imported block has another
+    /// hash. The dump is ignored once the database has blocks.
+    #[clap(env = "MADARA_GENESIS_DUMP", long, value_name = "PATH", requires = "genesis_state_root")]
+    pub genesis_dump: Option<PathBuf>,
+
+    /// Global state root expected once `--genesis-dump` is imported. The node will not start if the dump results
+    /// in another state root.
+    #[clap(env = "MADARA_GENESIS_STATE_ROOT", long, value_parser = parse_felt, value_name = "STATE ROOT")]
+    pub genesis_state_root: Option<Felt>,
+
+    /// Start a fresh database right after the trusted block of this signed JSON checkpoint, instead of syncing the
+    /// chain from genesis. The blocks before the checkpoint are not synced, and the global state roots of the
+    /// following blocks are not verified as the database does not have their state.
+    #[clap(
+        env = "MADARA_CHECKPOINT",
+        long,
+        value_name = "PATH",
+        requires = "checkpoint_public_key",
+        conflicts_with_all = ["unsafe_starting_block", "genesis_dump"]
+    )]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Public key the signature of `--checkpoint` is checked against. The node will not start if the checkpoint is
+    /// not signed by this key.
+    #[clap(env = "MADARA_CHECKPOINT_PUBLIC_KEY", long, value_parser = parse_felt, value_name = "PUBLIC KEY")]
+    pub checkpoint_public_key: Option<Felt>,
+
     /// Disable state root verification. When importing a block, the state root verification is the most expensive operation.
     /// Disabling it will mean the sync service will have a huge speed-up, at a security cost
     // TODO(docs): explain the security cost
     #[clap(env = "MADARA_DISABLE_ROOT", long)]
     pub disable_root: bool,
 
+    /// Trust the feeder gateway: do not verify class hashes, compiled class hashes and global state roots.
+    /// This implies `--disable-root`. This trades security for sync speed, and should only be used when
+    /// syncing from a fully trusted sequencer.
+    #[clap(env = "MADARA_TRUST_FEEDER", long)]
+    pub trust_feeder: bool,
+
+    /// Comma-separated list of block numbers whose global state root mismatch is ignored. The state diff of these
+    /// blocks is still applied, and the block is imported with the state root of the feeder gateway. This is an
+    /// escape hatch for a sync stuck on a block with known bad data: only use it if you accept the risk.
+    #[clap(env = "MADARA_SKIP_BLOCKS", long, value_delimiter = ',', value_name = "BLOCK NUMBERS")]
+    pub skip_blocks: Vec<u64>,
+
     /// Gateway api key to avoid rate limiting (optional).
     #[clap(env = "MADARA_GATEWAY_KEY", long, value_name = "API KEY")]
     pub gateway_key: Option<String>,
 
+    /// API key sent with every request to the feeder gateway, for private gateways behind an
+    /// authentication proxy. Use `--feeder-api-key-header` to choose the header it is sent in.
+    #[clap(env = "MADARA_FEEDER_API_KEY", long, value_name = "API KEY")]
+    pub feeder_api_key: Option<String>,
+
+    /// Header in which the feeder API key is sent. For bearer tokens, use `authorization` and
+    /// pass `Bearer <TOKEN>` as the API key.
+    #[clap(env = "MADARA_FEEDER_API_KEY_HEADER", long, value_name = "HEADER NAME", default_value = "x-api-key")]
+    pub feeder_api_key_header: String,
+
     /// Feeder gateway url used to sync blocks, state updates and classes
     #[clap(env = "MADARA_GATEWAY_URL", long, value_parser = parse_url, value_name = "URL")]
     pub gateway_url: Option<Url>,
 
+    /// Path prefix of the gateway, when it is hosted under a non standard path behind a reverse proxy. It is
+    /// inserted before the path of the gateway url: `https://proxy.example/gateway/` with the prefix
+    /// `starknet/mainnet` becomes `https://proxy.example/starknet/mainnet/gateway/`.
+    #[clap(env = "MADARA_GATEWAY_PATH_PREFIX", long, value_name = "PATH")]
+    pub gateway_path_prefix: Option<String>,
+
+    /// Same as `--gateway-path-prefix`, for the feeder gateway.
+    #[clap(env = "MADARA_FEEDER_GATEWAY_PATH_PREFIX", long, value_name = "PATH")]
+    pub feeder_gateway_path_prefix: Option<String>,
+
     /// The port used for nodes to make rpc calls during a warp update.
     #[arg(env = "MADARA_WARP_UPDATE_PORT_RPC", long, value_name = "WARP UPDATE PORT RPC", default_value_t = RPC_DEFAULT_PORT_ADMIN)]
     pub warp_update_port_rpc: u16,
@@ -42,6 +122,81 @@ pub struct SyncParams {
     #[arg(env = "MADARA_WARP_UPDATE_PORT_FGW", long, value_name = "WARP UPDATE FGW", default_value_t = FGW_DEFAULT_PORT)]
     pub warp_update_port_fgw: u16,
 
+    /// Timeout of a single request to the feeder gateway. Requests which time out are retried.
+    #[clap(
+		env = "MADARA_FEEDER_TIMEOUT",
+        long,
+        value_parser = parse_duration,
+        default_value = "20s",
+        value_name = "FEEDER TIMEOUT",
+        help = "Set the feeder gateway request timeout (e.g., '20s', '500ms', '1min')"
+    )]
+    pub feeder_timeout: Duration,
+
+    /// Maximum number of idle connections kept open to the feeder gateway, for reuse by the next requests.
+    /// Unlimited by default.
+    #[clap(env = "MADARA_FEEDER_POOL_MAX_IDLE", long, value_name = "CONNECTIONS")]
+    pub feeder_pool_max_idle: Option<usize>,
+
+    /// Idle connections to the feeder gateway are closed after this long.
+    #[clap(
+        env = "MADARA_FEEDER_KEEPALIVE",
+        long,
+        value_parser = parse_duration,
+        default_value = "90s",
+        value_name = "DURATION"
+    )]
+    pub feeder_keepalive: Duration,
+
+    /// Maximum number of requests per second made to the feeder gateway, shared between block, state update
+    /// and class requests. Useful for public feeder gateways which enforce request quotas.
+    #[clap(env = "MADARA_FEEDER_RPS", long, value_name = "REQUESTS PER SECOND")]
+    pub feeder_rps: Option<NonZeroU32>,
+
+    /// Maximum size of a feeder gateway response body. Larger responses are rejected before being buffered in
+    /// memory, protecting the node against a misbehaving feeder gateway. Unlimited by default.
+    #[clap(env = "MADARA_FEEDER_MAX_RESPONSE_BYTES", long, value_name = "BYTES")]
+    pub feeder_max_response_bytes: Option<NonZeroUsize>,
+
+    /// Ask the feeder gateway for gzip compressed responses, which are decompressed transparently. This greatly
+    /// reduces the bandwidth used by classes and state updates, at the cost of some CPU.
+    #[clap(env = "MADARA_FEEDER_COMPRESSION", long)]
+    pub feeder_compression: bool,
+
+    /// Record every block, state update, signature and class fetched from the feeder gateway in this directory, so
+    /// that the sync can later be reproduced with `--replay-dir`.
+    #[clap(env = "MADARA_RECORD_DIR", long, value_name = "PATH", conflicts_with = "replay_dir")]
+    pub record_dir: Option<PathBuf>,
+
+    /// Read blocks, state updates, signatures and classes from a directory written with `--record-dir` instead of
+    /// the feeder gateway. Useful for debugging the sync offline.
+    #[clap(env = "MADARA_REPLAY_DIR", long, value_name = "PATH")]
+    pub replay_dir: Option<PathBuf>,
+
+    /// Format of the files written by `--record-dir`. JSON is human readable, bincode is more compact for large
+    /// historical recordings. `--replay-dir` reads the files in either format.
+    #[clap(env = "MADARA_RECORD_FORMAT", long, value_enum, default_value_t = RecordFormatArg::Bincode)]
+    pub record_format: RecordFormatArg,
+
+    /// Also fetch the execution traces of every synced block from the feeder gateway. Traces are not stored in
+    /// the database, they are recorded with `--record-dir`. Failing to fetch traces does not stop the sync.
+    #[clap(env = "MADARA_FETCH_TRACES", long)]
+    pub fetch_traces: bool,
+
+    /// Log the time spent fetching, verifying and storing every block, at debug level. This helps finding out
+    /// whether the sync is bound by the network, the CPU or the database.
+    #[clap(env = "MADARA_SYNC_TIMING", long)]
+    pub sync_timing: bool,
+
+    /// Log the catch-up progress, with the highest block of the network and an ETA, every this many imported
+    /// blocks.
+    #[clap(env = "MADARA_SYNC_PROGRESS_EVERY_N_BLOCKS", long, value_name = "BLOCKS", default_value_t = 1000)]
+    pub sync_progress_every_n_blocks: u64,
+
+    /// Log the catch-up progress at least this often while blocks are being imported.
+    #[clap(env = "MADARA_SYNC_PROGRESS_INTERVAL", long, value_parser = parse_duration, default_value = "30s")]
+    pub sync_progress_interval: Duration,
+
     /// Polling interval, in seconds. This only affects the sync service once it has caught up with the blockchain tip.
     #[clap(
 		env = "MADARA_SYNC_POLLING_INTERVAL",
@@ -64,6 +219,11 @@ pub struct SyncParams {
     )]
     pub pending_block_poll_interval: Duration,
 
+    /// Do not track the pending block. This saves a feeder gateway request and a block conversion on every poll,
+    /// for nodes which do not serve RPC queries on the pending block.
+    #[clap(env = "MADARA_DISABLE_PENDING", long)]
+    pub disable_pending: bool,
+
     /// Disable sync polling. This currently means that the sync process will not import any more block once it has caught up with the
     /// blockchain tip.
     #[clap(env = "MADARA_NO_SYNC_POLLING", long)]
@@ -124,6 +284,17 @@ pub struct SyncParams {
     )]
     pub flush_every_n_seconds: u64,
 
+    /// Wait this long after each imported block before importing the next one, to pace the sync on test networks or
+    /// controlled replays. There is no delay by default.
+    #[clap(
+        env = "MADARA_BLOCK_COMMIT_THROTTLE",
+        long,
+        value_parser = parse_duration,
+        default_value = "0s",
+        value_name = "DURATION"
+    )]
+    pub block_commit_throttle: Duration,
+
     /// Number of blocks to fetch in parallel. This only affects sync time, and
     /// does not affect the node once it has reached the tip of the chain.
     /// Increasing this can lead to lower sync times at the cost of higher cpu
@@ -135,9 +306,141 @@ pub struct SyncParams {
         value_parser = clap::value_parser!(u8).range(1..)
     )]
     pub sync_parallelism: u8,
+
+    /// Maximum number of classes to download at once, across all the blocks fetched in parallel. A single block can
+    /// declare many classes: this bounds the load on the feeder gateway independently of `--sync-parallelism`.
+    /// Unlimited by default.
+    #[clap(env = "MADARA_CLASS_PARALLELISM", long, value_name = "CLASSES")]
+    pub class_parallelism: Option<NonZeroUsize>,
+
+    /// Fetch the blocks by windows of this many blocks during the initial sync: the state updates of a window are
+    /// fetched first, and then all the classes they declare in a single deduplicated batch, which still goes through
+    /// `--class-parallelism`. About `--sync-parallelism` blocks are fetched at once, in at least two windows.
+    /// When unset, each block is fetched along with its own classes.
+    #[clap(env = "MADARA_CLASS_PREFETCH_WINDOW", long, value_name = "BLOCKS")]
+    pub class_prefetch_window: Option<NonZeroUsize>,
+
+    /// Number of fetched blocks which can wait to be verified and imported. Fetching keeps going ahead of the
+    /// import until this buffer is full, so that network latency overlaps with the state root computation.
+    #[clap(
+        env = "MADARA_FETCH_BUFFER_SIZE",
+        long, value_name = "BLOCKS",
+        default_value_t = 8,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub fetch_buffer_size: u64,
+
+    /// Only commit the global tries every N blocks, instead of on every block. This speeds up historical sync: the
+    /// state root is only computed and verified every N blocks, and the blocks in between cannot be checked by
+    /// `check-state`. Staged trie updates are committed when the node stops gracefully. A state root mismatch is
+    /// reported for the whole range of blocks committed together.
+    #[clap(
+        env = "MADARA_TRIE_COMMIT_INTERVAL",
+        long,
+        alias = "verification-stride",
+        value_name = "BLOCKS",
+        default_value = "1"
+    )]
+    pub trie_commit_interval: NonZeroU64,
+
+    /// Write the state diff applied on the global tries for every block to `<DIR>/<BLOCK NUMBER>.json`, before the
+    /// global state root is verified. This is meant to debug state root mismatches.
+    #[clap(env = "MADARA_DUMP_STATE_DIFF_DIR", long, value_name = "DIR")]
+    pub dump_state_diff_dir: Option<PathBuf>,
+
+    /// Webhook called every time a new block is imported. The block number, block hash and global
+    /// state root are POSTed to this URL as JSON. Delivery failures are logged and never stop the sync.
+    #[clap(env = "MADARA_BLOCK_WEBHOOK_URL", long, value_parser = parse_url, value_name = "URL")]
+    pub block_webhook_url: Option<Url>,
+
+    /// Verify the signature of every block fetched from the feeder gateway against the public key of the sequencer.
+    /// This costs one more request per block. A block with an invalid signature stops the sync.
+    #[clap(env = "MADARA_VERIFY_SIGNATURES", long, default_value_t = false)]
+    pub verify_signatures: bool,
+
+    /// Public key of the sequencer, used by `--verify-signatures`. Defaults to the key of the network for mainnet
+    /// and the public testnets.
+    #[clap(env = "MADARA_SEQUENCER_PUBLIC_KEY", long, value_parser = parse_felt, value_name = "PUBLIC KEY")]
+    pub sequencer_public_key: Option<Felt>,
+
+    /// Cross-check the state diff of each fetched block against the `get_storage_at` and `get_nonce` endpoints of the
+    /// feeder gateway, for this many contracts picked at random. This is a cheaper, probabilistic alternative to
+    /// recomputing the global state root, and costs a request per checked storage value and nonce. A mismatch stops
+    /// the sync.
+    #[clap(env = "MADARA_VERIFY_SAMPLE_SIZE", long, value_name = "CONTRACTS")]
+    pub verify_sample_size: Option<NonZeroUsize>,
+
+    /// Maximum number of blocks the node can be behind the tip of the chain while still being
+    /// reported as ready on the `/ready` endpoint of the RPC server.
+    #[clap(env = "MADARA_SYNCED_THRESHOLD", long, value_name = "NUMBER OF BLOCKS", default_value_t = 10)]
+    pub synced_threshold: u64,
+
+    /// Only report the node as ready, and only import the pending block, once it has been caught up with the tip of
+    /// the chain for this many consecutive polls. This avoids trusting a tip which briefly matches the local chain,
+    /// for example during a reorg.
+    #[clap(env = "MADARA_CAUGHT_UP_POLLS", long, value_name = "NUMBER OF POLLS")]
+    pub caught_up_polls: Option<u32>,
+
+    /// Report the node as stalled on the `/ready` endpoint when it stays more than this many blocks behind the tip of
+    /// the chain for longer than `--max-sync-lag-timeout` without catching up. This usually means that the feeder
+    /// gateway or the block verification is stuck.
+    #[clap(env = "MADARA_MAX_SYNC_LAG", long, value_name = "NUMBER OF BLOCKS")]
+    pub max_sync_lag: Option<u64>,
+
+    /// How long the node can stay more than `--max-sync-lag` blocks behind without catching up.
+    #[clap(
+        env = "MADARA_MAX_SYNC_LAG_TIMEOUT",
+        long,
+        value_parser = parse_duration,
+        default_value = "5min",
+        value_name = "DURATION"
+    )]
+    pub max_sync_lag_timeout: Duration,
+
+    /// Report the feeder gateway as down on the `madara_feederHealth` admin RPC method after this many
+    /// consecutive failed requests.
+    #[clap(
+        env = "MADARA_FEEDER_DOWN_AFTER_FAILURES",
+        long,
+        value_name = "NUMBER OF REQUESTS",
+        default_value_t = mc_gateway_client::DEFAULT_DOWN_AFTER_FAILURES
+    )]
+    pub feeder_down_after_failures: u32,
+
+    /// Number of the latest verified state updates kept in memory, so that the recent state roots can be looked up
+    /// without reading the database. Zero disables it.
+    #[clap(
+        env = "MADARA_RECENT_STATE_UPDATES",
+        long,
+        value_name = "NUMBER OF BLOCKS",
+        default_value_t = mc_sync::l2::DEFAULT_RECENT_STATE_UPDATES
+    )]
+    pub recent_state_updates: usize,
+
+    /// Stop the node with exit code 3 when the feeder gateway fails more than this many requests in a row, instead
+    /// of retrying. A successful request resets the count. Unlimited by default.
+    #[clap(env = "MADARA_MAX_CONSECUTIVE_FAILURES", long, value_name = "NUMBER OF REQUESTS")]
+    pub max_consecutive_failures: Option<u32>,
+
+    /// Compile the Sierra classes to CASM as soon as they are fetched, in parallel with the fetch of the next
+    /// blocks. The block import then reuses the compiled classes instead of compiling them itself.
+    #[clap(env = "MADARA_COMPILE_CLASSES_ON_FETCH", long)]
+    pub compile_classes_on_fetch: bool,
 }
 
 impl SyncParams {
+    pub fn genesis_dump(&self) -> Option<GenesisDumpConfig> {
+        let path = self.genesis_dump.clone()?;
+        let state_root = self.genesis_state_root.expect("--genesis-dump requires --genesis-state-root");
+        Some(GenesisDumpConfig { path, state_root })
+    }
+
+    pub fn checkpoint(&self) -> Option<CheckpointConfig> {
+        let path = self.checkpoint.clone()?;
+        let public_key = self.checkpoint_public_key.expect("--checkpoint requires --checkpoint-public-key");
+        Some(CheckpointConfig { path, public_key })
+    }
+
     pub fn block_fetch_config(
         &self,
         chain_id: ChainId,
@@ -157,18 +460,64 @@ impl SyncParams {
         FetchConfig {
             gateway,
             feeder_gateway,
+            gateway_path_prefix: self.gateway_path_prefix.clone(),
+            feeder_gateway_path_prefix: self.feeder_gateway_path_prefix.clone(),
             chain_id,
             verify: !self.disable_root,
             api_key: self.gateway_key.clone(),
+            feeder_api_key: self.feeder_api_key.clone(),
+            feeder_api_key_header: self.feeder_api_key_header.clone(),
             sync_polling_interval: polling,
             n_blocks_to_sync: self.n_blocks_to_sync,
             flush_every_n_blocks: self.flush_every_n_blocks,
             flush_every_n_seconds: self.flush_every_n_seconds,
+            block_commit_throttle: self.block_commit_throttle,
             stop_on_sync: self.stop_on_sync,
             sync_parallelism: self.sync_parallelism,
+            class_parallelism: self.class_parallelism,
+            class_prefetch_window: self.class_prefetch_window,
+            fetch_buffer_size: self.fetch_buffer_size as usize,
             warp_update,
             warp_update_port_rpc: self.warp_update_port_rpc,
             warp_update_port_fgw: self.warp_update_port_fgw,
+            sound: cfg!(feature = "sound"),
+            block_webhook_url: self.block_webhook_url.clone(),
+            request_timeout: self.feeder_timeout,
+            pool_max_idle: self.feeder_pool_max_idle,
+            keepalive: self.feeder_keepalive,
+            feeder_rps: self.feeder_rps,
+            max_response_bytes: self.feeder_max_response_bytes,
+            feeder_compression: self.feeder_compression,
+            trust_feeder: self.trust_feeder,
+            skip_blocks: self.skip_blocks.iter().copied().collect(),
+            record_dir: self.record_dir.clone(),
+            replay_dir: self.replay_dir.clone(),
+            record_format: self.record_format.into(),
+            fetch_traces: self.fetch_traces,
+            sync_timing: self.sync_timing,
+            progress_every_n_blocks: self.sync_progress_every_n_blocks,
+            progress_interval: self.sync_progress_interval,
+            verify_signatures: self.verify_signatures,
+            sequencer_public_key: self.sequencer_public_key,
+            verify_sample_size: self.verify_sample_size,
+            max_consecutive_failures: self.max_consecutive_failures,
+            compile_classes: self.compile_classes_on_fetch,
+        }
+    }
+}
+
+/// See [`RecordFormat`].
+#[derive(Debug, Clone, Copy, clap::ValueEnum, PartialEq, Eq)]
+pub enum RecordFormatArg {
+    Json,
+    Bincode,
+}
+
+impl From<RecordFormatArg> for RecordFormat {
+    fn from(value: RecordFormatArg) -> Self {
+        match value {
+            RecordFormatArg::Json => RecordFormat::Json,
+            RecordFormatArg::Bincode => RecordFormat::Bincode,
         }
     }
 }
@@ -17,6 +17,8 @@ use mc_db::{DatabaseService, TrieLogConfig};
 use mc_gateway_client::GatewayProvider;
 use mc_mempool::{GasPriceProvider, L1DataProvider, Mempool};
 use mc_rpc::providers::{AddTransactionProvider, ForwardToProvider, MempoolAddTxProvider};
+use mc_sync::fetch::FetchError;
+use mc_sync::health::{MaxSyncLag, SyncHealthTracker};
 use mc_telemetry::{SysInfo, TelemetryService};
 use mp_utils::service::{Service, ServiceGroup};
 use service::{BlockProductionService, GatewayService, L1SyncService, L2SyncService, RpcService};
@@ -49,6 +51,12 @@ async fn main() -> anyhow::Result<()> {
         run_cmd.chain_config()?
     };
 
+    if run_cmd.print_config {
+        let config = run_cmd.effective_config(chain_config)?;
+        println!("{}", serde_json::to_string_pretty(&config).context("Serializing the config")?);
+        return Ok(());
+    }
+
     let node_name = run_cmd.node_name_or_provide().await.to_string();
     let node_version = env!("MADARA_BUILD_VERSION");
 
@@ -84,10 +92,17 @@ async fn main() -> anyhow::Result<()> {
     .await
     .context("Initializing db service")?;
 
-    let importer = Arc::new(
-        BlockImporter::new(Arc::clone(db_service.backend()), run_cmd.sync_params.unsafe_starting_block)
-            .context("Initializing importer service")?,
-    );
+    if let Some(cli::Command::CheckState(cmd)) = &run_cmd.command {
+        return cmd.run(db_service.backend());
+    }
+
+    let mut importer = BlockImporter::new(Arc::clone(db_service.backend()), run_cmd.sync_params.unsafe_starting_block)
+        .context("Initializing importer service")?
+        .with_trie_commit_interval(run_cmd.sync_params.trie_commit_interval);
+    if let Some(dir) = &run_cmd.sync_params.dump_state_diff_dir {
+        importer = importer.with_state_diff_dump_dir(dir.clone());
+    }
+    let importer = Arc::new(importer);
 
     let l1_gas_setter = GasPriceProvider::new();
 
@@ -127,52 +142,93 @@ async fn main() -> anyhow::Result<()> {
 
     // Block provider startup.
     // `rpc_add_txs_method_provider` is a trait object that tells the RPC task where to put the transactions when using the Write endpoints.
-    let (block_provider_service, rpc_add_txs_method_provider): (_, Arc<dyn AddTransactionProvider>) =
-        match run_cmd.is_sequencer() {
-            // Block production service. (authority)
-            true => {
-                let block_production_service = BlockProductionService::new(
-                    &run_cmd.block_production_params,
-                    &db_service,
-                    Arc::clone(&mempool),
-                    importer,
-                    Arc::clone(&l1_data_provider),
-                    run_cmd.devnet,
-                    telemetry_service.new_handle(),
-                )?;
-
-                (ServiceGroup::default().with(block_production_service), Arc::new(MempoolAddTxProvider::new(mempool)))
+    // `sync_health` and `feeder_health` are only tracked by full nodes, a sequencer is always ready.
+    let (block_provider_service, rpc_add_txs_method_provider, sync_health, feeder_health): (
+        _,
+        Arc<dyn AddTransactionProvider>,
+        _,
+        _,
+    ) = match run_cmd.is_sequencer() {
+        // Block production service. (authority)
+        true => {
+            let block_production_service = BlockProductionService::new(
+                &run_cmd.block_production_params,
+                &db_service,
+                Arc::clone(&mempool),
+                importer,
+                Arc::clone(&l1_data_provider),
+                run_cmd.devnet,
+                telemetry_service.new_handle(),
+            )?;
+
+            (
+                ServiceGroup::default().with(block_production_service),
+                Arc::new(MempoolAddTxProvider::new(mempool)),
+                None,
+                None,
+            )
+        }
+        // Block sync service. (full node)
+        false => {
+            // Feeder gateway sync service.
+            let mut sync_health =
+                SyncHealthTracker::new(Arc::clone(db_service.backend()), run_cmd.sync_params.synced_threshold);
+            if let Some(blocks) = run_cmd.sync_params.max_sync_lag {
+                sync_health =
+                    sync_health.with_max_lag(MaxSyncLag { blocks, timeout: run_cmd.sync_params.max_sync_lag_timeout });
             }
-            // Block sync service. (full node)
-            false => {
-                // Feeder gateway sync service.
-                let sync_service = L2SyncService::new(
-                    &run_cmd.sync_params,
-                    Arc::clone(&chain_config),
-                    &db_service,
-                    importer,
-                    telemetry_service.new_handle(),
-                    run_cmd.args_preset.warp_update_receiver,
+            if let Some(polls) = run_cmd.sync_params.caught_up_polls {
+                sync_health = sync_health.with_caught_up_polls(polls);
+            }
+            let sync_health = Arc::new(sync_health);
+            let sync_service = L2SyncService::new(
+                &run_cmd.sync_params,
+                Arc::clone(&chain_config),
+                &db_service,
+                importer,
+                telemetry_service.new_handle(),
+                run_cmd.args_preset.warp_update_receiver,
+                Arc::clone(&sync_health),
+            )
+            .await
+            .context("Initializing sync service")?;
+            let feeder_health = sync_service.feeder_health();
+
+            let gateway = mc_sync::with_path_prefix(
+                &chain_config.gateway_url,
+                run_cmd.sync_params.gateway_path_prefix.as_deref(),
+            )
+            .context("Invalid gateway path prefix")?;
+            let feeder_gateway = mc_sync::with_path_prefix(
+                &chain_config.feeder_gateway_url,
+                run_cmd.sync_params.feeder_gateway_path_prefix.as_deref(),
+            )
+            .context("Invalid feeder gateway path prefix")?;
+            let mut provider = GatewayProvider::new(gateway, feeder_gateway);
+            // gateway api key is needed for declare transactions on mainnet
+            if let Some(api_key) = run_cmd.sync_params.gateway_key {
+                provider.add_header(
+                    HeaderName::from_static("x-throttling-bypass"),
+                    HeaderValue::from_str(&api_key).with_context(|| "Invalid API key format")?,
                 )
-                .await
-                .context("Initializing sync service")?;
-
-                let mut provider =
-                    GatewayProvider::new(chain_config.gateway_url.clone(), chain_config.feeder_gateway_url.clone());
-                // gateway api key is needed for declare transactions on mainnet
-                if let Some(api_key) = run_cmd.sync_params.gateway_key {
-                    provider.add_header(
-                        HeaderName::from_static("x-throttling-bypass"),
-                        HeaderValue::from_str(&api_key).with_context(|| "Invalid API key format")?,
-                    )
-                }
-
-                (ServiceGroup::default().with(sync_service), Arc::new(ForwardToProvider::new(provider)))
             }
-        };
 
-    let rpc_service =
-        RpcService::new(run_cmd.rpc_params, Arc::clone(db_service.backend()), Arc::clone(&rpc_add_txs_method_provider));
+            (
+                ServiceGroup::default().with(sync_service),
+                Arc::new(ForwardToProvider::new(provider)),
+                Some(sync_health),
+                Some(feeder_health),
+            )
+        }
+    };
+
+    let rpc_service = RpcService::new(
+        run_cmd.rpc_params,
+        Arc::clone(db_service.backend()),
+        Arc::clone(&rpc_add_txs_method_provider),
+        sync_health,
+        feeder_health,
+    );
 
     let gateway_service = GatewayService::new(run_cmd.gateway_params, &db_service, rpc_add_txs_method_provider)
         .await
@@ -200,7 +256,17 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    app.start_and_drive_to_end().await?;
+    if let Err(err) = app.start_and_drive_to_end().await {
+        let too_many_failures = err
+            .chain()
+            .any(|cause| matches!(cause.downcast_ref::<FetchError>(), Some(FetchError::TooManyFailures { .. })));
+        if too_many_failures {
+            tracing::error!("{err:#}");
+            let _ = analytics.shutdown();
+            std::process::exit(FetchError::TOO_MANY_FAILURES_EXIT_CODE);
+        }
+        return Err(err);
+    }
 
     let _ = analytics.shutdown();
 
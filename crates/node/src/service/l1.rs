@@ -2,7 +2,8 @@ use crate::cli::l1::L1SyncParams;
 use alloy::primitives::Address;
 use anyhow::Context;
 use mc_db::{DatabaseService, MadaraBackend};
-use mc_eth::client::{EthereumClient, L1BlockMetrics};
+use mc_eth::client::{l1_chain_id_for, EthereumClient, L1BlockMetrics};
+use mc_eth::l1_gas_price::L1GasMode;
 use mc_mempool::{GasPriceProvider, Mempool};
 use mp_block::H160;
 use mp_utils::service::{MadaraService, Service, ServiceContext};
@@ -10,15 +11,20 @@ use starknet_api::core::ChainId;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::task::JoinSet;
+use url::Url;
 
 #[derive(Clone)]
 pub struct L1SyncService {
     db_backend: Arc<MadaraBackend>,
     eth_client: Option<EthereumClient>,
+    fallback_eth_client: Option<EthereumClient>,
     l1_gas_provider: GasPriceProvider,
     chain_id: ChainId,
     gas_price_sync_disabled: bool,
     gas_price_poll: Duration,
+    gas_price_request_timeout: Duration,
+    gas_price_max_delta_pct: Option<f64>,
+    l1_gas_mode: L1GasMode,
     mempool: Arc<Mempool>,
 }
 
@@ -36,12 +42,10 @@ impl L1SyncService {
     ) -> anyhow::Result<Self> {
         let eth_client = if !config.sync_l1_disabled && (config.l1_endpoint.is_some() || !devnet) {
             if let Some(l1_rpc_url) = &config.l1_endpoint {
-                let core_address = Address::from_slice(l1_core_address.as_bytes());
-                let l1_block_metrics = L1BlockMetrics::register().expect("Registering metrics");
                 Some(
-                    EthereumClient::new(l1_rpc_url.clone(), core_address, l1_block_metrics)
+                    Self::eth_client(config, l1_rpc_url, l1_core_address, &chain_id)
                         .await
-                        .context("Creating ethereum client")?,
+                        .context("Setting up the L1 endpoint")?,
                 )
             } else {
                 anyhow::bail!(
@@ -51,54 +55,121 @@ impl L1SyncService {
         } else {
             None
         };
+        // The fallback endpoint is only there in case the primary one fails, it must not prevent the node from starting.
+        let fallback_eth_client = match (&eth_client, &config.l1_endpoint_fallback) {
+            (Some(_), Some(l1_rpc_url)) => {
+                match Self::eth_client(config, l1_rpc_url, l1_core_address, &chain_id).await {
+                    Ok(fallback_eth_client) => Some(fallback_eth_client),
+                    Err(err) => {
+                        tracing::warn!(
+                            "⚠️ Could not set up the fallback L1 endpoint, using the primary one only: {err:#}"
+                        );
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
 
         // Note: gas price should be synced in case the madara is running in sequencer mode,
         // we haven't set any fix price for the gas, hence gas price should be none
-        let gas_price_sync_enabled =
-            authority && !devnet && (config.gas_price.is_none() || config.blob_gas_price.is_none());
+        let gas_price_sync_enabled = authority && !devnet && config.gas_price_sync_needed();
         let gas_price_poll = config.gas_price_poll;
 
+        if authority && !devnet && config.is_fully_fixed() {
+            tracing::info!("⛽ All L1 gas prices are fixed, the gas price worker will not be started");
+        }
+
         if gas_price_sync_enabled {
             let eth_client = eth_client
                 .clone()
                 .context("L1 gas prices require the ethereum service to be enabled. Either disable gas prices syncing using `--gas-price 0`, or disable L1 sync using the `--no-l1-sync` argument.")?;
             // running at-least once before the block production service
             tracing::info!("⏳ Getting initial L1 gas prices");
-            mc_eth::l1_gas_price::gas_price_worker_once(&eth_client, l1_gas_provider.clone(), gas_price_poll)
-                .await
-                .context("Getting initial ethereum gas prices")?;
+            mc_eth::l1_gas_price::gas_price_worker_once(
+                &eth_client,
+                l1_gas_provider.clone(),
+                gas_price_poll,
+                config.gas_price_request_timeout,
+                config.gas_price_max_delta_pct,
+                config.l1_gas_mode.into(),
+            )
+            .await
+            .context("Getting initial ethereum gas prices")?;
         }
 
         Ok(Self {
             db_backend: Arc::clone(db.backend()),
             eth_client,
+            fallback_eth_client,
             l1_gas_provider,
             chain_id,
             gas_price_sync_disabled: !gas_price_sync_enabled,
             gas_price_poll,
+            gas_price_request_timeout: config.gas_price_request_timeout,
+            gas_price_max_delta_pct: config.gas_price_max_delta_pct,
+            l1_gas_mode: config.l1_gas_mode.into(),
             mempool,
         })
     }
+
+    async fn eth_client(
+        config: &L1SyncParams,
+        l1_rpc_url: &Url,
+        l1_core_address: H160,
+        chain_id: &ChainId,
+    ) -> anyhow::Result<EthereumClient> {
+        let core_address = Address::from_slice(l1_core_address.as_bytes());
+        let l1_block_metrics = L1BlockMetrics::register().expect("Registering metrics");
+        let eth_client = EthereumClient::new(l1_rpc_url.clone(), core_address, l1_block_metrics)
+            .await
+            .context("Creating ethereum client")?;
+
+        if config.skip_l1_check {
+            tracing::warn!("⚠️ Skipping the L1 endpoint check, make sure the L1 endpoint matches the L2 chain");
+        } else {
+            eth_client
+                .verify_l1_endpoint(l1_chain_id_for(chain_id))
+                .await
+                .context("Checking the L1 endpoint. You can skip this check using `--skip-l1-check`")?;
+        }
+
+        Ok(eth_client)
+    }
 }
 
 #[async_trait::async_trait]
 impl Service for L1SyncService {
     async fn start(&mut self, join_set: &mut JoinSet<anyhow::Result<()>>, ctx: ServiceContext) -> anyhow::Result<()> {
-        let L1SyncService { l1_gas_provider, chain_id, gas_price_sync_disabled, gas_price_poll, mempool, .. } =
-            self.clone();
+        let L1SyncService {
+            l1_gas_provider,
+            chain_id,
+            gas_price_sync_disabled,
+            gas_price_poll,
+            gas_price_request_timeout,
+            gas_price_max_delta_pct,
+            l1_gas_mode,
+            mempool,
+            ..
+        } = self.clone();
 
         if let Some(eth_client) = self.eth_client.take() {
             // enabled
 
+            let fallback_eth_client = self.fallback_eth_client.take();
             let db_backend = Arc::clone(&self.db_backend);
             join_set.spawn(async move {
                 mc_eth::sync::l1_sync_worker(
                     &db_backend,
                     &eth_client,
+                    fallback_eth_client.as_ref(),
                     chain_id,
                     l1_gas_provider,
                     gas_price_sync_disabled,
                     gas_price_poll,
+                    gas_price_request_timeout,
+                    gas_price_max_delta_pct,
+                    l1_gas_mode,
                     mempool,
                     ctx,
                 )
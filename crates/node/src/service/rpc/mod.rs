@@ -4,7 +4,9 @@ use jsonrpsee::server::ServerHandle;
 use tokio::task::JoinSet;
 
 use mc_db::MadaraBackend;
+use mc_gateway_client::FeederHealth;
 use mc_rpc::{providers::AddTransactionProvider, rpc_api_admin, rpc_api_user, Starknet};
+use mc_sync::health::SyncHealthTracker;
 use mp_utils::service::{MadaraService, Service, ServiceContext};
 
 use metrics::RpcMetrics;
@@ -22,6 +24,8 @@ pub struct RpcService {
     config: RpcParams,
     backend: Arc<MadaraBackend>,
     add_txs_method_provider: Arc<dyn AddTransactionProvider>,
+    sync_health: Option<Arc<SyncHealthTracker>>,
+    feeder_health: Option<Arc<FeederHealth>>,
     server_handle_user: Option<ServerHandle>,
     server_handle_admin: Option<ServerHandle>,
 }
@@ -31,18 +35,31 @@ impl RpcService {
         config: RpcParams,
         backend: Arc<MadaraBackend>,
         add_txs_method_provider: Arc<dyn AddTransactionProvider>,
+        sync_health: Option<Arc<SyncHealthTracker>>,
+        feeder_health: Option<Arc<FeederHealth>>,
     ) -> Self {
-        Self { config, backend, add_txs_method_provider, server_handle_user: None, server_handle_admin: None }
+        Self {
+            config,
+            backend,
+            add_txs_method_provider,
+            sync_health,
+            feeder_health,
+            server_handle_user: None,
+            server_handle_admin: None,
+        }
     }
 }
 
 #[async_trait::async_trait]
 impl Service for RpcService {
     async fn start(&mut self, join_set: &mut JoinSet<anyhow::Result<()>>, ctx: ServiceContext) -> anyhow::Result<()> {
-        let RpcService { config, backend, add_txs_method_provider, .. } = self;
+        let RpcService { config, backend, add_txs_method_provider, sync_health, feeder_health, .. } = self;
 
-        let starknet =
+        let mut starknet =
             Starknet::new(backend.clone(), add_txs_method_provider.clone(), config.storage_proof_config(), ctx.clone());
+        if let Some(feeder_health) = feeder_health {
+            starknet = starknet.with_feeder_health(Arc::clone(feeder_health));
+        }
         let metrics = RpcMetrics::register()?;
 
         let server_config_user = if !config.rpc_disable {
@@ -62,6 +79,7 @@ impl Service for RpcService {
                 metrics: metrics.clone(),
                 cors: config.cors(),
                 rpc_version_default: mp_chain_config::RpcVersion::RPC_VERSION_LATEST,
+                sync_health: sync_health.clone(),
             })
         } else {
             None
@@ -84,6 +102,7 @@ impl Service for RpcService {
                 metrics,
                 cors: config.cors(),
                 rpc_version_default: mp_chain_config::RpcVersion::RPC_VERSION_LATEST_ADMIN,
+                sync_health: sync_health.clone(),
             })
         } else {
             None
@@ -3,9 +3,11 @@
 
 use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Context;
+use mc_sync::health::{SyncHealth, SyncHealthTracker};
 use mp_utils::service::ServiceContext;
 use tokio::task::JoinSet;
 use tower::Service;
@@ -35,6 +37,8 @@ pub struct ServerConfig {
     pub methods: jsonrpsee::Methods,
     /// Batch request config.
     pub batch_config: jsonrpsee::server::BatchRequestConfig,
+    /// Reported on the `/ready` endpoint. When `None`, the node is always ready.
+    pub sync_health: Option<Arc<SyncHealthTracker>>,
 }
 
 #[derive(Debug, Clone)]
@@ -64,6 +68,7 @@ pub async fn start_server(
         message_buffer_capacity,
         methods,
         batch_config,
+        sync_health,
     } = config;
 
     let listener = tokio::net::TcpListener::bind(addr)
@@ -103,6 +108,7 @@ pub async fn start_server(
     let make_service = hyper::service::make_service_fn(move |_| {
         let cfg = cfg.clone();
         let ctx1 = ctx1.clone();
+        let sync_health = sync_health.clone();
 
         async move {
             let cfg = cfg.clone();
@@ -123,6 +129,7 @@ pub async fn start_server(
 
                 let mut svc = service_builder.set_rpc_middleware(rpc_middleware).build(methods, stop_handle);
                 let ctx1 = ctx1.clone();
+                let sync_health = sync_health.clone();
 
                 async move {
                     if !ctx1.is_active() {
@@ -131,6 +138,8 @@ pub async fn start_server(
                             .body(hyper::Body::from("GONE"))?)
                     } else if req.uri().path() == "/health" {
                         Ok(hyper::Response::builder().status(hyper::StatusCode::OK).body(hyper::Body::from("OK"))?)
+                    } else if req.uri().path() == "/ready" {
+                        Ok(ready_response(sync_health.as_deref())?)
                     } else {
                         if is_websocket {
                             // Utilize the session close future to know when the actual WebSocket
@@ -174,6 +183,23 @@ pub async fn start_server(
     Ok(server_handle)
 }
 
+/// `200 OK` once the node is caught up with the tip of the chain, `503 Service Unavailable` otherwise.
+fn ready_response(sync_health: Option<&SyncHealthTracker>) -> Result<hyper::Response<hyper::Body>, hyper::http::Error> {
+    let health = sync_health.map(SyncHealthTracker::sync_health).unwrap_or(SyncHealth::Synced);
+    let (status, body) = match health {
+        SyncHealth::Synced => (hyper::StatusCode::OK, "OK".to_string()),
+        SyncHealth::Bootstrapping => (hyper::StatusCode::SERVICE_UNAVAILABLE, "BOOTSTRAPPING".to_string()),
+        SyncHealth::SyncingBehind { lag } => {
+            (hyper::StatusCode::SERVICE_UNAVAILABLE, format!("SYNCING: {lag} blocks behind"))
+        }
+        SyncHealth::Stalled { lag } => {
+            (hyper::StatusCode::SERVICE_UNAVAILABLE, format!("STALLED: {lag} blocks behind"))
+        }
+        SyncHealth::Paused => (hyper::StatusCode::SERVICE_UNAVAILABLE, "PAUSED".to_string()),
+    };
+    hyper::Response::builder().status(status).body(hyper::Body::from(body))
+}
+
 // Copied from https://github.com/paritytech/polkadot-sdk/blob/a0aefc6b233ace0a82a8631d67b6854e6aeb014b/substrate/client/rpc-servers/src/utils.rs#L192
 pub(crate) fn host_filtering(
     enabled: bool,
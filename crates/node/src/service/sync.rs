@@ -2,7 +2,13 @@ use crate::cli::SyncParams;
 use anyhow::Context;
 use mc_block_import::BlockImporter;
 use mc_db::{DatabaseService, MadaraBackend};
+use mc_gateway_client::FeederHealth;
+use mc_sync::checkpoint::CheckpointConfig;
+use mc_sync::events::SyncEvents;
 use mc_sync::fetch::fetchers::FetchConfig;
+use mc_sync::genesis::GenesisDumpConfig;
+use mc_sync::health::SyncHealthTracker;
+use mc_sync::l2::RecentStateUpdates;
 use mc_sync::SyncConfig;
 use mc_telemetry::TelemetryHandle;
 use mp_chain_config::ChainConfig;
@@ -18,9 +24,17 @@ pub struct L2SyncService {
     fetch_config: FetchConfig,
     backup_every_n_blocks: Option<u64>,
     starting_block: Option<u64>,
+    resync_tail: Option<u64>,
     start_params: Option<TelemetryHandle>,
     disabled: bool,
     pending_block_poll_interval: Duration,
+    disable_pending: bool,
+    health: Arc<SyncHealthTracker>,
+    feeder_health: Arc<FeederHealth>,
+    recent_state_updates: Arc<RecentStateUpdates>,
+    events: SyncEvents,
+    genesis_dump: Option<GenesisDumpConfig>,
+    checkpoint: Option<CheckpointConfig>,
 }
 
 impl L2SyncService {
@@ -31,39 +45,71 @@ impl L2SyncService {
         block_importer: Arc<BlockImporter>,
         telemetry: TelemetryHandle,
         warp_update: bool,
+        health: Arc<SyncHealthTracker>,
     ) -> anyhow::Result<Self> {
         let fetch_config = config.block_fetch_config(chain_config.chain_id.clone(), chain_config.clone(), warp_update);
 
-        tracing::info!("🛰️  Using feeder gateway URL: {}", fetch_config.feeder_gateway.as_str());
+        let feeder_gateway =
+            mc_sync::with_path_prefix(&fetch_config.feeder_gateway, fetch_config.feeder_gateway_path_prefix.as_deref())
+                .context("Invalid feeder gateway path prefix")?;
+        tracing::info!("🛰️  Using feeder gateway URL: {}", feeder_gateway.as_str());
 
         Ok(Self {
             db_backend: Arc::clone(db.backend()),
             fetch_config,
             starting_block: config.unsafe_starting_block,
+            resync_tail: config.resync_tail,
+            genesis_dump: config.genesis_dump(),
+            checkpoint: config.checkpoint(),
             backup_every_n_blocks: config.backup_every_n_blocks,
             block_importer,
             start_params: Some(telemetry),
-            disabled: config.sync_disabled,
+            disabled: config.sync_l2_disabled,
             pending_block_poll_interval: config.pending_block_poll_interval,
+            disable_pending: config.disable_pending,
+            health,
+            feeder_health: Arc::new(FeederHealth::new(config.feeder_down_after_failures)),
+            recent_state_updates: Arc::new(RecentStateUpdates::new(config.recent_state_updates)),
+            events: SyncEvents::default(),
         })
     }
+
+    /// Health of the feeder gateway, as seen by the sync.
+    pub fn feeder_health(&self) -> Arc<FeederHealth> {
+        Arc::clone(&self.feeder_health)
+    }
+
+    /// Latest state updates verified by the sync.
+    pub fn recent_state_updates(&self) -> Arc<RecentStateUpdates> {
+        Arc::clone(&self.recent_state_updates)
+    }
 }
 
 #[async_trait::async_trait]
 impl Service for L2SyncService {
     async fn start(&mut self, join_set: &mut JoinSet<anyhow::Result<()>>, ctx: ServiceContext) -> anyhow::Result<()> {
         if self.disabled {
+            tracing::info!("⏸️  The L2 sync is disabled, no new block will be fetched from the feeder gateway");
             return Ok(());
         }
         let L2SyncService {
             fetch_config,
             backup_every_n_blocks,
             starting_block,
+            resync_tail,
             pending_block_poll_interval,
+            disable_pending,
             block_importer,
+            health,
+            feeder_health,
+            recent_state_updates,
+            events,
+            genesis_dump,
+            checkpoint,
             ..
         } = self.clone();
         let telemetry = self.start_params.take().context("Service already started")?;
+        health.watch_services(&ctx);
 
         let db_backend = Arc::clone(&self.db_backend);
 
@@ -78,6 +124,14 @@ impl Service for L2SyncService {
                     backup_every_n_blocks,
                     telemetry,
                     pending_block_poll_interval,
+                    disable_pending,
+                    health,
+                    feeder_health,
+                    recent_state_updates,
+                    resync_tail,
+                    events,
+                    genesis_dump,
+                    checkpoint,
                 },
             )
             .await
@@ -90,3 +144,39 @@ impl Service for L2SyncService {
         MadaraService::L2Sync
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::RunCmd;
+    use clap::Parser;
+    use mc_telemetry::TelemetryService;
+
+    /// The L2 sync worker is not spawned when the L2 sync is disabled.
+    #[tokio::test]
+    async fn test_l2_sync_disabled() {
+        let run_cmd = RunCmd::try_parse_from(["madara", "--full", "--no-l2-sync"]).unwrap();
+        assert!(run_cmd.sync_params.sync_l2_disabled);
+
+        let chain_config = Arc::new(ChainConfig::madara_test());
+        let db = DatabaseService::open_for_testing(Arc::clone(&chain_config));
+        let block_importer = Arc::new(BlockImporter::new(Arc::clone(db.backend()), None).unwrap());
+        let telemetry = TelemetryService::new(false, vec![]).unwrap();
+        let health = Arc::new(SyncHealthTracker::new(Arc::clone(db.backend()), 0));
+        let mut service = L2SyncService::new(
+            &run_cmd.sync_params,
+            chain_config,
+            &db,
+            block_importer,
+            telemetry.new_handle(),
+            false,
+            health,
+        )
+        .await
+        .unwrap();
+
+        let mut join_set = JoinSet::new();
+        service.start(&mut join_set, ServiceContext::new_for_testing()).await.unwrap();
+        assert!(join_set.is_empty());
+    }
+}
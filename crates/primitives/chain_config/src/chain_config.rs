@@ -607,4 +607,35 @@ mod tests {
         );
         assert!(chain_config.exec_constants_by_protocol_version(StarknetVersion::new(0, 0, 0, 0)).is_err(),);
     }
+
+    #[rstest]
+    #[case::mainnet(
+        ChainConfig::starknet_mainnet(),
+        ChainId::Mainnet,
+        "alpha-mainnet",
+        eth_core_contract_address::MAINNET
+    )]
+    #[case::sepolia(
+        ChainConfig::starknet_sepolia(),
+        ChainId::Sepolia,
+        "alpha-sepolia",
+        eth_core_contract_address::SEPOLIA_TESTNET
+    )]
+    #[case::integration(
+        ChainConfig::starknet_integration(),
+        ChainId::IntegrationSepolia,
+        "integration-sepolia",
+        eth_core_contract_address::SEPOLIA_INTEGRATION
+    )]
+    fn test_network_presets(
+        #[case] chain_config: ChainConfig,
+        #[case] chain_id: ChainId,
+        #[case] host: &str,
+        #[case] core_contract: &str,
+    ) {
+        assert_eq!(chain_config.chain_id, chain_id);
+        assert_eq!(chain_config.gateway_url.as_str(), format!("https://{host}.starknet.io/gateway/"));
+        assert_eq!(chain_config.feeder_gateway_url.as_str(), format!("https://{host}.starknet.io/feeder_gateway/"));
+        assert_eq!(chain_config.eth_core_contract_address, core_contract.parse::<H160>().unwrap());
+    }
 }
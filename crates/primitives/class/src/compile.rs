@@ -114,6 +114,12 @@ impl FlattenedSierraClass {
 }
 
 impl CompiledSierra {
+    /// Computes the compiled class hash of this CASM definition, as returned by
+    /// [`FlattenedSierraClass::compile_to_casm`].
+    pub fn compiled_class_hash(&self) -> Result<Felt, ClassCompilationError> {
+        v2::compute_compiled_class_hash(&self.0)
+    }
+
     pub fn to_blockifier_class(
         &self,
     ) -> Result<blockifier::execution::contract_class::ContractClass, ClassCompilationError> {
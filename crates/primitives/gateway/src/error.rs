@@ -15,6 +15,12 @@ pub enum SequencerError {
     HttpError(#[from] hyper::http::Error),
     #[error("Error calling HTTP client: {0:#}")]
     HttpCallError(Box<dyn std::error::Error + Send + Sync>),
+    #[error("Request timed out")]
+    Timeout,
+    #[error("Response body is larger than the limit of {max_response_bytes} bytes")]
+    ResponseTooLarge { max_response_bytes: usize },
+    #[error("Error reading recorded response: {0:#}")]
+    Replay(std::io::Error),
     #[error("Error deserializing response: {serde_error:#}")]
     DeserializeBody { serde_error: serde_json::Error },
     #[error("Error serializing request: {0:#}")]
@@ -71,6 +71,17 @@ impl From<mp_state_update::StateUpdate> for ProviderStateUpdate {
     }
 }
 
+impl From<ProviderStateUpdate> for mp_state_update::StateUpdate {
+    fn from(state_update: ProviderStateUpdate) -> Self {
+        Self {
+            block_hash: state_update.block_hash,
+            old_root: state_update.old_root,
+            new_root: state_update.new_root,
+            state_diff: state_update.state_diff.into(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(deny_unknown_fields)]
 pub struct ProviderStateUpdatePending {
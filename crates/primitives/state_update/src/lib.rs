@@ -63,80 +63,76 @@ impl StateDiff {
         self.nonces.sort_by_key(|nonce| nonce.contract_address);
     }
 
+    /// Computes the state diff commitment.
+    ///
+    /// The commitment is defined over the sorted state diff: this sorts references into `self` rather than a copy of
+    /// it, so that the only allocation proportional to the size of the diff is the list of hashed elements.
     pub fn compute_hash(&self) -> Felt {
-        let updated_contracts_sorted = {
-            let mut updated_contracts = self
-                .deployed_contracts
-                .iter()
-                .map(|deployed_contract| (deployed_contract.address, deployed_contract.class_hash))
-                .chain(
-                    self.replaced_classes
-                        .iter()
-                        .map(|replaced_class| (replaced_class.contract_address, replaced_class.class_hash)),
-                )
-                .collect::<Vec<_>>();
-            updated_contracts.sort_by_key(|(address, _)| *address);
-            updated_contracts
-        };
-
-        let declared_classes_sorted = {
-            let mut declared_classes = self.declared_classes.clone();
-            declared_classes.sort_by_key(|declared_class| declared_class.class_hash);
-            declared_classes
-        };
-
-        let deprecated_declared_classes_sorted = {
-            let mut deprecated_declared_classes = self.deprecated_declared_classes.clone();
-            deprecated_declared_classes.sort();
-            deprecated_declared_classes
-        };
-
-        let nonces_sorted = {
-            let mut nonces = self.nonces.clone();
-            nonces.sort_by_key(|nonce| nonce.contract_address);
-            nonces
-        };
-
-        let storage_diffs_sorted = {
-            let mut storage_diffs = self.storage_diffs.clone();
-            storage_diffs.iter_mut().for_each(|storage_diff| storage_diff.sort_storage_entries());
-            storage_diffs.sort_by_key(|storage_diff| storage_diff.address);
-            storage_diffs
-        };
-
-        let updated_contracts_len_as_felt = (updated_contracts_sorted.len() as u64).into();
-        let declared_classes_len_as_felt = (declared_classes_sorted.len() as u64).into();
-        let deprecated_declared_classes_len_as_felt = (deprecated_declared_classes_sorted.len() as u64).into();
-        let nonces_len_as_felt = (nonces_sorted.len() as u64).into();
-        let storage_diffs_len_as_felt = (storage_diffs_sorted.len() as u64).into();
-
-        let elements: Vec<Felt> = std::iter::once(Felt::from_bytes_be_slice(b"STARKNET_STATE_DIFF0"))
-            .chain(std::iter::once(updated_contracts_len_as_felt))
-            .chain(updated_contracts_sorted.into_iter().flat_map(|(address, class_hash)| vec![address, class_hash]))
-            .chain(std::iter::once(declared_classes_len_as_felt))
+        let mut updated_contracts = self
+            .deployed_contracts
+            .iter()
+            .map(|deployed_contract| (&deployed_contract.address, &deployed_contract.class_hash))
             .chain(
-                declared_classes_sorted
-                    .into_iter()
-                    .flat_map(|declared_class| vec![declared_class.class_hash, declared_class.compiled_class_hash]),
+                self.replaced_classes
+                    .iter()
+                    .map(|replaced_class| (&replaced_class.contract_address, &replaced_class.class_hash)),
             )
-            .chain(std::iter::once(deprecated_declared_classes_len_as_felt))
-            .chain(deprecated_declared_classes_sorted)
-            .chain(std::iter::once(Felt::ONE))
-            .chain(std::iter::once(Felt::ZERO))
-            .chain(std::iter::once(storage_diffs_len_as_felt))
-            .chain(storage_diffs_sorted.into_iter().flat_map(|storage_diff| {
-                let storage_entries_len_as_felt: Felt = (storage_diff.storage_entries.len() as u64).into();
-                std::iter::once(storage_diff.address).chain(std::iter::once(storage_entries_len_as_felt)).chain(
-                    storage_diff
-                        .storage_entries
-                        .iter()
-                        .flat_map(|storage_entry| vec![storage_entry.key, storage_entry.value])
-                        .collect::<Vec<_>>(),
-                )
-            }))
-            .chain(std::iter::once(nonces_len_as_felt))
-            .chain(nonces_sorted.into_iter().flat_map(|nonce| vec![nonce.contract_address, nonce.nonce]))
-            .collect();
+            .collect::<Vec<_>>();
+        updated_contracts.sort_by_key(|(address, _)| **address);
+
+        let mut declared_classes = self.declared_classes.iter().collect::<Vec<_>>();
+        declared_classes.sort_by_key(|declared_class| declared_class.class_hash);
+
+        let mut deprecated_declared_classes = self.deprecated_declared_classes.iter().collect::<Vec<_>>();
+        deprecated_declared_classes.sort();
+
+        let mut nonces = self.nonces.iter().collect::<Vec<_>>();
+        nonces.sort_by_key(|nonce| nonce.contract_address);
+
+        let mut storage_diffs = self
+            .storage_diffs
+            .iter()
+            .map(|storage_diff| {
+                let mut storage_entries = storage_diff.storage_entries.iter().collect::<Vec<_>>();
+                storage_entries.sort_by_key(|storage_entry| storage_entry.key);
+                (&storage_diff.address, storage_entries)
+            })
+            .collect::<Vec<_>>();
+        storage_diffs.sort_by_key(|(address, _)| **address);
+
+        let n_elements = 8
+            + 2 * updated_contracts.len()
+            + 2 * declared_classes.len()
+            + deprecated_declared_classes.len()
+            + storage_diffs.iter().map(|(_, storage_entries)| 2 + 2 * storage_entries.len()).sum::<usize>()
+            + 2 * nonces.len();
+        let mut elements = Vec::with_capacity(n_elements);
+
+        elements.push(Felt::from_bytes_be_slice(b"STARKNET_STATE_DIFF0"));
+        elements.push((updated_contracts.len() as u64).into());
+        for (address, class_hash) in updated_contracts {
+            elements.extend([*address, *class_hash]);
+        }
+        elements.push((declared_classes.len() as u64).into());
+        for declared_class in declared_classes {
+            elements.extend([declared_class.class_hash, declared_class.compiled_class_hash]);
+        }
+        elements.push((deprecated_declared_classes.len() as u64).into());
+        elements.extend(deprecated_declared_classes);
+        elements.push(Felt::ONE);
+        elements.push(Felt::ZERO);
+        elements.push((storage_diffs.len() as u64).into());
+        for (address, storage_entries) in storage_diffs {
+            elements.extend([*address, (storage_entries.len() as u64).into()]);
+            for storage_entry in storage_entries {
+                elements.extend([storage_entry.key, storage_entry.value]);
+            }
+        }
+        elements.push((nonces.len() as u64).into());
+        for nonce in nonces {
+            elements.extend([nonce.contract_address, nonce.nonce]);
+        }
+        debug_assert_eq!(elements.len(), n_elements);
 
         Poseidon::hash_array(&elements)
     }
@@ -242,6 +238,30 @@ mod tests {
         assert_eq!(state_diff_one.compute_hash(), state_diff_two.compute_hash());
     }
 
+    #[test]
+    fn test_compute_hash_large_diff() {
+        // A large, unsorted state diff: hashing it must not require sorting a copy of it.
+        let n = 10_000u64;
+        let state_diff = StateDiff {
+            storage_diffs: (0..n)
+                .rev()
+                .map(|i| ContractStorageDiffItem {
+                    address: Felt::from(i),
+                    storage_entries: (0..8)
+                        .rev()
+                        .map(|k| StorageEntry { key: Felt::from(k), value: Felt::from(i) })
+                        .collect(),
+                })
+                .collect(),
+            nonces: (0..n).rev().map(|i| NonceUpdate { contract_address: Felt::from(i), nonce: Felt::ONE }).collect(),
+            ..Default::default()
+        };
+        let mut sorted = state_diff.clone();
+        sorted.sort();
+
+        assert_eq!(state_diff.compute_hash(), sorted.compute_hash());
+    }
+
     pub(crate) fn dummy_state_diff() -> StateDiff {
         StateDiff {
             storage_diffs: vec![
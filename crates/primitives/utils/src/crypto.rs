@@ -29,6 +29,12 @@ impl ZeroingPrivateKey {
     }
 }
 
+/// Checks a `[r, s]` signature of `hash`, as made by [`ZeroingPrivateKey::sign`].
+pub fn verify_signature(public_key: &Felt, hash: &Felt, signature: &[Felt]) -> bool {
+    let &[r, s] = signature else { return false };
+    starknet_core::crypto::ecdsa_verify(public_key, hash, &starknet_core::crypto::Signature { r, s }).unwrap_or(false)
+}
+
 impl Default for ZeroingPrivateKey {
     // Implementation taken from starknet-signers
     // https://github.com/xJonathanLEI/starknet-rs/blob/1b1071e2c5975c8810c1b05b776aaa58cb172037/starknet-signers/src/key_pair.rs#L38
@@ -2,6 +2,7 @@ use std::time::Duration;
 
 use serde::{Deserialize, Deserializer};
 use starknet_types_core::felt::Felt;
+use url::Url;
 
 use crate::{crypto::ZeroingPrivateKey, parsers::parse_duration};
 
@@ -24,6 +25,45 @@ where
     }
 }
 
+pub fn serialize_optional_duration<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match duration {
+        Some(duration) => serialize_duration(duration, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Hides a secret, such as an API key, while still telling whether it is set.
+pub fn serialize_redacted<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match value {
+        Some(_) => serializer.serialize_str("<redacted>"),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Urls of remote endpoints usually hold an API key in their credentials, path or query, only their origin is shown.
+pub fn serialize_url_origin<S>(url: &Url, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&format!("{}/<redacted>", url.origin().ascii_serialization()))
+}
+
+pub fn serialize_optional_url_origin<S>(url: &Option<Url>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match url {
+        Some(url) => serialize_url_origin(url, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
 pub fn deserialize_private_key<'de, D>(deserializer: D) -> Result<ZeroingPrivateKey, D::Error>
 where
     D: Deserializer<'de>,